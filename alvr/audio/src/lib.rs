@@ -10,7 +10,10 @@ use rodio::{OutputStream, Source};
 use serde::Serialize;
 use std::{
     collections::VecDeque,
-    sync::{mpsc as smpsc, Arc},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc as smpsc, Arc,
+    },
     thread,
 };
 use tokio::sync::mpsc as tmpsc;
@@ -40,6 +43,10 @@ use winapi::{
 #[cfg(windows)]
 use wio::com::ComPtr;
 
+// Disruptions of the playback of receive_samples_loop, for statistics
+pub static BUFFER_OVERFLOW_COUNT: AtomicUsize = AtomicUsize::new(0);
+pub static BUFFER_UNDERFLOW_COUNT: AtomicUsize = AtomicUsize::new(0);
+
 lazy_static! {
     static ref VIRTUAL_MICROPHONE_PAIRS: Vec<(String, String)> = vec![
         ("CABLE Input".into(), "CABLE Output".into()),
@@ -57,8 +64,8 @@ lazy_static! {
 
 #[derive(Serialize)]
 pub struct AudioDevicesList {
-    output: Vec<String>,
-    input: Vec<String>,
+    pub output: Vec<String>,
+    pub input: Vec<String>,
 }
 
 #[cfg_attr(not(target_os = "linux"), allow(unused_variables))]
@@ -516,6 +523,8 @@ pub async fn receive_samples_loop(
     average_buffer_frames_count: usize,
 ) -> StrResult {
     let mut recovery_sample_buffer = vec![];
+    // Before the first recovery the buffer is being filled, which is not an underflow
+    let mut is_playing = false;
     loop {
         let packet = receiver.recv().await?;
         let new_samples = packet
@@ -540,6 +549,10 @@ pub async fn receive_samples_loop(
         }
 
         if sample_buffer_ref.len() / channels_count < batch_frames_count {
+            if is_playing && !packet.had_packet_loss && recovery_sample_buffer.is_empty() {
+                BUFFER_UNDERFLOW_COUNT.fetch_add(1, Ordering::Relaxed);
+            }
+
             recovery_sample_buffer.extend(sample_buffer_ref.drain(..));
         }
 
@@ -573,6 +586,7 @@ pub async fn receive_samples_loop(
                 }
 
                 sample_buffer_ref.extend(recovery_sample_buffer.drain(..));
+                is_playing = true;
                 info!("Audio recovered");
             }
         } else {
@@ -583,6 +597,7 @@ pub async fn receive_samples_loop(
         let buffer_frames_size = sample_buffer_ref.len() / channels_count;
         if buffer_frames_size > 2 * average_buffer_frames_count + batch_frames_count {
             info!("Audio buffer overflow! size: {buffer_frames_size}");
+            BUFFER_OVERFLOW_COUNT.fetch_add(1, Ordering::Relaxed);
 
             let drained_samples = sample_buffer_ref
                 .drain(0..(buffer_frames_size - average_buffer_frames_count) * channels_count)
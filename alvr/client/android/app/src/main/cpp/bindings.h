@@ -161,6 +161,8 @@ extern "C" void renderLoadingNative();
 extern "C" void onTrackingNative(bool clientsidePrediction);
 extern "C" OnResumeResult onResumeNative(void *surface, bool darkMode);
 extern "C" void setStreamConfig(StreamConfig config);
+// Applied by renderNative(), on the GL thread
+extern "C" void updateStreamConfig(StreamConfig config);
 extern "C" void onStreamStartNative();
 extern "C" void onPauseNative();
 extern "C" void onHapticsFeedbackNative(unsigned long long path,
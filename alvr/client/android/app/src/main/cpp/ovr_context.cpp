@@ -28,6 +28,7 @@
 #include <inttypes.h>
 #include <glm/gtx/euler_angles.hpp>
 #include <mutex>
+#include <atomic>
 
 using namespace std;
 using namespace gl_render_utils;
@@ -62,6 +63,8 @@ public:
     std::function<void()> openDashboard;
 
     StreamConfig streamConfig{};
+    // Set when the stream config is updated while streaming
+    std::atomic_bool streamConfigUpdated = false;
 
     vector<float> refreshRatesBuffer;
 
@@ -652,7 +655,13 @@ void setStreamConfig(StreamConfig config) {
     g_ctx.streamConfig = config;
 }
 
-void onStreamStartNative() {
+void updateStreamConfig(StreamConfig config) {
+    g_ctx.streamConfig = config;
+    g_ctx.streamConfigUpdated = true;
+}
+
+// Must be called on the GL thread
+void recreateRenderer() {
     ovrRenderer_Destroy(&g_ctx.Renderer);
     ovrRenderer_Create(&g_ctx.Renderer, g_ctx.streamConfig.eyeWidth, g_ctx.streamConfig.eyeHeight,
                        g_ctx.streamTexture.get(), g_ctx.loadingTexture,
@@ -662,6 +671,11 @@ void onStreamStartNative() {
                         g_ctx.streamConfig.foveationCenterShiftX, g_ctx.streamConfig.foveationCenterShiftY,
                         g_ctx.streamConfig.foveationEdgeRatioX, g_ctx.streamConfig.foveationEdgeRatioY});
     ovrRenderer_CreateScene(&g_ctx.Renderer, g_ctx.darkMode);
+}
+
+void onStreamStartNative() {
+    g_ctx.streamConfigUpdated = false;
+    recreateRenderer();
 
     // On Oculus Quest, without ExtraLatencyMode frames passed to vrapi_SubmitFrame2 are sometimes discarded from VrAPI(?).
     // Which introduces stutter animation.
@@ -809,6 +823,11 @@ void renderNative(long long targetTimespampNs) {
 
     updateHapticsState();
 
+    // The foveation parameters are baked in the renderer
+    if (g_ctx.streamConfigUpdated.exchange(false)) {
+        recreateRenderer();
+    }
+
     ovrTracking2 tracking;
     {
         std::lock_guard<std::mutex> lock(g_ctx.trackingFrameMutex);
@@ -10,6 +10,10 @@ import android.os.Message;
 import androidx.annotation.NonNull;
 import android.view.Surface;
 
+import org.json.JSONArray;
+import org.json.JSONException;
+import org.json.JSONObject;
+
 import java.io.IOException;
 import java.nio.ByteBuffer;
 import java.util.LinkedList;
@@ -22,6 +26,8 @@ public class DecoderThread extends ThreadBase implements Handler.Callback {
     private static final int CODEC_H265 = 1;
     private int mCodec = CODEC_H265;
     private int mPriority = 0;
+    // JSON array of [key, {"type": "Float" | "Int32" | "Int64" | "String", "content": value}]
+    private String mCodecOptions = "[]";
 
     private static final String VIDEO_FORMAT_H264 = "video/avc";
     private static final String VIDEO_FORMAT_H265 = "video/hevc";
@@ -98,6 +104,7 @@ public class DecoderThread extends ThreadBase implements Handler.Callback {
                   format.setInteger("vendor.qti-ext-dec-low-latency.enable", 1); //Qualcomm low latency mode
                   format.setInteger(MediaFormat.KEY_OPERATING_RATE, Short.MAX_VALUE);
                   format.setInteger(MediaFormat.KEY_PRIORITY, mPriority);
+                  applyCodecOptions(format);
                   format.setByteBuffer("csd-0", ByteBuffer.wrap(nal.buf, 0, nal.buf.length));
                   MediaCodecList codecs = new MediaCodecList(MediaCodecList.REGULAR_CODECS);
                   String codec = codecs.findDecoderForFormat(format);
@@ -234,23 +241,53 @@ public class DecoderThread extends ThreadBase implements Handler.Callback {
         }
     }
 
-    public void onConnect(int codec, boolean realtime) {
+    public void onConnect(int codec, boolean realtime, String codecOptions) {
         Utils.logi(TAG, () -> "onConnect()");
         mQueue.reset();
-        notifyCodecChange(codec, realtime);
+        notifyCodecChange(codec, realtime, codecOptions);
+    }
+
+    // The options from the settings are set last, so they can override the defaults
+    private void applyCodecOptions(MediaFormat format) {
+        try {
+            JSONArray options = new JSONArray(mCodecOptions);
+            for (int i = 0; i < options.length(); i++) {
+                JSONArray option = options.getJSONArray(i);
+                String key = option.getString(0);
+                JSONObject value = option.getJSONObject(1);
+                switch (value.getString("type")) {
+                    case "Float":
+                        format.setFloat(key, (float) value.getDouble("content"));
+                        break;
+                    case "Int32":
+                        format.setInteger(key, value.getInt("content"));
+                        break;
+                    case "Int64":
+                        format.setLong(key, value.getLong("content"));
+                        break;
+                    case "String":
+                        format.setString(key, value.getString("content"));
+                        break;
+                }
+                Utils.logi(TAG, () -> "Codec option set: " + key + "=" + value.opt("content"));
+            }
+        } catch (JSONException e) {
+            Utils.loge(TAG, () -> "Invalid codec options: " + e.getMessage());
+        }
     }
 
     public void onDisconnect() {
         mQueue.stop();
     }
 
-    private void notifyCodecChange(int codec, boolean realtime) {
+    private void notifyCodecChange(int codec, boolean realtime, String codecOptions) {
         final int priority = realtime ? 0 : 1;
-        if (codec != mCodec || priority != mPriority) {
+        if (codec != mCodec || priority != mPriority || !codecOptions.equals(mCodecOptions)) {
             Utils.logi(TAG, () -> "notifyCodecChange: Codec was changed. New Codec=" + codec);
             stopAndWait();
             mCodec = codec;
             mPriority = priority;
+            mCodecOptions = codecOptions;
             if (mCodec == CODEC_H264) {
                 mFormat = VIDEO_FORMAT_H264;
             } else {
@@ -12,6 +12,7 @@ import android.content.res.AssetManager;
 import android.graphics.SurfaceTexture;
 import android.media.AudioManager;
 import android.net.Uri;
+import android.net.wifi.WifiManager;
 import android.opengl.EGL14;
 import android.opengl.EGLContext;
 import android.os.BatteryManager;
@@ -99,6 +100,8 @@ public class OvrActivity extends Activity {
     float mRefreshRate = 60f;
     String mDashboardURL = null;
     String mLoadingMessage = "";
+    // Without it, multicast packets (used for mDNS discovery) are filtered out by the Wi-Fi driver
+    WifiManager.MulticastLock mMulticastLock = null;
 
     // Cache method references for performance reasons
     final Runnable mRenderRunnable = this::render;
@@ -125,6 +128,13 @@ public class OvrActivity extends Activity {
         holder.addCallback(new RenderingCallbacks());
 
         requestAudioPermissions();
+
+        WifiManager wifiManager = (WifiManager) getApplicationContext().getSystemService(Context.WIFI_SERVICE);
+        if (wifiManager != null) {
+            mMulticastLock = wifiManager.createMulticastLock("alvr");
+            mMulticastLock.setReferenceCounted(false);
+        }
+
         this.registerReceiver(this.mBatInfoReceiver, new IntentFilter(Intent.ACTION_BATTERY_CHANGED));
     }
 
@@ -182,6 +192,9 @@ public class OvrActivity extends Activity {
         super.onResume();
 
         mResumed = true;
+        if (mMulticastLock != null) {
+            mMulticastLock.acquire();
+        }
         maybeResume();
     }
 
@@ -213,6 +226,9 @@ public class OvrActivity extends Activity {
     protected void onPause() {
         maybePause();
         mResumed = false;
+        if (mMulticastLock != null) {
+            mMulticastLock.release();
+        }
 
         super.onPause();
     }
@@ -398,12 +414,12 @@ public class OvrActivity extends Activity {
     }
 
     @SuppressWarnings("unused")
-    public void onServerConnected(float fps, int codec, boolean realtimeDecoder, String dashboardURL) {
+    public void onServerConnected(float fps, int codec, boolean realtimeDecoder, String codecOptions, String dashboardURL) {
         mRefreshRate = fps;
         mDashboardURL = dashboardURL;
         mRenderingHandler.post(() -> {
             onStreamStartNative();
-            mDecoderThread.onConnect(codec, realtimeDecoder);
+            mDecoderThread.onConnect(codec, realtimeDecoder, codecOptions);
         });
     }
 
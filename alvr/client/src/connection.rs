@@ -2,7 +2,7 @@
 
 use crate::{
     connection_utils::{self, ConnectionError},
-    TimeSync, VideoFrame, BATTERY_SENDER, INPUT_SENDER, TIME_SYNC_SENDER,
+    TimeSync, VideoFrame, BATTERY_SENDER, INPUT_SENDER, NETWORK_CONFIG, TIME_SYNC_SENDER,
     VIDEO_ERROR_REPORT_SENDER, VIEWS_CONFIG_SENDER,
 };
 use alvr_common::{
@@ -11,7 +11,7 @@ use alvr_common::{
     prelude::*,
     ALVR_NAME, ALVR_VERSION,
 };
-use alvr_session::{CodecType, SessionDesc};
+use alvr_session::{CodecType, SessionDesc, Settings};
 use alvr_sockets::{
     spawn_cancelable, ClientConfigPacket, ClientControlPacket, ClientHandshakePacket, Haptics,
     HeadsetInfoPacket, PeerType, PrivateIdentity, ProtoControlSocket, ServerControlPacket,
@@ -98,6 +98,71 @@ fn set_loading_message(
     Ok(())
 }
 
+fn stream_config(
+    eye_width: u32,
+    eye_height: u32,
+    fps: f32,
+    settings: &Settings,
+) -> crate::StreamConfig {
+    crate::StreamConfig {
+        eyeWidth: eye_width,
+        eyeHeight: eye_height,
+        refreshRate: fps,
+        enableFoveation: matches!(settings.video.foveated_rendering, Switch::Enabled(_)),
+        foveationCenterSizeX: if let Switch::Enabled(foveation_vars) =
+            &settings.video.foveated_rendering
+        {
+            foveation_vars.center_size_x
+        } else {
+            3_f32 / 5_f32
+        },
+        foveationCenterSizeY: if let Switch::Enabled(foveation_vars) =
+            &settings.video.foveated_rendering
+        {
+            foveation_vars.center_size_y
+        } else {
+            2_f32 / 5_f32
+        },
+        foveationCenterShiftX: if let Switch::Enabled(foveation_vars) =
+            &settings.video.foveated_rendering
+        {
+            foveation_vars.center_shift_x
+        } else {
+            2_f32 / 5_f32
+        },
+        foveationCenterShiftY: if let Switch::Enabled(foveation_vars) =
+            &settings.video.foveated_rendering
+        {
+            foveation_vars.center_shift_y
+        } else {
+            1_f32 / 10_f32
+        },
+        foveationEdgeRatioX: if let Switch::Enabled(foveation_vars) =
+            &settings.video.foveated_rendering
+        {
+            foveation_vars.edge_ratio_x
+        } else {
+            2_f32
+        },
+        foveationEdgeRatioY: if let Switch::Enabled(foveation_vars) =
+            &settings.video.foveated_rendering
+        {
+            foveation_vars.edge_ratio_y
+        } else {
+            2_f32
+        },
+        extraLatencyMode: settings.headset.extra_latency_mode,
+    }
+}
+
+// The session sent by the server contains the settings of this client
+fn settings_from_session_json(session_json: &str) -> StrResult<Settings> {
+    let mut session_desc = SessionDesc::default();
+    session_desc.merge_from_json(&trace_err!(json::from_str(session_json))?)?;
+
+    Ok(session_desc.to_settings())
+}
+
 async fn connection_pipeline(
     headset_info: &HeadsetInfoPacket,
     device_name: String,
@@ -117,8 +182,11 @@ async fn connection_pipeline(
         reserved2: "".into(),
     };
 
+    // Settings received during the previous connection, if any
+    let network_config = *NETWORK_CONFIG.lock();
+
     let (mut proto_socket, server_ip) = tokio::select! {
-        res = connection_utils::announce_client_loop(handshake_packet) => {
+        res = connection_utils::announce_client_loop(handshake_packet, network_config) => {
             match res? {
                 ConnectionError::ServerMessage(message) => {
                     info!("Server response: {message:?}");
@@ -155,7 +223,14 @@ async fn connection_pipeline(
         },
         pair = async {
             loop {
-                if let Ok(pair) = ProtoControlSocket::connect_to(PeerType::Server).await {
+                if let Ok(pair) =
+                    ProtoControlSocket::connect_to(
+                        PeerType::Server,
+                        private_identity,
+                        &network_config,
+                    )
+                    .await
+                {
                     break pair;
                 }
 
@@ -164,7 +239,7 @@ async fn connection_pipeline(
         } => pair
     };
 
-    trace_err!(proto_socket.send(&(headset_info, server_ip)).await)?;
+    trace_err!(proto_socket.send(&(headset_info, server_ip.ip)).await)?;
     let config_packet = trace_err!(proto_socket.recv::<ClientConfigPacket>().await)?;
 
     let (control_sender, mut control_receiver) = proto_socket.split();
@@ -197,15 +272,23 @@ async fn connection_pipeline(
         }
     }
 
-    let settings = {
-        let mut session_desc = SessionDesc::default();
-        session_desc.merge_from_json(&trace_err!(json::from_str(&config_packet.session_desc))?)?;
-        session_desc.to_settings()
+    let settings = settings_from_session_json(&config_packet.session_desc)?;
+
+    // The bind address and the multicast interface are local to each host, so the client keeps its
+    // own. Only the ports are taken from the server, for the next connections.
+    let network_config = {
+        let mut network_config = NETWORK_CONFIG.lock();
+        network_config.discovery_port = settings.connection.discovery_port;
+        network_config.control_port = settings.connection.control_port;
+
+        *network_config
     };
 
     let stream_socket_builder = StreamSocketBuilder::listen_for_server(
+        network_config.bind_ip,
         settings.connection.stream_port,
         settings.connection.stream_protocol,
+        private_identity,
     )
     .await?;
 
@@ -256,66 +339,24 @@ async fn connection_pipeline(
     let (battery_sender, mut battery_receiver) = tmpsc::unbounded_channel();
     *BATTERY_SENDER.lock() = Some(battery_sender);
 
-    unsafe {
-        crate::setStreamConfig(crate::StreamConfig {
-            eyeWidth: config_packet.eye_resolution_width,
-            eyeHeight: config_packet.eye_resolution_height,
-            refreshRate: config_packet.fps,
-            enableFoveation: matches!(settings.video.foveated_rendering, Switch::Enabled(_)),
-            foveationCenterSizeX: if let Switch::Enabled(foveation_vars) =
-                &settings.video.foveated_rendering
-            {
-                foveation_vars.center_size_x
-            } else {
-                3_f32 / 5_f32
-            },
-            foveationCenterSizeY: if let Switch::Enabled(foveation_vars) =
-                &settings.video.foveated_rendering
-            {
-                foveation_vars.center_size_y
-            } else {
-                2_f32 / 5_f32
-            },
-            foveationCenterShiftX: if let Switch::Enabled(foveation_vars) =
-                &settings.video.foveated_rendering
-            {
-                foveation_vars.center_shift_x
-            } else {
-                2_f32 / 5_f32
-            },
-            foveationCenterShiftY: if let Switch::Enabled(foveation_vars) =
-                &settings.video.foveated_rendering
-            {
-                foveation_vars.center_shift_y
-            } else {
-                1_f32 / 10_f32
-            },
-            foveationEdgeRatioX: if let Switch::Enabled(foveation_vars) =
-                &settings.video.foveated_rendering
-            {
-                foveation_vars.edge_ratio_x
-            } else {
-                2_f32
-            },
-            foveationEdgeRatioY: if let Switch::Enabled(foveation_vars) =
-                &settings.video.foveated_rendering
-            {
-                foveation_vars.edge_ratio_y
-            } else {
-                2_f32
-            },
-            extraLatencyMode: settings.headset.extra_latency_mode,
-        });
-    }
+    let (eye_width, eye_height, fps) = (
+        config_packet.eye_resolution_width,
+        config_packet.eye_resolution_height,
+        config_packet.fps,
+    );
+    unsafe { crate::setStreamConfig(stream_config(eye_width, eye_height, fps, &settings)) };
 
     trace_err!(trace_err!(java_vm.attach_current_thread())?.call_method(
         &*activity_ref,
         "onServerConnected",
-        "(FIZLjava/lang/String;)V",
+        "(FIZLjava/lang/String;Ljava/lang/String;)V",
         &[
             config_packet.fps.into(),
             (matches!(settings.video.codec, CodecType::HEVC) as i32).into(),
             settings.video.client_request_realtime_decoder.into(),
+            trace_err!(trace_err!(java_vm.attach_current_thread())?
+                .new_string(trace_err!(json::to_string(&settings.video.codec_options))?))?
+            .into(),
             trace_err!(trace_err!(java_vm.attach_current_thread())?
                 .new_string(config_packet.dashboard_url))?
             .into()
@@ -678,6 +719,16 @@ async fn connection_pipeline(
 
                                 legacy_receive_data_sender.lock().await.send(buffer).ok();
                             },
+                            // Only the foveation is applied by the client, the other hot-reloaded
+                            // settings are applied by the server
+                            Ok(ServerControlPacket::SettingsUpdate(session_json)) => {
+                                let settings = settings_from_session_json(&session_json)?;
+                                unsafe {
+                                    crate::updateStreamConfig(stream_config(
+                                        eye_width, eye_height, fps, &settings,
+                                    ))
+                                };
+                            }
                             Ok(_) => (),
                             Err(e) => {
                                 info!("Server disconnected. Cause: {e}");
@@ -695,11 +746,17 @@ async fn connection_pipeline(
         }
     };
 
-    let receive_loop = async move { stream_socket.receive_loop().await };
+    // The stream packets are sent and received in the background, and they stop together
+    let socket_loop = async move {
+        tokio::select! {
+            res = stream_socket.send_loop() => res,
+            res = stream_socket.receive_loop() => res,
+        }
+    };
 
     // Run many tasks concurrently. Threading is managed by the runtime, for best performance.
     tokio::select! {
-        res = spawn_cancelable(receive_loop) => {
+        res = spawn_cancelable(socket_loop) => {
             if let Err(e) = res {
                 info!("Server disconnected. Cause: {e}");
             }
@@ -18,7 +18,7 @@ use alvr_common::{
 use alvr_session::Fov;
 use alvr_sockets::{
     BatteryPacket, HeadsetInfoPacket, Input, LegacyController, LegacyInput, MotionData,
-    PrivateIdentity, TimeSyncPacket, ViewsConfig,
+    NetworkConfig, PrivateIdentity, TimeSyncPacket, ViewsConfig,
 };
 use jni::{
     objects::{JClass, JObject, JString},
@@ -52,6 +52,9 @@ lazy_static! {
         Mutex::new(None);
     static ref IDR_REQUEST_NOTIFIER: Notify = Notify::new();
     static ref ON_PAUSE_NOTIFIER: Notify = Notify::new();
+    // The ports are taken from the settings sent by the server, so that the next connections use
+    // the configured ones. The defaults are used until the first connection.
+    static ref NETWORK_CONFIG: Mutex<NetworkConfig> = Mutex::new(NetworkConfig::default());
 }
 
 #[no_mangle]
@@ -298,6 +301,10 @@ pub unsafe extern "system" fn Java_com_polygraphene_alvr_OvrActivity_onCreateNat
                 fps: data.fps,
                 server_total_latency: data.serverTotalLatency,
                 tracking_recv_frame_index: data.trackingRecvFrameIndex,
+                audio_buffer_overflows: alvr_audio::BUFFER_OVERFLOW_COUNT.load(Ordering::Relaxed)
+                    as u64,
+                audio_buffer_underflows: alvr_audio::BUFFER_UNDERFLOW_COUNT.load(Ordering::Relaxed)
+                    as u64,
             };
 
             sender.send(time_sync).ok();
@@ -30,4 +30,65 @@ requirejs.config({
         },
     },
 });
-requirejs(["app/main"]);
+
+// Remote dashboards receive the token in the URL fragment or ask the user for it. The local one can
+// request it from the server
+function promptAccessToken() {
+    const token = window.prompt(
+        "Enter the access token, saved in web_server_token.txt in the ALVR config folder",
+    );
+    if (token && token.trim()) {
+        sessionStorage.setItem("accessToken", token.trim());
+        return token.trim();
+    }
+
+    return null;
+}
+
+function getAccessToken(callback) {
+    const match = window.location.hash.match(/token=([0-9a-f]+)/);
+    if (match) {
+        sessionStorage.setItem("accessToken", match[1]);
+        history.replaceState(null, "", window.location.pathname);
+    }
+
+    const storedToken = sessionStorage.getItem("accessToken");
+    if (storedToken) {
+        callback(storedToken);
+        return;
+    }
+
+    const request = new XMLHttpRequest();
+    request.open("GET", "api/auth/token");
+    request.onload = () => {
+        if (request.status === 200) {
+            sessionStorage.setItem("accessToken", request.responseText);
+            callback(request.responseText);
+        } else {
+            callback(promptAccessToken());
+        }
+    };
+    request.onerror = () => callback(null);
+    request.send();
+}
+
+// Websockets cannot send headers either
+function tokenQuery() {
+    const token = sessionStorage.getItem("accessToken");
+    return token ? "?token=" + token : "";
+}
+
+getAccessToken((token) => {
+    if (!token) {
+        requirejs(["app/main"]);
+        return;
+    }
+
+    // Modules loaded with the json and text plugins cannot send headers, so the token is also
+    // passed as query parameter
+    requirejs.config({ urlArgs: "token=" + token });
+    requirejs(["jquery"], ($) => {
+        $.ajaxSetup({ headers: { Authorization: "Bearer " + token } });
+        requirejs(["app/main"]);
+    });
+});
@@ -146,8 +146,76 @@ define([
 
             const elem = document.getElementById("progressBar");
 
-            // Create WebSocket connection.
-            const webSocket = new WebSocket("ws://" + window.location.host + "/api/events");
+            // The last received event. If the connection drops during the download, the stream is
+            // resumed after it
+            let lastEvent = null;
+            let downloading = true;
+            let webSocket = null;
+
+            function eventsQuery() {
+                const query = tokenQuery();
+                if (lastEvent === null) {
+                    return query;
+                }
+
+                return (
+                    query +
+                    (query ? "&" : "?") +
+                    "after=" +
+                    lastEvent.sequence +
+                    "&epoch=" +
+                    lastEvent.epoch
+                );
+            }
+
+            function onEvent(event) {
+                try {
+                    const dataJSON = JSON.parse(event.data);
+                    lastEvent = dataJSON;
+                    if (dataJSON.id === "UpdateDownloadedBytesCount") {
+                        const BtoMB = 1.0 / (1024 * 1024);
+                        const sizeMb = size * BtoMB;
+                        const downloadProgress = (dataJSON.data * BtoMB).toFixed(2);
+                        document.getElementById("downloadProgress").innerText =
+                            downloadProgress + "MB" + " / " + sizeMb.toFixed(2) + "MB";
+                        const progress = ((100.0 * dataJSON.data) / size).toFixed(2);
+                        elem.style.width = progress + "%";
+                        elem.innerText = progress + "%";
+                    }
+                } catch (error) {
+                    console.log("Error with message: ", event);
+                    Lobibox.notify("error", {
+                        rounded: true,
+                        delay: -1,
+                        delayIndicator: false,
+                        sound: false,
+                        position: "bottom left",
+                        iconSource: "fontAwesome",
+                        msg: error.stack,
+                        closable: true,
+                        messageHeight: 250,
+                    });
+                }
+            }
+
+            function openEvents() {
+                webSocket = new WebSocket(
+                    "ws://" + window.location.host + "/api/events" + eventsQuery(),
+                );
+                webSocket.onmessage = onEvent;
+                webSocket.onclose = function () {
+                    if (downloading) {
+                        setTimeout(openEvents, 1000);
+                    }
+                };
+            }
+
+            function stopEvents() {
+                downloading = false;
+                webSocket.close();
+            }
+
+            openEvents();
 
             $.ajax({
                 type: "POST",
@@ -159,49 +227,18 @@ define([
                         console.log("Success");
                     } else {
                         console.log("Info: ", res);
-                        webSocket.close();
+                        stopEvents();
                         $("#bodyContent").show();
                         $("#updating").hide();
                     }
                 },
                 error: function (res) {
                     console.log("Error: ", res);
-                    webSocket.close();
+                    stopEvents();
                     $("#bodyContent").show();
                     $("#updating").hide();
                 },
             });
-
-            if (webSocket !== null && typeof webSocket !== undefined) {
-                webSocket.onmessage = function (event) {
-                    try {
-                        const dataJSON = JSON.parse(event.data);
-                        if (dataJSON.id === "UpdateDownloadedBytesCount") {
-                            const BtoMB = 1.0 / (1024 * 1024);
-                            const sizeMb = size * BtoMB;
-                            const downloadProgress = (dataJSON.data * BtoMB).toFixed(2);
-                            document.getElementById("downloadProgress").innerText =
-                                downloadProgress + "MB" + " / " + sizeMb.toFixed(2) + "MB";
-                            const progress = ((100.0 * dataJSON.data) / size).toFixed(2);
-                            elem.style.width = progress + "%";
-                            elem.innerText = progress + "%";
-                        }
-                    } catch (error) {
-                        console.log("Error with message: ", event);
-                        Lobibox.notify("error", {
-                            rounded: true,
-                            delay: -1,
-                            delayIndicator: false,
-                            sound: false,
-                            position: "bottom left",
-                            iconSource: "fontAwesome",
-                            msg: error.stack,
-                            closable: true,
-                            messageHeight: 250,
-                        });
-                    }
-                };
-            }
         }
 
         $("#bodyContent").append(template);
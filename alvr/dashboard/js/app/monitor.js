@@ -31,7 +31,9 @@ define([
             const url = window.location.href;
             const arr = url.split("/");
 
-            const log_listener = new WebSocket("ws://" + arr[2] + "/api/log");
+            const log_listener = new WebSocket(
+                "ws://" + arr[2] + "/api/log" + tokenQuery(),
+            );
 
             log_listener.onopen = (ev) => {
                 console.log("Log listener started");
@@ -174,6 +176,7 @@ define([
                 templateConfigureClient = compiledTemplate({
                     i18n: i18n,
                     knownIps: session.clientConnections[hostname].manualIps,
+                    certificatePinned: !!session.clientConnections[hostname].certificatePem,
                 });
 
                 $("#configureClientModal").remove();
@@ -230,6 +233,17 @@ define([
                     });
 
                     configureClientModal_BindRemoveIpButtons(_hostmane);
+
+                    $("#forgetCertificateButton").click((evt) => {
+                        $.ajax({
+                            type: "POST",
+                            url: "api/client/forget-certificate",
+                            contentType: "application/json;charset=UTF-8",
+                            data: JSON.stringify(_hostmane),
+                        });
+
+                        $(evt.target).parent().parent().remove();
+                    });
                 });
             });
         }
@@ -956,10 +970,10 @@ define([
                 return;
             }
             isUpdating = true;
-            $.getJSON("api/session/load", function (newSession) {
+            $.getJSON("api/session/load", function (newSession, status, xhr) {
                 session = newSession;
                 updateClients();
-                alvrSettings.updateSession(session);
+                alvrSettings.updateSession(session, xhr.getResponseHeader("ETag"));
                 isUpdating = false;
             });
         }
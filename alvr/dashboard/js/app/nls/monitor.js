@@ -25,6 +25,9 @@ define({
         configureClientButton: "Configure",
         configureClientAddIp: "Add new IP",
         configureClientRemoveIp: "Remove",
+        configureClientCertificatePinned:
+            "The certificate of this client is pinned. Forget it after reinstalling or resetting the client",
+        configureClientForgetCertificate: "Forget certificate",
         // Statistics container
         statistics: "Statistics",
         streamingStatistics: "Streaming Statistics",
@@ -298,10 +298,11 @@ define({
         "_root_connection_webServerPort.name": "Web server port",
         "_root_connection_streamProtocol-choice-.name": "Streaming protocol",
         "_root_connection_streamProtocol-choice-.description":
-            "Network protocol used to stream data between client and server. UDP works best at low bitrates (<30), Throttled UDP works best at medium bitrates (~100), TCP works at any bitrate.",
+            "Network protocol used to stream data between client and server. UDP works best at low bitrates (<30), Throttled UDP works best at medium bitrates (~100), TCP works at any bitrate. QUIC is encrypted and has congestion control.",
         "_root_connection_streamProtocol_udp-choice-.name": "UDP",
         "_root_connection_streamProtocol_throttledUdp-choice-.name": "Throttled UDP",
         "_root_connection_streamProtocol_tcp-choice-.name": "TCP",
+        "_root_connection_streamProtocol_quic-choice-.name": "QUIC", // adv
         "_root_connection_streamPort.name": "Server streaming port", // adv
         "_root_connection_streamPort.description": "Port used by the server to receive packets.", // adv
         "_root_connection_aggressiveKeyframeResend.name": "Aggressive keyframe resend",
@@ -332,6 +333,11 @@ define({
         "_root_extra_updateChannel_stable-choice-.name": "Stable",
         "_root_extra_updateChannel_nightly-choice-.name": "Nightly",
         "_root_extra_logToDisk.name": "Log to disk (session_log.txt)",
+        "_root_extra_sessionRecording.name": "Record streaming sessions", // adv
+        "_root_extra_sessionRecording_enabled.description":
+            "Write statistics, bitrate decisions and connection events of each client connection to a file in the recordings folder, to be attached to bug reports. Long connections are split in files of at most the maximum size. Summarize a recording with \"cargo xtask summarize-recording --recording <file>\"", // adv
+        "_root_extra_sessionRecording_content_maxRecordings.name": "Recordings to keep", // adv
+        "_root_extra_sessionRecording_content_maxRecordingSizeMb.name": "Maximum recording size (MB)", // adv
         "_root_extra_notificationLevel-choice-.name": "Notification level", // adv
         "_root_extra_notificationLevel-choice-.description":
             "At which level notification will be generated. From less details to all details: \n- Error \n- Warning \n- Informations \n- Debug", // adv
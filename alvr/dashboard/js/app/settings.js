@@ -1,6 +1,7 @@
 define([
     "json!../../api/settings-schema",
     "json!../../api/session/load",
+    "text!../../api/server-os",
     "app/customSettings",
     "lib/lodash",
     "i18n!app/nls/settings",
@@ -10,6 +11,7 @@ define([
 ], function (
     schema,
     session,
+    serverOs,
     CustomSettings,
     _,
     i18n,
@@ -24,6 +26,9 @@ define([
         const customSettings = new CustomSettings(self);
         let index = 0;
         const usedi18n = {};
+        // ETag of the loaded session. It is sent back with If-Match, so that the changes made in the
+        // meantime by others are not overwritten.
+        let revision = null;
 
         function randomAlphanumericID() {
             const len = 10;
@@ -56,14 +61,21 @@ define([
 
             customSettings.setCustomSettings();
 
+            // The session loaded with the page has no ETag
+            loadSession();
+
             addListeners();
             addHelpTooltips();
             printUnusedi18n();
         }
 
-        self.updateSession = function (newSession) {
+        self.updateSession = function (newSession, newRevision) {
             updating = true;
             session = newSession;
+            // Local changes keep the revision they are based on
+            if (newRevision !== undefined) {
+                revision = newRevision;
+            }
             setProperties(newSession.sessionSettings, "_root");
             updating = false;
         };
@@ -166,6 +178,12 @@ define([
             }
         };
 
+        function loadSession() {
+            $.getJSON("api/session/load", function (storedSession, status, xhr) {
+                self.updateSession(storedSession, xhr.getResponseHeader("ETag"));
+            });
+        }
+
         self.storeSession = function (updateType) {
             if (updating) {
                 return;
@@ -175,14 +193,16 @@ define([
                 type: "POST",
                 url: "/api/session/store",
                 contentType: "application/json;charset=UTF-8",
+                headers: revision ? { "If-Match": revision } : {},
                 data: JSON.stringify({
                     updateType: updateType,
                     webClientId: webClientId,
                     session: session,
                 }),
                 processData: false,
-                success: function (res) {
+                success: function (res, status, xhr) {
                     if (res === "") {
+                        revision = xhr.getResponseHeader("ETag");
                         console.log("SUCCESS");
                     } else {
                         Lobibox.notify("error", {
@@ -195,22 +215,63 @@ define([
                         });
 
                         console.log("FAILED");
-                        updating = true;
-                        session = res;
-                        setProperties(res.sessionSettings, "_root");
-                        updating = false;
+                        loadSession();
                     }
                 },
                 error: function (res) {
                     console.log("FAILED");
-                    updating = true;
-                    session = res;
-                    setProperties(res.sessionSettings, "_root");
-                    updating = false;
+                    // Invalid settings are listed by path
+                    if (res.status === 400 && Array.isArray(res.responseJSON)) {
+                        Lobibox.notify("error", {
+                            size: "mini",
+                            rounded: true,
+                            delayIndicator: false,
+                            sound: false,
+                            title: getI18n("settingsStoreError").name,
+                            msg: res.responseJSON
+                                .map((error) => error.path + ": " + error.message)
+                                .join("<br>"),
+                        });
+                    } else if (res.status === 412) {
+                        // The session was changed by others, the stored one is loaded
+                        Lobibox.notify("error", {
+                            size: "mini",
+                            rounded: true,
+                            delayIndicator: false,
+                            sound: false,
+                            title: getI18n("settingsStoreError").name,
+                            msg: getI18n("settingsStoreError").description,
+                        });
+                    }
+
+                    loadSession();
                 },
             });
         };
 
+        // Evaluates a schema condition (visible_if, requires) on the loaded session, see the Rust
+        // Condition type for the syntax
+        function conditionHolds(condition) {
+            return condition.split("||").some((conjunction) =>
+                conjunction.split("&&").every((comparison) => {
+                    const equal = comparison.includes("==");
+                    const [path, value] = comparison.split(equal ? "==" : "!=");
+                    const currentValue =
+                        path.trim() == "os"
+                            ? serverOs
+                            : getProperties(session.sessionSettings, path.trim(), ".");
+
+                    // Missing values are null, as in Rust
+                    return (
+                        _.isEqual(
+                            currentValue === undefined ? null : currentValue,
+                            JSON.parse(value.trim())
+                        ) == equal
+                    );
+                })
+            );
+        }
+
         function setProperties(object, path) {
             for (const item in object) {
                 if (Array.isArray(object[item])) {
@@ -443,6 +504,12 @@ define([
                     }
 
                     node.content.entries.forEach((el) => {
+                        if (el[1] != null && el[1].visibleIf != null) {
+                            if (!conditionHolds(el[1].visibleIf)) {
+                                return;
+                            }
+                        }
+
                         if (el[1] != null) {
                             fillNode(
                                 el[1].content,
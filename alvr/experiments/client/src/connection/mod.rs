@@ -459,11 +459,17 @@ async fn connection_pipeline(
         }
     };
 
-    let receive_loop = async move { stream_socket.receive_loop().await };
+    // The stream packets are sent and received in the background, and they stop together
+    let socket_loop = async move {
+        tokio::select! {
+            res = stream_socket.send_loop() => res,
+            res = stream_socket.receive_loop() => res,
+        }
+    };
 
     // Run many tasks concurrently. Threading is managed by the runtime, for best performance.
     tokio::select! {
-        res = spawn_cancelable(receive_loop) => {
+        res = spawn_cancelable(socket_loop) => {
             if let Err(e) = res {
                 error!("Server disconnected. Cause: {e}");
             }
@@ -117,7 +117,7 @@ impl Dashboard {
                         .update(SettingsEvent::SessionUpdated(session), request_handler);
                 }
                 ServerEvent::SessionUpdated => (), // deprecated
-                ServerEvent::SessionSettingsExtrapolationFailed => todo!(),
+                ServerEvent::SessionSettingsReset(_) => todo!(),
                 ServerEvent::ClientFoundOk => todo!(),
                 ServerEvent::ClientFoundInvalid => todo!(),
                 ServerEvent::ClientFoundWrongVersion(_) => todo!(),
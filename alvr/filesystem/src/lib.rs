@@ -225,6 +225,14 @@ impl Layout {
         self.config_dir.join("session.json")
     }
 
+    pub fn web_server_token(&self) -> PathBuf {
+        self.config_dir.join("web_server_token.txt")
+    }
+
+    pub fn setting_overrides(&self) -> PathBuf {
+        self.config_dir.join("setting_overrides.json")
+    }
+
     pub fn session_log(&self) -> PathBuf {
         if cfg!(windows) {
             self.log_dir.join("session_log.txt")
@@ -237,6 +245,14 @@ impl Layout {
         self.log_dir.join("crash_log.txt")
     }
 
+    pub fn recordings_dir(&self) -> PathBuf {
+        if cfg!(windows) {
+            self.log_dir.join("recordings")
+        } else {
+            self.log_dir.join("alvr_recordings")
+        }
+    }
+
     pub fn openvr_driver_lib_dir(&self) -> PathBuf {
         let platform = if cfg!(windows) {
             "win64"
@@ -25,6 +25,36 @@ fn spawn_no_window(command: &mut Command) {
     command.spawn().ok();
 }
 
+// Overrides are checked against the stored session here, to report errors before launching SteamVR.
+// The server reads the file every time SteamVR loads the driver, so the overrides last for the
+// launcher session, including the SteamVR restarts triggered by the server. The session ends when
+// the launcher is started again, which replaces or deletes the file.
+pub fn store_setting_overrides(args: &[String]) -> StrResult {
+    let layout = afs::filesystem_layout_from_launcher_exe(&env::current_exe().unwrap());
+
+    let overrides = alvr_session::setting_overrides_from_args(args)?;
+    if overrides.is_empty() {
+        // Left by the previous launcher session
+        fs::remove_file(layout.setting_overrides()).ok();
+
+        return Ok(());
+    }
+
+    let session_settings = alvr_session::load_session(&layout.session())
+        .map(|session_desc| session_desc.session_settings)
+        .unwrap_or_else(|_| alvr_session::session_settings_default());
+    alvr_session::apply_setting_overrides(&session_settings, &overrides)?;
+
+    let overrides_path = layout.setting_overrides();
+    if let Some(config_dir) = overrides_path.parent() {
+        fs::create_dir_all(config_dir).ok();
+    }
+    trace_err!(fs::write(
+        overrides_path,
+        trace_err!(json::to_string_pretty(&overrides))?
+    ))
+}
+
 pub fn is_steamvr_running() -> bool {
     let mut system = System::new_with_specifics(
         RefreshKind::new().with_processes(ProcessRefreshKind::everything()),
@@ -250,7 +250,10 @@ fn main() {
         Some(flag) if flag == "--restart-steamvr" => commands::restart_steamvr(),
         Some(flag) if flag == "--update" => commands::invoke_installer(),
         Some(_) | None => {
-            alvr_common::show_err_blocking(make_window());
+            alvr_common::show_err_blocking(
+                commands::store_setting_overrides(args.get(1..).unwrap_or_default())
+                    .and_then(|_| make_window()),
+            );
         }
     }
 }
@@ -146,7 +146,10 @@ fn run(app_data: &mut AppData) -> Result<(), Box<dyn std::error::Error>> {
         if !alxr_init(&ctx, &mut sys_properties) {
             return Ok(());
         }
-        init_connections(&sys_properties);
+        init_connections(
+            &sys_properties,
+            Some(ndk_glue::native_activity().internal_data_path().to_owned()),
+        );
 
         while !app_data.destroy_requested {
             // Main game loop
@@ -65,7 +65,7 @@ fn main() {
                 break;
             }
             if !APP_CONFIG.no_alvr_server {
-                init_connections(&sys_properties);
+                init_connections(&sys_properties, None);
             }
 
             let mut request_restart = false;
@@ -115,8 +115,10 @@ async fn connection_pipeline(
 
     println!("host_name: {0}", handshake_packet.version);
 
+    let network_config = APP_CONFIG.network_config();
+
     let (mut proto_socket, server_ip) = tokio::select! {
-        res = connection_utils::announce_client_loop(handshake_packet) => {
+        res = connection_utils::announce_client_loop(handshake_packet, network_config) => {
             match res? {
                 ConnectionError::ServerMessage(message) => {
                     info!("Server response: {:?}", message);
@@ -156,7 +158,14 @@ async fn connection_pipeline(
         },
         pair = async {
             loop {
-                if let Ok(pair) = ProtoControlSocket::connect_to(PeerType::Server).await {
+                if let Ok(pair) =
+                    ProtoControlSocket::connect_to(
+                        PeerType::Server,
+                        private_identity,
+                        &network_config,
+                    )
+                    .await
+                {
                     break pair;
                 }
 
@@ -165,7 +174,7 @@ async fn connection_pipeline(
         } => pair
     };
 
-    trace_err!(proto_socket.send(&(headset_info, server_ip)).await)?;
+    trace_err!(proto_socket.send(&(headset_info, server_ip.ip)).await)?;
     let config_packet = trace_err!(proto_socket.recv::<ClientConfigPacket>().await)?;
 
     let (control_sender, mut control_receiver) = proto_socket.split();
@@ -216,8 +225,10 @@ async fn connection_pipeline(
     };
 
     let stream_socket_builder = StreamSocketBuilder::listen_for_server(
+        network_config.bind_ip,
         settings.connection.stream_port,
         settings.connection.stream_protocol,
+        private_identity,
     )
     .await?;
 
@@ -279,6 +290,10 @@ async fn connection_pipeline(
         config_packet.eye_resolution_width, config_packet.eye_resolution_height
     );
     //println!("setting display refresh to {0}Hz", config_packet.fps);
+    // todo: ALXRDecoderConfig (in the engine submodule) has no field for the options yet
+    if !settings.video.codec_options.is_empty() {
+        warn!("Codec options are not supported by this client and are ignored");
+    }
     unsafe {
         crate::alxr_set_stream_config(crate::ALXRStreamConfig {
             trackingSpaceType: ALXRTrackingSpace_StageRefSpace,
@@ -670,6 +685,8 @@ async fn connection_pipeline(
     let control_loop = {
         // let java_vm = Arc::clone(&java_vm);
         // let activity_ref = Arc::clone(&activity_ref);
+        let stream_foveated_rendering =
+            trace_err!(json::to_value(&settings.video.foveated_rendering))?;
         async move {
             loop {
                 tokio::select! {
@@ -719,6 +736,20 @@ async fn connection_pipeline(
 
                                 legacy_receive_data_sender.lock().await.send(buffer).ok();
                             },
+                            // The stream config is applied only when connecting
+                            Ok(ServerControlPacket::SettingsUpdate(session_json)) => {
+                                let mut session_desc = SessionDesc::default();
+                                session_desc.merge_from_json(&trace_err!(json::from_str(&session_json))?)?;
+                                let foveated_rendering = trace_err!(json::to_value(
+                                    &session_desc.to_settings().video.foveated_rendering
+                                ))?;
+
+                                if foveated_rendering != stream_foveated_rendering {
+                                    info!("Reconnecting to apply the foveation settings");
+                                    println!("Reconnecting to apply the foveation settings");
+                                    break Ok(());
+                                }
+                            }
                             Ok(_) => (),
                             Err(e) => {
                                 info!("Server disconnected. Cause: {}", e);
@@ -738,11 +769,17 @@ async fn connection_pipeline(
         }
     };
 
-    let receive_loop = async move { stream_socket.receive_loop().await };
+    // The stream packets are sent and received in the background, and they stop together
+    let socket_loop = async move {
+        tokio::select! {
+            res = stream_socket.send_loop() => res,
+            res = stream_socket.receive_loop() => res,
+        }
+    };
 
     // Run many tasks concurrently. Threading is managed by the runtime, for best performance.
     tokio::select! {
-        res = spawn_cancelable(receive_loop) => {
+        res = spawn_cancelable(socket_loop) => {
             if let Err(e) = res {
                 info!("Server disconnected. Cause: {}", e);
                 println!("Server disconnected. Cause: {}", e);
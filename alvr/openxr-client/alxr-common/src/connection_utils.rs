@@ -1,11 +1,10 @@
-use crate::APP_CONFIG;
 use alvr_common::prelude::*;
 use alvr_sockets::{
-    ClientHandshakePacket, HandshakePacket, ServerHandshakePacket, CONTROL_PORT, LOCAL_IP,
+    ClientHandshakePacket, HandshakePacket, NetworkConfig, ServerHandshakePacket,
     MAX_HANDSHAKE_PACKET_SIZE_BYTES,
 };
-use std::{net::Ipv4Addr, time::Duration};
-use tokio::{net::UdpSocket, time};
+use std::time::Duration;
+use tokio::time;
 
 const CLIENT_HANDSHAKE_RESEND_INTERVAL: Duration = Duration::from_secs(1);
 
@@ -16,30 +15,42 @@ pub enum ConnectionError {
 
 pub async fn announce_client_loop(
     handshake_packet: ClientHandshakePacket,
+    network_config: NetworkConfig,
 ) -> StrResult<ConnectionError> {
     println!("announce_client_loop");
-    println!("is localhost? {0}", APP_CONFIG.localhost);
 
-    let control_port = if APP_CONFIG.localhost {
-        CONTROL_PORT + 1
-    } else {
-        CONTROL_PORT
-    };
-    let mut handshake_socket = trace_err!(UdpSocket::bind((LOCAL_IP, control_port)).await)?;
-    trace_err!(handshake_socket.set_broadcast(true))?;
+    // The ephemeral port does not conflict with the server when running on the same host
+    let mut handshake_socket = alvr_sockets::bind_discovery_sender(&network_config)?;
+
+    // mDNS is optional, the broadcast discovery keeps working without it
+    let mut mdns_browser = alvr_sockets::MdnsBrowser::new(&network_config)
+        .map_err(|e| warn!("mDNS discovery unavailable: {e}"))
+        .ok();
 
     let client_handshake_packet = trace_err!(bincode::serialize(&HandshakePacket::Client(
         handshake_packet
     )))?;
 
     loop {
-        let broadcast_result = handshake_socket
-            .send_to(
-                &client_handshake_packet,
-                (Ipv4Addr::BROADCAST, CONTROL_PORT),
-            )
-            .await;
-        if broadcast_result.is_err() {
+        // The network is unreachable only if no discovery address can be reached
+        let mut sent = false;
+        let mdns_addresses = mdns_browser
+            .as_mut()
+            .map(|browser| browser.servers())
+            .unwrap_or_default()
+            .into_iter()
+            .flat_map(|server| server.addresses);
+        for address in network_config
+            .discovery_addresses()
+            .into_iter()
+            .chain(mdns_addresses)
+        {
+            sent |= handshake_socket
+                .send_to(&client_handshake_packet, address)
+                .await
+                .is_ok();
+        }
+        if !sent {
             break Ok(ConnectionError::NetworkUnreachable);
         }
 
@@ -48,7 +59,6 @@ pub async fn announce_client_loop(
             async move {
                 let mut server_response_buffer = [0; MAX_HANDSHAKE_PACKET_SIZE_BYTES];
                 loop {
-                    // this call will receive also the broadcasted client packet that must be ignored
                     let (packet_size, _) = trace_err!(
                         handshake_socket
                             .recv_from(&mut server_response_buffer)
@@ -8,7 +8,7 @@ use alvr_common::{prelude::*, ALVR_VERSION, HEAD_ID, LEFT_HAND_ID, RIGHT_HAND_ID
 use alvr_session::Fov;
 use alvr_sockets::{
     BatteryPacket, HeadsetInfoPacket, Input, LegacyController, LegacyInput, MotionData,
-    TimeSyncPacket, ViewsConfig,
+    NetworkConfig, PrivateIdentity, TimeSyncPacket, ViewsConfig,
 };
 pub use alxr_engine_sys::*;
 use lazy_static::lazy_static;
@@ -16,6 +16,9 @@ use local_ipaddress;
 use parking_lot::Mutex;
 use std::ffi::CStr;
 use std::{
+    fs,
+    net::IpAddr,
+    path::PathBuf,
     slice,
     sync::atomic::{AtomicBool, Ordering},
 };
@@ -75,6 +78,18 @@ pub struct Options {
 
     #[structopt(/*short,*/ long)]
     pub disable_localdimming: bool,
+
+    /// Address of the network interface used to connect to the server. Use "::" for IPv4 and IPv6.
+    #[structopt(long)]
+    pub bind_address: Option<IpAddr>,
+
+    /// Must match the discovery port set on the server.
+    #[structopt(long, default_value = "9943")]
+    pub discovery_port: u16,
+
+    /// Must match the control port set on the server.
+    #[structopt(long, default_value = "9943")]
+    pub control_port: u16,
     // /// Set speed
     // // we don't want to name it "speed", need to look smart
     // #[structopt(short = "v", long = "velocity", default_value = "42")]
@@ -113,6 +128,9 @@ impl Options {
             no_server_framerate_lock: false,
             no_frameskip: false,
             disable_localdimming: false,
+            bind_address: None,
+            discovery_port: alvr_sockets::DISCOVERY_PORT,
+            control_port: alvr_sockets::CONTROL_PORT,
         };
 
         let sys_properties = AndroidSystemProperties::new();
@@ -205,11 +223,25 @@ impl Options {
             no_server_framerate_lock: false,
             no_frameskip: false,
             disable_localdimming: false,
+            bind_address: None,
+            discovery_port: alvr_sockets::DISCOVERY_PORT,
+            control_port: alvr_sockets::CONTROL_PORT,
         };
         new_options
     }
 }
 
+impl Options {
+    pub fn network_config(&self) -> NetworkConfig {
+        NetworkConfig {
+            bind_ip: self.bind_address.unwrap_or(alvr_sockets::LOCAL_IP),
+            discovery_port: self.discovery_port,
+            control_port: self.control_port,
+            ..Default::default()
+        }
+    }
+}
+
 lazy_static! {
     pub static ref RUNTIME: Mutex<Option<Runtime>> = Mutex::new(None);
     static ref IDR_REQUEST_NOTIFIER: Notify = Notify::new();
@@ -236,7 +268,9 @@ lazy_static! {
     pub static ref APP_CONFIG: Options = Options::from_system_properties();
 }
 
-pub fn init_connections(sys_properties: &ALXRSystemProperties) {
+// The identity of the client is saved in `maybe_data_dir`, or in the config directory of the user
+// if None. Android apps pass their internal storage path.
+pub fn init_connections(sys_properties: &ALXRSystemProperties, maybe_data_dir: Option<PathBuf>) {
     alvr_common::show_err(|| -> StrResult {
         println!("Init-connections started.");
 
@@ -269,7 +303,7 @@ pub fn init_connections(sys_properties: &ALXRSystemProperties) {
         } else {
             local_ipaddress::get().unwrap_or(alvr_sockets::LOCAL_IP.to_string())
         };
-        let private_identity = alvr_sockets::create_identity(Some(ip_addr)).unwrap();
+        let private_identity = load_or_create_identity(ip_addr, maybe_data_dir)?;
 
         let runtime = trace_err!(Runtime::new())?;
 
@@ -290,6 +324,41 @@ pub fn init_connections(sys_properties: &ALXRSystemProperties) {
     }());
 }
 
+// The identity is saved so that the server recognizes the certificate of this client across
+// restarts. Without a directory to save it in, the server rejects the client after a restart until
+// its certificate is forgotten from the dashboard.
+fn load_or_create_identity(
+    hostname: String,
+    maybe_data_dir: Option<PathBuf>,
+) -> StrResult<PrivateIdentity> {
+    let identity_path =
+        match maybe_data_dir.or_else(|| dirs::config_dir().map(|dir| dir.join("alxr"))) {
+            Some(dir) => dir.join("identity.json"),
+            None => {
+                warn!("No directory to save the identity of this client");
+                return alvr_sockets::create_identity(Some(hostname));
+            }
+        };
+
+    if let Some(identity) = fs::read_to_string(&identity_path)
+        .ok()
+        .and_then(|text| serde_json::from_str::<PrivateIdentity>(&text).ok())
+    {
+        if identity.hostname == hostname {
+            return Ok(identity);
+        }
+    }
+
+    let identity = alvr_sockets::create_identity(Some(hostname))?;
+    trace_err!(fs::create_dir_all(trace_none!(identity_path.parent())?))?;
+    trace_err!(fs::write(
+        &identity_path,
+        trace_err!(serde_json::to_string(&identity))?
+    ))?;
+
+    Ok(identity)
+}
+
 pub fn shutdown() {
     ON_PAUSE_NOTIFIER.notify_waiters();
     drop(RUNTIME.lock().take());
@@ -499,6 +568,15 @@ pub extern "C" fn battery_send(device_id: u64, gauge_value: f32, is_plugged: boo
 pub extern "C" fn time_sync_send(data_ptr: *const TimeSync) {
     let data: &TimeSync = unsafe { &*data_ptr };
     if let Some(sender) = &*TIME_SYNC_SENDER.lock() {
+        // Game audio is played only on Android
+        #[cfg(target_os = "android")]
+        let (audio_buffer_overflows, audio_buffer_underflows) = (
+            alvr_audio::BUFFER_OVERFLOW_COUNT.load(Ordering::Relaxed) as u64,
+            alvr_audio::BUFFER_UNDERFLOW_COUNT.load(Ordering::Relaxed) as u64,
+        );
+        #[cfg(not(target_os = "android"))]
+        let (audio_buffer_overflows, audio_buffer_underflows) = (0, 0);
+
         let time_sync = TimeSyncPacket {
             mode: data.mode,
             server_time: data.serverTime,
@@ -515,6 +593,8 @@ pub extern "C" fn time_sync_send(data_ptr: *const TimeSync) {
             fps: data.fps,
             server_total_latency: data.serverTotalLatency,
             tracking_recv_frame_index: data.trackingRecvFrameIndex,
+            audio_buffer_overflows,
+            audio_buffer_underflows,
         };
         sender.send(time_sync).ok();
     }
@@ -172,7 +172,7 @@ void ClientConnection::ProcessTimeSync(TimeSync data) {
 		if (now - m_LastStatisticsUpdate > STATISTICS_TIMEOUT_US)
 		{
 			// Text statistics only, some values averaged
-			Info("#{ \"id\": \"Statistics\", \"data\": {"
+			SendEvent("{ \"id\": \"Statistics\", \"data\": {"
 				"\"totalPackets\": %llu, "
 				"\"packetRate\": %llu, "
 				"\"packetsLostTotal\": %llu, "
@@ -193,7 +193,7 @@ void ClientConnection::ProcessTimeSync(TimeSync data) {
 				"\"batteryHMD\": %d, "
 				"\"batteryLeft\": %d, "
 				"\"batteryRight\": %d"
-				"} }#\n",
+				"} }\n",
 				m_Statistics->GetPacketsSentTotal(),
 				m_Statistics->GetPacketsSentInSecond(),
 				m_reportedStatistics.packetsLostTotal,
@@ -220,7 +220,7 @@ void ClientConnection::ProcessTimeSync(TimeSync data) {
 		};
 
 		// Continously send statistics info for updating graphs
-		Info("#{ \"id\": \"GraphStatistics\", \"data\": [%llu,%.3f,%.3f,%.3f,%.3f,%.3f,%.3f,%.3f,%.3f,%.3f,%.3f,%.3f] }#\n",
+		SendEvent("{ \"id\": \"GraphStatistics\", \"data\": [%llu,%.3f,%.3f,%.3f,%.3f,%.3f,%.3f,%.3f,%.3f,%.3f,%.3f,%.3f] }\n",
 			Current / 1000,                                                //time
 			sendBuf.serverTotalLatency / 1000.0,                           //totalLatency
 			m_reportedStatistics.averageSendLatency / 1000.0,              //receiveLatency
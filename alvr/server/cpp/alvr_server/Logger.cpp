@@ -58,6 +58,14 @@ void Info(const char *format, ...)
 	va_end(args);
 }
 
+void SendEvent(const char *format, ...)
+{
+	va_list args;
+	va_start(args, format);
+	_log(format, args, ReportEvent);
+	va_end(args);
+}
+
 void Debug(const char *format, ...)
 {
 // Use our define instead of _DEBUG - see build.rs for details.
@@ -8,3 +8,6 @@ void Error(const char *format, ...);
 void Warn(const char *format, ...);
 void Info(const char *format, ...);
 void Debug(const char *format, ...);
+
+// Publishes a JSON formatted ServerEvent. It is not logged
+void SendEvent(const char *format, ...);
@@ -1,6 +1,7 @@
 #pragma once
 
 #include <algorithm>
+#include <atomic>
 #include <stdint.h>
 #include <time.h>
 
@@ -106,26 +107,16 @@ public:
 		return m_sendLatency;
 	}
 
+	// Called by the adaptive bitrate controller, implemented in Rust
+	void SetBitrate(uint64_t bitrateMbs) {
+		m_bitrate = bitrateMbs;
+	}
+
 	bool CheckBitrateUpdated() {
-		if (m_enableAdaptiveBitrate) {
-			uint64_t latencyUs = m_sendLatency;
-			if (latencyUs != 0) {
-				if (latencyUs > m_adaptiveBitrateTarget + m_adaptiveBitrateThreshold) {
-					if (m_bitrate < 5 + m_adaptiveBitrateDownRate)
-						m_bitrate = 5;
-					else
-						m_bitrate -= m_adaptiveBitrateDownRate;
-				} else if (latencyUs < m_adaptiveBitrateTarget - m_adaptiveBitrateThreshold) {
-					if (m_bitrate > m_adaptiveBitrateMaximum - m_adaptiveBitrateDownRate)
-						m_bitrate = m_adaptiveBitrateMaximum;
-					else if (m_bitsSentInSecondPrev * 1e-6 > m_bitrate * m_adaptiveBitrateLightLoadThreshold * (m_framesPrevious == 0 ? m_refreshRate : m_framesPrevious) / m_refreshRate)
-						m_bitrate += m_adaptiveBitrateUpRate;
-				}
-			}
-			if (m_bitrateUpdated != m_bitrate) {
-				m_bitrateUpdated = m_bitrate;
-				return true;
-			}
+		uint64_t bitrate = m_bitrate;
+		if (m_bitrateUpdated != bitrate) {
+			m_bitrateUpdated = bitrate;
+			return true;
 		}
 		return false;
 	}
@@ -170,15 +161,6 @@ private:
 		m_encodeSampleCount = 0;
 		m_encodeLatencyMin = UINT64_MAX;
 		m_encodeLatencyMax = 0;
-
-		if (m_adaptiveBitrateUseFrametime) {
-			if (m_framesPrevious > 0) {
-				m_adaptiveBitrateTarget = 1e6 / m_framesPrevious + m_adaptiveBitrateTargetOffset;
-			}
-			if (m_adaptiveBitrateTarget > m_adaptiveBitrateTargetMaximum) {
-				m_adaptiveBitrateTarget = m_adaptiveBitrateTargetMaximum;
-			}
-		}
 	}
 
 	void CheckAndResetSecond() {
@@ -212,24 +194,10 @@ private:
 	
 	uint64_t m_sendLatency = 0;
 
-	uint64_t m_bitrate = Settings::Instance().mEncodeBitrateMBs;
+	// Written by the Rust thread, read by the encoder thread
+	std::atomic<uint64_t> m_bitrate{Settings::Instance().mEncodeBitrateMBs};
 	uint64_t m_bitrateUpdated = Settings::Instance().mEncodeBitrateMBs;
 
-	int64_t m_refreshRate = Settings::Instance().m_refreshRate;
-
-	bool m_enableAdaptiveBitrate = Settings::Instance().m_enableAdaptiveBitrate;
-	uint64_t m_adaptiveBitrateMaximum = Settings::Instance().m_adaptiveBitrateMaximum;
-	uint64_t m_adaptiveBitrateTarget = Settings::Instance().m_adaptiveBitrateTarget;
-	bool m_adaptiveBitrateUseFrametime = Settings::Instance().m_adaptiveBitrateUseFrametime;
-	uint64_t m_adaptiveBitrateTargetMaximum = Settings::Instance().m_adaptiveBitrateTargetMaximum;
-	int32_t m_adaptiveBitrateTargetOffset = Settings::Instance().m_adaptiveBitrateTargetOffset;
-	uint64_t m_adaptiveBitrateThreshold = Settings::Instance().m_adaptiveBitrateThreshold;
-
-	uint64_t m_adaptiveBitrateUpRate = Settings::Instance().m_adaptiveBitrateUpRate;
-	uint64_t m_adaptiveBitrateDownRate = Settings::Instance().m_adaptiveBitrateDownRate;
-
-	float m_adaptiveBitrateLightLoadThreshold = Settings::Instance().m_adaptiveBitrateLightLoadThreshold;
-
 	time_t m_current;
 	
 	// Total/Encode/Send/Decode/ClientFPS/Ping
@@ -164,6 +164,7 @@ void (*LogError)(const char *stringPtr);
 void (*LogWarn)(const char *stringPtr);
 void (*LogInfo)(const char *stringPtr);
 void (*LogDebug)(const char *stringPtr);
+void (*ReportEvent)(const char *jsonPtr);
 void (*DriverReadyIdle)(bool setDefaultChaprone);
 void (*VideoSend)(VideoFrame header, unsigned char *buf, int len);
 void (*HapticsSend)(unsigned long long path, float duration_s, float frequency, float amplitude);
@@ -277,3 +278,53 @@ void SetBattery(unsigned long long top_level_path, float gauge_value, bool is_pl
         }
     }
 }
+
+void SetBitrate(unsigned long long bitrateMbs) {
+    if (g_driver_provider.hmd && g_driver_provider.hmd->m_Listener) {
+        g_driver_provider.hmd->m_Listener->GetStatistics()->SetBitrate(bitrateMbs);
+    }
+}
+
+void SetColorCorrection(ColorCorrectionParams params) {
+    auto &settings = Settings::Instance();
+    settings.m_brightness = params.brightness;
+    settings.m_contrast = params.contrast;
+    settings.m_saturation = params.saturation;
+    settings.m_gamma = params.gamma;
+    settings.m_sharpening = params.sharpening;
+
+#ifdef _WIN32
+    if (g_driver_provider.hmd && g_driver_provider.hmd->m_encoder) {
+        g_driver_provider.hmd->m_encoder->OnColorCorrectionUpdated();
+    }
+#endif
+}
+
+bool SetFoveation(FoveationParams params) {
+    auto &settings = Settings::Instance();
+    settings.m_foveationCenterSizeX = params.centerSizeX;
+    settings.m_foveationCenterSizeY = params.centerSizeY;
+    settings.m_foveationCenterShiftX = params.centerShiftX;
+    settings.m_foveationCenterShiftY = params.centerShiftY;
+    settings.m_foveationEdgeRatioX = params.edgeRatioX;
+    settings.m_foveationEdgeRatioY = params.edgeRatioY;
+
+#ifdef _WIN32
+    // The encoder is created once, with the resolution given by the foveation parameters
+    if (g_driver_provider.hmd && g_driver_provider.hmd->m_encoder) {
+        return g_driver_provider.hmd->m_encoder->OnFoveationUpdated();
+    }
+#endif
+
+    return true;
+}
+
+void SetHapticsCurve(HapticsCurve curve) {
+    // The curve is read for every haptics event
+    auto &settings = Settings::Instance();
+    settings.m_hapticsIntensity = curve.intensity;
+    settings.m_hapticsAmplitudeCurve = curve.amplitudeCurve;
+    settings.m_hapticsMinDuration = curve.minDuration;
+    settings.m_hapticsLowDurationAmplitudeMultiplier = curve.lowDurationAmplitudeMultiplier;
+    settings.m_hapticsLowDurationRange = curve.lowDurationRange;
+}
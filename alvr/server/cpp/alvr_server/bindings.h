@@ -139,6 +139,31 @@ struct ViewsConfigData {
     float ipd_m;
 };
 
+struct ColorCorrectionParams {
+    float brightness;
+    float contrast;
+    float saturation;
+    float gamma;
+    float sharpening;
+};
+
+struct FoveationParams {
+    float centerSizeX;
+    float centerSizeY;
+    float centerShiftX;
+    float centerShiftY;
+    float edgeRatioX;
+    float edgeRatioY;
+};
+
+struct HapticsCurve {
+    float intensity;
+    float amplitudeCurve;
+    float minDuration;
+    float lowDurationAmplitudeMultiplier;
+    float lowDurationRange;
+};
+
 extern "C" const unsigned char *FRAME_RENDER_VS_CSO_PTR;
 extern "C" unsigned int FRAME_RENDER_VS_CSO_LEN;
 extern "C" const unsigned char *FRAME_RENDER_PS_CSO_PTR;
@@ -157,6 +182,7 @@ extern "C" void (*LogError)(const char *stringPtr);
 extern "C" void (*LogWarn)(const char *stringPtr);
 extern "C" void (*LogInfo)(const char *stringPtr);
 extern "C" void (*LogDebug)(const char *stringPtr);
+extern "C" void (*ReportEvent)(const char *jsonPtr);
 extern "C" void (*DriverReadyIdle)(bool setDefaultChaprone);
 extern "C" void (*VideoSend)(VideoFrame header, unsigned char *buf, int len);
 extern "C" void (*HapticsSend)(unsigned long long path,
@@ -179,4 +205,9 @@ extern "C" void ShutdownSteamvr();
 
 extern "C" void SetOpenvrProperty(unsigned long long topLevelPath, OpenvrProperty prop);
 extern "C" void SetViewsConfig(ViewsConfigData config);
-extern "C" void SetBattery(unsigned long long topLevelPath, float gauge_value, bool is_plugged);
\ No newline at end of file
+extern "C" void SetBattery(unsigned long long topLevelPath, float gauge_value, bool is_plugged);
+extern "C" void SetBitrate(unsigned long long bitrateMbs);
+extern "C" void SetColorCorrection(ColorCorrectionParams params);
+// Returns false if the new parameters need a different encoding resolution
+extern "C" bool SetFoveation(FoveationParams params);
+extern "C" void SetHapticsCurve(HapticsCurve curve);
\ No newline at end of file
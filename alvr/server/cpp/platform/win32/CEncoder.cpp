@@ -126,4 +126,14 @@
 
 		void CEncoder::InsertIDR() {
 			m_scheduler.InsertIDR();
+		}
+
+		void CEncoder::OnColorCorrectionUpdated() {
+			if (m_FrameRender) {
+				m_FrameRender->OnColorCorrectionUpdated();
+			}
+		}
+
+		bool CEncoder::OnFoveationUpdated() {
+			return !m_FrameRender || m_FrameRender->OnFoveationUpdated();
 		}
\ No newline at end of file
@@ -53,6 +53,10 @@
 
 		void InsertIDR();
 
+		void OnColorCorrectionUpdated();
+
+		bool OnFoveationUpdated();
+
 	private:
 		CThreadEvent m_newFrameReady, m_encodeFinished;
 		std::shared_ptr<VideoEncoder> m_videoEncoder;
@@ -79,7 +79,7 @@ FFR::FFR(ID3D11Device* device) : mDevice(device) {}
 
 void FFR::Initialize(ID3D11Texture2D* compositionTexture) {
 	auto fovVars = CalculateFoveationVars();
-	ComPtr<ID3D11Buffer> foveatedRenderingBuffer = CreateBuffer(mDevice.Get(), fovVars);
+	mFoveatedRenderingBuffer = CreateBuffer(mDevice.Get(), fovVars, D3D11_USAGE_DEFAULT);
 
 	std::vector<uint8_t> quadShaderCSO(QUAD_SHADER_CSO_PTR, QUAD_SHADER_CSO_PTR + QUAD_SHADER_CSO_LEN);
 	mQuadVertexShader = CreateVertexShader(mDevice.Get(), quadShaderCSO);
@@ -91,7 +91,7 @@ void FFR::Initialize(ID3D11Texture2D* compositionTexture) {
 		std::vector<uint8_t> compressAxisAlignedShaderCSO(COMPRESS_AXIS_ALIGNED_CSO_PTR, COMPRESS_AXIS_ALIGNED_CSO_PTR + COMPRESS_AXIS_ALIGNED_CSO_LEN);
 		auto compressAxisAlignedPipeline = RenderPipeline(mDevice.Get());
 		compressAxisAlignedPipeline.Initialize({ compositionTexture }, mQuadVertexShader.Get(),
-			compressAxisAlignedShaderCSO, mOptimizedTexture.Get(), foveatedRenderingBuffer.Get());
+			compressAxisAlignedShaderCSO, mOptimizedTexture.Get(), mFoveatedRenderingBuffer.Get());
 
 		mPipelines.push_back(compressAxisAlignedPipeline);
 	} else {
@@ -99,6 +99,11 @@ void FFR::Initialize(ID3D11Texture2D* compositionTexture) {
 	}
 }
 
+void FFR::UpdateFoveationVars(ID3D11DeviceContext* context) {
+	auto fovVars = CalculateFoveationVars();
+	UpdateBuffer(context, mFoveatedRenderingBuffer.Get(), &fovVars);
+}
+
 void FFR::Render() {
 	for (auto &p : mPipelines) {
 		p.Render();
@@ -9,11 +9,14 @@ public:
 	void Initialize(ID3D11Texture2D* compositionTexture);
 	void Render();
 	void GetOptimizedResolution(uint32_t* width, uint32_t* height);
+	// Uploads the foveation parameters in Settings. The optimized resolution must not change
+	void UpdateFoveationVars(ID3D11DeviceContext* context);
 	ID3D11Texture2D* GetOutputTexture();
 
 private:
 	Microsoft::WRL::ComPtr<ID3D11Device> mDevice;
 	Microsoft::WRL::ComPtr<ID3D11Texture2D> mOptimizedTexture;
+	Microsoft::WRL::ComPtr<ID3D11Buffer> mFoveatedRenderingBuffer;
 	Microsoft::WRL::ComPtr<ID3D11VertexShader> mQuadVertexShader;
 
 	std::vector<d3d_render_utils::RenderPipeline> mPipelines;
@@ -8,6 +8,27 @@ extern uint64_t g_DriverTestMode;
 
 using namespace d3d_render_utils;
 
+namespace {
+
+	struct ColorCorrection {
+		float renderWidth;
+		float renderHeight;
+		float brightness;
+		float contrast;
+		float saturation;
+		float gamma;
+		float sharpening;
+		float _align;
+	};
+
+	ColorCorrection GetColorCorrection() {
+		return { (float)Settings::Instance().m_renderWidth, (float)Settings::Instance().m_renderHeight,
+				 Settings::Instance().m_brightness, Settings::Instance().m_contrast + 1.f,
+				 Settings::Instance().m_saturation + 1.f, Settings::Instance().m_gamma,
+				 Settings::Instance().m_sharpening };
+	}
+}
+
 
 FrameRender::FrameRender(std::shared_ptr<CD3DRender> pD3DRender)
 	: m_pD3DRender(pD3DRender)
@@ -281,25 +302,12 @@ bool FrameRender::Startup()
 			Settings::Instance().m_renderWidth, Settings::Instance().m_renderHeight,
 			DXGI_FORMAT_R8G8B8A8_UNORM_SRGB);
 
-		struct ColorCorrection {
-			float renderWidth;
-			float renderHeight;
-			float brightness;
-			float contrast;
-			float saturation;
-			float gamma;
-			float sharpening;
-			float _align;
-		};
-		ColorCorrection colorCorrectionStruct = { (float)Settings::Instance().m_renderWidth, (float)Settings::Instance().m_renderHeight,
-												  Settings::Instance().m_brightness, Settings::Instance().m_contrast + 1.f,
-												  Settings::Instance().m_saturation + 1.f, Settings::Instance().m_gamma,
-												  Settings::Instance().m_sharpening };
-		ComPtr<ID3D11Buffer> colorCorrectionBuffer = CreateBuffer(m_pD3DRender->GetDevice(), colorCorrectionStruct);
+		// Not immutable, the parameters can be updated while streaming
+		m_colorCorrectionBuffer = CreateBuffer(m_pD3DRender->GetDevice(), GetColorCorrection(), D3D11_USAGE_DEFAULT);
 
 		m_colorCorrectionPipeline = std::make_unique<RenderPipeline>(m_pD3DRender->GetDevice());
 		m_colorCorrectionPipeline->Initialize({ m_pStagingTexture.Get() }, quadVertexShader.Get(), colorCorrectionShaderCSO,
-											  colorCorrectedTexture.Get(), colorCorrectionBuffer.Get());
+											  colorCorrectedTexture.Get(), m_colorCorrectionBuffer.Get());
 
 		m_pStagingTexture = colorCorrectedTexture;
 	}
@@ -465,11 +473,19 @@ bool FrameRender::RenderFrame(ID3D11Texture2D *pTexture[][2], vr::VRTextureBound
 		m_pD3DRender->GetContext()->DrawIndexed(VERTEX_INDEX_COUNT, 0, 0);
 	}
 
+	// The buffers are updated on the render thread, which owns the immediate context
 	if (enableColorCorrection) {
+		if (m_colorCorrectionUpdated.exchange(false)) {
+			auto colorCorrection = GetColorCorrection();
+			UpdateBuffer(m_pD3DRender->GetContext(), m_colorCorrectionBuffer.Get(), &colorCorrection);
+		}
 		m_colorCorrectionPipeline->Render();
 	}
 
 	if (enableFFR) {
+		if (m_foveationUpdated.exchange(false)) {
+			m_ffr->UpdateFoveationVars(m_pD3DRender->GetContext());
+		}
 		m_ffr->Render();
 	}
 
@@ -492,4 +508,26 @@ void FrameRender::GetEncodingResolution(uint32_t *width, uint32_t *height) {
 		*height = Settings::Instance().m_renderHeight;
 	}
 	
-}
\ No newline at end of file
+}
+
+void FrameRender::OnColorCorrectionUpdated() {
+	m_colorCorrectionUpdated = true;
+}
+
+bool FrameRender::OnFoveationUpdated() {
+	if (!enableFFR) {
+		return true;
+	}
+
+	uint32_t width, height;
+	m_ffr->GetOptimizedResolution(&width, &height);
+
+	D3D11_TEXTURE2D_DESC desc;
+	m_ffr->GetOutputTexture()->GetDesc(&desc);
+	if (width != desc.Width || height != desc.Height) {
+		return false;
+	}
+
+	m_foveationUpdated = true;
+	return true;
+}
@@ -14,6 +14,7 @@
 #include <dxgi.h>
 #include <unknwn.h>
 #include <cinttypes>
+#include <atomic>
 
 #include "shared/d3drender.h"
 #include "openvr_driver.h"
@@ -49,6 +50,9 @@ public:
 	bool Startup();
 	bool RenderFrame(ID3D11Texture2D *pTexture[][2], vr::VRTextureBounds_t bounds[][2], int layerCount, bool recentering, const std::string& message, const std::string& debugText);
 	void GetEncodingResolution(uint32_t *width, uint32_t *height);
+	// Called from any thread after the parameters in Settings are changed
+	void OnColorCorrectionUpdated();
+	bool OnFoveationUpdated();
 
 	ComPtr<ID3D11Texture2D> GetTexture();
 private:
@@ -86,10 +90,13 @@ private:
 	static const int VERTEX_INDEX_COUNT = 12;
 
 	std::unique_ptr<d3d_render_utils::RenderPipeline> m_colorCorrectionPipeline;
+	ComPtr<ID3D11Buffer> m_colorCorrectionBuffer;
 	bool enableColorCorrection;
+	std::atomic_bool m_colorCorrectionUpdated = false;
 
 	std::unique_ptr<FFR> m_ffr;
 	bool enableFFR;
+	std::atomic_bool m_foveationUpdated = false;
 
 	static bool SetGpuPriority(ID3D11Device* device)
 	{
@@ -0,0 +1,310 @@
+// Adaptive bitrate controller. Once per second it decides the encoder bitrate from the transport
+// feedback: the packet loss and transport latency reported by the client, the RTT measured by the
+// stream socket and the client decode latency. The decision is then forwarded to the encoder.
+// This module has no dependency on the C++ code, so it can be tested on recorded traces.
+
+use alvr_session::AdaptiveBitrateDesc;
+use settings_schema::Switch;
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+pub const UPDATE_INTERVAL: Duration = Duration::from_secs(1);
+const MIN_BITRATE_MBS: u64 = 5;
+
+// Weight of a new transport latency sample, the same used by the legacy C++ statistics
+const LATENCY_SMOOTHING: f32 = 0.1;
+
+// The minimum RTT is taken over this window, so that a route change that raises the base RTT does
+// not keep the bitrate low forever
+const MIN_RTT_WINDOW: Duration = Duration::from_secs(10);
+
+pub struct BitrateController {
+    desc: AdaptiveBitrateDesc,
+    refresh_rate: f32,
+    bitrate_mbs: u64,
+    latency_target_us: u64,
+
+    window_start: Instant,
+    bytes_sent_in_window: usize,
+    frames_sent_in_window: usize,
+    packets_lost_in_window: u64,
+
+    last_packets_lost_total: Option<u64>,
+    transport_latency_us: Option<f32>,
+    decode_latency_us: Option<u64>,
+    rtt: Option<Duration>,
+    // RTT samples of the last MIN_RTT_WINDOW
+    rtt_samples: VecDeque<(Instant, Duration)>,
+}
+
+impl BitrateController {
+    pub fn new(
+        desc: AdaptiveBitrateDesc,
+        initial_bitrate_mbs: u64,
+        refresh_rate: f32,
+        now: Instant,
+    ) -> Self {
+        let latency_target_us = desc.latency_target;
+        Self {
+            desc,
+            refresh_rate,
+            bitrate_mbs: initial_bitrate_mbs,
+            latency_target_us,
+            window_start: now,
+            bytes_sent_in_window: 0,
+            frames_sent_in_window: 0,
+            packets_lost_in_window: 0,
+            last_packets_lost_total: None,
+            transport_latency_us: None,
+            decode_latency_us: None,
+            rtt: None,
+            rtt_samples: VecDeque::new(),
+        }
+    }
+
+    pub fn bitrate_mbs(&self) -> u64 {
+        self.bitrate_mbs
+    }
+
+    pub fn report_frame_sent(&mut self, size: usize) {
+        self.bytes_sent_in_window += size;
+        self.frames_sent_in_window += 1;
+    }
+
+    // Values of the TimeSync packets sent by the client
+    pub fn report_client_statistics(
+        &mut self,
+        packets_lost_total: u64,
+        transport_latency_us: u32,
+        decode_latency_us: u64,
+    ) {
+        if let Some(last_total) = self.last_packets_lost_total {
+            self.packets_lost_in_window += packets_lost_total.saturating_sub(last_total);
+        }
+        self.last_packets_lost_total = Some(packets_lost_total);
+
+        self.transport_latency_us = Some(
+            self.transport_latency_us
+                .map(|latency| {
+                    latency * (1. - LATENCY_SMOOTHING)
+                        + transport_latency_us as f32 * LATENCY_SMOOTHING
+                })
+                .unwrap_or(transport_latency_us as _),
+        );
+        self.decode_latency_us = Some(decode_latency_us);
+    }
+
+    // The minimum RTT is the baseline without queuing delay. Rising above it means that the
+    // bitrate exceeds the link capacity.
+    pub fn report_rtt(&mut self, rtt: Duration, now: Instant) {
+        self.rtt = Some(rtt);
+
+        while let Some(&(time, _)) = self.rtt_samples.front() {
+            if now - time < MIN_RTT_WINDOW {
+                break;
+            }
+            self.rtt_samples.pop_front();
+        }
+        self.rtt_samples.push_back((now, rtt));
+    }
+
+    fn min_rtt(&self) -> Option<Duration> {
+        self.rtt_samples.iter().map(|&(_, rtt)| rtt).min()
+    }
+
+    // Returns the new bitrate if it changed
+    pub fn update(&mut self, now: Instant) -> Option<u64> {
+        let window_duration = now - self.window_start;
+        if window_duration < UPDATE_INTERVAL {
+            return None;
+        }
+
+        let window_secs = window_duration.as_secs_f32();
+        let fps = if self.frames_sent_in_window > 0 {
+            self.frames_sent_in_window as f32 / window_secs
+        } else {
+            self.refresh_rate
+        };
+        let sent_mbits_per_second = self.bytes_sent_in_window as f32 * 8. / 1e6 / window_secs;
+        let packets_lost = self.packets_lost_in_window;
+
+        self.window_start = now;
+        self.bytes_sent_in_window = 0;
+        self.frames_sent_in_window = 0;
+        self.packets_lost_in_window = 0;
+
+        if let Switch::Enabled(frametime_desc) = &self.desc.latency_use_frametime {
+            let target = 1e6 / fps + frametime_desc.latency_target_offset as f32;
+            self.latency_target_us =
+                (target.max(0.) as u64).min(frametime_desc.latency_target_maximum);
+        }
+
+        let threshold_us = self.desc.latency_threshold;
+        let frame_interval_us = 1e6 / fps;
+
+        let latency_too_high = self
+            .transport_latency_us
+            .map(|latency| latency > (self.latency_target_us + threshold_us) as f32)
+            .unwrap_or(false);
+        let rtt_rising = match (self.rtt, self.min_rtt()) {
+            (Some(rtt), Some(min_rtt)) => rtt > min_rtt + Duration::from_micros(threshold_us),
+            _ => false,
+        };
+        let decoder_saturated = self
+            .decode_latency_us
+            .map(|latency| latency as f32 > frame_interval_us)
+            .unwrap_or(false);
+
+        let previous_bitrate = self.bitrate_mbs;
+
+        if packets_lost > 0 || latency_too_high || rtt_rising || decoder_saturated {
+            self.bitrate_mbs = self
+                .bitrate_mbs
+                .saturating_sub(self.desc.bitrate_down_rate)
+                .max(MIN_BITRATE_MBS);
+        } else {
+            let latency_low = self
+                .transport_latency_us
+                .map(|latency| latency < self.latency_target_us.saturating_sub(threshold_us) as f32)
+                .unwrap_or(false);
+
+            // Increase only if the encoder is actually using most of the current bitrate
+            let heavy_load = sent_mbits_per_second
+                > self.bitrate_mbs as f32 * self.desc.bitrate_light_load_threshold * fps
+                    / self.refresh_rate;
+
+            if latency_low && heavy_load {
+                self.bitrate_mbs =
+                    (self.bitrate_mbs + self.desc.bitrate_up_rate).min(self.desc.bitrate_maximum);
+            }
+        }
+
+        (self.bitrate_mbs != previous_bitrate).then_some(self.bitrate_mbs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alvr_session::LatencyUseFrametimeDesc;
+
+    fn controller(now: Instant) -> BitrateController {
+        BitrateController::new(
+            AdaptiveBitrateDesc {
+                bitrate_maximum: 100,
+                latency_target: 12000,
+                latency_use_frametime: Switch::Disabled,
+                latency_threshold: 3000,
+                bitrate_up_rate: 1,
+                bitrate_down_rate: 3,
+                bitrate_light_load_threshold: 0.7,
+            },
+            50,
+            72.,
+            now,
+        )
+    }
+
+    // Simulates one second of streaming at 72 fps, using the whole bitrate
+    fn stream_one_second(controller: &mut BitrateController, now: &mut Instant) -> Option<u64> {
+        let frame_size = (controller.bitrate_mbs() * 1_000_000 / 8 / 72) as usize;
+        for _ in 0..72 {
+            controller.report_frame_sent(frame_size);
+        }
+        *now += UPDATE_INTERVAL;
+
+        controller.update(*now)
+    }
+
+    #[test]
+    fn increase_with_low_latency() {
+        let mut now = Instant::now();
+        let mut controller = controller(now);
+        controller.report_client_statistics(0, 5000, 5000);
+
+        assert_eq!(stream_one_second(&mut controller, &mut now), Some(51));
+        assert_eq!(stream_one_second(&mut controller, &mut now), Some(52));
+    }
+
+    #[test]
+    fn no_increase_with_light_load() {
+        let mut now = Instant::now();
+        let mut controller = controller(now);
+        controller.report_client_statistics(0, 5000, 5000);
+
+        now += UPDATE_INTERVAL;
+        assert_eq!(controller.update(now), None);
+    }
+
+    #[test]
+    fn decrease_with_high_latency() {
+        let mut now = Instant::now();
+        let mut controller = controller(now);
+        controller.report_client_statistics(0, 20000, 5000);
+
+        assert_eq!(stream_one_second(&mut controller, &mut now), Some(47));
+    }
+
+    #[test]
+    fn decrease_with_packet_loss() {
+        let mut now = Instant::now();
+        let mut controller = controller(now);
+        controller.report_client_statistics(10, 5000, 5000);
+        controller.report_client_statistics(15, 5000, 5000);
+
+        assert_eq!(stream_one_second(&mut controller, &mut now), Some(47));
+        // The loss has been accounted for
+        assert_eq!(stream_one_second(&mut controller, &mut now), Some(48));
+    }
+
+    #[test]
+    fn decrease_with_rising_rtt() {
+        let mut now = Instant::now();
+        let mut controller = controller(now);
+        controller.report_client_statistics(0, 5000, 5000);
+        controller.report_rtt(Duration::from_millis(2), now);
+        controller.report_rtt(Duration::from_millis(10), now);
+
+        assert_eq!(stream_one_second(&mut controller, &mut now), Some(47));
+    }
+
+    #[test]
+    fn recover_after_base_rtt_increase() {
+        let mut now = Instant::now();
+        let mut controller = controller(now);
+        controller.report_client_statistics(0, 5000, 5000);
+        controller.report_rtt(Duration::from_millis(2), now);
+
+        // The route changed, the RTT is higher but stable
+        let mut bitrate_mbs = 50;
+        for _ in 0..MIN_RTT_WINDOW.as_secs() {
+            controller.report_rtt(Duration::from_millis(10), now);
+            if let Some(bitrate) = stream_one_second(&mut controller, &mut now) {
+                bitrate_mbs = bitrate;
+            }
+        }
+        assert!(bitrate_mbs < 50);
+
+        controller.report_rtt(Duration::from_millis(10), now);
+        assert_eq!(
+            stream_one_second(&mut controller, &mut now),
+            Some(bitrate_mbs + 1)
+        );
+    }
+
+    #[test]
+    fn frametime_latency_target() {
+        let mut now = Instant::now();
+        let mut controller = controller(now);
+        controller.desc.latency_use_frametime = Switch::Enabled(LatencyUseFrametimeDesc {
+            latency_target_maximum: 30000,
+            latency_target_offset: 0,
+        });
+        // Above the fixed target, but below the 72 fps frame time plus the threshold
+        controller.report_client_statistics(0, 14000, 5000);
+
+        assert_eq!(stream_one_second(&mut controller, &mut now), None);
+    }
+}
@@ -1,7 +1,9 @@
 use crate::{
-    connection_utils, ClientListAction, EyeFov, TimeSync, TrackingInfo, TrackingInfo_Controller,
-    TrackingInfo_Controller__bindgen_ty_1, TrackingQuat, TrackingVector3, CLIENTS_UPDATED_NOTIFIER,
-    HAPTICS_SENDER, RESTART_NOTIFIER, SESSION_MANAGER, TIME_SYNC_SENDER, VIDEO_SENDER,
+    bitrate::{self, BitrateController},
+    connection_utils, metrics, recorder, ClientListAction, EyeFov, TimeSync, TrackingInfo,
+    TrackingInfo_Controller, TrackingInfo_Controller__bindgen_ty_1, TrackingQuat, TrackingVector3,
+    CLIENTS_UPDATED_NOTIFIER, HAPTICS_SENDER, RESTART_NOTIFIER, SERVER_IDENTITY, SESSION_MANAGER,
+    SETTINGS_UPDATED_NOTIFIER, TIME_SYNC_SENDER, VIDEO_SENDER,
 };
 use alvr_audio::{AudioDevice, AudioDeviceType};
 use alvr_common::{
@@ -12,12 +14,14 @@ use alvr_common::{
     HEAD_ID, LEFT_HAND_ID, RIGHT_HAND_ID,
 };
 use alvr_session::{
-    CodecType, FrameSize, OpenvrConfig, OpenvrPropValue, OpenvrPropertyKey, ServerEvent,
+    CodecType, FrameSize, OpenvrConfig, OpenvrPropValue, OpenvrPropertyKey, RecordingData,
+    ReloadRequirement, ServerEvent, SessionDesc, SessionSettings, Settings,
 };
 use alvr_sockets::{
     spawn_cancelable, ClientConfigPacket, ClientControlPacket, ControlSocketReceiver,
-    ControlSocketSender, HeadsetInfoPacket, Input, PeerType, ProtoControlSocket,
-    ServerControlPacket, StreamSocketBuilder, AUDIO, HAPTICS, INPUT, VIDEO,
+    ControlSocketSender, HeadsetInfoPacket, Input, NetworkConfig, PeerIp, PeerType,
+    ProtoControlSocket, PublicIdentity, ServerControlPacket, StreamSocketBuilder, AUDIO, HAPTICS,
+    INPUT, VIDEO,
 };
 use futures::future::{BoxFuture, Either};
 use settings_schema::Switch;
@@ -28,7 +32,7 @@ use std::{
     str::FromStr,
     sync::{mpsc as smpsc, Arc},
     thread,
-    time::Duration,
+    time::{Duration, Instant},
 };
 use tokio::{
     sync::{mpsc as tmpsc, Mutex},
@@ -39,6 +43,8 @@ const CONTROL_CONNECT_RETRY_PAUSE: Duration = Duration::from_millis(500);
 const RETRY_CONNECT_MIN_INTERVAL: Duration = Duration::from_secs(1);
 const NETWORK_KEEPALIVE_INTERVAL: Duration = Duration::from_secs(1);
 const CLEANUP_PAUSE: Duration = Duration::from_millis(500);
+// Mode 0 time sync packets are sent for every frame
+const TIME_SYNC_RECORDING_INTERVAL: Duration = Duration::from_secs(1);
 
 fn align32(value: f32) -> u32 {
     ((value / 32.).floor() * 32.) as u32
@@ -51,12 +57,15 @@ fn mbits_to_bytes(value: u64) -> u32 {
 #[derive(Clone)]
 struct ClientId {
     hostname: String,
-    ip: IpAddr,
+    ip: PeerIp,
 }
 
-async fn client_discovery(auto_trust_clients: bool) -> StrResult<ClientId> {
+async fn client_discovery(
+    auto_trust_clients: bool,
+    network_config: NetworkConfig,
+) -> StrResult<ClientId> {
     let (ip, handshake_packet) =
-        connection_utils::search_client_loop(|handshake_packet| async move {
+        connection_utils::search_client_loop(network_config, |handshake_packet| async move {
             crate::update_client_list(
                 handshake_packet.hostname.clone(),
                 ClientListAction::AddIfMissing {
@@ -84,42 +93,164 @@ async fn client_discovery(auto_trust_clients: bool) -> StrResult<ClientId> {
 }
 
 struct ConnectionInfo {
-    client_ip: IpAddr,
+    client_ip: PeerIp,
     version: Option<Version>,
+    fps: f32,
+    client_hostname: Option<String>,
+    // Certificate presented on the control socket, pinned by the stream socket
+    client_certificate_pem: Option<String>,
+    // Effective settings for this client, with its profile applied
+    session_settings: SessionSettings,
+    settings: Settings,
     control_sender: ControlSocketSender<ServerControlPacket>,
     control_receiver: ControlSocketReceiver<ClientControlPacket>,
 }
 
+fn client_session_settings(client_hostname: Option<&str>) -> SessionSettings {
+    let session_manager = SESSION_MANAGER.lock();
+    let session = session_manager.get();
+
+    match client_hostname {
+        Some(hostname) => session
+            .client_session_settings(hostname)
+            .unwrap_or_else(|e| {
+                warn!("{e}. Using the base settings");
+                session.session_settings.clone()
+            }),
+        None => session.session_settings.clone(),
+    }
+}
+
+// Session sent to the client. It contains only the settings of the client, the rest of the session
+// (other clients, their certificates and profiles) is not shared.
+fn client_session_json(session_settings: &SessionSettings) -> StrResult<String> {
+    let mut session = SessionDesc {
+        session_settings: session_settings.clone(),
+        ..Default::default()
+    };
+    if cfg!(target_os = "linux") {
+        session.session_settings.video.foveated_rendering.enabled = false;
+    }
+
+    trace_err!(serde_json::to_string(&session))
+}
+
+// Applies the hot-reloadable settings to the driver. Returns false if the driver must be restarted
+// instead.
+fn apply_hot_settings(session_settings: &SessionSettings) -> bool {
+    let video = &session_settings.video;
+    // The adaptive bitrate takes precedence
+    if !video.adaptive_bitrate.enabled {
+        unsafe { crate::SetBitrate(video.encode_bitrate_mbs) };
+    }
+
+    let color_correction = &video.color_correction.content;
+    unsafe {
+        crate::SetColorCorrection(crate::ColorCorrectionParams {
+            brightness: color_correction.brightness,
+            contrast: color_correction.contrast,
+            saturation: color_correction.saturation,
+            gamma: color_correction.gamma,
+            sharpening: color_correction.sharpening,
+        })
+    };
+
+    let controllers = &session_settings.headset.controllers.content;
+    unsafe {
+        crate::SetHapticsCurve(crate::HapticsCurve {
+            intensity: controllers.haptics_intensity,
+            amplitudeCurve: controllers.haptics_amplitude_curve,
+            minDuration: controllers.haptics_min_duration,
+            lowDurationAmplitudeMultiplier: controllers.haptics_low_duration_amplitude_multiplier,
+            lowDurationRange: controllers.haptics_low_duration_range,
+        })
+    };
+
+    let foveation = &video.foveated_rendering.content;
+    unsafe {
+        crate::SetFoveation(crate::FoveationParams {
+            centerSizeX: foveation.center_size_x,
+            centerSizeY: foveation.center_size_y,
+            centerShiftX: foveation.center_shift_x,
+            centerShiftY: foveation.center_shift_y,
+            edgeRatioX: foveation.edge_ratio_x,
+            edgeRatioY: foveation.edge_ratio_y,
+        })
+    }
+}
+
 async fn client_handshake(
     trusted_discovered_client_id: Option<ClientId>,
+    network_config: NetworkConfig,
 ) -> StrResult<ConnectionInfo> {
-    let client_ips = if let Some(id) = trusted_discovered_client_id {
-        vec![id.ip]
-    } else {
-        SESSION_MANAGER.lock().get().client_connections.iter().fold(
-            Vec::new(),
-            |mut clients_info, (_, client)| {
-                clients_info.extend(client.manual_ips.clone());
-                clients_info
-            },
-        )
+    let clients = {
+        let client_connections = SESSION_MANAGER.lock().get().client_connections.clone();
+
+        let public_identity = |hostname: &String| PublicIdentity {
+            hostname: hostname.clone(),
+            certificate_pem: client_connections
+                .get(hostname)
+                .and_then(|client| client.certificate_pem.clone()),
+        };
+
+        if let Some(id) = trusted_discovered_client_id {
+            vec![(id.ip, public_identity(&id.hostname))]
+        } else {
+            client_connections
+                .iter()
+                .fold(Vec::new(), |mut clients_info, (hostname, client)| {
+                    clients_info.extend(
+                        client
+                            .manual_ips
+                            .iter()
+                            .map(|&ip| (ip.into(), public_identity(hostname))),
+                    );
+                    clients_info
+                })
+        }
     };
 
     let (mut proto_socket, client_ip) = loop {
-        if let Ok(pair) =
-            ProtoControlSocket::connect_to(PeerType::AnyClient(client_ips.clone())).await
+        match ProtoControlSocket::connect_to(
+            PeerType::AnyClient(clients.clone()),
+            &SERVER_IDENTITY,
+            &network_config,
+        )
+        .await
         {
-            break pair;
+            Ok(pair) => break pair,
+            Err(e) => debug!("{e}. Retrying"),
         }
 
-        debug!("Timeout while searching for client. Retrying");
         time::sleep(CONTROL_CONNECT_RETRY_PAUSE).await;
     };
 
+    let client_hostname = clients
+        .iter()
+        .find(|(ip, _)| *ip == client_ip)
+        .map(|(_, identity)| identity.hostname.clone());
+
+    // Pin the certificate of the client on first connection. The clients list is not updated
+    // through update_client_list() because that would interrupt this handshake.
+    let client_certificate_pem = proto_socket.peer_certificate_pem();
+    if let (Some(hostname), Some(certificate_pem)) = (&client_hostname, &client_certificate_pem) {
+        if let Some(client) = SESSION_MANAGER
+            .lock()
+            .get_mut()
+            .client_connections
+            .get_mut(hostname)
+        {
+            client
+                .certificate_pem
+                .get_or_insert_with(|| certificate_pem.clone());
+        }
+    }
+
     let (headset_info, server_ip) =
         trace_err!(proto_socket.recv::<(HeadsetInfoPacket, IpAddr)>().await)?;
 
-    let settings = SESSION_MANAGER.lock().get().to_settings();
+    let session_settings = client_session_settings(client_hostname.as_deref());
+    let settings = alvr_session::session_settings_to_settings(&session_settings);
 
     let (eye_width, eye_height) = match settings.video.render_resolution {
         FrameSize::Scale(scale) => (
@@ -161,6 +292,8 @@ async fn client_handshake(
         warn!("Chosen refresh rate not supported. Using {fps}Hz");
     }
 
+    // The dashboard is reachable by the headset only with LAN access. The access token authorizes
+    // every endpoint, so it is not included and the user has to enter it.
     let dashboard_url = format!(
         "http://{server_ip}:{}/",
         settings.connection.web_server_port
@@ -194,14 +327,7 @@ async fn client_handshake(
     let version = Version::from_str(&headset_info.reserved).ok();
 
     let client_config = ClientConfigPacket {
-        session_desc: {
-            let mut session = SESSION_MANAGER.lock().get().clone();
-            if cfg!(target_os = "linux") {
-                session.session_settings.video.foveated_rendering.enabled = false;
-            }
-
-            trace_err!(serde_json::to_string(&session))?
-        },
+        session_desc: client_session_json(&session_settings)?,
         dashboard_url,
         eye_resolution_width: video_eye_width,
         eye_resolution_height: video_eye_height,
@@ -214,8 +340,6 @@ async fn client_handshake(
 
     let (mut control_sender, control_receiver) = proto_socket.split();
 
-    let session_settings = SESSION_MANAGER.lock().get().session_settings.clone();
-
     let controller_pose_offset = match settings.headset.controllers {
         Switch::Enabled(content) => {
             if content.clientside_prediction {
@@ -463,41 +587,50 @@ async fn client_handshake(
         linux_async_reprojection: session_settings.extra.patches.linux_async_reprojection,
     };
 
-    if SESSION_MANAGER.lock().get().openvr_config != new_openvr_config {
-        SESSION_MANAGER.lock().get_mut().openvr_config = new_openvr_config;
+    let old_openvr_config = SESSION_MANAGER.lock().get().openvr_config.clone();
+    if old_openvr_config != new_openvr_config {
+        // The driver loads the hot-reloadable values when the stream starts
+        SESSION_MANAGER.lock().get_mut().openvr_config = new_openvr_config.clone();
 
-        control_sender
-            .send(&ServerControlPacket::Restarting)
-            .await
-            .ok();
+        if old_openvr_config.requires_restart(&new_openvr_config) {
+            control_sender
+                .send(&ServerControlPacket::Restarting)
+                .await
+                .ok();
 
-        crate::notify_restart_driver();
+            crate::notify_restart_driver();
 
-        // waiting for execution canceling
-        future::pending::<()>().await;
+            // waiting for execution canceling
+            future::pending::<()>().await;
+        }
     }
 
     Ok(ConnectionInfo {
         client_ip,
         version,
+        fps,
+        client_hostname,
+        client_certificate_pem,
+        settings: alvr_session::session_settings_to_settings(&session_settings),
+        session_settings,
         control_sender,
         control_receiver,
     })
 }
 
 // close stream on Drop (manual disconnection or execution canceling)
-struct StreamCloseGuard;
+struct StreamCloseGuard {
+    on_disconnect_script: String,
+}
 
 impl Drop for StreamCloseGuard {
     fn drop(&mut self) {
         unsafe { crate::DeinitializeStreaming() };
 
-        let settings = SESSION_MANAGER.lock().get().to_settings();
-
-        let on_disconnect_script = settings.connection.on_disconnect_script;
+        let on_disconnect_script = &self.on_disconnect_script;
         if !on_disconnect_script.is_empty() {
             info!("Running on disconnect script (disconnect): {on_disconnect_script}");
-            if let Err(e) = Command::new(&on_disconnect_script)
+            if let Err(e) = Command::new(on_disconnect_script)
                 .env("ACTION", "disconnect")
                 .spawn()
             {
@@ -508,6 +641,11 @@ impl Drop for StreamCloseGuard {
 }
 
 async fn connection_pipeline() -> StrResult {
+    // The client is not known yet. Profiles cannot change these server-wide settings, see
+    // alvr_session::SessionDesc::profile_session_settings()
+    let network_config =
+        NetworkConfig::from_settings(&SESSION_MANAGER.lock().get().to_settings().connection)?;
+
     let mut trusted_discovered_client_id = None;
     let connection_info = loop {
         let client_discovery_config = SESSION_MANAGER
@@ -523,8 +661,8 @@ async fn connection_pipeline() -> StrResult {
             {
                 Box::pin(async move {
                     let either = futures::future::select(
-                        Box::pin(client_discovery(config.auto_trust_clients)),
-                        Box::pin(client_handshake(None)),
+                        Box::pin(client_discovery(config.auto_trust_clients, network_config)),
+                        Box::pin(client_handshake(None, network_config)),
                     )
                     .await;
 
@@ -535,7 +673,10 @@ async fn connection_pipeline() -> StrResult {
                 })
             } else {
                 Box::pin(async {
-                    Either::Right(client_handshake(trusted_discovered_client_id.clone()).await)
+                    Either::Right(
+                        client_handshake(trusted_discovered_client_id.clone(), network_config)
+                            .await,
+                    )
                 })
             };
 
@@ -568,6 +709,11 @@ async fn connection_pipeline() -> StrResult {
     let ConnectionInfo {
         client_ip,
         version: _,
+        fps,
+        client_hostname,
+        client_certificate_pem,
+        session_settings,
+        settings,
         control_sender,
         mut control_receiver,
     } = connection_info;
@@ -589,14 +735,14 @@ async fn connection_pipeline() -> StrResult {
         }
     }
 
-    let settings = SESSION_MANAGER.lock().get().to_settings();
-
     let stream_socket = tokio::select! {
         res = StreamSocketBuilder::connect_to_client(
+            network_config.bind_ip,
             client_ip,
             settings.connection.stream_port,
             settings.connection.stream_protocol,
-            mbits_to_bytes(settings.video.encode_bitrate_mbs)
+            mbits_to_bytes(settings.video.encode_bitrate_mbs),
+            client_certificate_pem.as_deref(),
         ) => res?,
         _ = time::sleep(Duration::from_secs(5)) => {
             return fmt_e!("Timeout while setting up streams");
@@ -604,14 +750,17 @@ async fn connection_pipeline() -> StrResult {
     };
     let stream_socket = Arc::new(stream_socket);
 
-    alvr_session::log_event(ServerEvent::ClientConnected);
+    metrics::set_client_hostname(client_hostname.clone());
+    recorder::start_recording(&settings);
+    recorder::record(RecordingData::Client(client_hostname.clone()));
+    alvr_session::publish_event(ServerEvent::ClientConnected);
 
     {
-        let on_connect_script = settings.connection.on_connect_script;
+        let on_connect_script = &settings.connection.on_connect_script;
 
         if !on_connect_script.is_empty() {
             info!("Running on connect script (connect): {on_connect_script}");
-            if let Err(e) = Command::new(&on_connect_script)
+            if let Err(e) = Command::new(on_connect_script)
                 .env("ACTION", "connect")
                 .spawn()
             {
@@ -621,7 +770,55 @@ async fn connection_pipeline() -> StrResult {
     }
 
     unsafe { crate::InitializeStreaming() };
-    let _stream_guard = StreamCloseGuard;
+    let _stream_guard = StreamCloseGuard {
+        on_disconnect_script: settings.connection.on_disconnect_script.clone(),
+    };
+
+    // The encoder is kept between streams, it could need a different resolution
+    if !apply_hot_settings(&session_settings) {
+        control_sender
+            .lock()
+            .await
+            .send(&ServerControlPacket::Restarting)
+            .await
+            .ok();
+
+        crate::notify_restart_driver();
+
+        // waiting for execution canceling
+        future::pending::<()>().await;
+    }
+
+    let bitrate_controller = if let Switch::Enabled(desc) = &settings.video.adaptive_bitrate {
+        Some(Arc::new(Mutex::new(BitrateController::new(
+            desc.clone(),
+            settings.video.encode_bitrate_mbs,
+            fps,
+            Instant::now(),
+        ))))
+    } else {
+        None
+    };
+
+    let bitrate_loop: BoxFuture<_> = if let Some(controller) = bitrate_controller.clone() {
+        let stream_socket = Arc::clone(&stream_socket);
+        Box::pin(async move {
+            loop {
+                time::sleep(bitrate::UPDATE_INTERVAL).await;
+
+                let mut controller = controller.lock().await;
+                if let Some(rtt) = stream_socket.statistics().smoothed_rtt {
+                    controller.report_rtt(rtt, Instant::now());
+                }
+                if let Some(bitrate_mbs) = controller.update(Instant::now()) {
+                    recorder::record(RecordingData::BitrateDecision(bitrate_mbs));
+                    unsafe { crate::SetBitrate(bitrate_mbs) };
+                }
+            }
+        })
+    } else {
+        Box::pin(future::pending())
+    };
 
     let game_audio_loop: BoxFuture<_> = if let Switch::Enabled(desc) = settings.audio.game_audio {
         let device = AudioDevice::new(
@@ -717,11 +914,16 @@ async fn connection_pipeline() -> StrResult {
 
     let video_send_loop = {
         let mut socket_sender = stream_socket.request_stream(VIDEO).await?;
+        let bitrate_controller = bitrate_controller.clone();
         async move {
             let (data_sender, mut data_receiver) = tmpsc::unbounded_channel();
             *VIDEO_SENDER.lock() = Some(data_sender);
 
             while let Some((header, data)) = data_receiver.recv().await {
+                if let Some(controller) = &bitrate_controller {
+                    controller.lock().await.report_frame_sent(data.len());
+                }
+
                 let mut buffer = socket_sender.new_buffer(&header, data.len())?;
                 buffer.get_mut().extend(data);
                 socket_sender.send_buffer(buffer).await.ok();
@@ -947,7 +1149,7 @@ async fn connection_pipeline() -> StrResult {
                     .send(&ServerControlPacket::KeepAlive)
                     .await;
                 if let Err(e) = res {
-                    alvr_session::log_event(ServerEvent::ClientDisconnected);
+                    alvr_session::publish_event(ServerEvent::ClientDisconnected);
                     info!("Client disconnected. Cause: {e}");
                     break Ok(());
                 }
@@ -957,6 +1159,7 @@ async fn connection_pipeline() -> StrResult {
     };
 
     let control_loop = async move {
+        let mut last_time_sync_recording = None::<Instant>;
         loop {
             match control_receiver.recv().await {
                 Ok(ClientControlPacket::PlayspaceSync(packet)) => {
@@ -966,6 +1169,31 @@ async fn connection_pipeline() -> StrResult {
                 }
                 Ok(ClientControlPacket::RequestIdr) => unsafe { crate::RequestIDR() },
                 Ok(ClientControlPacket::TimeSync(data)) => {
+                    // Mode 0 packets carry the client statistics
+                    if data.mode == 0 {
+                        metrics::set_client_audio_buffer_counts(
+                            data.audio_buffer_overflows,
+                            data.audio_buffer_underflows,
+                        );
+
+                        let now = Instant::now();
+                        if last_time_sync_recording.map_or(true, |last| {
+                            now.saturating_duration_since(last) >= TIME_SYNC_RECORDING_INTERVAL
+                        }) {
+                            last_time_sync_recording = Some(now);
+                            if let Ok(value) = serde_json::to_value(&data) {
+                                recorder::record(RecordingData::TimeSync(value));
+                            }
+                        }
+                    }
+                    if let (0, Some(controller)) = (data.mode, &bitrate_controller) {
+                        controller.lock().await.report_client_statistics(
+                            data.packets_lost_total,
+                            data.average_transport_latency,
+                            data.average_decode_latency,
+                        );
+                    }
+
                     let time_sync = TimeSync {
                         mode: data.mode,
                         serverTime: data.server_time,
@@ -1015,7 +1243,7 @@ async fn connection_pipeline() -> StrResult {
                 },
                 Ok(_) => (),
                 Err(e) => {
-                    alvr_session::log_event(ServerEvent::ClientDisconnected);
+                    alvr_session::publish_event(ServerEvent::ClientDisconnected);
                     info!("Client disconnected. Cause: {e}");
                     break;
                 }
@@ -1025,12 +1253,68 @@ async fn connection_pipeline() -> StrResult {
         Ok(())
     };
 
-    let receive_loop = async move { stream_socket.receive_loop().await };
+    // The settings that cannot be hot-reloaded are applied by reconnecting or restarting SteamVR
+    let settings_reload_loop = {
+        let control_sender = Arc::clone(&control_sender);
+        async move {
+            let mut session_settings = session_settings;
+            loop {
+                SETTINGS_UPDATED_NOTIFIER.notified().await;
+
+                let new_session_settings = client_session_settings(client_hostname.as_deref());
+                match alvr_session::reload_requirement(&session_settings, &new_session_settings) {
+                    Some(ReloadRequirement::HotReload) => {
+                        if !apply_hot_settings(&new_session_settings) {
+                            info!("Reconnecting to apply the foveation settings");
+                            break Ok(());
+                        }
+
+                        control_sender
+                            .lock()
+                            .await
+                            .send(&ServerControlPacket::SettingsUpdate(client_session_json(
+                                &new_session_settings,
+                            )?))
+                            .await?;
+
+                        session_settings = new_session_settings;
+                    }
+                    Some(ReloadRequirement::Reconnect) => {
+                        info!("Reconnecting to apply the settings");
+                        break Ok(());
+                    }
+                    Some(ReloadRequirement::Restart) => {
+                        info!("Restarting SteamVR to apply the settings");
+                        control_sender
+                            .lock()
+                            .await
+                            .send(&ServerControlPacket::Restarting)
+                            .await
+                            .ok();
+
+                        crate::notify_restart_driver();
+
+                        // waiting for execution canceling
+                        future::pending::<()>().await;
+                    }
+                    None => (),
+                }
+            }
+        }
+    };
+
+    // The stream packets are sent and received in the background, and they stop together
+    let socket_loop = async move {
+        tokio::select! {
+            res = stream_socket.send_loop() => res,
+            res = stream_socket.receive_loop() => res,
+        }
+    };
 
     tokio::select! {
         // Spawn new tasks and let the runtime manage threading
-        res = spawn_cancelable(receive_loop) => {
-            alvr_session::log_event(ServerEvent::ClientDisconnected);
+        res = spawn_cancelable(socket_loop) => {
+            alvr_session::publish_event(ServerEvent::ClientDisconnected);
             if let Err(e) = res {
                 info!("Client disconnected. Cause: {e}" );
             }
@@ -1043,10 +1327,12 @@ async fn connection_pipeline() -> StrResult {
         res = spawn_cancelable(time_sync_send_loop) => res,
         res = spawn_cancelable(haptics_send_loop) => res,
         res = spawn_cancelable(input_receive_loop) => res,
+        res = spawn_cancelable(bitrate_loop) => res,
 
         // Leave these loops on the current task
         res = keepalive_loop => res,
         res = control_loop => res,
+        res = settings_reload_loop => res,
 
         _ = RESTART_NOTIFIER.notified() => {
             control_sender
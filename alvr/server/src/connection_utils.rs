@@ -1,18 +1,24 @@
 use alvr_common::{prelude::*, ALVR_NAME};
 use alvr_session::ServerEvent;
 use alvr_sockets::{
-    ClientHandshakePacket, HandshakePacket, ServerHandshakePacket, CONTROL_PORT, LOCAL_IP,
+    ClientHandshakePacket, HandshakePacket, NetworkConfig, PeerIp, ServerHandshakePacket,
     MAX_HANDSHAKE_PACKET_SIZE_BYTES,
 };
-use std::{future::Future, net::IpAddr};
-use tokio::net::UdpSocket;
+use std::future::Future;
 
 // client_found_cb: returns true if client is trusted, false otherwise
 pub async fn search_client_loop<F: Future<Output = bool>>(
+    network_config: NetworkConfig,
     client_found_cb: impl Fn(ClientHandshakePacket) -> F,
-) -> StrResult<(IpAddr, ClientHandshakePacket)> {
+) -> StrResult<(PeerIp, ClientHandshakePacket)> {
     // use naked UdpSocket + [u8] packet buffer to have more control over datagram data
-    let handshake_socket = trace_err!(UdpSocket::bind((LOCAL_IP, CONTROL_PORT)).await)?;
+    let handshake_socket = alvr_sockets::bind_discovery_listener(&network_config)?;
+
+    // Clients that resolve the server with mDNS send the handshake packet directly to this socket.
+    // mDNS is optional, the broadcast discovery keeps working without it.
+    let _mdns_advertiser = alvr_sockets::MdnsAdvertiser::new(&network_config)
+        .map_err(|e| warn!("mDNS advertising unavailable: {e}"))
+        .ok();
 
     let mut packet_buffer = [0u8; MAX_HANDSHAKE_PACKET_SIZE_BYTES];
 
@@ -30,12 +36,12 @@ pub async fn search_client_loop<F: Future<Output = bool>>(
         {
             packet
         } else if &packet_buffer[..5] == b"\x01ALVR" {
-            alvr_session::log_event(ServerEvent::ClientFoundWrongVersion(
+            alvr_session::publish_event(ServerEvent::ClientFoundWrongVersion(
                 "v11 or previous".into(),
             ));
             return fmt_e!("ALVR client version is too old!");
         } else if &packet_buffer[..4] == b"ALVR" {
-            alvr_session::log_event(ServerEvent::ClientFoundWrongVersion(
+            alvr_session::publish_event(ServerEvent::ClientFoundWrongVersion(
                 "v12.x.x - v13.x.x".into(),
             ));
             return fmt_e!("ALVR client version is too old!");
@@ -45,7 +51,7 @@ pub async fn search_client_loop<F: Future<Output = bool>>(
         };
 
         if handshake_packet.alvr_name != ALVR_NAME {
-            alvr_session::log_event(ServerEvent::ClientFoundInvalid);
+            alvr_session::publish_event(ServerEvent::ClientFoundInvalid);
             return fmt_e!("Error while identifying client");
         }
 
@@ -58,7 +64,7 @@ pub async fn search_client_loop<F: Future<Output = bool>>(
                 .await
                 .ok();
 
-            alvr_session::log_event(ServerEvent::ClientFoundWrongVersion(
+            alvr_session::publish_event(ServerEvent::ClientFoundWrongVersion(
                 handshake_packet.version.to_string(),
             ));
             return fmt_e!("Found ALVR client with incompatible version");
@@ -74,7 +80,8 @@ pub async fn search_client_loop<F: Future<Output = bool>>(
                 .await
                 .ok();
         } else {
-            break Ok((client_address.ip(), handshake_packet));
+            // The scope id of link-local IPv6 clients is needed to connect back to them
+            break Ok((PeerIp::from_address(client_address), handshake_packet));
         }
     }
 }
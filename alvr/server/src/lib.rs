@@ -1,8 +1,12 @@
+mod bitrate;
 mod connection;
 mod connection_utils;
 mod dashboard;
 mod graphics_info;
 mod logging_backend;
+mod metrics;
+mod recorder;
+mod web_api;
 mod web_server;
 
 #[allow(
@@ -20,13 +24,16 @@ use alvr_common::{lazy_static, log, prelude::*, ALVR_VERSION};
 use alvr_filesystem::{self as afs, Layout};
 use alvr_session::{
     ClientConnectionDesc, OpenvrPropValue, OpenvrPropertyKey, ServerEvent, SessionManager,
+    SettingOverride,
 };
-use alvr_sockets::{Haptics, TimeSyncPacket, VideoFrameHeaderPacket};
+use alvr_sockets::{Haptics, PrivateIdentity, TimeSyncPacket, VideoFrameHeaderPacket};
 use graphics_info::GpuVendor;
 use parking_lot::Mutex;
+use settings_schema::ValidationError;
 use std::{
     collections::{hash_map::Entry, HashSet},
     ffi::{c_void, CStr, CString},
+    fs,
     net::IpAddr,
     os::raw::c_char,
     ptr,
@@ -49,7 +56,11 @@ lazy_static! {
     static ref SESSION_MANAGER: Mutex<SessionManager> =
         Mutex::new(SessionManager::new(&FILESYSTEM_LAYOUT.session()));
     static ref RUNTIME: Mutex<Option<Runtime>> = Mutex::new(Runtime::new().ok());
+    // Clients do not pin the server certificate, so a new identity is created on each run
+    static ref SERVER_IDENTITY: PrivateIdentity =
+        alvr_sockets::create_identity(Some("server.alvr".into())).unwrap();
     static ref MAYBE_WINDOW: Mutex<Option<Arc<alcro::UI>>> = Mutex::new(None);
+    static ref WEB_SERVER_TOKEN: String = web_server::load_or_create_token();
 
     static ref VIDEO_SENDER: Mutex<Option<mpsc::UnboundedSender<(VideoFrameHeaderPacket, Vec<u8>)>>> =
         Mutex::new(None);
@@ -60,6 +71,7 @@ lazy_static! {
 
     static ref CLIENTS_UPDATED_NOTIFIER: Notify = Notify::new();
     static ref RESTART_NOTIFIER: Notify = Notify::new();
+    static ref SETTINGS_UPDATED_NOTIFIER: Notify = Notify::new();
     static ref SHUTDOWN_NOTIFIER: Notify = Notify::new();
 
     static ref FRAME_RENDER_VS_CSO: Vec<u8> =
@@ -116,7 +128,7 @@ pub fn to_cpp_openvr_prop(key: OpenvrPropertyKey, value: OpenvrPropValue) -> Ope
 }
 
 pub fn shutdown_runtime() {
-    alvr_session::log_event(ServerEvent::ServerQuitting);
+    alvr_session::publish_event(ServerEvent::ServerQuitting);
 
     if let Some(window) = MAYBE_WINDOW.lock().take() {
         window.close();
@@ -162,6 +174,19 @@ pub enum ClientListAction {
     AddIfMissing { display_name: String },
     TrustAndMaybeAddIp(Option<IpAddr>),
     RemoveIpOrEntry(Option<IpAddr>),
+    // The certificate presented on the next connection is pinned instead
+    ForgetCertificate,
+    SetProfile(Option<String>),
+}
+
+// Profiles are checked when they are bound, a client with an invalid profile would fall back to the
+// base settings
+pub fn check_settings_profile(maybe_profile: &Option<String>) -> Result<(), Vec<ValidationError>> {
+    if let Some(profile) = maybe_profile {
+        SESSION_MANAGER.lock().get().validate_profile(profile)?;
+    }
+
+    Ok(())
 }
 
 pub fn update_client_list(hostname: String, action: ClientListAction) {
@@ -177,6 +202,8 @@ pub fn update_client_list(hostname: String, action: ClientListAction) {
                     trusted: false,
                     manual_ips: HashSet::new(),
                     display_name,
+                    certificate_pem: None,
+                    profile: None,
                 };
                 new_entry.insert(client_connection_desc);
 
@@ -203,6 +230,20 @@ pub fn update_client_list(hostname: String, action: ClientListAction) {
                     entry.remove_entry();
                 }
 
+                updated = true;
+            }
+        }
+        ClientListAction::ForgetCertificate => {
+            if let Entry::Occupied(mut entry) = maybe_client_entry {
+                entry.get_mut().certificate_pem = None;
+
+                updated = true;
+            }
+        }
+        ClientListAction::SetProfile(maybe_profile) => {
+            if let Entry::Occupied(mut entry) = maybe_client_entry {
+                entry.get_mut().profile = maybe_profile;
+
                 updated = true;
             }
         }
@@ -215,16 +256,84 @@ pub fn update_client_list(hostname: String, action: ClientListAction) {
     }
 }
 
+// Overrides stored by the launcher come first, so environment variables take precedence. The
+// launcher file is left in place, so that the SteamVR restarts triggered by the server keep its
+// overrides.
+fn setting_overrides() -> StrResult<Vec<SettingOverride>> {
+    let mut overrides = match fs::read_to_string(FILESYSTEM_LAYOUT.setting_overrides()) {
+        Ok(overrides_json) => trace_err!(serde_json::from_str(&overrides_json))?,
+        Err(_) => vec![],
+    };
+    overrides.extend(alvr_session::setting_overrides_from_env()?);
+
+    Ok(overrides)
+}
+
 fn init() {
     let (log_sender, _) = broadcast::channel(web_server::WS_BROADCAST_CAPACITY);
     let (events_sender, _) = broadcast::channel(web_server::WS_BROADCAST_CAPACITY);
-    logging_backend::init_logging(log_sender.clone(), events_sender.clone());
+    logging_backend::init_logging(log_sender.clone());
+
+    // Events are forwarded to the websockets. The current dashboard reads them from the log stream,
+    // as JSON wrapped in pound signs
+    let event_records = alvr_session::subscribe_events();
+    thread::spawn({
+        let log_sender = log_sender.clone();
+        let events_sender = events_sender.clone();
+        move || {
+            for record in event_records {
+                metrics::record_event(&record.event);
+                recorder::record_event(&record);
+
+                if !matches!(record.event, ServerEvent::Raw(_)) {
+                    log_sender
+                        .send(format!(
+                            "{} [INFO] #{}#",
+                            chrono::Local::now().format("%H:%M:%S.%f"),
+                            serde_json::to_string(&record.event).unwrap()
+                        ))
+                        .ok();
+                }
+                events_sender.send(record).ok();
+            }
+        }
+    });
 
     if let Some(runtime) = RUNTIME.lock().as_mut() {
         // Acquire and drop the session_manager lock to create session.json if not present
         // this is needed until Settings.cpp is replaced with Rust. todo: remove
         SESSION_MANAGER.lock().get_mut();
 
+        match setting_overrides() {
+            Ok(overrides) if !overrides.is_empty() => {
+                if let Err(e) = SESSION_MANAGER.lock().set_setting_overrides(overrides) {
+                    error!("Setting overrides not applied: {e}");
+                }
+            }
+            Ok(_) => (),
+            Err(e) => error!("Setting overrides not applied: {e}"),
+        }
+
+        // Manual edits of session.json are loaded, and settings changes from any source are
+        // forwarded to the connection
+        let session_updates = SESSION_MANAGER.lock().subscribe();
+        thread::spawn(move || {
+            let _maybe_watcher =
+                alvr_session::watch_session_file(&FILESYSTEM_LAYOUT.session(), || {
+                    if let Err(e) = SESSION_MANAGER.lock().reload_from_file() {
+                        warn!("session.json not reloaded: {e}");
+                    }
+                })
+                .map_err(|e| warn!("Cannot watch session.json: {e}"))
+                .ok();
+
+            for update in session_updates {
+                if update.reload_requirement.is_some() {
+                    SETTINGS_UPDATED_NOTIFIER.notify_waiters();
+                }
+            }
+        });
+
         runtime.spawn(async move {
             let connections = SESSION_MANAGER.lock().get().client_connections.clone();
             for (hostname, connection) in connections {
@@ -319,6 +428,13 @@ pub unsafe extern "C" fn HmdDriverFactory(
         log(log::Level::Debug, string_ptr);
     }
 
+    unsafe extern "C" fn report_event(json_ptr: *const c_char) {
+        match serde_json::from_str(&CStr::from_ptr(json_ptr).to_string_lossy()) {
+            Ok(event) => alvr_session::publish_event(event),
+            Err(e) => warn!("Invalid event from the driver: {e}"),
+        }
+    }
+
     extern "C" fn video_send(header: VideoFrame, buffer_ptr: *mut u8, len: i32) {
         if let Some(sender) = &*VIDEO_SENDER.lock() {
             let header = VideoFrameHeaderPacket {
@@ -373,6 +489,9 @@ pub unsafe extern "C" fn HmdDriverFactory(
                 fps: data.fps,
                 server_total_latency: data.serverTotalLatency,
                 tracking_recv_frame_index: data.trackingRecvFrameIndex,
+                // Only read from the client packets
+                audio_buffer_overflows: 0,
+                audio_buffer_underflows: 0,
             };
 
             sender.send(time_sync).ok();
@@ -411,6 +530,7 @@ pub unsafe extern "C" fn HmdDriverFactory(
     LogWarn = Some(log_warn);
     LogInfo = Some(log_info);
     LogDebug = Some(log_debug);
+    ReportEvent = Some(report_event);
     DriverReadyIdle = Some(driver_ready_idle);
     VideoSend = Some(video_send);
     HapticsSend = Some(haptics_send);
@@ -5,30 +5,21 @@ use fern::Dispatch;
 use std::fs;
 use tokio::sync::broadcast::Sender;
 
-pub fn init_logging(log_sender: Sender<String>, events_sender: Sender<String>) {
+pub fn init_logging(log_sender: Sender<String>) {
     let mut log_dispatch = Dispatch::new().format(move |out, message, record| {
-        let maybe_event = format!("{message}");
-        if maybe_event.contains("#{") {
-            let event_data = maybe_event.replace("#{", "{").replace("}#", "}");
-            events_sender.send(event_data).ok();
-        } else {
-            let severity = match record.level() {
-                log::Level::Error => EventSeverity::Error,
-                log::Level::Warn => EventSeverity::Warning,
-                log::Level::Info => EventSeverity::Info,
-                log::Level::Debug | log::Level::Trace => EventSeverity::Debug,
-            };
+        let severity = match record.level() {
+            log::Level::Error => EventSeverity::Error,
+            log::Level::Warn => EventSeverity::Warning,
+            log::Level::Info => EventSeverity::Info,
+            log::Level::Debug | log::Level::Trace => EventSeverity::Debug,
+        };
 
-            let event = ServerEvent::Raw(Raw {
-                timestamp: chrono::Local::now().format("%H:%M:%S.%f").to_string(),
-                severity,
-                content: message.to_string(),
-            });
+        alvr_session::publish_event(ServerEvent::Raw(Raw {
+            timestamp: chrono::Local::now().format("%H:%M:%S.%f").to_string(),
+            severity,
+            content: message.to_string(),
+        }));
 
-            events_sender
-                .send(serde_json::to_string(&event).unwrap())
-                .ok();
-        }
         let log_line = format!(
             "{} [{}] {message}",
             chrono::Local::now().format("%H:%M:%S.%f"),
@@ -0,0 +1,309 @@
+// Streaming statistics in the OpenMetrics text format, served at /metrics. Values are updated by
+// the server events. Streaming metrics are labeled with the hostname of the client.
+
+use alvr_common::lazy_static;
+use alvr_session::{ServerEvent, Statistics};
+use parking_lot::Mutex;
+use std::{fmt::Write, sync::atomic::Ordering};
+
+pub const OPENMETRICS_CONTENT_TYPE: &str =
+    "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+#[derive(Default)]
+struct MetricsState {
+    client_hostname: Option<String>,
+    client_connected: bool,
+    connections_count: u64,
+    // Statistics of the current connection
+    maybe_statistics: Option<Statistics>,
+    // Overflows and underflows of the game audio played by the client
+    maybe_client_audio_buffer_counts: Option<(u64, u64)>,
+}
+
+lazy_static! {
+    static ref METRICS: Mutex<MetricsState> = Mutex::new(MetricsState::default());
+}
+
+// Called before the ClientConnected event
+pub fn set_client_hostname(maybe_hostname: Option<String>) {
+    METRICS.lock().client_hostname = maybe_hostname;
+}
+
+// Reported by the client with the time sync packets
+pub fn set_client_audio_buffer_counts(overflows: u64, underflows: u64) {
+    METRICS.lock().maybe_client_audio_buffer_counts = Some((overflows, underflows));
+}
+
+pub fn record_event(event: &ServerEvent) {
+    let mut metrics = METRICS.lock();
+    match event {
+        ServerEvent::ClientConnected => {
+            metrics.client_connected = true;
+            metrics.connections_count += 1;
+            metrics.maybe_statistics = None;
+            metrics.maybe_client_audio_buffer_counts = None;
+        }
+        ServerEvent::ClientDisconnected => {
+            metrics.client_connected = false;
+            metrics.maybe_statistics = None;
+            metrics.maybe_client_audio_buffer_counts = None;
+        }
+        ServerEvent::Statistics(statistics) => metrics.maybe_statistics = Some(statistics.clone()),
+        _ => (),
+    }
+}
+
+fn escape_label_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+struct MetricsWriter {
+    text: String,
+    labels: String,
+}
+
+impl MetricsWriter {
+    fn family(&mut self, name: &str, metric_type: &str, help: &str) {
+        writeln!(self.text, "# TYPE {name} {metric_type}").ok();
+        writeln!(self.text, "# HELP {name} {help}").ok();
+    }
+
+    // Adds the common labels to `extra_labels`
+    fn sample(&mut self, name: &str, extra_labels: &str, value: impl ToString) {
+        let labels = match (self.labels.is_empty(), extra_labels.is_empty()) {
+            (true, true) => String::new(),
+            (false, true) => format!("{{{}}}", self.labels),
+            (true, false) => format!("{{{extra_labels}}}"),
+            (false, false) => format!("{{{},{extra_labels}}}", self.labels),
+        };
+        writeln!(self.text, "{name}{labels} {}", value.to_string()).ok();
+    }
+
+    fn gauge(&mut self, name: &str, help: &str, value: impl ToString) {
+        self.family(name, "gauge", help);
+        self.sample(name, "", value);
+    }
+
+    // `name` is without the _total suffix
+    fn counter(&mut self, name: &str, help: &str, value: impl ToString) {
+        self.family(name, "counter", help);
+        self.sample(&format!("{name}_total"), "", value);
+    }
+}
+
+pub fn openmetrics_text() -> String {
+    let metrics = METRICS.lock();
+
+    let mut writer = MetricsWriter {
+        text: String::new(),
+        labels: metrics
+            .client_hostname
+            .as_ref()
+            .map(|hostname| format!("hostname=\"{}\"", escape_label_value(hostname)))
+            .unwrap_or_default(),
+    };
+
+    writer.gauge(
+        "alvr_client_connected",
+        "Whether a client is streaming",
+        metrics.client_connected as u8,
+    );
+    writer.counter(
+        "alvr_client_connections",
+        "Streaming sessions started since the server started",
+        metrics.connections_count,
+    );
+
+    // The microphone is played by the server, the game audio by the client
+    let mut audio_buffer_counts = vec![(
+        "microphone",
+        alvr_audio::BUFFER_OVERFLOW_COUNT.load(Ordering::Relaxed) as u64,
+        alvr_audio::BUFFER_UNDERFLOW_COUNT.load(Ordering::Relaxed) as u64,
+    )];
+    if let (true, Some((overflows, underflows))) = (
+        metrics.client_connected,
+        metrics.maybe_client_audio_buffer_counts,
+    ) {
+        audio_buffer_counts.push(("game_audio", overflows, underflows));
+    }
+    writer.family(
+        "alvr_audio_buffer_overflows",
+        "counter",
+        "Times the audio buffer has been trimmed because it grew too big",
+    );
+    for (stream, overflows, _) in &audio_buffer_counts {
+        writer.sample(
+            "alvr_audio_buffer_overflows_total",
+            &format!("stream=\"{stream}\""),
+            overflows,
+        );
+    }
+    writer.family(
+        "alvr_audio_buffer_underflows",
+        "counter",
+        "Times the audio buffer ran out of samples during playback",
+    );
+    for (stream, _, underflows) in &audio_buffer_counts {
+        writer.sample(
+            "alvr_audio_buffer_underflows_total",
+            &format!("stream=\"{stream}\""),
+            underflows,
+        );
+    }
+
+    if let (true, Some(statistics)) = (metrics.client_connected, &metrics.maybe_statistics) {
+        writer.counter(
+            "alvr_packets_sent",
+            "Video packets sent in this session",
+            statistics.total_packets,
+        );
+        writer.counter(
+            "alvr_packets_lost",
+            "Video packets lost in this session",
+            statistics.packets_lost_total,
+        );
+        writer.counter(
+            "alvr_sent_megabytes",
+            "Megabytes sent in this session",
+            statistics.total_sent,
+        );
+        writer.gauge(
+            "alvr_packet_rate",
+            "Video packets sent per second",
+            statistics.packet_rate,
+        );
+        writer.gauge(
+            "alvr_packets_lost_rate",
+            "Video packets lost per second",
+            statistics.packets_lost_per_second,
+        );
+        writer.gauge(
+            "alvr_sent_rate_mbps",
+            "Sent megabits per second",
+            statistics.sent_rate,
+        );
+        writer.gauge(
+            "alvr_bitrate_mbps",
+            "Target video bitrate in megabits per second",
+            statistics.bitrate,
+        );
+        writer.gauge("alvr_ping_ms", "Round trip time", statistics.ping);
+        writer.gauge(
+            "alvr_total_latency_ms",
+            "Motion to photon latency",
+            statistics.total_latency,
+        );
+        writer.gauge(
+            "alvr_encode_latency_ms",
+            "Video encoding latency",
+            statistics.encode_latency,
+        );
+        writer.gauge(
+            "alvr_send_latency_ms",
+            "Video transport latency",
+            statistics.send_latency,
+        );
+        writer.gauge(
+            "alvr_decode_latency_ms",
+            "Video decoding latency",
+            statistics.decode_latency,
+        );
+        writer.gauge(
+            "alvr_fec_percentage",
+            "Forward error correction percentage",
+            statistics.fec_percentage,
+        );
+        writer.counter(
+            "alvr_fec_failures",
+            "Frames that forward error correction could not recover in this session",
+            statistics.fec_failure_total,
+        );
+        writer.gauge(
+            "alvr_client_fps",
+            "Frames per second decoded by the client",
+            statistics.client_f_p_s,
+        );
+        writer.gauge(
+            "alvr_server_fps",
+            "Frames per second encoded by the server",
+            statistics.server_f_p_s,
+        );
+
+        writer.family("alvr_battery_percent", "gauge", "Battery charge");
+        for (device, value) in [
+            ("hmd", statistics.battery_h_m_d),
+            ("left_controller", statistics.battery_left),
+            ("right_controller", statistics.battery_right),
+        ] {
+            writer.sample(
+                "alvr_battery_percent",
+                &format!("device=\"{device}\""),
+                value,
+            );
+        }
+    }
+
+    writer.text + "# EOF\n"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn label_escaping() {
+        let mut writer = MetricsWriter {
+            text: String::new(),
+            labels: format!("hostname=\"{}\"", escape_label_value("a\"b\\c")),
+        };
+        writer.counter("alvr_test", "Test", 3);
+
+        assert_eq!(
+            writer.text,
+            "# TYPE alvr_test counter\n# HELP alvr_test Test\nalvr_test_total{hostname=\"a\\\"b\\\\c\"} 3\n"
+        );
+    }
+
+    #[test]
+    fn openmetrics_structure() {
+        set_client_hostname(Some("client".into()));
+        record_event(&ServerEvent::ClientConnected);
+        set_client_audio_buffer_counts(1, 2);
+
+        let text = openmetrics_text();
+        assert!(text.ends_with("\n# EOF\n"));
+        assert!(text.contains(
+            "alvr_audio_buffer_underflows_total{hostname=\"client\",stream=\"game_audio\"} 2\n"
+        ));
+
+        // Metric types by family name
+        let mut families = HashMap::new();
+        let mut last_type = None;
+        for line in text.lines().filter(|line| *line != "# EOF") {
+            if let Some(declaration) = line.strip_prefix("# TYPE ") {
+                let (name, metric_type) = declaration.split_once(' ').unwrap();
+                assert!(families.insert(name, metric_type).is_none(), "{line}");
+                last_type = Some(name);
+            } else if let Some(help) = line.strip_prefix("# HELP ") {
+                // The help text follows the type
+                assert_eq!(help.split_once(' ').map(|(name, _)| name), last_type);
+                last_type = None;
+            } else {
+                let name = line.split(['{', ' ']).next().unwrap();
+                let family = name.strip_suffix("_total").unwrap_or(name);
+                match families.get(family) {
+                    Some(&"counter") => assert!(name.ends_with("_total"), "{line}"),
+                    Some(_) => assert_eq!(name, family, "{line}"),
+                    None => panic!("Sample before the family declaration: {line}"),
+                }
+            }
+        }
+
+        record_event(&ServerEvent::ClientDisconnected);
+        assert!(!openmetrics_text().contains("game_audio"));
+    }
+}
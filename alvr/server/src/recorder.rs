@@ -0,0 +1,142 @@
+// Opt-in recording of the streaming statistics, see alvr_session::RecordingEntry. A new recording is
+// started for each client connection, following the settings of the client. The recording of a
+// session is split by size in part files named <session>_<part>, where the session name is the start
+// date.
+
+use crate::FILESYSTEM_LAYOUT;
+use alvr_common::{lazy_static, prelude::*};
+use alvr_session::{EventRecord, RecordingData, RecordingEntry, Settings, RECORDING_EXTENSION};
+use parking_lot::Mutex;
+use settings_schema::Switch;
+use std::{
+    collections::BTreeMap,
+    fs::{self, File, OpenOptions},
+    io::Write,
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+struct Recording {
+    file: File,
+    session_name: String,
+    part: u32,
+    size: u64,
+    max_size: u64,
+}
+
+lazy_static! {
+    static ref RECORDING: Mutex<Option<Recording>> = Mutex::new(None);
+}
+
+// Deletes the oldest sessions to make room for a new one. The parts of a session are kept or deleted
+// together.
+fn prune_sessions(max_recordings: usize) -> StrResult {
+    let recordings_dir = FILESYSTEM_LAYOUT.recordings_dir();
+    trace_err!(fs::create_dir_all(&recordings_dir))?;
+
+    // Session names start with the date, so they are sorted from the oldest
+    let mut sessions = BTreeMap::<String, Vec<PathBuf>>::new();
+    for path in trace_err!(fs::read_dir(&recordings_dir))?
+        .filter_map(|maybe_entry| maybe_entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.extension()
+                .map_or(false, |extension| extension == RECORDING_EXTENSION)
+        })
+    {
+        let maybe_session_name = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .and_then(|stem| stem.rsplit_once('_'))
+            .map(|(session_name, _)| session_name.to_owned());
+        if let Some(session_name) = maybe_session_name {
+            sessions.entry(session_name).or_default().push(path);
+        }
+    }
+
+    let remove_count = (sessions.len() + 1).saturating_sub(max_recordings.max(1));
+    for path in sessions.values().take(remove_count).flatten() {
+        fs::remove_file(path).ok();
+    }
+
+    Ok(())
+}
+
+// Parts are numbered from 1, padded so that they are sorted by name
+fn create_part_file(session_name: &str, part: u32) -> StrResult<File> {
+    let file_name = format!("{session_name}_{part:03}.{RECORDING_EXTENSION}");
+    trace_err!(OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(FILESYSTEM_LAYOUT.recordings_dir().join(file_name)))
+}
+
+// The previous recording is closed
+pub fn start_recording(settings: &Settings) {
+    let mut recording = RECORDING.lock();
+    *recording = None;
+
+    if let Switch::Enabled(desc) = &settings.extra.session_recording {
+        // With milliseconds, sessions started in the same second do not share their files
+        let session_name = chrono::Local::now()
+            .format("%Y-%m-%d_%H-%M-%S_%3f")
+            .to_string();
+        let res = prune_sessions(desc.max_recordings as usize)
+            .and_then(|_| create_part_file(&session_name, 1));
+        match res {
+            Ok(file) => {
+                *recording = Some(Recording {
+                    file,
+                    session_name,
+                    part: 1,
+                    size: 0,
+                    max_size: desc.max_recording_size_mb * 1024 * 1024,
+                })
+            }
+            Err(e) => warn!("Session not recorded: {e}"),
+        }
+    }
+}
+
+fn write_entry(entry: RecordingEntry) {
+    let mut maybe_recording = RECORDING.lock();
+    if let Some(recording) = &mut *maybe_recording {
+        if let Ok(line) = serde_json::to_string(&entry) {
+            // Lines are written whole, a recording can still be read if SteamVR crashes
+            writeln!(recording.file, "{line}").ok();
+            recording.size += line.len() as u64 + 1;
+        }
+
+        if recording.size >= recording.max_size {
+            match create_part_file(&recording.session_name, recording.part + 1) {
+                Ok(file) => {
+                    recording.file = file;
+                    recording.part += 1;
+                    recording.size = 0;
+                }
+                Err(e) => {
+                    warn!("Session recording stopped: {e}");
+                    *maybe_recording = None;
+                }
+            }
+        }
+    }
+}
+
+pub fn record(data: RecordingData) {
+    write_entry(RecordingEntry {
+        timestamp_ms: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_millis() as u64)
+            .unwrap_or_default(),
+        data,
+    })
+}
+
+pub fn record_event(record: &EventRecord) {
+    if alvr_session::is_recorded_event(&record.event) {
+        write_entry(RecordingEntry {
+            timestamp_ms: record.timestamp_ms,
+            data: RecordingData::Event(record.event.clone()),
+        })
+    }
+}
@@ -0,0 +1,327 @@
+// Handlers of the versioned API. Routes and bodies are described in alvr_session::api. Errors are
+// returned as ApiError JSON bodies.
+
+use crate::{
+    graphics_info,
+    web_server::{self, StoreSessionError},
+    ClientListAction, FILESYSTEM_LAYOUT, SESSION_MANAGER,
+};
+use alvr_common::{prelude::*, ALVR_VERSION};
+use alvr_session::{
+    AddClientRequest, ApiEndpoint, ApiError, AudioDevices, ClientHostnameRequest, ClientIpRequest,
+    ClientProfileRequest, DriverPath, FirewallRulesRequest, PatchFormat, RouteError, UrlRequest,
+    VersionInfo,
+};
+use hyper::{
+    header::{CONTENT_TYPE, ETAG},
+    Body, Request, Response, StatusCode,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json as json;
+use settings_schema::ValidationError;
+use std::{env::consts::OS, path::PathBuf};
+
+type ApiResult<T = Response<Body>> = Result<T, (StatusCode, ApiError)>;
+
+fn error(status: StatusCode, message: impl Into<String>) -> (StatusCode, ApiError) {
+    (
+        status,
+        ApiError {
+            message: message.into(),
+            invalid_settings: None,
+        },
+    )
+}
+
+fn invalid_settings(errors: Vec<ValidationError>) -> (StatusCode, ApiError) {
+    (
+        StatusCode::BAD_REQUEST,
+        ApiError {
+            message: "Invalid settings".into(),
+            invalid_settings: Some(errors),
+        },
+    )
+}
+
+// Failures of the server, not caused by the request
+fn internal_error(e: impl ToString) -> (StatusCode, ApiError) {
+    error(StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+}
+
+fn respond(status: StatusCode) -> ApiResult {
+    Response::builder()
+        .status(status)
+        .body(Body::empty())
+        .map_err(internal_error)
+}
+
+fn respond_json<T: Serialize>(value: &T) -> ApiResult {
+    Response::builder()
+        .header(CONTENT_TYPE, "application/json")
+        .body(json::to_string(value).map_err(internal_error)?.into())
+        .map_err(internal_error)
+}
+
+async fn text_body(request: Request<Body>) -> ApiResult<String> {
+    let bytes = hyper::body::to_bytes(request.into_body())
+        .await
+        .map_err(|e| error(StatusCode::BAD_REQUEST, e.to_string()))?;
+
+    String::from_utf8(bytes.to_vec()).map_err(|e| error(StatusCode::BAD_REQUEST, e.to_string()))
+}
+
+async fn json_body<T: DeserializeOwned>(request: Request<Body>) -> ApiResult<T> {
+    json::from_str(&text_body(request).await?)
+        .map_err(|e| error(StatusCode::BAD_REQUEST, format!("Invalid body: {e}")))
+}
+
+fn format_parameter(request: &Request<Body>) -> ApiResult<PatchFormat> {
+    let maybe_format = request.uri().query().and_then(|query| {
+        query
+            .split('&')
+            .find_map(|pair| pair.strip_prefix("format="))
+    });
+
+    match maybe_format {
+        None | Some("json") => Ok(PatchFormat::Json),
+        Some("toml") => Ok(PatchFormat::Toml),
+        Some(other) => Err(error(
+            StatusCode::BAD_REQUEST,
+            format!("Unknown format \"{other}\""),
+        )),
+    }
+}
+
+fn store_session(session_json: &json::Value, maybe_revision: Option<u64>) -> ApiResult {
+    match web_server::try_store_session(session_json, maybe_revision) {
+        Ok(revision) => Response::builder()
+            .status(StatusCode::NO_CONTENT)
+            .header(ETAG, format!("\"{revision}\""))
+            .body(Body::empty())
+            .map_err(internal_error),
+        Err(StoreSessionError::InvalidSettings(errors)) => Err(invalid_settings(errors)),
+        Err(StoreSessionError::RevisionMismatch) => Err(error(
+            StatusCode::PRECONDITION_FAILED,
+            "The session changed in the meantime",
+        )),
+    }
+}
+
+async fn handle(endpoint: ApiEndpoint, request: Request<Body>) -> ApiResult {
+    match endpoint {
+        ApiEndpoint::GetVersion => respond_json(&VersionInfo {
+            version: ALVR_VERSION.to_string(),
+            os: OS.into(),
+        }),
+        ApiEndpoint::GetOpenapi => respond_json(&alvr_session::openapi_v1()),
+        ApiEndpoint::GetSettingsSchema => respond_json(&alvr_session::settings_schema(
+            alvr_session::session_settings_default(),
+        )),
+        ApiEndpoint::GetSession => {
+            let session_manager = SESSION_MANAGER.lock();
+
+            Response::builder()
+                .header(CONTENT_TYPE, "application/json")
+                .header(ETAG, format!("\"{}\"", session_manager.revision()))
+                .body(
+                    json::to_string(session_manager.get())
+                        .map_err(internal_error)?
+                        .into(),
+                )
+                .map_err(internal_error)
+        }
+        ApiEndpoint::PutSession => {
+            let maybe_revision = web_server::if_match_revision(&request);
+            let session_json = json_body::<json::Value>(request).await?;
+
+            store_session(&session_json, maybe_revision)
+        }
+        ApiEndpoint::PutSessionSettings => {
+            let maybe_revision = web_server::if_match_revision(&request);
+            let session_settings_json = json_body::<json::Value>(request).await?;
+
+            store_session(
+                &json::json!({ "sessionSettings": session_settings_json }),
+                maybe_revision,
+            )
+        }
+        ApiEndpoint::PostSessionDiff => {
+            let other_session_json = json_body::<json::Value>(request).await?;
+
+            let session_manager = SESSION_MANAGER.lock();
+            let mut other_session = session_manager.get().clone();
+            other_session
+                .merge_json(&other_session_json)
+                .map_err(|e| error(StatusCode::BAD_REQUEST, e))?;
+
+            respond_json(&alvr_session::diff_sessions(
+                session_manager.get(),
+                &other_session,
+            ))
+        }
+        ApiEndpoint::GetSettingsExport => {
+            let format = format_parameter(&request)?;
+            let patch = alvr_session::export_settings_patch(
+                &SESSION_MANAGER.lock().get().session_settings,
+                format,
+            )
+            .map_err(internal_error)?;
+            let content_type = match format {
+                PatchFormat::Json => "application/json",
+                PatchFormat::Toml => "application/toml",
+            };
+
+            Response::builder()
+                .header(CONTENT_TYPE, content_type)
+                .body(patch.into())
+                .map_err(internal_error)
+        }
+        ApiEndpoint::PostSettingsImport => {
+            let format = format_parameter(&request)?;
+            let patch = alvr_session::parse_settings_patch(&text_body(request).await?, format)
+                .map_err(|e| error(StatusCode::BAD_REQUEST, e))?;
+
+            SESSION_MANAGER
+                .lock()
+                .get_mut()
+                .import_settings_patch(&patch)
+                .map_err(invalid_settings)?;
+
+            respond(StatusCode::NO_CONTENT)
+        }
+        // Without SteamVR there are no drivers
+        ApiEndpoint::GetDrivers => respond_json(
+            &alvr_commands::get_registered_drivers()
+                .unwrap_or_default()
+                .iter()
+                .map(|path| path.to_string_lossy().into_owned())
+                .collect::<Vec<_>>(),
+        ),
+        ApiEndpoint::PostDriverRegister => {
+            alvr_commands::driver_registration(
+                &[FILESYSTEM_LAYOUT.openvr_driver_root_dir.clone()],
+                true,
+            )
+            .map_err(internal_error)?;
+
+            respond(StatusCode::NO_CONTENT)
+        }
+        ApiEndpoint::PostDriverUnregister => {
+            let DriverPath { path } = json_body(request).await?;
+            alvr_commands::driver_registration(&[PathBuf::from(path)], false)
+                .map_err(internal_error)?;
+
+            respond(StatusCode::NO_CONTENT)
+        }
+        ApiEndpoint::PostFirewallRules => {
+            let FirewallRulesRequest { add } = json_body(request).await?;
+            alvr_commands::firewall_rules(add).map_err(|code| {
+                internal_error(format!("Setting firewall rules failed: code {code}"))
+            })?;
+
+            respond(StatusCode::NO_CONTENT)
+        }
+        ApiEndpoint::GetAudioDevices => {
+            let linux_backend = SESSION_MANAGER
+                .lock()
+                .get()
+                .to_settings()
+                .audio
+                .linux_backend;
+            let devices = alvr_audio::get_devices_list(linux_backend).map_err(internal_error)?;
+
+            respond_json(&AudioDevices {
+                output: devices.output,
+                input: devices.input,
+            })
+        }
+        ApiEndpoint::GetGraphicsDevices => respond_json(&graphics_info::get_gpu_names()),
+        ApiEndpoint::PostSteamvrRestart => {
+            crate::notify_restart_driver();
+
+            respond(StatusCode::ACCEPTED)
+        }
+        ApiEndpoint::PostClient => {
+            let AddClientRequest {
+                display_name,
+                hostname,
+                ip,
+            } = json_body(request).await?;
+            crate::update_client_list(
+                hostname.clone(),
+                ClientListAction::AddIfMissing { display_name },
+            );
+            crate::update_client_list(hostname, ClientListAction::TrustAndMaybeAddIp(Some(ip)));
+
+            respond(StatusCode::NO_CONTENT)
+        }
+        ApiEndpoint::PostClientTrust => {
+            let ClientIpRequest { hostname, ip } = json_body(request).await?;
+            crate::update_client_list(hostname, ClientListAction::TrustAndMaybeAddIp(ip));
+
+            respond(StatusCode::NO_CONTENT)
+        }
+        ApiEndpoint::PostClientRemove => {
+            let ClientIpRequest { hostname, ip } = json_body(request).await?;
+            crate::update_client_list(hostname, ClientListAction::RemoveIpOrEntry(ip));
+
+            respond(StatusCode::NO_CONTENT)
+        }
+        ApiEndpoint::PostClientForgetCertificate => {
+            let ClientHostnameRequest { hostname } = json_body(request).await?;
+            crate::update_client_list(hostname, ClientListAction::ForgetCertificate);
+
+            respond(StatusCode::NO_CONTENT)
+        }
+        ApiEndpoint::PutClientProfile => {
+            let ClientProfileRequest { hostname, profile } = json_body(request).await?;
+            crate::check_settings_profile(&profile).map_err(invalid_settings)?;
+            crate::update_client_list(hostname, ClientListAction::SetProfile(profile));
+
+            respond(StatusCode::NO_CONTENT)
+        }
+        ApiEndpoint::PostOpen => {
+            let UrlRequest { url } = json_body(request).await?;
+            webbrowser::open(&url).map_err(internal_error)?;
+
+            respond(StatusCode::NO_CONTENT)
+        }
+        ApiEndpoint::PostUpdate => {
+            let UrlRequest { url } = json_body(request).await?;
+
+            // The request does not wait for the download, which is followed with events
+            tokio::spawn(async move {
+                if let Err(e) = web_server::download_update(&url).await {
+                    error!("{e}");
+                }
+            });
+
+            respond(StatusCode::ACCEPTED)
+        }
+    }
+}
+
+pub async fn api_v1(request: Request<Body>) -> StrResult<Response<Body>> {
+    let result = match alvr_session::route_api_v1(request.method().as_str(), request.uri().path()) {
+        Ok(endpoint) => handle(endpoint, request).await,
+        Err(RouteError::NotFound) => Err(error(StatusCode::NOT_FOUND, "Unknown endpoint")),
+        Err(RouteError::MethodNotAllowed) => Err(error(
+            StatusCode::METHOD_NOT_ALLOWED,
+            "Method not allowed for this endpoint",
+        )),
+    };
+
+    match result {
+        Ok(response) => Ok(response),
+        Err((status, api_error)) => {
+            if status == StatusCode::INTERNAL_SERVER_ERROR {
+                error!("{}", api_error.message);
+            }
+
+            trace_err!(Response::builder()
+                .status(status)
+                .header(CONTENT_TYPE, "application/json")
+                .body(trace_err!(json::to_string(&api_error))?.into()))
+        }
+    }
+}
@@ -1,22 +1,137 @@
-use crate::{graphics_info, ClientListAction, FILESYSTEM_LAYOUT, SESSION_MANAGER};
+use crate::{
+    graphics_info, metrics, web_api, ClientListAction, FILESYSTEM_LAYOUT, SESSION_MANAGER,
+    WEB_SERVER_TOKEN,
+};
 use alvr_common::{prelude::*, ALVR_VERSION};
-use alvr_session::ServerEvent;
+use alvr_session::{EventFilter, EventRecord, PatchFormat, ServerEvent, API_V1_PREFIX};
 use bytes::Buf;
 use futures::SinkExt;
 use headers::HeaderMapExt;
 use hyper::{
-    header::{self, HeaderValue, ACCESS_CONTROL_ALLOW_ORIGIN, CACHE_CONTROL, CONTENT_TYPE},
-    service, Body, Request, Response, StatusCode,
+    header::{
+        self, HeaderValue, AUTHORIZATION, CACHE_CONTROL, CONTENT_TYPE, ETAG, HOST, IF_MATCH, ORIGIN,
+    },
+    server::conn::AddrStream,
+    service, Body, Method, Request, Response, StatusCode,
 };
 use serde::{de::DeserializeOwned, Serialize};
 use serde_json as json;
-use std::{env::consts::OS, fs, io::Write, net::SocketAddr, path::PathBuf};
+use settings_schema::ValidationError;
+use std::{
+    env::consts::OS,
+    fs,
+    io::Write,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    path::PathBuf,
+};
 use tokio::sync::broadcast::{self, error::RecvError};
 use tokio_tungstenite::{tungstenite::protocol, WebSocketStream};
 use tokio_util::codec::{BytesCodec, FramedRead};
 
 pub const WS_BROADCAST_CAPACITY: usize = 256;
 
+// Endpoints that change the server state or run programs. They always need the access token
+const PROTECTED_PATHS: &[&str] = &[
+    "/api/session/store-settings",
+    "/api/session/store",
+    "/api/settings/import/json",
+    "/api/settings/import/toml",
+    "/api/driver/register",
+    "/api/driver/unregister",
+    "/api/firewall-rules/add",
+    "/api/firewall-rules/remove",
+    "/restart-steamvr",
+    "/api/client/add",
+    "/api/client/trust",
+    "/api/client/remove",
+    "/api/client/forget-certificate",
+    "/api/client/profile",
+    "/api/open",
+    "/api/update",
+];
+
+// The token is kept across restarts, so that remote dashboards stay authorized
+pub fn load_or_create_token() -> String {
+    let token_path = FILESYSTEM_LAYOUT.web_server_token();
+
+    if let Ok(token) = fs::read_to_string(&token_path) {
+        let token = token.trim();
+        if !token.is_empty() {
+            return token.to_owned();
+        }
+    }
+
+    let token = (0..32)
+        .map(|_| format!("{:02x}", rand::random::<u8>()))
+        .collect::<String>();
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    if let Err(e) = options
+        .open(&token_path)
+        .and_then(|mut file| file.write_all(token.as_bytes()))
+    {
+        warn!("Failed to save web server token: {e}");
+    }
+
+    token
+}
+
+// Constant time comparison, so that the token cannot be guessed from the response times
+fn is_valid_token(token: &str) -> bool {
+    let expected = WEB_SERVER_TOKEN.as_bytes();
+    token.len() == expected.len()
+        && token
+            .bytes()
+            .zip(expected)
+            .fold(0, |acc, (a, b)| acc | (a ^ b))
+            == 0
+}
+
+// The token is sent as bearer token, or as query parameter for websockets
+fn has_valid_token(request: &Request<Body>) -> bool {
+    let header_token = request
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    let query_token = request.uri().query().and_then(|query| {
+        query
+            .split('&')
+            .find_map(|pair| pair.strip_prefix("token="))
+    });
+
+    header_token
+        .or(query_token)
+        .map(is_valid_token)
+        .unwrap_or(false)
+}
+
+// Requests must address the server by IP or as localhost, which defeats DNS rebinding, and must not
+// come from pages of other origins
+fn is_same_origin(request: &Request<Body>) -> bool {
+    let host = match request.headers().get(HOST).and_then(|h| h.to_str().ok()) {
+        Some(host) => host,
+        None => return false,
+    };
+
+    let hostname = match host.rsplit_once(':') {
+        Some((hostname, port)) if port.parse::<u16>().is_ok() => hostname,
+        _ => host,
+    };
+    let hostname = hostname.trim_start_matches('[').trim_end_matches(']');
+    if hostname != "localhost" && hostname.parse::<IpAddr>().is_err() {
+        return false;
+    }
+
+    match request.headers().get(ORIGIN) {
+        Some(origin) => origin.to_str().ok() == Some(format!("http://{host}").as_str()),
+        None => true,
+    }
+}
+
 fn reply(code: StatusCode) -> StrResult<Response<Body>> {
     trace_err!(Response::builder().status(code).body(Body::empty()))
 }
@@ -27,36 +142,156 @@ fn reply_json<T: Serialize>(obj: &T) -> StrResult<Response<Body>> {
         .body(trace_err!(json::to_string(obj))?.into()))
 }
 
+// The body lists the invalid settings, addressed by path
+fn reply_validation_errors(errors: &[ValidationError]) -> StrResult<Response<Body>> {
+    trace_err!(Response::builder()
+        .status(StatusCode::BAD_REQUEST)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(trace_err!(json::to_string(errors))?.into()))
+}
+
 async fn from_request_body<T: DeserializeOwned>(request: Request<Body>) -> StrResult<T> {
     trace_err!(json::from_reader(
         trace_err!(hyper::body::aggregate(request).await)?.reader()
     ))
 }
 
-async fn text_websocket(
+async fn text_from_request_body(request: Request<Body>) -> StrResult<String> {
+    trace_err!(String::from_utf8(
+        trace_err!(hyper::body::to_bytes(request).await)?.to_vec()
+    ))
+}
+
+fn patch_format(uri: &str) -> PatchFormat {
+    if uri.ends_with("toml") {
+        PatchFormat::Toml
+    } else {
+        PatchFormat::Json
+    }
+}
+
+// The session revision is used as entity tag, so that clients holding a copy of the session can
+// store it only if it has not changed in the meantime
+pub fn if_match_revision(request: &Request<Body>) -> Option<u64> {
+    request
+        .headers()
+        .get(IF_MATCH)?
+        .to_str()
+        .ok()?
+        .trim_matches('"')
+        .parse()
+        .ok()
+}
+
+pub enum StoreSessionError {
+    InvalidSettings(Vec<ValidationError>),
+    // The session changed since the revision of the request
+    RevisionMismatch,
+}
+
+// The session is changed only if every value of the request can be applied and the merged
+// settings are valid. Validating the merged settings instead of the request lets partial sessions
+// and sessions of older versions through migration. Returns the new revision.
+pub fn try_store_session(
+    session_json: &json::Value,
+    maybe_revision: Option<u64>,
+) -> Result<u64, StoreSessionError> {
+    let mut session_manager = SESSION_MANAGER.lock();
+
+    let mut session = session_manager.get().clone();
+    session
+        .merge_from_request_json(session_json)
+        .map_err(StoreSessionError::InvalidSettings)?;
+
+    if let Some(revision) = maybe_revision {
+        session_manager
+            .compare_and_swap(revision, session)
+            .map_err(|_| StoreSessionError::RevisionMismatch)
+    } else {
+        *session_manager.get_mut() = session;
+        Ok(session_manager.revision())
+    }
+}
+
+fn store_session(
+    session_json: &json::Value,
+    maybe_revision: Option<u64>,
+) -> StrResult<Response<Body>> {
+    match try_store_session(session_json, maybe_revision) {
+        Ok(revision) => trace_err!(Response::builder()
+            .header(ETAG, format!("\"{revision}\""))
+            .body(Body::empty())),
+        Err(StoreSessionError::InvalidSettings(errors)) => reply_validation_errors(&errors),
+        Err(StoreSessionError::RevisionMismatch) => reply(StatusCode::PRECONDITION_FAILED),
+    }
+}
+
+// Progress and errors are reported with events
+pub async fn download_update(url: &str) -> StrResult {
+    let redirection_response = trace_err!(reqwest::get(url).await)?;
+    let mut resource_response = trace_err!(reqwest::get(redirection_response.url().clone()).await)?;
+
+    let mut file = trace_err!(fs::File::create(alvr_filesystem::installer_path()))?;
+
+    let mut downloaded_bytes_count = 0;
+    loop {
+        match resource_response.chunk().await {
+            Ok(Some(chunk)) => {
+                downloaded_bytes_count += chunk.len();
+                trace_err!(file.write_all(&chunk))?;
+                alvr_session::publish_event(ServerEvent::UpdateDownloadedBytesCount(
+                    downloaded_bytes_count,
+                ));
+            }
+            Ok(None) => break,
+            Err(e) => {
+                alvr_session::publish_event(ServerEvent::UpdateDownloadError);
+                return fmt_e!("Download update failed: {e}");
+            }
+        }
+    }
+
+    crate::notify_application_update();
+
+    Ok(())
+}
+
+// The initial lines are sent first, then the items of the receiver. Items mapped to None are skipped
+async fn text_websocket<T: Clone + Send + 'static>(
     request: Request<Body>,
-    sender: broadcast::Sender<String>,
+    mut receiver: broadcast::Receiver<T>,
+    initial_lines: Vec<String>,
+    mut to_line: impl FnMut(T) -> Option<String> + Send + 'static,
 ) -> StrResult<Response<Body>> {
     if let Some(key) = request.headers().typed_get::<headers::SecWebsocketKey>() {
         tokio::spawn(async move {
             match hyper::upgrade::on(request).await {
                 Ok(upgraded) => {
-                    let mut log_receiver = sender.subscribe();
-
                     let mut ws =
                         WebSocketStream::from_raw_socket(upgraded, protocol::Role::Server, None)
                             .await;
 
+                    for line in initial_lines {
+                        if let Err(e) = ws.send(protocol::Message::text(line)).await {
+                            info!("Failed to send with websocket: {e}");
+                            return;
+                        }
+                    }
+
                     loop {
-                        match log_receiver.recv().await {
-                            Ok(line) => {
+                        match receiver.recv().await {
+                            Ok(item) => {
+                                let line = match to_line(item) {
+                                    Some(line) => line,
+                                    None => continue,
+                                };
                                 if let Err(e) = ws.send(protocol::Message::text(line)).await {
-                                    info!("Failed to send log with websocket: {e}");
+                                    info!("Failed to send with websocket: {e}");
                                     break;
                                 }
                             }
                             Err(RecvError::Lagged(_)) => {
-                                warn!("Some log lines have been lost because the buffer is full");
+                                warn!("Some lines have been lost because the buffer is full");
                             }
                             Err(RecvError::Closed) => break,
                         }
@@ -85,51 +320,189 @@ async fn text_websocket(
 
 async fn http_api(
     request: Request<Body>,
+    remote_addr: SocketAddr,
     log_sender: broadcast::Sender<String>,
-    events_sender: broadcast::Sender<String>,
+    events_sender: broadcast::Sender<EventRecord>,
 ) -> StrResult<Response<Body>> {
-    let mut response = match request.uri().path() {
+    let path = request.uri().path();
+    let is_local_page = remote_addr.ip().is_loopback() && is_same_origin(&request);
+
+    // Versioned endpoints are protected unless they only read
+    let is_protected = PROTECTED_PATHS.contains(&path)
+        || (path.starts_with(API_V1_PREFIX) && request.method() != Method::GET);
+
+    // Without token, only pages served to this machine can use the API, and only to read
+    if (path.starts_with("/api/") || path == "/metrics" || is_protected)
+        && !has_valid_token(&request)
+        && (!is_local_page || is_protected)
+    {
+        return reply(StatusCode::UNAUTHORIZED);
+    }
+
+    let mut response = match path {
+        // Lets the local dashboard authorize itself. Pages of other origins cannot read it
+        "/api/auth/token" => {
+            if is_local_page {
+                Response::new(WEB_SERVER_TOKEN.clone().into())
+            } else {
+                reply(StatusCode::FORBIDDEN)?
+            }
+        }
+        _ if path.starts_with(&format!("{API_V1_PREFIX}/")) => web_api::api_v1(request).await?,
+        "/metrics" => trace_err!(Response::builder()
+            .header(CONTENT_TYPE, metrics::OPENMETRICS_CONTENT_TYPE)
+            .body(metrics::openmetrics_text().into()))?,
+        // Endpoints used by the current dashboard. New clients should use the versioned API
         "/api/settings-schema" => reply_json(&alvr_session::settings_schema(
             alvr_session::session_settings_default(),
         ))?,
-        "/api/session/load" => reply_json(SESSION_MANAGER.lock().get())?,
+        "/api/session/load" => {
+            let session_manager = SESSION_MANAGER.lock();
+
+            let mut response = reply_json(session_manager.get())?;
+            response.headers_mut().insert(
+                ETAG,
+                trace_err!(HeaderValue::from_str(&format!(
+                    "\"{}\"",
+                    session_manager.revision()
+                )))?,
+            );
+
+            response
+        }
         "/api/session/store-settings" => {
+            let maybe_revision = if_match_revision(&request);
             if let Ok(session_settings) = from_request_body::<json::Value>(request).await {
-                let res = SESSION_MANAGER
-                    .lock()
-                    .get_mut()
-                    .merge_from_json(&json::json!({ "session_settings": session_settings }));
-                if let Err(e) = res {
-                    warn!("{e}");
-                    // HTTP Code: WARNING
-                    reply(trace_err!(StatusCode::from_u16(199))?)?
-                } else {
-                    reply(StatusCode::OK)?
-                }
+                store_session(
+                    &json::json!({ "sessionSettings": session_settings }),
+                    maybe_revision,
+                )?
             } else {
                 reply(StatusCode::BAD_REQUEST)?
             }
         }
         "/api/session/store" => {
+            let maybe_revision = if_match_revision(&request);
             if let Ok(data) = from_request_body::<json::Value>(request).await {
                 if let Some(value) = data.get("session") {
-                    let res = SESSION_MANAGER.lock().get_mut().merge_from_json(value);
-                    if let Err(e) = res {
-                        warn!("{e}");
-                        // HTTP Code: WARNING
-                        reply(trace_err!(StatusCode::from_u16(199))?)?
+                    store_session(value, maybe_revision)?
+                } else {
+                    reply(StatusCode::BAD_REQUEST)?
+                }
+            } else {
+                reply(StatusCode::BAD_REQUEST)?
+            }
+        }
+        uri @ ("/api/settings/export/json" | "/api/settings/export/toml") => {
+            let format = patch_format(uri);
+            let patch = alvr_session::export_settings_patch(
+                &SESSION_MANAGER.lock().get().session_settings,
+                format,
+            )?;
+            let content_type = match format {
+                PatchFormat::Json => "application/json",
+                PatchFormat::Toml => "application/toml",
+            };
+
+            trace_err!(Response::builder()
+                .header(CONTENT_TYPE, content_type)
+                .body(patch.into()))?
+        }
+        uri @ ("/api/settings/import/json" | "/api/settings/import/toml") => {
+            let format = patch_format(uri);
+            let res = text_from_request_body(request)
+                .await
+                .and_then(|text| alvr_session::parse_settings_patch(&text, format));
+
+            match res {
+                Ok(patch) => {
+                    let res = SESSION_MANAGER
+                        .lock()
+                        .get_mut()
+                        .import_settings_patch(&patch);
+                    if let Err(errors) = res {
+                        warn!("Settings import failed: invalid settings");
+                        reply_validation_errors(&errors)?
                     } else {
                         reply(StatusCode::OK)?
                     }
-                } else {
-                    reply(StatusCode::BAD_REQUEST)?
                 }
+                Err(e) => {
+                    warn!("Settings import failed: {e}");
+                    trace_err!(Response::builder()
+                        .status(StatusCode::BAD_REQUEST)
+                        .body(e.into()))?
+                }
+            }
+        }
+        // Compares the current session with the one in the body, which can be partial or from an
+        // older version
+        "/api/session/diff" => {
+            if let Ok(other_session_json) = from_request_body::<json::Value>(request).await {
+                let mut other_session = SESSION_MANAGER.lock().get().clone();
+                other_session.merge_json(&other_session_json)?;
+
+                let changes =
+                    alvr_session::diff_sessions(SESSION_MANAGER.lock().get(), &other_session)
+                        .iter()
+                        .map(|change| change.to_string())
+                        .collect::<Vec<_>>();
+
+                reply_json(&changes)?
             } else {
                 reply(StatusCode::BAD_REQUEST)?
             }
         }
-        "/api/log" => text_websocket(request, log_sender).await?,
-        "/api/events" => text_websocket(request, events_sender).await?,
+        "/api/log" => text_websocket(request, log_sender.subscribe(), vec![], Some).await?,
+        // Clients that reconnect pass the epoch and the sequence number of the last event they
+        // received
+        "/api/events" => {
+            let mut filter = EventFilter::from_query(request.uri().query().unwrap_or_default());
+
+            // Subscribing before reading the history makes sure that no event is missed
+            let receiver = events_sender.subscribe();
+            let mut initial_lines = vec![];
+            let mut last_sequence = 0;
+            if let Some(after) = filter.after {
+                let replay = alvr_session::events_after(filter.epoch, after);
+                filter.after = Some(replay.after);
+
+                initial_lines.extend(
+                    replay
+                        .missed
+                        .iter()
+                        .chain(
+                            replay
+                                .records
+                                .iter()
+                                .filter(|record| filter.matches(record)),
+                        )
+                        .map(|record| json::to_string(record).unwrap()),
+                );
+
+                // Events found in the history can be received again
+                last_sequence = replay
+                    .records
+                    .last()
+                    .map(|record| record.sequence)
+                    .unwrap_or(replay.after);
+            }
+
+            text_websocket(
+                request,
+                receiver,
+                initial_lines,
+                move |record: EventRecord| {
+                    if record.sequence > last_sequence && filter.matches(&record) {
+                        last_sequence = record.sequence;
+                        Some(json::to_string(&record).unwrap())
+                    } else {
+                        None
+                    }
+                },
+            )
+            .await?
+        }
         "/api/driver/register" => {
             if alvr_commands::driver_registration(
                 &[FILESYSTEM_LAYOUT.openvr_driver_root_dir.clone()],
@@ -208,6 +581,28 @@ async fn http_api(
                 reply(StatusCode::BAD_REQUEST)?
             }
         }
+        "/api/client/forget-certificate" => {
+            if let Ok(hostname) = from_request_body::<String>(request).await {
+                crate::update_client_list(hostname, ClientListAction::ForgetCertificate);
+                reply(StatusCode::OK)?
+            } else {
+                reply(StatusCode::BAD_REQUEST)?
+            }
+        }
+        "/api/client/profile" => {
+            match from_request_body::<(String, Option<String>)>(request).await {
+                Ok((hostname, maybe_profile))
+                    if crate::check_settings_profile(&maybe_profile).is_ok() =>
+                {
+                    crate::update_client_list(
+                        hostname,
+                        ClientListAction::SetProfile(maybe_profile),
+                    );
+                    reply(StatusCode::OK)?
+                }
+                _ => reply(StatusCode::BAD_REQUEST)?,
+            }
+        }
         "/api/version" => Response::new(ALVR_VERSION.to_string().into()),
         "/api/open" => {
             if let Ok(url) = from_request_body::<String>(request).await {
@@ -220,32 +615,10 @@ async fn http_api(
         "/api/server-os" => Response::new(OS.into()),
         "/api/update" => {
             if let Ok(url) = from_request_body::<String>(request).await {
-                let redirection_response = trace_err!(reqwest::get(&url).await)?;
-                let mut resource_response =
-                    trace_err!(reqwest::get(redirection_response.url().clone()).await)?;
-
-                let mut file = trace_err!(fs::File::create(alvr_filesystem::installer_path()))?;
-
-                let mut downloaded_bytes_count = 0;
-                loop {
-                    match resource_response.chunk().await {
-                        Ok(Some(chunk)) => {
-                            downloaded_bytes_count += chunk.len();
-                            trace_err!(file.write_all(&chunk))?;
-                            alvr_session::log_event(ServerEvent::UpdateDownloadedBytesCount(
-                                downloaded_bytes_count,
-                            ));
-                        }
-                        Ok(None) => break,
-                        Err(e) => {
-                            alvr_session::log_event(ServerEvent::UpdateDownloadError);
-                            error!("Download update failed: {e}");
-                            return reply(StatusCode::BAD_GATEWAY);
-                        }
-                    }
+                if let Err(e) = download_update(&url).await {
+                    error!("{e}");
+                    return reply(StatusCode::BAD_GATEWAY);
                 }
-
-                crate::notify_application_update();
             }
             reply(StatusCode::BAD_REQUEST)?
         }
@@ -284,25 +657,25 @@ async fn http_api(
         CACHE_CONTROL,
         trace_err!(HeaderValue::from_str("no-cache, no-store, must-revalidate"))?,
     );
-    response
-        .headers_mut()
-        .insert(ACCESS_CONTROL_ALLOW_ORIGIN, HeaderValue::from_static("*"));
 
     Ok(response)
 }
 
 pub async fn web_server(
     log_sender: broadcast::Sender<String>,
-    events_sender: broadcast::Sender<String>,
+    events_sender: broadcast::Sender<EventRecord>,
 ) -> StrResult {
-    let web_server_port = SESSION_MANAGER
-        .lock()
-        .get()
-        .to_settings()
-        .connection
-        .web_server_port;
-
-    let service = service::make_service_fn(|_| {
+    let connection_settings = SESSION_MANAGER.lock().get().to_settings().connection;
+
+    // Other devices can connect only if LAN access is enabled
+    let bind_ip = if connection_settings.web_server_lan_access {
+        Ipv4Addr::UNSPECIFIED
+    } else {
+        Ipv4Addr::LOCALHOST
+    };
+
+    let service = service::make_service_fn(|conn: &AddrStream| {
+        let remote_addr = conn.remote_addr();
         let log_sender = log_sender.clone();
         let events_sender = events_sender.clone();
         async move {
@@ -310,7 +683,7 @@ pub async fn web_server(
                 let log_sender = log_sender.clone();
                 let events_sender = events_sender.clone();
                 async move {
-                    let res = http_api(request, log_sender, events_sender).await;
+                    let res = http_api(request, remote_addr, log_sender, events_sender).await;
                     if let Err(e) = &res {
                         alvr_common::show_e(e);
                     }
@@ -323,8 +696,8 @@ pub async fn web_server(
 
     trace_err!(
         hyper::Server::bind(&SocketAddr::new(
-            "0.0.0.0".parse().unwrap(),
-            web_server_port
+            bind_ip.into(),
+            connection_settings.web_server_port
         ))
         .serve(service)
         .await
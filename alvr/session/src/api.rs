@@ -0,0 +1,671 @@
+// Versioned web API of the server. Routes and bodies are described by the items of this module,
+// which are also used to generate the OpenAPI document, so that the two cannot diverge. Within a
+// version, changes must be backward compatible.
+
+use crate::SessionChange;
+use alvr_common::ALVR_VERSION;
+use serde::{Deserialize, Serialize};
+use serde_json::{self as json, json};
+use settings_schema::ValidationError;
+use std::net::IpAddr;
+
+pub const API_V1_PREFIX: &str = "/api/v1";
+
+pub trait ApiSchema {
+    // Inline schema, or reference to a component schema
+    fn api_schema() -> json::Value;
+
+    // Optional fields are not required and can be null
+    fn is_optional() -> bool {
+        false
+    }
+}
+
+// Named types, described once in the components of the OpenAPI document
+pub trait ApiComponent {
+    const NAME: &'static str;
+
+    fn api_definition() -> json::Value;
+}
+
+impl<T: ApiComponent> ApiSchema for T {
+    fn api_schema() -> json::Value {
+        json!({ "$ref": format!("#/components/schemas/{}", T::NAME) })
+    }
+}
+
+impl ApiSchema for String {
+    fn api_schema() -> json::Value {
+        json!({ "type": "string" })
+    }
+}
+
+impl ApiSchema for bool {
+    fn api_schema() -> json::Value {
+        json!({ "type": "boolean" })
+    }
+}
+
+impl ApiSchema for IpAddr {
+    fn api_schema() -> json::Value {
+        json!({ "type": "string", "description": "IPv4 or IPv6 address" })
+    }
+}
+
+// Any JSON value
+impl ApiSchema for json::Value {
+    fn api_schema() -> json::Value {
+        json!({})
+    }
+}
+
+impl<T: ApiSchema> ApiSchema for Option<T> {
+    fn api_schema() -> json::Value {
+        json!({ "anyOf": [T::api_schema(), { "type": "null" }] })
+    }
+
+    fn is_optional() -> bool {
+        true
+    }
+}
+
+impl<T: ApiSchema> ApiSchema for Vec<T> {
+    fn api_schema() -> json::Value {
+        json!({ "type": "array", "items": T::api_schema() })
+    }
+}
+
+fn camel_case(name: &str) -> String {
+    let mut parts = name.split('_');
+    let first = parts.next().unwrap_or_default().to_owned();
+
+    parts.fold(first, |mut text, part| {
+        let mut chars = part.chars();
+        if let Some(first_char) = chars.next() {
+            text.extend(first_char.to_uppercase());
+            text.push_str(chars.as_str());
+        }
+
+        text
+    })
+}
+
+// Each field is (name, schema, is optional)
+fn object_definition(description: &str, fields: Vec<(String, json::Value, bool)>) -> json::Value {
+    let required = fields
+        .iter()
+        .filter(|(_, _, is_optional)| !is_optional)
+        .map(|(name, ..)| name.clone())
+        .collect::<Vec<_>>();
+    let properties = fields
+        .into_iter()
+        .map(|(name, schema, _)| (name, schema))
+        .collect::<json::Map<_, _>>();
+
+    json!({
+        "type": "object",
+        "description": description,
+        "properties": properties,
+        "required": required
+    })
+}
+
+// Defines structs with camelCase JSON fields and their component schemas. The doc comment of each
+// struct becomes the description of the schema.
+macro_rules! api_types {
+    ($(
+        $(#[doc = $doc:literal])+
+        $name:ident {
+            $($(#[$attr:meta])* $field:ident: $ty:ty,)*
+        }
+    )*) => {$(
+        $(#[doc = $doc])+
+        #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+        #[serde(rename_all = "camelCase")]
+        pub struct $name {
+            $($(#[$attr])* pub $field: $ty,)*
+        }
+
+        impl ApiComponent for $name {
+            const NAME: &'static str = stringify!($name);
+
+            fn api_definition() -> json::Value {
+                object_definition(
+                    &[$($doc.trim()),+].join(" "),
+                    vec![$((
+                        camel_case(stringify!($field)),
+                        <$ty as ApiSchema>::api_schema(),
+                        <$ty as ApiSchema>::is_optional(),
+                    )),*],
+                )
+            }
+        }
+    )*};
+}
+
+api_types! {
+    /// Body of the error responses
+    ApiError {
+        message: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        invalid_settings: Option<Vec<ValidationError>>,
+    }
+
+    /// Version and operating system of the server
+    VersionInfo {
+        version: String,
+        os: String,
+    }
+
+    /// Names of the audio devices of the server
+    AudioDevices {
+        output: Vec<String>,
+        input: Vec<String>,
+    }
+
+    /// Root directory of an OpenVR driver
+    DriverPath {
+        path: String,
+    }
+
+    /// Adds or removes the firewall rules needed for streaming
+    FirewallRulesRequest {
+        add: bool,
+    }
+
+    /// Adds a client if missing, then trusts it
+    AddClientRequest {
+        display_name: String,
+        hostname: String,
+        ip: IpAddr,
+    }
+
+    /// Without IP the request applies to the whole client, otherwise only the IP is added or
+    /// removed
+    ClientIpRequest {
+        hostname: String,
+        ip: Option<IpAddr>,
+    }
+
+    /// Identifies a client
+    ClientHostnameRequest {
+        hostname: String,
+    }
+
+    /// Sets or clears the settings profile of a client
+    ClientProfileRequest {
+        hostname: String,
+        profile: Option<String>,
+    }
+
+    /// URL to open in the browser of the server, or to download an update from
+    UrlRequest {
+        url: String,
+    }
+}
+
+impl ApiComponent for ValidationError {
+    const NAME: &'static str = "ValidationError";
+
+    fn api_definition() -> json::Value {
+        object_definition(
+            "Invalid setting, addressed by path",
+            vec![
+                ("path".into(), String::api_schema(), false),
+                ("message".into(), String::api_schema(), false),
+            ],
+        )
+    }
+}
+
+impl ApiComponent for SessionChange {
+    const NAME: &'static str = "SessionChange";
+
+    fn api_definition() -> json::Value {
+        object_definition(
+            "Value that differs between two sessions. Values are null if added or removed",
+            vec![
+                ("path".into(), String::api_schema(), false),
+                ("oldValue".into(), json::Value::api_schema(), true),
+                ("newValue".into(), json::Value::api_schema(), true),
+            ],
+        )
+    }
+}
+
+fn component_schemas() -> json::Map<String, json::Value> {
+    macro_rules! components {
+        ($($ty:ty),*) => {
+            [$((<$ty>::NAME.to_owned(), <$ty>::api_definition())),*]
+                .into_iter()
+                .collect()
+        };
+    }
+
+    components!(
+        ApiError,
+        ValidationError,
+        VersionInfo,
+        AudioDevices,
+        DriverPath,
+        FirewallRulesRequest,
+        AddClientRequest,
+        ClientIpRequest,
+        ClientHostnameRequest,
+        ClientProfileRequest,
+        UrlRequest,
+        SessionChange
+    )
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ApiEndpoint {
+    GetVersion,
+    GetOpenapi,
+    GetSettingsSchema,
+    GetSession,
+    PutSession,
+    PutSessionSettings,
+    PostSessionDiff,
+    GetSettingsExport,
+    PostSettingsImport,
+    GetDrivers,
+    PostDriverRegister,
+    PostDriverUnregister,
+    PostFirewallRules,
+    GetAudioDevices,
+    GetGraphicsDevices,
+    PostSteamvrRestart,
+    PostClient,
+    PostClientTrust,
+    PostClientRemove,
+    PostClientForgetCertificate,
+    PutClientProfile,
+    PostOpen,
+    PostUpdate,
+}
+
+struct EndpointDoc {
+    method: &'static str,
+    // Relative to API_V1_PREFIX
+    path: &'static str,
+    summary: &'static str,
+    parameters: Vec<json::Value>,
+    // OpenAPI content objects
+    request_content: Option<json::Value>,
+    response_status: u16,
+    response_content: Option<json::Value>,
+}
+
+fn json_content(schema: json::Value) -> Option<json::Value> {
+    Some(json!({ "application/json": { "schema": schema } }))
+}
+
+fn patch_content() -> Option<json::Value> {
+    Some(json!({
+        "application/json": { "schema": {} },
+        "application/toml": { "schema": { "type": "string" } }
+    }))
+}
+
+fn format_parameter() -> json::Value {
+    json!({
+        "name": "format",
+        "in": "query",
+        "description": "Format of the settings patch",
+        "schema": { "enum": ["json", "toml"], "default": "json" }
+    })
+}
+
+fn if_match_parameter() -> json::Value {
+    json!({
+        "name": "If-Match",
+        "in": "header",
+        "description": "Revision returned in the ETag header. If the session changed since then, \
+            the request fails with 412",
+        "schema": { "type": "string" }
+    })
+}
+
+impl ApiEndpoint {
+    pub const ALL: [ApiEndpoint; 23] = [
+        ApiEndpoint::GetVersion,
+        ApiEndpoint::GetOpenapi,
+        ApiEndpoint::GetSettingsSchema,
+        ApiEndpoint::GetSession,
+        ApiEndpoint::PutSession,
+        ApiEndpoint::PutSessionSettings,
+        ApiEndpoint::PostSessionDiff,
+        ApiEndpoint::GetSettingsExport,
+        ApiEndpoint::PostSettingsImport,
+        ApiEndpoint::GetDrivers,
+        ApiEndpoint::PostDriverRegister,
+        ApiEndpoint::PostDriverUnregister,
+        ApiEndpoint::PostFirewallRules,
+        ApiEndpoint::GetAudioDevices,
+        ApiEndpoint::GetGraphicsDevices,
+        ApiEndpoint::PostSteamvrRestart,
+        ApiEndpoint::PostClient,
+        ApiEndpoint::PostClientTrust,
+        ApiEndpoint::PostClientRemove,
+        ApiEndpoint::PostClientForgetCertificate,
+        ApiEndpoint::PutClientProfile,
+        ApiEndpoint::PostOpen,
+        ApiEndpoint::PostUpdate,
+    ];
+
+    fn doc(self) -> EndpointDoc {
+        let (method, path, summary) = match self {
+            ApiEndpoint::GetVersion => ("GET", "/version", "Version of the server"),
+            ApiEndpoint::GetOpenapi => ("GET", "/openapi.json", "This document"),
+            ApiEndpoint::GetSettingsSchema => (
+                "GET",
+                "/settings/schema",
+                "Schema of the session settings, used to build the settings UI",
+            ),
+            ApiEndpoint::GetSession => (
+                "GET",
+                "/session",
+                "Current session. Its revision is returned in the ETag header",
+            ),
+            ApiEndpoint::PutSession => (
+                "PUT",
+                "/session",
+                "Stores a session, which can be partial or from an older version",
+            ),
+            ApiEndpoint::PutSessionSettings => (
+                "PUT",
+                "/session/settings",
+                "Stores the session settings, which can be partial",
+            ),
+            ApiEndpoint::PostSessionDiff => (
+                "POST",
+                "/session/diff",
+                "Changes from the current session to the one in the body",
+            ),
+            ApiEndpoint::GetSettingsExport => (
+                "GET",
+                "/settings/export",
+                "Settings that differ from the defaults",
+            ),
+            ApiEndpoint::PostSettingsImport => (
+                "POST",
+                "/settings/import",
+                "Replaces the settings with the defaults plus the patch in the body",
+            ),
+            ApiEndpoint::GetDrivers => ("GET", "/drivers", "Registered OpenVR drivers"),
+            ApiEndpoint::PostDriverRegister => (
+                "POST",
+                "/drivers/register",
+                "Registers the driver of this installation",
+            ),
+            ApiEndpoint::PostDriverUnregister => (
+                "POST",
+                "/drivers/unregister",
+                "Unregisters an OpenVR driver",
+            ),
+            ApiEndpoint::PostFirewallRules => ("POST", "/firewall-rules", "Sets firewall rules"),
+            ApiEndpoint::GetAudioDevices => ("GET", "/audio-devices", "Audio devices"),
+            ApiEndpoint::GetGraphicsDevices => ("GET", "/graphics-devices", "Names of the GPUs"),
+            ApiEndpoint::PostSteamvrRestart => ("POST", "/steamvr/restart", "Restarts SteamVR"),
+            ApiEndpoint::PostClient => ("POST", "/clients", "Adds a client"),
+            ApiEndpoint::PostClientTrust => ("POST", "/clients/trust", "Trusts a client"),
+            ApiEndpoint::PostClientRemove => ("POST", "/clients/remove", "Removes a client"),
+            ApiEndpoint::PostClientForgetCertificate => (
+                "POST",
+                "/clients/forget-certificate",
+                "Forgets the pinned certificate of a client, which is pinned again on the next connection",
+            ),
+            ApiEndpoint::PutClientProfile => ("PUT", "/clients/profile", "Sets a client profile"),
+            ApiEndpoint::PostOpen => ("POST", "/open", "Opens a URL in the browser of the server"),
+            ApiEndpoint::PostUpdate => (
+                "POST",
+                "/update",
+                "Downloads and installs an update. The progress is reported with events",
+            ),
+        };
+
+        let mut doc = EndpointDoc {
+            method,
+            path,
+            summary,
+            parameters: vec![],
+            request_content: None,
+            response_status: 204,
+            response_content: None,
+        };
+
+        match self {
+            ApiEndpoint::GetVersion => {
+                doc.response_status = 200;
+                doc.response_content = json_content(VersionInfo::api_schema());
+            }
+            ApiEndpoint::GetOpenapi | ApiEndpoint::GetSettingsSchema | ApiEndpoint::GetSession => {
+                doc.response_status = 200;
+                doc.response_content = json_content(json::Value::api_schema());
+            }
+            ApiEndpoint::PutSession | ApiEndpoint::PutSessionSettings => {
+                doc.parameters = vec![if_match_parameter()];
+                doc.request_content = json_content(json::Value::api_schema());
+            }
+            ApiEndpoint::PostSessionDiff => {
+                doc.request_content = json_content(json::Value::api_schema());
+                doc.response_status = 200;
+                doc.response_content = json_content(Vec::<SessionChange>::api_schema());
+            }
+            ApiEndpoint::GetSettingsExport => {
+                doc.parameters = vec![format_parameter()];
+                doc.response_status = 200;
+                doc.response_content = patch_content();
+            }
+            ApiEndpoint::PostSettingsImport => {
+                doc.parameters = vec![format_parameter()];
+                doc.request_content = patch_content();
+            }
+            ApiEndpoint::GetDrivers | ApiEndpoint::GetGraphicsDevices => {
+                doc.response_status = 200;
+                doc.response_content = json_content(Vec::<String>::api_schema());
+            }
+            ApiEndpoint::PostDriverUnregister => {
+                doc.request_content = json_content(DriverPath::api_schema());
+            }
+            ApiEndpoint::PostFirewallRules => {
+                doc.request_content = json_content(FirewallRulesRequest::api_schema());
+            }
+            ApiEndpoint::GetAudioDevices => {
+                doc.response_status = 200;
+                doc.response_content = json_content(AudioDevices::api_schema());
+            }
+            ApiEndpoint::PostSteamvrRestart => doc.response_status = 202,
+            ApiEndpoint::PostClient => {
+                doc.request_content = json_content(AddClientRequest::api_schema());
+            }
+            ApiEndpoint::PostClientTrust | ApiEndpoint::PostClientRemove => {
+                doc.request_content = json_content(ClientIpRequest::api_schema());
+            }
+            ApiEndpoint::PostClientForgetCertificate => {
+                doc.request_content = json_content(ClientHostnameRequest::api_schema());
+            }
+            ApiEndpoint::PutClientProfile => {
+                doc.request_content = json_content(ClientProfileRequest::api_schema());
+            }
+            ApiEndpoint::PostOpen => doc.request_content = json_content(UrlRequest::api_schema()),
+            ApiEndpoint::PostUpdate => {
+                doc.request_content = json_content(UrlRequest::api_schema());
+                doc.response_status = 202;
+            }
+            ApiEndpoint::PostDriverRegister => (),
+        }
+
+        doc
+    }
+
+    pub fn method(self) -> &'static str {
+        self.doc().method
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RouteError {
+    NotFound,
+    MethodNotAllowed,
+}
+
+// `path` includes API_V1_PREFIX
+pub fn route_api_v1(method: &str, path: &str) -> Result<ApiEndpoint, RouteError> {
+    let relative_path = path
+        .strip_prefix(API_V1_PREFIX)
+        .ok_or(RouteError::NotFound)?;
+
+    let mut path_found = false;
+    for endpoint in ApiEndpoint::ALL {
+        let doc = endpoint.doc();
+        if doc.path == relative_path {
+            if doc.method == method {
+                return Ok(endpoint);
+            }
+            path_found = true;
+        }
+    }
+
+    Err(if path_found {
+        RouteError::MethodNotAllowed
+    } else {
+        RouteError::NotFound
+    })
+}
+
+// All endpoints need the access token, except the reads made by the dashboard of the server machine
+pub fn openapi_v1() -> json::Value {
+    let mut paths = json::Map::new();
+    for endpoint in ApiEndpoint::ALL {
+        let doc = endpoint.doc();
+
+        // operationId is the variant name in camelCase
+        let variant_name = format!("{endpoint:?}");
+        let operation_id = variant_name[..1].to_lowercase() + &variant_name[1..];
+
+        let mut success_response = json!({ "description": "Success" });
+        if let Some(content) = doc.response_content {
+            success_response["content"] = content;
+        }
+
+        let mut operation = json!({
+            "operationId": operation_id,
+            "summary": doc.summary,
+            "responses": {
+                doc.response_status.to_string(): success_response,
+                "default": {
+                    "description": "Error",
+                    "content": json_content(ApiError::api_schema())
+                }
+            }
+        });
+        if !doc.parameters.is_empty() {
+            operation["parameters"] = json::Value::Array(doc.parameters);
+        }
+        if let Some(content) = doc.request_content {
+            operation["requestBody"] = json!({ "required": true, "content": content });
+        }
+
+        paths
+            .entry(doc.path)
+            .or_insert_with(|| json!({}))
+            .as_object_mut()
+            .unwrap()
+            .insert(doc.method.to_lowercase(), operation);
+    }
+
+    json!({
+        "openapi": "3.1.0",
+        "info": {
+            "title": "ALVR server API",
+            "version": ALVR_VERSION.to_string()
+        },
+        "servers": [{ "url": API_V1_PREFIX }],
+        "paths": paths,
+        "components": {
+            "schemas": component_schemas(),
+            "securitySchemes": {
+                "accessToken": { "type": "http", "scheme": "bearer" }
+            }
+        },
+        "security": [{ "accessToken": [] }]
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn collect_refs(value: &json::Value, refs: &mut Vec<String>) {
+        match value {
+            json::Value::Object(fields) => {
+                if let Some(json::Value::String(reference)) = fields.get("$ref") {
+                    refs.push(reference.clone());
+                }
+                fields.values().for_each(|value| collect_refs(value, refs));
+            }
+            json::Value::Array(values) => values.iter().for_each(|value| collect_refs(value, refs)),
+            _ => (),
+        }
+    }
+
+    #[test]
+    fn routing() {
+        assert_eq!(
+            route_api_v1("GET", "/api/v1/session"),
+            Ok(ApiEndpoint::GetSession)
+        );
+        assert_eq!(
+            route_api_v1("PUT", "/api/v1/session"),
+            Ok(ApiEndpoint::PutSession)
+        );
+        assert_eq!(
+            route_api_v1("DELETE", "/api/v1/session"),
+            Err(RouteError::MethodNotAllowed)
+        );
+        assert_eq!(
+            route_api_v1("GET", "/api/v1/unknown"),
+            Err(RouteError::NotFound)
+        );
+        assert_eq!(
+            route_api_v1("GET", "/api/session/load"),
+            Err(RouteError::NotFound)
+        );
+    }
+
+    #[test]
+    fn openapi_document() {
+        let document = openapi_v1();
+
+        let operations_count = document["paths"]
+            .as_object()
+            .unwrap()
+            .values()
+            .map(|operations| operations.as_object().unwrap().len())
+            .sum::<usize>();
+        assert_eq!(operations_count, ApiEndpoint::ALL.len());
+
+        let mut refs = vec![];
+        collect_refs(&document, &mut refs);
+        for reference in refs {
+            let name = reference.strip_prefix("#/components/schemas/").unwrap();
+            assert!(document["components"]["schemas"].get(name).is_some());
+        }
+
+        assert_eq!(
+            document["components"]["schemas"]["ClientIpRequest"]["required"],
+            json!(["hostname"])
+        );
+        assert_eq!(
+            json::to_value(AddClientRequest {
+                display_name: "Quest".into(),
+                hostname: "1234.client.alvr".into(),
+                ip: "192.168.1.2".parse().unwrap(),
+            })
+            .unwrap(),
+            json!({ "displayName": "Quest", "hostname": "1234.client.alvr", "ip": "192.168.1.2" })
+        );
+    }
+}
@@ -1,8 +1,16 @@
 // fixme: this module is misplaced. Find a way to resolve the mutual dependency with alvr_session
 
 use crate::SessionDesc;
-use alvr_common::log;
+use alvr_common::lazy_static;
 use serde::{Deserialize, Serialize};
+use std::{
+    collections::VecDeque,
+    sync::{mpsc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+// Events kept for the clients that resume the stream
+const EVENT_HISTORY_CAPACITY: usize = 256;
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub enum EventSeverity {
@@ -22,16 +30,20 @@ pub struct Statistics {
     pub packets_lost_per_second: u64,
     pub total_sent: u64,
     pub sent_rate: f32,
+    pub bitrate: u64,
+    pub ping: f32,
     pub total_latency: f32,
     pub encode_latency: f32,
-    pub encode_latency_max: f32,
-    pub transport_latency: f32,
+    pub send_latency: f32,
     pub decode_latency: f32,
     pub fec_percentage: u32,
     pub fec_failure_total: u64,
     pub fec_failure_in_second: u64,
-    pub client_f_p_s: u32, // the name will be fixed after the old dashboard is removed
-    pub server_f_p_s: u32,
+    pub client_f_p_s: f32, // the name will be fixed after the old dashboard is removed
+    pub server_f_p_s: f32,
+    pub battery_h_m_d: i32,
+    pub battery_left: i32,
+    pub battery_right: i32,
 }
 
 // This struct is temporary, until we switch to the new event system
@@ -42,14 +54,14 @@ pub struct Raw {
     pub content: String,
 }
 
-// Event is serialized as #{ "id": "..." [, "data": ...] }#
-// Pound signs are used to identify start and finish of json
+// Event is serialized as { "id": "..." [, "data": ...] }
 #[derive(Serialize, Deserialize, Clone, Debug)]
 #[serde(tag = "id", content = "data")]
 pub enum ServerEvent {
     Session(Box<SessionDesc>),
     SessionUpdated, // deprecated
-    SessionSettingsExtrapolationFailed,
+    // Paths of the settings that could not be migrated and have been reset
+    SessionSettingsReset(Vec<String>),
     ClientFoundOk,
     ClientFoundInvalid,
     ClientFoundWrongVersion(String),
@@ -58,11 +70,338 @@ pub enum ServerEvent {
     UpdateDownloadedBytesCount(usize),
     UpdateDownloadError,
     Statistics(Statistics),
+    // Latencies and frame rates of the last frame, used for the graphs of the dashboard
+    GraphStatistics(Vec<f32>),
     ServerQuitting,
     Raw(Raw),
     EchoQuery(String),
+    // Sent only to a client that resumes the stream, if some events after the requested sequence
+    // have been discarded from the history. Its sequence is the last discarded one.
+    EventsMissed,
+}
+
+impl ServerEvent {
+    pub fn id(&self) -> &'static str {
+        match self {
+            ServerEvent::Session(_) => "Session",
+            ServerEvent::SessionUpdated => "SessionUpdated",
+            ServerEvent::SessionSettingsReset(_) => "SessionSettingsReset",
+            ServerEvent::ClientFoundOk => "ClientFoundOk",
+            ServerEvent::ClientFoundInvalid => "ClientFoundInvalid",
+            ServerEvent::ClientFoundWrongVersion(_) => "ClientFoundWrongVersion",
+            ServerEvent::ClientConnected => "ClientConnected",
+            ServerEvent::ClientDisconnected => "ClientDisconnected",
+            ServerEvent::UpdateDownloadedBytesCount(_) => "UpdateDownloadedBytesCount",
+            ServerEvent::UpdateDownloadError => "UpdateDownloadError",
+            ServerEvent::Statistics(_) => "Statistics",
+            ServerEvent::GraphStatistics(_) => "GraphStatistics",
+            ServerEvent::ServerQuitting => "ServerQuitting",
+            ServerEvent::Raw(_) => "Raw",
+            ServerEvent::EchoQuery(_) => "EchoQuery",
+            ServerEvent::EventsMissed => "EventsMissed",
+        }
+    }
+
+    // Statistics and log lines are superseded quickly, so they are not kept for resuming clients
+    pub fn is_transient(&self) -> bool {
+        matches!(
+            self,
+            ServerEvent::Statistics(_)
+                | ServerEvent::GraphStatistics(_)
+                | ServerEvent::Raw(_)
+                | ServerEvent::EventsMissed
+        )
+    }
+}
+
+// Serialized as { "epoch": ..., "sequence": ..., "timestampMs": ..., "id": "..." [, "data": ...] }
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct EventRecord {
+    // Identifies the server process. Sequences of different epochs are not comparable. Missing in
+    // the recordings of older versions.
+    #[serde(default)]
+    pub epoch: u64,
+    // Increases by one for each published event, starting from 1
+    pub sequence: u64,
+    // Milliseconds since the UNIX epoch
+    pub timestamp_ms: u64,
+    #[serde(flatten)]
+    pub event: ServerEvent,
+}
+
+struct EventBus {
+    epoch: u64,
+    next_sequence: u64,
+    history: VecDeque<EventRecord>,
+    // Sequence of the last record removed from the full history, 0 if none
+    last_discarded_sequence: u64,
+    subscribers: Vec<mpsc::Sender<EventRecord>>,
+}
+
+fn timestamp_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+lazy_static! {
+    static ref EVENT_BUS: Mutex<EventBus> = Mutex::new(EventBus {
+        // The startup time, unique enough to tell apart the processes seen by a client. In
+        // milliseconds, to be exact as a JavaScript number.
+        epoch: timestamp_ms(),
+        next_sequence: 1,
+        history: VecDeque::new(),
+        last_discarded_sequence: 0,
+        subscribers: vec![],
+    });
+}
+
+// Events do not go through the log, so they can be published from the logger itself
+pub fn publish_event(event: ServerEvent) {
+    let mut bus = EVENT_BUS.lock().unwrap();
+
+    let record = EventRecord {
+        epoch: bus.epoch,
+        sequence: bus.next_sequence,
+        timestamp_ms: timestamp_ms(),
+        event,
+    };
+    bus.next_sequence += 1;
+
+    if !record.event.is_transient() {
+        if bus.history.len() == EVENT_HISTORY_CAPACITY {
+            if let Some(discarded) = bus.history.pop_front() {
+                bus.last_discarded_sequence = discarded.sequence;
+            }
+        }
+        bus.history.push_back(record.clone());
+    }
+
+    bus.subscribers
+        .retain(|subscriber| subscriber.send(record.clone()).is_ok());
+}
+
+// The receiver gets all the events published from now on
+pub fn subscribe_events() -> mpsc::Receiver<EventRecord> {
+    let (sender, receiver) = mpsc::channel();
+    EVENT_BUS.lock().unwrap().subscribers.push(sender);
+
+    receiver
+}
+
+pub struct EventReplay {
+    // The sequence the replay starts after. It is 0 if the requested one was ignored.
+    pub after: u64,
+    // An EventsMissed record, if the oldest events of the replay have been discarded
+    pub missed: Option<EventRecord>,
+    // Non transient events, oldest first
+    pub records: Vec<EventRecord>,
 }
 
-pub fn log_event(id: ServerEvent) {
-    log::info!("#{}#", serde_json::to_string(&id).unwrap());
+// Events published after `sequence`. The sequence is ignored, and the whole history is replayed,
+// if it was received from another server process (`epoch`) or if it has not been published yet.
+pub fn events_after(epoch: Option<u64>, sequence: u64) -> EventReplay {
+    let bus = EVENT_BUS.lock().unwrap();
+
+    let after = if epoch.map(|epoch| epoch != bus.epoch).unwrap_or(false)
+        || sequence >= bus.next_sequence
+    {
+        0
+    } else {
+        sequence
+    };
+
+    EventReplay {
+        after,
+        missed: (after < bus.last_discarded_sequence).then(|| EventRecord {
+            epoch: bus.epoch,
+            sequence: bus.last_discarded_sequence,
+            timestamp_ms: timestamp_ms(),
+            event: ServerEvent::EventsMissed,
+        }),
+        records: bus
+            .history
+            .iter()
+            .filter(|record| record.sequence > after)
+            .cloned()
+            .collect(),
+    }
+}
+
+// Query of the event stream, like `ids=ClientConnected,ClientDisconnected&after=42&epoch=7`.
+// Without ids all events are selected
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct EventFilter {
+    pub ids: Option<Vec<String>>,
+    pub after: Option<u64>,
+    pub epoch: Option<u64>,
+}
+
+impl EventFilter {
+    pub fn from_query(query: &str) -> Self {
+        let mut filter = EventFilter::default();
+        for (name, value) in query.split('&').filter_map(|pair| pair.split_once('=')) {
+            match name {
+                "ids" => {
+                    filter.ids = Some(
+                        value
+                            .split(',')
+                            .filter(|id| !id.is_empty())
+                            .map(|id| id.to_owned())
+                            .collect(),
+                    )
+                }
+                "after" => filter.after = value.parse().ok(),
+                "epoch" => filter.epoch = value.parse().ok(),
+                _ => (),
+            }
+        }
+
+        filter
+    }
+
+    pub fn matches(&self, record: &EventRecord) -> bool {
+        self.after
+            .map(|after| record.sequence > after)
+            .unwrap_or(true)
+            && self
+                .ids
+                .as_ref()
+                .map(|ids| ids.iter().any(|id| id == record.event.id()))
+                .unwrap_or(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json as json;
+
+    #[test]
+    fn publish_and_resume() {
+        let receiver = subscribe_events();
+
+        publish_event(ServerEvent::ClientConnected);
+        publish_event(ServerEvent::GraphStatistics(vec![1.0, 2.0]));
+        publish_event(ServerEvent::ClientDisconnected);
+
+        // Other tests can publish events at the same time
+        let records = receiver.try_iter().collect::<Vec<_>>();
+        let connected = records
+            .iter()
+            .find(|record| matches!(record.event, ServerEvent::ClientConnected))
+            .unwrap();
+        let disconnected = records
+            .iter()
+            .find(|record| matches!(record.event, ServerEvent::ClientDisconnected))
+            .unwrap();
+        assert!(connected.sequence < disconnected.sequence);
+
+        let resumed = events_after(Some(connected.epoch), connected.sequence);
+        assert_eq!(resumed.after, connected.sequence);
+        assert!(resumed
+            .records
+            .iter()
+            .any(|record| record.sequence == disconnected.sequence));
+        assert!(resumed
+            .records
+            .iter()
+            .all(|record| !record.event.is_transient() && record.sequence > connected.sequence));
+
+        // Sequences of another process, or not published yet, are ignored
+        let resumed = events_after(Some(connected.epoch + 1), disconnected.sequence);
+        assert_eq!(resumed.after, 0);
+        assert!(resumed
+            .records
+            .iter()
+            .any(|record| record.sequence == connected.sequence));
+        assert_eq!(events_after(None, u64::MAX).after, 0);
+    }
+
+    #[test]
+    fn missed_events() {
+        let receiver = subscribe_events();
+
+        publish_event(ServerEvent::ClientConnected);
+        let first = receiver.try_iter().next().unwrap();
+        // Other tests can publish events at the same time
+        for _ in 0..=EVENT_HISTORY_CAPACITY {
+            publish_event(ServerEvent::ClientDisconnected);
+        }
+
+        let resumed = events_after(Some(first.epoch), first.sequence - 1);
+        let missed = resumed.missed.unwrap();
+        assert!(matches!(missed.event, ServerEvent::EventsMissed));
+        assert!(missed.sequence >= first.sequence);
+        assert!(resumed
+            .records
+            .iter()
+            .all(|record| record.sequence > missed.sequence));
+
+        let last = receiver.try_iter().last().unwrap();
+        assert!(events_after(Some(last.epoch), last.sequence - 1)
+            .missed
+            .is_none());
+    }
+
+    #[test]
+    fn event_filter() {
+        let filter = EventFilter::from_query("ids=ClientConnected,ClientDisconnected&after=5");
+        assert_eq!(
+            filter,
+            EventFilter {
+                ids: Some(vec!["ClientConnected".into(), "ClientDisconnected".into()]),
+                after: Some(5),
+                epoch: None,
+            }
+        );
+
+        let record = |sequence, event| EventRecord {
+            epoch: 0,
+            sequence,
+            timestamp_ms: 0,
+            event,
+        };
+        assert!(filter.matches(&record(6, ServerEvent::ClientConnected)));
+        assert!(!filter.matches(&record(5, ServerEvent::ClientConnected)));
+        assert!(!filter.matches(&record(6, ServerEvent::ServerQuitting)));
+        assert!(EventFilter::from_query("").matches(&record(1, ServerEvent::ServerQuitting)));
+    }
+
+    #[test]
+    fn record_serialization() {
+        let record = EventRecord {
+            epoch: 7,
+            sequence: 3,
+            timestamp_ms: 1000,
+            event: ServerEvent::UpdateDownloadedBytesCount(10),
+        };
+        let record_json = json::to_value(&record).unwrap();
+        assert_eq!(
+            record_json,
+            json::json!({
+                "epoch": 7,
+                "sequence": 3,
+                "timestampMs": 1000,
+                "id": "UpdateDownloadedBytesCount",
+                "data": 10
+            })
+        );
+        assert_eq!(
+            json::from_value::<EventRecord>(record_json)
+                .unwrap()
+                .event
+                .id(),
+            "UpdateDownloadedBytesCount"
+        );
+
+        for event in [
+            ServerEvent::ClientConnected,
+            ServerEvent::EchoQuery("".into()),
+        ] {
+            assert_eq!(json::to_value(&event).unwrap()["id"], event.id());
+        }
+    }
 }
@@ -1,19 +1,33 @@
+mod api;
 mod events;
+mod manager;
+mod migration;
+mod overrides;
+mod patch;
+mod recording;
+mod reload;
 mod settings;
 
+pub use api::*;
 pub use events::*;
+pub use manager::*;
+pub use migration::{MigrationReport, MigrationStep, MIGRATION_STEPS};
+pub use overrides::*;
+pub use patch::*;
+pub use recording::*;
+pub use reload::*;
 pub use settings::*;
 
 use alvr_common::{prelude::*, semver::Version, ALVR_VERSION};
 use serde::{Deserialize, Serialize};
 use serde_json as json;
-use settings_schema::SchemaNode;
+use settings_schema::{SchemaNode, ValidationError};
 use std::{
     collections::{HashMap, HashSet},
     fs,
+    io::Write,
     net::IpAddr,
-    ops::{Deref, DerefMut},
-    path::{Path, PathBuf},
+    path::Path,
 };
 
 // SessionSettings is similar to Settings but it contains every branch, even unused ones. This is
@@ -24,11 +38,17 @@ pub fn load_session(path: &Path) -> StrResult<SessionDesc> {
     trace_err!(json::from_str(&trace_err!(fs::read_to_string(path))?))
 }
 
+// The session is written to a temporary file first, then renamed over session.json, so a crash
+// cannot leave a partially written session
 pub fn save_session(session_desc: &SessionDesc, path: &Path) -> StrResult {
-    trace_err!(fs::write(
-        path,
-        trace_err!(json::to_string_pretty(session_desc))?
-    ))
+    let temp_path = path.with_extension("json.tmp");
+
+    let mut file = trace_err!(fs::File::create(&temp_path))?;
+    trace_err!(file.write_all(trace_err!(json::to_string_pretty(session_desc))?.as_bytes()))?;
+    trace_err!(file.sync_all())?;
+    drop(file);
+
+    trace_err!(fs::rename(&temp_path, path))
 }
 
 // This structure is used to store the minimum configuration data that ALVR driver needs to
@@ -37,8 +57,8 @@ pub fn save_session(session_desc: &SessionDesc, path: &Path) -> StrResult {
 // instance is equivalent to the one stored in the session, otherwise SteamVR is restarted.
 // Other components (like the encoder, audio recorder) don't need this treatment and are initialized
 // dynamically.
-// todo: properties that can be set after the OpenVR initialization should be removed and set with
-// UpdateForStream.
+// The values that can be set after the OpenVR initialization are excluded from the comparison (see
+// OpenvrConfig::requires_restart()) and are applied to the running driver instead.
 #[derive(Serialize, Deserialize, PartialEq, Default, Clone, Debug)]
 pub struct OpenvrConfig {
     pub universe_id: u64,
@@ -117,12 +137,17 @@ pub struct OpenvrConfig {
     pub linux_async_reprojection: bool,
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct ClientConnectionDesc {
     pub display_name: String,
     pub manual_ips: HashSet<IpAddr>,
     pub trusted: bool,
+    // Certificate presented by the client the first time it connected while trusted
+    pub certificate_pem: Option<String>,
+    // Settings profile applied when this client connects. None uses the base session settings.
+    #[serde(default)]
+    pub profile: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -137,6 +162,10 @@ pub struct SessionDesc {
     // The hashmap key is the hostname
     pub client_connections: HashMap<String, ClientConnectionDesc>,
     pub session_settings: SessionSettings,
+    // Named overlays of session_settings. A profile contains any subset of the session_settings
+    // JSON, the missing values are taken from session_settings.
+    #[serde(default)]
+    pub settings_profiles: HashMap<String, json::Value>,
     pub advanced: bool,
 }
 
@@ -172,179 +201,392 @@ impl Default for SessionDesc {
             },
             client_connections: HashMap::new(),
             session_settings: settings::session_settings_default(),
+            settings_profiles: HashMap::new(),
             advanced: false,
         }
     }
 }
 
 impl SessionDesc {
-    // If json_value is not a valid representation of SessionDesc (because of version upgrade), use
-    // some fuzzy logic to extrapolate as much information as possible.
+    // Merges a full or partial session, possibly written by an older server version. The JSON is
+    // first converted to the current layout by the migration steps. Then each value is kept only if
+    // it matches the current schema, otherwise the current value is kept and the field is reported
+    // as reset.
     // Since SessionDesc cannot have a schema (because SessionSettings would need to also have a
-    // schema, but it is generated out of our control), I only do basic name checking on fields and
-    // deserialization will fail if the type of values does not match. Because of this,
-    // `session_settings` must be handled separately to do a better job of retrieving data using the
-    // settings schema.
-    pub fn merge_from_json(&mut self, json_value: &json::Value) -> StrResult {
+    // schema, but it is generated out of our control), fields other than `session_settings` are
+    // checked by deserialization, one at a time.
+    pub fn merge_from_json(&mut self, json_value: &json::Value) -> StrResult<MigrationReport> {
+        let report = self.merge_json(json_value)?;
+
+        if !report.reset_fields.is_empty() {
+            publish_event(ServerEvent::SessionSettingsReset(
+                report.reset_fields.clone(),
+            ));
+        }
+
+        Ok(report)
+    }
+
+    // Merges a session sent to the server. Unlike merge_from_json(), the values that cannot be
+    // applied are errors and the merged settings must be valid. The session is changed only if
+    // there is no error.
+    pub fn merge_from_request_json(
+        &mut self,
+        json_value: &json::Value,
+    ) -> Result<(), Vec<ValidationError>> {
+        let mut session = self.clone();
+        let report = session.merge_json(json_value).map_err(|message| {
+            vec![ValidationError {
+                path: "".into(),
+                message,
+            }]
+        })?;
+
+        if !report.reset_fields.is_empty() {
+            return Err(report
+                .reset_fields
+                .into_iter()
+                .map(|path| ValidationError {
+                    path,
+                    message: "invalid value".into(),
+                })
+                .collect());
+        }
+
+        let session_settings_json = json::to_value(&session.session_settings).map_err(|e| {
+            vec![ValidationError {
+                path: "sessionSettings".into(),
+                message: e.to_string(),
+            }]
+        })?;
+        validate_session_settings(&session_settings_json)?;
+
+        // Profiles are stored as overlays, only the settings they produce can be validated
+        let profile_errors = session
+            .settings_profiles
+            .keys()
+            .filter_map(|profile_name| session.validate_profile(profile_name).err())
+            .flatten()
+            .collect::<Vec<_>>();
+        if !profile_errors.is_empty() {
+            return Err(profile_errors);
+        }
+
+        *self = session;
+
+        Ok(())
+    }
+
+    // Same as merge_from_json(), without publishing the reset fields. Used for sessions that are
+    // only compared and never stored.
+    pub fn merge_json(&mut self, json_value: &json::Value) -> StrResult<MigrationReport> {
+        const SERVER_VERSION_STR: &str = "serverVersion";
         const SESSION_SETTINGS_STR: &str = "sessionSettings";
 
         if let Ok(session_desc) = json::from_value(json_value.clone()) {
             *self = session_desc;
-            return Ok(());
+            return Ok(MigrationReport::default());
         }
 
-        let old_session_json = trace_err!(json::to_value(&self))?;
-        let old_session_fields = trace_none!(old_session_json.as_object())?;
-
-        let maybe_session_settings_json =
-            json_value
-                .get(SESSION_SETTINGS_STR)
-                .map(|new_session_settings_json| {
-                    extrapolate_session_settings_from_session_settings(
-                        &old_session_json[SESSION_SETTINGS_STR],
-                        new_session_settings_json,
-                        &settings::settings_schema(settings::session_settings_default()),
-                    )
-                });
+        // Partial sessions without version are expected to be in the current layout. They are not
+        // migrated, the steps would add the missing fields and overwrite the current values.
+        let maybe_from_version = json_value
+            .get(SERVER_VERSION_STR)
+            .and_then(|version| version.as_str())
+            .and_then(|version| Version::parse(version).ok());
+
+        let mut migrated_json = json_value.clone();
+        let mut report = MigrationReport {
+            applied_steps: maybe_from_version
+                .map(|from_version| migration::migrate(&mut migrated_json, &from_version))
+                .unwrap_or_default(),
+            reset_fields: vec![],
+        };
 
-        let new_fields = old_session_fields
-            .iter()
-            .map(|(name, json_field_value)| {
-                let new_json_field_value = if name == SESSION_SETTINGS_STR {
-                    json::to_value(settings::session_settings_default()).unwrap()
+        if let Ok(session_desc) = json::from_value::<SessionDesc>(migrated_json.clone()) {
+            *self = SessionDesc {
+                server_version: ALVR_VERSION.clone(),
+                ..session_desc
+            };
+            return Ok(report);
+        }
+
+        let migrated_fields = migrated_json
+            .as_object()
+            .ok_or_else(|| "The session must be a JSON object".to_owned())?;
+
+        let mut session_json = trace_err!(json::to_value(&self))?;
+        let session_fields = trace_none!(session_json.as_object_mut())?;
+
+        for (name, value) in migrated_fields {
+            if name == SERVER_VERSION_STR {
+                // The session is now in the current layout
+                continue;
+            } else if name == SESSION_SETTINGS_STR {
+                let session_settings_json = reconcile_session_settings(
+                    &session_fields[SESSION_SETTINGS_STR],
+                    value,
+                    &settings::settings_schema(settings::session_settings_default()),
+                    SESSION_SETTINGS_STR,
+                    &mut report.reset_fields,
+                );
+
+                // The content of vectors and dictionaries is checked only here
+                if json::from_value::<SessionSettings>(session_settings_json.clone()).is_ok() {
+                    session_fields.insert(name.clone(), session_settings_json);
                 } else {
-                    json_value.get(name).unwrap_or(json_field_value).clone()
-                };
-                (name.clone(), new_json_field_value)
-            })
-            .collect();
-        // Failure to extrapolate other session_desc fields is not notified.
-        let mut session_desc_mut =
-            json::from_value::<SessionDesc>(json::Value::Object(new_fields)).unwrap_or_default();
-
-        match json::from_value::<SessionSettings>(trace_none!(maybe_session_settings_json)?) {
-            Ok(session_settings) => {
-                session_desc_mut.session_settings = session_settings;
-                *self = session_desc_mut;
-                Ok(())
+                    report.reset_fields.push(SESSION_SETTINGS_STR.into());
+                }
+            } else if let Some(previous_value) = session_fields.insert(name.clone(), value.clone())
+            {
+                if json::from_value::<SessionDesc>(json::Value::Object(session_fields.clone()))
+                    .is_err()
+                {
+                    session_fields.insert(name.clone(), previous_value);
+                    report.reset_fields.push(name.clone());
+                }
+            } else {
+                // Not part of the current layout
+                session_fields.remove(name);
+                report.reset_fields.push(name.clone());
             }
-            Err(e) => {
-                *self = session_desc_mut;
+        }
+
+        *self = trace_err!(json::from_value(session_json))?;
+
+        Ok(report)
+    }
+
+    pub fn to_settings(&self) -> Settings {
+        session_settings_to_settings(&self.session_settings)
+    }
 
-                log_event(ServerEvent::SessionSettingsExtrapolationFailed);
-                fmt_e!("Error while deserializing extrapolated session settings: {e}")
+    // Session settings with the profile applied on top. The server-wide settings are kept.
+    pub fn profile_session_settings(&self, profile_name: &str) -> StrResult<SessionSettings> {
+        let profile = self
+            .settings_profiles
+            .get(profile_name)
+            .ok_or_else(|| format!("Settings profile \"{profile_name}\" not found"))?;
+
+        let base_json = trace_err!(json::to_value(&self.session_settings))?;
+        let mut session_settings_json = base_json.clone();
+        overlay_json(&mut session_settings_json, profile);
+        for pointer in SERVER_WIDE_SETTINGS {
+            if let (Some(value), Some(base_value)) = (
+                session_settings_json.pointer_mut(pointer),
+                base_json.pointer(pointer),
+            ) {
+                *value = base_value.clone();
             }
         }
+
+        json::from_value(session_settings_json)
+            .map_err(|e| format!("Invalid settings profile \"{profile_name}\": {e}"))
     }
 
-    // This function requires that settings enums with data have tag = "type" and content = "content", and
-    // enums without data do not have tag and content set.
-    pub fn to_settings(&self) -> Settings {
-        let session_settings_json = json::to_value(&self.session_settings).unwrap();
-        let schema = settings::settings_schema(settings::session_settings_default());
-
-        if let Err(e) = json::from_value::<Settings>(json_session_settings_to_settings(
-            &session_settings_json,
-            &schema,
-        )) {
-            dbg!(e);
+    // Validates the session settings with the profile applied. Paths are relative to the session.
+    pub fn validate_profile(&self, profile_name: &str) -> Result<(), Vec<ValidationError>> {
+        let profile_path = format!("settingsProfiles.{profile_name}");
+
+        let session_settings_json = self
+            .profile_session_settings(profile_name)
+            .and_then(|session_settings| trace_err!(json::to_value(session_settings)))
+            .map_err(|message| {
+                vec![ValidationError {
+                    path: profile_path.clone(),
+                    message,
+                }]
+            })?;
+
+        validate_session_settings(&session_settings_json).map_err(|errors| {
+            errors
+                .into_iter()
+                .map(|error| ValidationError {
+                    path: if error.path.is_empty() {
+                        profile_path.clone()
+                    } else {
+                        format!("{profile_path}.{}", error.path)
+                    },
+                    message: error.message,
+                })
+                .collect()
+        })
+    }
+
+    // Session settings with the profile bound to the client applied on top
+    pub fn client_session_settings(&self, hostname: &str) -> StrResult<SessionSettings> {
+        let maybe_profile_name = self
+            .client_connections
+            .get(hostname)
+            .and_then(|client| client.profile.as_ref());
+
+        if let Some(profile_name) = maybe_profile_name {
+            self.profile_session_settings(profile_name)
+        } else {
+            Ok(self.session_settings.clone())
         }
-        json::from_value(json_session_settings_to_settings(
-            &session_settings_json,
-            &schema,
+    }
+
+    // Effective settings for the client
+    pub fn to_client_settings(&self, hostname: &str) -> StrResult<Settings> {
+        Ok(session_settings_to_settings(
+            &self.client_session_settings(hostname)?,
         ))
-        .unwrap()
     }
 }
 
-// Current data extrapolation strategy: match both field name and value type exactly.
-// Integer bounds are not validated, if they do not match the schema, deserialization will fail and
-// all data is lost.
-// Future strategies: check if value respects schema constraints, fuzzy field name matching, accept
-// integer to float and float to integer, tree traversal.
-fn extrapolate_session_settings_from_session_settings(
+// Checks bounds and steps too, which are not enforced when deserializing SessionSettings
+pub fn validate_session_settings(
+    session_settings_json: &json::Value,
+) -> Result<(), Vec<ValidationError>> {
+    settings_schema::validate(
+        &settings::settings_schema(settings::session_settings_default()),
+        session_settings_json,
+    )
+}
+
+// This function requires that settings enums with data have tag = "type" and content = "content", and
+// enums without data do not have tag and content set.
+pub fn session_settings_to_settings(session_settings: &SessionSettings) -> Settings {
+    let session_settings_json = json::to_value(session_settings).unwrap();
+    let schema = settings::settings_schema(settings::session_settings_default());
+
+    json::from_value(json_session_settings_to_settings(
+        &session_settings_json,
+        &schema,
+    ))
+    .unwrap()
+}
+
+// Settings used by the whole server or before the client is known. Profiles cannot change them, so
+// they can be read from the base settings.
+const SERVER_WIDE_SETTINGS: [&str; 9] = [
+    "/connection/clientDiscovery",
+    "/connection/bindAddress",
+    "/connection/multicastInterfaceIndex",
+    "/connection/discoveryPort",
+    "/connection/controlPort",
+    "/connection/webServerPort",
+    "/connection/webServerLanAccess",
+    "/audio/linuxBackend",
+    "/extra/logToDisk",
+];
+
+// Objects are merged recursively, any other overlay value replaces the base value
+fn overlay_json(base: &mut json::Value, overlay: &json::Value) {
+    match (base, overlay) {
+        (json::Value::Object(base_fields), json::Value::Object(overlay_fields)) => {
+            for (name, overlay_value) in overlay_fields {
+                match base_fields.get_mut(name) {
+                    Some(base_value) => overlay_json(base_value, overlay_value),
+                    None => {
+                        base_fields.insert(name.clone(), overlay_value.clone());
+                    }
+                }
+            }
+        }
+        (base, overlay) => *base = overlay.clone(),
+    }
+}
+
+// Keeps the values of new_session_settings that match the schema, both in name and type. The others
+// are taken from old_session_settings and their path is added to reset_fields.
+// Integer bounds are not validated, and neither is the content of vectors and dictionaries.
+fn reconcile_session_settings(
     old_session_settings: &json::Value,
     new_session_settings: &json::Value,
     schema: &SchemaNode,
+    path: &str,
+    reset_fields: &mut Vec<String>,
 ) -> json::Value {
+    // Reconciles an optional child of new_session_settings
+    let mut reconcile_child = |name: &str, schema: &SchemaNode| {
+        if let Some(new_value_json) = new_session_settings.get(name) {
+            reconcile_session_settings(
+                &old_session_settings[name],
+                new_value_json,
+                schema,
+                &child_path(path, name),
+                reset_fields,
+            )
+        } else {
+            old_session_settings[name].clone()
+        }
+    };
+
     match schema {
-        SchemaNode::Section { entries } => json::Value::Object(
-            entries
+        SchemaNode::Section { entries } => {
+            let fields = entries
                 .iter()
                 .filter_map(|(field_name, maybe_data)| {
                     maybe_data.as_ref().map(|data_schema| {
-                        let value_json =
-                            if let Some(new_value_json) = new_session_settings.get(field_name) {
-                                extrapolate_session_settings_from_session_settings(
-                                    &old_session_settings[field_name],
-                                    new_value_json,
-                                    &data_schema.content,
-                                )
-                            } else {
-                                old_session_settings[field_name].clone()
-                            };
-                        (field_name.clone(), value_json)
+                        (
+                            field_name.clone(),
+                            reconcile_child(field_name, &data_schema.content),
+                        )
                     })
                 })
-                .collect(),
-        ),
+                .collect();
 
-        SchemaNode::Choice { variants, .. } => {
-            let variant_json = new_session_settings
-                .get("variant")
-                .cloned()
-                .filter(|new_variant_json| {
-                    new_variant_json
-                        .as_str()
-                        .map(|variant_str| {
-                            variants
-                                .iter()
-                                .any(|(variant_name, _)| variant_str == variant_name)
-                        })
-                        .is_some()
-                })
-                .unwrap_or_else(|| old_session_settings["variant"].clone());
+            report_unknown_fields(
+                new_session_settings,
+                entries.iter().map(|(name, _)| name.as_str()),
+                path,
+                reset_fields,
+            );
 
+            json::Value::Object(fields)
+        }
+
+        SchemaNode::Choice { variants, .. } => {
             let mut fields: json::Map<_, _> = variants
                 .iter()
                 .filter_map(|(variant_name, maybe_data)| {
                     maybe_data.as_ref().map(|data_schema| {
-                        let value_json =
-                            if let Some(new_value_json) = new_session_settings.get(variant_name) {
-                                extrapolate_session_settings_from_session_settings(
-                                    &old_session_settings[variant_name],
-                                    new_value_json,
-                                    &data_schema.content,
-                                )
-                            } else {
-                                old_session_settings[variant_name].clone()
-                            };
-                        (variant_name.clone(), value_json)
+                        (
+                            variant_name.clone(),
+                            reconcile_child(variant_name, &data_schema.content),
+                        )
                     })
                 })
                 .collect();
+
+            let variant_json = reconcile_value(
+                &old_session_settings["variant"],
+                new_session_settings.get("variant"),
+                |variant_json| {
+                    variant_json
+                        .as_str()
+                        .map(|variant_str| variants.iter().any(|(name, _)| variant_str == name))
+                        .unwrap_or(false)
+                },
+                &child_path(path, "variant"),
+                reset_fields,
+            );
             fields.insert("variant".into(), variant_json);
 
+            report_unknown_fields(
+                new_session_settings,
+                variants
+                    .iter()
+                    .map(|(name, _)| name.as_str())
+                    .chain(["variant"]),
+                path,
+                reset_fields,
+            );
+
             json::Value::Object(fields)
         }
 
         SchemaNode::Optional { content, .. } => {
-            let set_json = new_session_settings
-                .get("set")
-                .cloned()
-                .filter(|new_set_json| new_set_json.is_boolean())
-                .unwrap_or_else(|| old_session_settings["set"].clone());
-
-            let content_json = new_session_settings
-                .get("content")
-                .map(|new_content_json| {
-                    extrapolate_session_settings_from_session_settings(
-                        &old_session_settings["content"],
-                        new_content_json,
-                        content,
-                    )
-                })
-                .unwrap_or_else(|| old_session_settings["content"].clone());
+            let content_json = reconcile_child("content", content);
+            let set_json = reconcile_value(
+                &old_session_settings["set"],
+                new_session_settings.get("set"),
+                json::Value::is_boolean,
+                &child_path(path, "set"),
+                reset_fields,
+            );
 
             json::json!({
                 "set": set_json,
@@ -353,22 +595,14 @@ fn extrapolate_session_settings_from_session_settings(
         }
 
         SchemaNode::Switch { content, .. } => {
-            let enabled_json = new_session_settings
-                .get("enabled")
-                .cloned()
-                .filter(|new_enabled_json| new_enabled_json.is_boolean())
-                .unwrap_or_else(|| old_session_settings["enabled"].clone());
-
-            let content_json = new_session_settings
-                .get("content")
-                .map(|new_content_json| {
-                    extrapolate_session_settings_from_session_settings(
-                        &old_session_settings["content"],
-                        new_content_json,
-                        content,
-                    )
-                })
-                .unwrap_or_else(|| old_session_settings["content"].clone());
+            let content_json = reconcile_child("content", content);
+            let enabled_json = reconcile_value(
+                &old_session_settings["enabled"],
+                new_session_settings.get("enabled"),
+                json::Value::is_boolean,
+                &child_path(path, "enabled"),
+                reset_fields,
+            );
 
             json::json!({
                 "enabled": enabled_json,
@@ -376,68 +610,62 @@ fn extrapolate_session_settings_from_session_settings(
             })
         }
 
-        SchemaNode::Boolean { .. } => {
-            if new_session_settings.is_boolean() {
-                new_session_settings.clone()
-            } else {
-                old_session_settings.clone()
-            }
-        }
+        SchemaNode::Boolean { .. } => reconcile_value(
+            old_session_settings,
+            Some(new_session_settings),
+            json::Value::is_boolean,
+            path,
+            reset_fields,
+        ),
 
-        SchemaNode::Integer { .. } => {
-            if new_session_settings.is_i64() {
-                new_session_settings.clone()
-            } else {
-                old_session_settings.clone()
-            }
-        }
+        SchemaNode::Integer { .. } => reconcile_value(
+            old_session_settings,
+            Some(new_session_settings),
+            |value| value.is_i64() || value.is_u64(),
+            path,
+            reset_fields,
+        ),
 
-        SchemaNode::Float { .. } => {
-            if new_session_settings.is_number() {
-                new_session_settings.clone()
-            } else {
-                old_session_settings.clone()
-            }
-        }
+        SchemaNode::Float { .. } => reconcile_value(
+            old_session_settings,
+            Some(new_session_settings),
+            json::Value::is_number,
+            path,
+            reset_fields,
+        ),
 
-        SchemaNode::Text { .. } => {
-            if new_session_settings.is_string() {
-                new_session_settings.clone()
-            } else {
-                old_session_settings.clone()
-            }
-        }
+        SchemaNode::Text { .. } => reconcile_value(
+            old_session_settings,
+            Some(new_session_settings),
+            json::Value::is_string,
+            path,
+            reset_fields,
+        ),
 
-        SchemaNode::Array(array_schema) => {
-            let array_vec = (0..array_schema.len())
-                .map(|idx| {
-                    new_session_settings
-                        .get(idx)
-                        .cloned()
-                        .unwrap_or_else(|| old_session_settings[idx].clone())
+        SchemaNode::Array(array_schema) => json::Value::Array(
+            array_schema
+                .iter()
+                .enumerate()
+                .map(|(idx, element_schema)| {
+                    if let Some(new_element_json) = new_session_settings.get(idx) {
+                        reconcile_session_settings(
+                            &old_session_settings[idx],
+                            new_element_json,
+                            element_schema,
+                            &child_path(path, &idx.to_string()),
+                            reset_fields,
+                        )
+                    } else {
+                        old_session_settings[idx].clone()
+                    }
                 })
-                .collect();
-            json::Value::Array(array_vec)
-        }
+                .collect(),
+        ),
 
         SchemaNode::Vector {
             default_element, ..
         } => {
-            let element_json = new_session_settings
-                .get("element")
-                .map(|new_element_json| {
-                    extrapolate_session_settings_from_session_settings(
-                        &old_session_settings["element"],
-                        new_element_json,
-                        default_element,
-                    )
-                })
-                .unwrap_or_else(|| old_session_settings["element"].clone());
-
-            // todo: content field cannot be properly validated until I implement plain settings
-            // validation (not to be confused with session/session_settings validation). Any
-            // problem inside this new_session_settings content will result in the loss all data in the new
-            // session_settings.
+            let element_json = reconcile_child("element", default_element);
             let content_json = new_session_settings
                 .get("content")
                 .cloned()
@@ -450,24 +678,14 @@ fn extrapolate_session_settings_from_session_settings(
         }
 
         SchemaNode::Dictionary { default_value, .. } => {
-            let key_json = new_session_settings
-                .get("key")
-                .cloned()
-                .filter(|new_key| new_key.is_string())
-                .unwrap_or_else(|| old_session_settings["key"].clone());
-
-            let value_json = new_session_settings
-                .get("value")
-                .map(|new_value_json| {
-                    extrapolate_session_settings_from_session_settings(
-                        &old_session_settings["value"],
-                        new_value_json,
-                        default_value,
-                    )
-                })
-                .unwrap_or_else(|| old_session_settings["value"].clone());
-
-            // todo: validate content using settings validation
+            let value_json = reconcile_child("value", default_value);
+            let key_json = reconcile_value(
+                &old_session_settings["key"],
+                new_session_settings.get("key"),
+                json::Value::is_string,
+                &child_path(path, "key"),
+                reset_fields,
+            );
             let content_json = new_session_settings
                 .get("content")
                 .cloned()
@@ -482,6 +700,49 @@ fn extrapolate_session_settings_from_session_settings(
     }
 }
 
+// Path of a settings field, made of the JSON keys separated by dots. The root has an empty path.
+fn child_path(path: &str, name: &str) -> String {
+    if path.is_empty() {
+        name.to_owned()
+    } else {
+        format!("{path}.{name}")
+    }
+}
+
+fn reconcile_value(
+    old_value: &json::Value,
+    maybe_new_value: Option<&json::Value>,
+    is_valid: impl Fn(&json::Value) -> bool,
+    path: &str,
+    reset_fields: &mut Vec<String>,
+) -> json::Value {
+    match maybe_new_value {
+        Some(new_value) if is_valid(new_value) => new_value.clone(),
+        Some(_) => {
+            reset_fields.push(path.to_owned());
+            old_value.clone()
+        }
+        None => old_value.clone(),
+    }
+}
+
+fn report_unknown_fields<'a>(
+    session_settings: &json::Value,
+    known_fields: impl Iterator<Item = &'a str> + Clone,
+    path: &str,
+    reset_fields: &mut Vec<String>,
+) {
+    if let Some(fields) = session_settings.as_object() {
+        for name in fields.keys() {
+            if !known_fields.clone().any(|known_name| known_name == name) {
+                reset_fields.push(child_path(path, name));
+            }
+        }
+    } else {
+        reset_fields.push(path.to_owned());
+    }
+}
+
 // session_settings does not get validated here, it must be already valid
 fn json_session_settings_to_settings(
     session_settings: &json::Value,
@@ -572,115 +833,148 @@ fn json_session_settings_to_settings(
     }
 }
 
-// SessionDesc wrapper that saves settings.json and session.json on destruction.
-pub struct SessionLock<'a> {
-    session_desc: &'a mut SessionDesc,
-    session_path: &'a Path,
-}
-
-impl Deref for SessionLock<'_> {
-    type Target = SessionDesc;
-    fn deref(&self) -> &SessionDesc {
-        self.session_desc
-    }
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-impl DerefMut for SessionLock<'_> {
-    fn deref_mut(&mut self) -> &mut SessionDesc {
-        self.session_desc
+    #[test]
+    fn test_session_to_settings() {
+        let _settings = SessionDesc::default().to_settings();
     }
-}
 
-impl Drop for SessionLock<'_> {
-    fn drop(&mut self) {
-        save_session(self.session_desc, self.session_path).unwrap();
-        log_event(ServerEvent::SessionUpdated); // deprecated
-        log_event(ServerEvent::Session(Box::new(self.session_desc.clone())));
+    #[test]
+    fn test_codec_options() {
+        let mut session = SessionDesc::default();
+        session.session_settings.video.codec_options.content = vec![
+            ("low-latency".into(), MediacodecDataType::Int32(1)),
+            ("vendor.name".into(), MediacodecDataType::String("x".into())),
+        ];
+
+        let session_json = json::to_value(&session).unwrap();
+        assert_eq!(
+            session_json["sessionSettings"]["video"]["codecOptions"]["content"][0],
+            json::json!(["low-latency", { "type": "Int32", "content": 1 }])
+        );
+        assert!(validate_session_settings(&session_json["sessionSettings"]).is_ok());
+
+        assert_eq!(
+            session.to_settings().video.codec_options,
+            session.session_settings.video.codec_options.content
+        );
     }
-}
-
-// Correct usage:
-// SessionManager should be used behind a Mutex. Each write of the session should be preceded by a
-// read, within the same lock.
-// fixme: the dashboard is doing this wrong because it is holding its own session state. If read and
-// write need to happen on separate threads, a critical region should be implemented.
-pub struct SessionManager {
-    session_desc: SessionDesc,
-    session_path: PathBuf,
-}
 
-impl SessionManager {
-    pub fn new(session_path: &Path) -> Self {
-        let config_dir = session_path.parent().unwrap();
-        fs::create_dir_all(config_dir).ok();
-
-        let session_desc = match fs::read_to_string(&session_path) {
-            Ok(session_string) => {
-                let json_value = json::from_str::<json::Value>(&session_string).unwrap();
-                match json::from_value(json_value.clone()) {
-                    Ok(session_desc) => session_desc,
-                    Err(_) => {
-                        fs::write(config_dir.join("session_old.json"), &session_string).ok();
-                        let mut session_desc = SessionDesc::default();
-                        match session_desc.merge_from_json(&json_value) {
-                            Ok(_) => info!(
-                                "{} {}",
-                                "Session extrapolated successfully.",
-                                "Old session.json is stored as session_old.json"
-                            ),
-                            Err(e) => error!(
-                                "{} {} {}",
-                                "Error while extrapolating session.",
-                                "Old session.json is stored as session_old.json.",
-                                e
-                            ),
-                        }
-                        // not essential, but useful to avoid duplicated errors
-                        save_session(&session_desc, session_path).ok();
+    #[test]
+    fn test_session_settings_validation() {
+        let mut session_settings_json =
+            json::to_value(SessionDesc::default().session_settings).unwrap();
+        assert!(validate_session_settings(&session_settings_json).is_ok());
 
-                        session_desc
-                    }
-                }
-            }
-            Err(_) => SessionDesc::default(),
-        };
+        session_settings_json["video"]["encodeBitrateMbs"] = json::json!(900);
+        session_settings_json["video"]["preferredFps"] = json::json!("fast");
+        session_settings_json["audio"]["unknownSetting"] = json::json!(1);
 
-        Self {
-            session_desc,
-            session_path: session_path.to_owned(),
-        }
+        let errors = validate_session_settings(&session_settings_json)
+            .unwrap_err()
+            .iter()
+            .map(|error| error.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            errors,
+            [
+                "video.preferredFps: expected number, found \"fast\"",
+                "video.encodeBitrateMbs: 900 > max 500",
+                "audio.unknownSetting: unknown field",
+            ]
+        );
     }
 
-    pub fn get(&self) -> &SessionDesc {
-        &self.session_desc
+    #[test]
+    fn test_client_settings_profile() {
+        let mut session = SessionDesc::default();
+        session.settings_profiles.insert(
+            "quest".into(),
+            json::json!({
+                "video": { "encodeBitrateMbs": 150 },
+                "connection": { "controlPort": 1234 }
+            }),
+        );
+        session.client_connections.insert(
+            "quest.client.alvr".into(),
+            ClientConnectionDesc {
+                display_name: "Quest 2".into(),
+                manual_ips: HashSet::new(),
+                trusted: true,
+                certificate_pem: None,
+                profile: Some("quest".into()),
+            },
+        );
+
+        let settings = session.to_client_settings("quest.client.alvr").unwrap();
+        assert_eq!(settings.video.encode_bitrate_mbs, 150);
+        assert_eq!(
+            settings.video.preferred_fps,
+            session.to_settings().video.preferred_fps
+        );
+        // Server-wide settings are not overridden
+        assert_eq!(
+            settings.connection.control_port,
+            session.to_settings().connection.control_port
+        );
+
+        // Clients without a profile use the base settings
+        let settings = session.to_client_settings("pico.client.alvr").unwrap();
+        assert_eq!(
+            settings.video.encode_bitrate_mbs,
+            session.to_settings().video.encode_bitrate_mbs
+        );
     }
 
-    pub fn get_mut(&mut self) -> SessionLock {
-        SessionLock {
-            session_desc: &mut self.session_desc,
-            session_path: &self.session_path,
+    #[test]
+    fn test_server_wide_settings_exist() {
+        let session_settings_json = json::to_value(settings::session_settings_default()).unwrap();
+        for pointer in SERVER_WIDE_SETTINGS {
+            assert!(
+                session_settings_json.pointer(pointer).is_some(),
+                "{pointer}"
+            );
         }
     }
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
 
     #[test]
-    fn test_session_to_settings() {
-        let _settings = SessionDesc::default().to_settings();
+    fn test_client_settings_invalid_profile() {
+        let mut session = SessionDesc::default();
+        session.client_connections.insert(
+            "quest.client.alvr".into(),
+            ClientConnectionDesc {
+                display_name: "Quest 2".into(),
+                manual_ips: HashSet::new(),
+                trusted: true,
+                certificate_pem: None,
+                profile: Some("quest".into()),
+            },
+        );
+        assert!(session
+            .client_session_settings("quest.client.alvr")
+            .is_err());
+
+        session.settings_profiles.insert(
+            "quest".into(),
+            json::json!({ "video": { "encodeBitrateMbs": "high" } }),
+        );
+        assert!(session
+            .client_session_settings("quest.client.alvr")
+            .is_err());
     }
 
     #[test]
-    fn test_session_extrapolation_trivial() {
+    fn test_session_migration_trivial() {
         SessionDesc::default()
             .merge_from_json(&json::to_value(SessionDesc::default()).unwrap())
             .unwrap();
     }
 
     #[test]
-    fn test_session_extrapolation_oculus_go() {
+    fn test_session_migration_oculus_go() {
         let input_json_string = r#"{
             "sessionSettings": {
               "fjdshfks":false,
@@ -695,8 +989,140 @@ mod tests {
             }
           }"#;
 
-        SessionDesc::default()
+        let report = SessionDesc::default()
             .merge_from_json(&json::from_str(input_json_string).unwrap())
             .unwrap();
+        assert_eq!(report.reset_fields, ["sessionSettings.fjdshfks"]);
+    }
+
+    #[test]
+    fn test_session_migration_steps() {
+        let mut session_json = json::to_value(SessionDesc::default()).unwrap();
+        session_json["serverVersion"] = json::json!("18.2.2");
+        session_json
+            .as_object_mut()
+            .unwrap()
+            .remove("settingsProfiles");
+        // The session cannot be loaded as is, so the steps are applied
+        session_json["locale"] = json::json!(42);
+
+        let mut session = SessionDesc::default();
+        let report = session.merge_from_json(&session_json).unwrap();
+        assert_eq!(report.applied_steps, ["Add settings profiles"]);
+        assert_eq!(report.reset_fields, ["locale"]);
+        assert!(session.settings_profiles.is_empty());
+        assert_eq!(session.server_version, *ALVR_VERSION);
+    }
+
+    #[test]
+    fn test_request_with_type_error() {
+        let mut session = SessionDesc::default();
+        let errors = session
+            .merge_from_request_json(&json::json!({
+                "locale": 42,
+                "sessionSettings": {
+                    "video": {
+                        "preferredFps": "fast",
+                        "encodeBitrateMbs": 150
+                    }
+                }
+            }))
+            .unwrap_err()
+            .iter()
+            .map(|error| error.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            errors,
+            [
+                "locale: invalid value",
+                "sessionSettings.video.preferredFps: invalid value"
+            ]
+        );
+
+        // Nothing is applied
+        assert_eq!(
+            session.to_settings().video.encode_bitrate_mbs,
+            SessionDesc::default()
+                .to_settings()
+                .video
+                .encode_bitrate_mbs
+        );
+    }
+
+    #[test]
+    fn test_request_with_invalid_profile() {
+        let mut session = SessionDesc::default();
+        let errors = session
+            .merge_from_request_json(&json::json!({
+                "settingsProfiles": {
+                    "quest": { "video": { "encodeBitrateMbs": 900 } }
+                }
+            }))
+            .unwrap_err()
+            .iter()
+            .map(|error| error.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            errors,
+            ["settingsProfiles.quest.video.encodeBitrateMbs: 900 > max 500"]
+        );
+        assert!(session.settings_profiles.is_empty());
+    }
+
+    #[test]
+    fn test_partial_session_keeps_profiles() {
+        let mut session = SessionDesc::default();
+        session.settings_profiles.insert(
+            "quest".into(),
+            json::json!({ "video": { "encodeBitrateMbs": 150 } }),
+        );
+        session.client_connections.insert(
+            "quest.client.alvr".into(),
+            ClientConnectionDesc {
+                display_name: "Quest 2".into(),
+                manual_ips: HashSet::new(),
+                trusted: true,
+                certificate_pem: None,
+                profile: Some("quest".into()),
+            },
+        );
+
+        // Partial store sent by the dashboard, without serverVersion
+        let report = session
+            .merge_from_json(&json::json!({
+                "sessionSettings": { "video": { "encodeBitrateMbs": 100 } }
+            }))
+            .unwrap();
+
+        assert!(report.applied_steps.is_empty());
+        assert!(report.reset_fields.is_empty());
+        assert_eq!(session.to_settings().video.encode_bitrate_mbs, 100);
+        assert!(session.settings_profiles.contains_key("quest"));
+        assert_eq!(
+            session.client_connections["quest.client.alvr"].profile,
+            Some("quest".into())
+        );
+    }
+
+    #[test]
+    fn test_session_migration_reset_fields() {
+        let input_json = json::json!({
+            "locale": 42,
+            "sessionSettings": {
+                "video": {
+                    "preferredFps": "fast",
+                    "encodeBitrateMbs": 150
+                }
+            }
+        });
+
+        let mut session = SessionDesc::default();
+        let report = session.merge_from_json(&input_json).unwrap();
+        assert_eq!(
+            report.reset_fields,
+            ["locale", "sessionSettings.video.preferredFps"]
+        );
+        assert_eq!(session.locale, SessionDesc::default().locale);
+        assert_eq!(session.to_settings().video.encode_bitrate_mbs, 150);
     }
 }
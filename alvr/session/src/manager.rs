@@ -0,0 +1,474 @@
+use crate::{
+    apply_setting_overrides, changed_settings, overrides::json_at_path, publish_event,
+    save_session, setting_reload_requirement, validate_session_settings, ReloadRequirement,
+    ServerEvent, SessionDesc, SettingOverride,
+};
+use alvr_common::prelude::*;
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use serde_json as json;
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    ops::{Deref, DerefMut},
+    path::{Path, PathBuf},
+    sync::mpsc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SessionUpdateOrigin {
+    Server,
+    // session.json has been edited by another program
+    File,
+}
+
+// Sent to the SessionManager subscribers after each change of the session
+#[derive(Clone, Debug)]
+pub struct SessionUpdate {
+    pub revision: u64,
+    pub origin: SessionUpdateOrigin,
+    // Paths in the session_settings JSON layout. Empty if only other fields (like the client list)
+    // changed.
+    pub changed_settings: Vec<String>,
+    // None if no setting changed
+    pub reload_requirement: Option<ReloadRequirement>,
+}
+
+// HashSets are serialized in arbitrary order, so the client list is compared directly
+fn is_same_session(old: &SessionDesc, new: &SessionDesc) -> bool {
+    let without_clients = |session_desc: &SessionDesc| {
+        json::to_value(SessionDesc {
+            client_connections: HashMap::new(),
+            ..session_desc.clone()
+        })
+        .unwrap()
+    };
+
+    old.client_connections == new.client_connections && without_clients(old) == without_clients(new)
+}
+
+// SessionDesc wrapper that saves session.json and notifies the changes on destruction.
+pub struct SessionLock<'a> {
+    manager: &'a mut SessionManager,
+    old_session_desc: SessionDesc,
+}
+
+impl Deref for SessionLock<'_> {
+    type Target = SessionDesc;
+    fn deref(&self) -> &SessionDesc {
+        &self.manager.session_desc
+    }
+}
+
+impl DerefMut for SessionLock<'_> {
+    fn deref_mut(&mut self) -> &mut SessionDesc {
+        &mut self.manager.session_desc
+    }
+}
+
+impl Drop for SessionLock<'_> {
+    fn drop(&mut self) {
+        self.manager.reapply_setting_overrides();
+
+        if let Err(e) = save_session(
+            &self.manager.stored_session_desc(),
+            &self.manager.session_path,
+        ) {
+            error!("Failed to save the session: {e}");
+        }
+
+        self.manager
+            .commit(&self.old_session_desc, SessionUpdateOrigin::Server);
+    }
+}
+
+// Correct usage:
+// SessionManager should be used behind a Mutex. Each write of the session should be preceded by a
+// read, within the same lock. If read and write need to happen at different times (like for the
+// dashboard, which holds its own copy of the session), the write should use compare_and_swap() with
+// the revision that was read.
+pub struct SessionManager {
+    // With the setting overrides applied
+    session_desc: SessionDesc,
+    session_path: PathBuf,
+    // Each override is paired with the stored value it replaces
+    setting_overrides: Vec<(SettingOverride, json::Value)>,
+    // Incremented on every change of the session. It is not persisted, so it starts from the
+    // startup time, to not repeat a revision read by a dashboard before a server restart.
+    revision: u64,
+    subscribers: Vec<mpsc::Sender<SessionUpdate>>,
+}
+
+impl SessionManager {
+    pub fn new(session_path: &Path) -> Self {
+        let config_dir = session_path.parent().unwrap();
+        fs::create_dir_all(config_dir).ok();
+
+        let session_desc = match fs::read_to_string(session_path) {
+            Ok(session_string) => {
+                let json_value = json::from_str::<json::Value>(&session_string).unwrap();
+                match json::from_value(json_value.clone()) {
+                    Ok(session_desc) => session_desc,
+                    Err(_) => {
+                        fs::write(config_dir.join("session_old.json"), &session_string).ok();
+                        let mut session_desc = SessionDesc::default();
+                        match session_desc.merge_from_json(&json_value) {
+                            Ok(report) => {
+                                info!(
+                                    "{} {}",
+                                    "Session migrated successfully.",
+                                    "Old session.json is stored as session_old.json"
+                                );
+                                for step in report.applied_steps {
+                                    info!("Session migration step: {step}");
+                                }
+                                if !report.reset_fields.is_empty() {
+                                    warn!(
+                                        "Settings reset to default: {}",
+                                        report.reset_fields.join(", ")
+                                    );
+                                }
+                            }
+                            Err(e) => error!(
+                                "{} {} {}",
+                                "Error while migrating session.",
+                                "Old session.json is stored as session_old.json.",
+                                e
+                            ),
+                        }
+                        // not essential, but useful to avoid duplicated errors
+                        save_session(&session_desc, session_path).ok();
+
+                        session_desc
+                    }
+                }
+            }
+            Err(_) => SessionDesc::default(),
+        };
+
+        Self {
+            session_desc,
+            session_path: session_path.to_owned(),
+            setting_overrides: vec![],
+            revision: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|duration| duration.as_millis() as u64)
+                .unwrap_or_default(),
+            subscribers: vec![],
+        }
+    }
+
+    // The setting overrides are applied
+    pub fn get(&self) -> &SessionDesc {
+        &self.session_desc
+    }
+
+    // The session as saved in session.json, without setting overrides
+    pub fn stored_session_desc(&self) -> SessionDesc {
+        let mut session_desc = self.session_desc.clone();
+        if self.setting_overrides.is_empty() {
+            return session_desc;
+        }
+
+        let mut session_settings_json = json::to_value(&session_desc.session_settings).unwrap();
+        for (setting_override, stored_value) in &self.setting_overrides {
+            if let Some(value) = json_at_path(&mut session_settings_json, &setting_override.path) {
+                *value = stored_value.clone();
+            }
+        }
+        if let Ok(session_settings) = json::from_value(session_settings_json) {
+            session_desc.session_settings = session_settings;
+        }
+
+        session_desc
+    }
+
+    // Replaces the previous overrides. If a path is overridden more than once, the last override
+    // wins. The session is not saved.
+    pub fn set_setting_overrides(&mut self, overrides: Vec<SettingOverride>) -> StrResult {
+        let mut overrides = overrides;
+        let mut paths = HashSet::new();
+        overrides.reverse();
+        overrides.retain(|setting_override| paths.insert(setting_override.path.clone()));
+        overrides.reverse();
+
+        let mut session_desc = self.stored_session_desc();
+
+        let mut stored_session_settings_json =
+            trace_err!(json::to_value(&session_desc.session_settings))?;
+        session_desc.session_settings =
+            apply_setting_overrides(&session_desc.session_settings, &overrides)?;
+
+        self.setting_overrides = overrides
+            .into_iter()
+            .map(|setting_override| {
+                let stored_value =
+                    json_at_path(&mut stored_session_settings_json, &setting_override.path)
+                        .cloned()
+                        .unwrap_or_default();
+                (setting_override, stored_value)
+            })
+            .collect();
+
+        let old_session_desc = std::mem::replace(&mut self.session_desc, session_desc);
+        self.commit(&old_session_desc, SessionUpdateOrigin::Server);
+
+        Ok(())
+    }
+
+    pub fn setting_overrides(&self) -> Vec<SettingOverride> {
+        self.setting_overrides
+            .iter()
+            .map(|(setting_override, _)| setting_override.clone())
+            .collect()
+    }
+
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    pub fn get_mut(&mut self) -> SessionLock<'_> {
+        SessionLock {
+            old_session_desc: self.session_desc.clone(),
+            manager: self,
+        }
+    }
+
+    // Replaces the session only if it has not changed since `expected_revision` was read. Returns
+    // the new revision, or the current revision if the session has changed in the meantime.
+    pub fn compare_and_swap(
+        &mut self,
+        expected_revision: u64,
+        session_desc: SessionDesc,
+    ) -> Result<u64, u64> {
+        if expected_revision != self.revision {
+            return Err(self.revision);
+        }
+
+        *self.get_mut() = session_desc;
+
+        Ok(self.revision)
+    }
+
+    // The receiver is dropped from the subscribers when it is disconnected
+    pub fn subscribe(&mut self) -> mpsc::Receiver<SessionUpdate> {
+        let (sender, receiver) = mpsc::channel();
+        self.subscribers.push(sender);
+
+        receiver
+    }
+
+    // Picks up manual edits of session.json. The file is merged like an imported session, then the
+    // settings are validated as a whole. Saves of the server itself are ignored since they leave the
+    // session unchanged.
+    pub fn reload_from_file(&mut self) -> StrResult {
+        let json_value = trace_err!(json::from_str::<json::Value>(&trace_err!(
+            fs::read_to_string(&self.session_path)
+        )?))?;
+
+        let mut session_desc = self.stored_session_desc();
+        session_desc.merge_from_json(&json_value)?;
+
+        let session_settings_json = trace_err!(json::to_value(&session_desc.session_settings))?;
+        if let Err(errors) = validate_session_settings(&session_settings_json) {
+            return fmt_e!(
+                "Invalid settings: {}",
+                errors
+                    .iter()
+                    .map(|error| error.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+        }
+
+        let old_session_desc = std::mem::replace(&mut self.session_desc, session_desc);
+        self.reapply_setting_overrides();
+        self.commit(&old_session_desc, SessionUpdateOrigin::File);
+
+        Ok(())
+    }
+
+    // Values written over the overridden settings become the stored values, while the overrides
+    // stay in effect. Writing the overridden value itself does not change the stored value.
+    fn reapply_setting_overrides(&mut self) {
+        if self.setting_overrides.is_empty() {
+            return;
+        }
+
+        let mut session_settings_json =
+            json::to_value(&self.session_desc.session_settings).unwrap();
+        for (setting_override, stored_value) in &mut self.setting_overrides {
+            if let Some(value) = json_at_path(&mut session_settings_json, &setting_override.path) {
+                if *value != setting_override.value {
+                    *stored_value = value.clone();
+                    *value = setting_override.value.clone();
+                }
+            }
+        }
+        if let Ok(session_settings) = json::from_value(session_settings_json) {
+            self.session_desc.session_settings = session_settings;
+        }
+    }
+
+    fn commit(&mut self, old_session_desc: &SessionDesc, origin: SessionUpdateOrigin) {
+        if is_same_session(old_session_desc, &self.session_desc) {
+            return;
+        }
+
+        self.revision += 1;
+
+        publish_event(ServerEvent::SessionUpdated); // deprecated
+        publish_event(ServerEvent::Session(Box::new(self.session_desc.clone())));
+
+        let changed_settings = changed_settings(
+            &old_session_desc.session_settings,
+            &self.session_desc.session_settings,
+        );
+        let update = SessionUpdate {
+            revision: self.revision,
+            origin,
+            reload_requirement: changed_settings
+                .iter()
+                .map(|path| setting_reload_requirement(path))
+                .max(),
+            changed_settings,
+        };
+
+        self.subscribers
+            .retain(|sender| sender.send(update.clone()).is_ok());
+    }
+}
+
+// Calls `on_change` when session.json is written, by the server or by other programs. The parent
+// directory is watched because saving replaces the file. Watching stops when the watcher is dropped.
+pub fn watch_session_file(
+    session_path: &Path,
+    on_change: impl Fn() + Send + 'static,
+) -> StrResult<RecommendedWatcher> {
+    let file_name = session_path.file_name().map(|name| name.to_owned());
+
+    let mut watcher = trace_err!(notify::recommended_watcher(
+        move |res: notify::Result<notify::Event>| {
+            if let Ok(event) = res {
+                if (event.kind.is_create() || event.kind.is_modify())
+                    && event
+                        .paths
+                        .iter()
+                        .any(|path| path.file_name() == file_name.as_deref())
+                {
+                    on_change();
+                }
+            }
+        }
+    ))?;
+    trace_err!(watcher.watch(
+        trace_none!(session_path.parent())?,
+        RecursiveMode::NonRecursive
+    ))?;
+
+    Ok(watcher)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn revisions_and_updates() {
+        let session_path = std::env::temp_dir()
+            .join(format!("alvr_session_manager_{}", std::process::id()))
+            .join("session.json");
+
+        let mut manager = SessionManager::new(&session_path);
+        let receiver = manager.subscribe();
+        let initial_revision = manager.revision();
+        assert_ne!(initial_revision, 0);
+
+        // Saving without changes does not create a revision
+        manager.get_mut();
+        assert!(session_path.exists());
+        assert_eq!(manager.revision(), initial_revision);
+
+        let mut session_desc = manager.get().clone();
+        session_desc.session_settings.video.encode_bitrate_mbs += 10;
+        assert_eq!(
+            manager.compare_and_swap(initial_revision, session_desc.clone()),
+            Ok(initial_revision + 1)
+        );
+        assert_eq!(
+            manager.compare_and_swap(initial_revision, session_desc),
+            Err(initial_revision + 1)
+        );
+
+        let update = receiver.try_recv().unwrap();
+        assert_eq!(update.revision, initial_revision + 1);
+        assert_eq!(update.origin, SessionUpdateOrigin::Server);
+        assert_eq!(update.changed_settings, ["video.encodeBitrateMbs"]);
+        assert_eq!(
+            update.reload_requirement,
+            Some(ReloadRequirement::HotReload)
+        );
+        assert!(receiver.try_recv().is_err());
+
+        // Reloading the file saved by the manager itself is a no-op
+        manager.reload_from_file().unwrap();
+        assert_eq!(manager.revision(), initial_revision + 1);
+
+        let mut session_json = json::to_value(manager.get()).unwrap();
+        session_json["sessionSettings"]["video"]["preferredFps"] = json::json!(90.);
+        fs::write(&session_path, session_json.to_string()).unwrap();
+        manager.reload_from_file().unwrap();
+
+        let update = receiver.try_recv().unwrap();
+        assert_eq!(update.revision, initial_revision + 2);
+        assert_eq!(update.origin, SessionUpdateOrigin::File);
+        assert_eq!(update.changed_settings, ["video.preferredFps"]);
+
+        fs::remove_dir_all(session_path.parent().unwrap()).ok();
+    }
+
+    #[test]
+    fn setting_overrides_are_not_saved() {
+        let session_path = std::env::temp_dir()
+            .join(format!("alvr_session_overrides_{}", std::process::id()))
+            .join("session.json");
+
+        let mut manager = SessionManager::new(&session_path);
+        let stored_bitrate = manager.get().session_settings.video.encode_bitrate_mbs;
+
+        manager
+            .set_setting_overrides(vec![crate::parse_setting_override(
+                "video.encodeBitrateMbs",
+                "80",
+            )
+            .unwrap()])
+            .unwrap();
+        assert_eq!(manager.get().session_settings.video.encode_bitrate_mbs, 80);
+
+        // Storing back the overridden session keeps the stored value
+        let session_desc = manager.get().clone();
+        *manager.get_mut() = session_desc;
+        let saved_session = crate::load_session(&session_path).unwrap();
+        assert_eq!(
+            saved_session.session_settings.video.encode_bitrate_mbs,
+            stored_bitrate
+        );
+
+        // New values are stored, the override still applies
+        manager.get_mut().session_settings.video.encode_bitrate_mbs = 100;
+        assert_eq!(manager.get().session_settings.video.encode_bitrate_mbs, 80);
+        assert_eq!(
+            manager
+                .stored_session_desc()
+                .session_settings
+                .video
+                .encode_bitrate_mbs,
+            100
+        );
+
+        manager.reload_from_file().unwrap();
+        assert_eq!(manager.get().session_settings.video.encode_bitrate_mbs, 80);
+
+        fs::remove_dir_all(session_path.parent().unwrap()).ok();
+    }
+}
@@ -0,0 +1,119 @@
+// Session migrations. Each step converts the session JSON written by older server versions to the
+// layout used starting from `version`. When a session is loaded, the steps newer than its
+// serverVersion are applied in order.
+//
+// Development builds share the version of the last release, so a session may already be in the
+// layout of a step that is applied to it. Steps must leave such sessions unchanged.
+//
+// Steps only transform JSON, they never deserialize it: the layout of a step must not depend on
+// the current Rust types, which keep changing.
+
+use alvr_common::semver::Version;
+use serde_json as json;
+
+#[derive(Default, Debug)]
+pub struct MigrationReport {
+    pub applied_steps: Vec<&'static str>,
+    // Paths of the fields that could not be migrated. They keep the value they had before the
+    // merge, which is the default value when loading the session.
+    pub reset_fields: Vec<String>,
+}
+
+pub struct MigrationStep {
+    // First server version that writes the migrated layout
+    pub version: &'static str,
+    pub description: &'static str,
+    pub migrate: fn(&mut json::Value),
+}
+
+pub const MIGRATION_STEPS: &[MigrationStep] = &[MigrationStep {
+    version: "18.2.3",
+    description: "Add settings profiles",
+    migrate: add_settings_profiles,
+}];
+
+// Returns the descriptions of the steps that have been applied
+pub fn migrate(session_json: &mut json::Value, from_version: &Version) -> Vec<&'static str> {
+    MIGRATION_STEPS
+        .iter()
+        .filter(|step| Version::parse(step.version).unwrap() > *from_version)
+        .map(|step| {
+            (step.migrate)(session_json);
+            step.description
+        })
+        .collect()
+}
+
+fn add_settings_profiles(session_json: &mut json::Value) {
+    if let Some(session_fields) = session_json.as_object_mut() {
+        session_fields
+            .entry("settingsProfiles")
+            .or_insert_with(|| json::json!({}));
+    }
+
+    if let Some(client_connections) = session_json
+        .get_mut("clientConnections")
+        .and_then(|clients| clients.as_object_mut())
+    {
+        for client in client_connections.values_mut() {
+            if let Some(client_fields) = client.as_object_mut() {
+                client_fields.entry("profile").or_insert(json::Value::Null);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alvr_common::ALVR_VERSION;
+
+    // A step newer than the server would be applied again each time the session is loaded
+    #[test]
+    fn steps_are_not_newer_than_server() {
+        for step in MIGRATION_STEPS {
+            assert!(Version::parse(step.version).unwrap() <= *ALVR_VERSION);
+        }
+    }
+
+    #[test]
+    fn steps_are_ordered() {
+        let versions = MIGRATION_STEPS
+            .iter()
+            .map(|step| Version::parse(step.version).unwrap())
+            .collect::<Vec<_>>();
+
+        assert!(versions.windows(2).all(|pair| pair[0] <= pair[1]));
+    }
+
+    #[test]
+    fn newer_sessions_are_not_migrated() {
+        let mut session_json = json::json!({ "clientConnections": {} });
+        let applied = migrate(&mut session_json, &ALVR_VERSION);
+
+        assert!(applied.is_empty());
+        assert_eq!(session_json, json::json!({ "clientConnections": {} }));
+    }
+
+    #[test]
+    fn settings_profiles() {
+        let mut session_json = json::json!({
+            "clientConnections": {
+                "quest.client.alvr": { "trusted": true }
+            }
+        });
+        add_settings_profiles(&mut session_json);
+
+        let expected_json = json::json!({
+            "settingsProfiles": {},
+            "clientConnections": {
+                "quest.client.alvr": { "trusted": true, "profile": null }
+            }
+        });
+        assert_eq!(session_json, expected_json);
+
+        // Already migrated
+        add_settings_profiles(&mut session_json);
+        assert_eq!(session_json, expected_json);
+    }
+}
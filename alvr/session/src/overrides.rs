@@ -0,0 +1,261 @@
+// Overrides of single settings, for headless and scripted setups. They are applied on top of the
+// stored session and never saved. They come from environment variables like
+// `ALVR_SETTING__video__encodeBitrateMbs=80` or from arguments like `--set video.codec=HEVC`.
+// Paths use the session_settings JSON layout. Choices, switches and optionals can also be set
+// directly, with the variant name or a boolean.
+
+use crate::{settings, validate_session_settings, SessionSettings};
+use alvr_common::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_json as json;
+use settings_schema::{EntryData, SchemaNode};
+
+pub const SETTING_ENV_VAR_PREFIX: &str = "ALVR_SETTING__";
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SettingOverride {
+    // Path of the overridden value, in the session_settings JSON layout
+    pub path: String,
+    pub value: json::Value,
+}
+
+enum OverrideTarget<'a> {
+    Node(&'a SchemaNode),
+    Variant(&'a [(String, Option<EntryData>)]),
+    Boolean,
+    Text,
+    // Vector and dictionary content. It is checked by deserialization
+    Json,
+}
+
+fn resolve_path<'a>(schema: &'a SchemaNode, segments: &[&str]) -> Option<OverrideTarget<'a>> {
+    let (segment, rest) = match segments.split_first() {
+        Some(split) => split,
+        None => return Some(OverrideTarget::Node(schema)),
+    };
+
+    let target = match (schema, *segment) {
+        (SchemaNode::Section { entries }, name) => OverrideTarget::Node(
+            &entries
+                .iter()
+                .find(|(entry_name, _)| entry_name == name)?
+                .1
+                .as_ref()?
+                .content,
+        ),
+        (SchemaNode::Choice { variants, .. }, "variant") => OverrideTarget::Variant(variants),
+        (SchemaNode::Choice { variants, .. }, name) => OverrideTarget::Node(
+            &variants
+                .iter()
+                .find(|(variant_name, _)| variant_name == name)?
+                .1
+                .as_ref()?
+                .content,
+        ),
+        (SchemaNode::Switch { .. }, "enabled") | (SchemaNode::Optional { .. }, "set") => {
+            OverrideTarget::Boolean
+        }
+        (SchemaNode::Switch { content, .. }, "content")
+        | (SchemaNode::Optional { content, .. }, "content") => OverrideTarget::Node(content),
+        (
+            SchemaNode::Vector {
+                default_element, ..
+            },
+            "element",
+        ) => OverrideTarget::Node(default_element),
+        (SchemaNode::Dictionary { .. }, "key") => OverrideTarget::Text,
+        (SchemaNode::Dictionary { default_value, .. }, "value") => {
+            OverrideTarget::Node(default_value)
+        }
+        (SchemaNode::Vector { .. }, "content") | (SchemaNode::Dictionary { .. }, "content") => {
+            OverrideTarget::Json
+        }
+        (SchemaNode::Array(elements), index) => {
+            OverrideTarget::Node(elements.get(index.parse::<usize>().ok()?)?)
+        }
+        _ => return None,
+    };
+
+    match target {
+        OverrideTarget::Node(node) => resolve_path(node, rest),
+        target => rest.is_empty().then(|| target),
+    }
+}
+
+fn parse_bool(text: &str) -> StrResult<json::Value> {
+    match text {
+        "true" | "1" | "on" => Ok(json::Value::Bool(true)),
+        "false" | "0" | "off" => Ok(json::Value::Bool(false)),
+        _ => fmt_e!("expected boolean, found \"{text}\""),
+    }
+}
+
+fn parse_variant(variants: &[(String, Option<EntryData>)], text: &str) -> StrResult<json::Value> {
+    if variants.iter().any(|(name, _)| name == text) {
+        Ok(json::Value::String(text.to_owned()))
+    } else {
+        fmt_e!(
+            "expected one of {}, found \"{text}\"",
+            variants
+                .iter()
+                .map(|(name, _)| name.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        )
+    }
+}
+
+// Returns the path of the value to set (which may be a child of `path`) and the value
+fn parse_value(target: OverrideTarget, path: &str, text: &str) -> StrResult<(String, json::Value)> {
+    let child = |name: &str| format!("{path}.{name}");
+
+    match target {
+        OverrideTarget::Node(SchemaNode::Boolean { .. }) | OverrideTarget::Boolean => {
+            Ok((path.to_owned(), parse_bool(text)?))
+        }
+        OverrideTarget::Node(SchemaNode::Integer { .. }) => match text.parse::<i64>() {
+            Ok(value) => Ok((path.to_owned(), json::json!(value))),
+            Err(_) => fmt_e!("expected integer, found \"{text}\""),
+        },
+        OverrideTarget::Node(SchemaNode::Float { .. }) => match text.parse::<f64>() {
+            Ok(value) if value.is_finite() => Ok((path.to_owned(), json::json!(value))),
+            _ => fmt_e!("expected number, found \"{text}\""),
+        },
+        OverrideTarget::Node(SchemaNode::Text { .. }) | OverrideTarget::Text => {
+            Ok((path.to_owned(), json::Value::String(text.to_owned())))
+        }
+        OverrideTarget::Node(SchemaNode::Choice { variants, .. }) => {
+            Ok((child("variant"), parse_variant(variants, text)?))
+        }
+        OverrideTarget::Variant(variants) => Ok((path.to_owned(), parse_variant(variants, text)?)),
+        OverrideTarget::Node(SchemaNode::Switch { .. }) => {
+            Ok((child("enabled"), parse_bool(text)?))
+        }
+        OverrideTarget::Node(SchemaNode::Optional { .. }) => Ok((child("set"), parse_bool(text)?)),
+        OverrideTarget::Node(_) | OverrideTarget::Json => match json::from_str(text) {
+            Ok(value) => Ok((path.to_owned(), value)),
+            Err(e) => fmt_e!("expected JSON: {e}"),
+        },
+    }
+}
+
+pub fn parse_setting_override(path: &str, text: &str) -> StrResult<SettingOverride> {
+    let schema = settings::settings_schema(settings::session_settings_default());
+
+    let target = resolve_path(&schema, &path.split('.').collect::<Vec<_>>())
+        .ok_or_else(|| format!("{path}: unknown setting"))?;
+    let (path, value) = parse_value(target, path, text).map_err(|e| format!("{path}: {e}"))?;
+
+    Ok(SettingOverride { path, value })
+}
+
+// Variables like ALVR_SETTING__video__encodeBitrateMbs=80
+pub fn setting_overrides_from_env() -> StrResult<Vec<SettingOverride>> {
+    std::env::vars()
+        .filter_map(|(name, value)| {
+            let path = name
+                .strip_prefix(SETTING_ENV_VAR_PREFIX)?
+                .replace("__", ".");
+            Some(parse_setting_override(&path, &value))
+        })
+        .collect()
+}
+
+// Arguments like `--set video.codec=HEVC` or `--set=video.codec=HEVC`. Other arguments are ignored
+pub fn setting_overrides_from_args(args: &[String]) -> StrResult<Vec<SettingOverride>> {
+    let mut overrides = vec![];
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let assignment = if arg == "--set" {
+            args.next()
+                .ok_or_else(|| "Missing assignment after --set".to_owned())?
+        } else if let Some(assignment) = arg.strip_prefix("--set=") {
+            assignment
+        } else {
+            continue;
+        };
+
+        let (path, value) = assignment
+            .split_once('=')
+            .ok_or_else(|| format!("Expected <path>=<value>, found \"{assignment}\""))?;
+        overrides.push(parse_setting_override(path, value)?);
+    }
+
+    Ok(overrides)
+}
+
+// The result is checked against the schema, including bounds and steps
+pub fn apply_setting_overrides(
+    session_settings: &SessionSettings,
+    overrides: &[SettingOverride],
+) -> StrResult<SessionSettings> {
+    let mut session_settings_json = trace_err!(json::to_value(session_settings))?;
+    for setting_override in overrides {
+        *json_at_path(&mut session_settings_json, &setting_override.path)
+            .ok_or_else(|| format!("{}: unknown setting", setting_override.path))? =
+            setting_override.value.clone();
+    }
+
+    if let Err(errors) = validate_session_settings(&session_settings_json) {
+        return fmt_e!(
+            "Invalid setting overrides: {}",
+            errors
+                .iter()
+                .map(|error| error.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    }
+
+    json::from_value(session_settings_json).map_err(|e| format!("Invalid setting overrides: {e}"))
+}
+
+pub(crate) fn json_at_path<'a>(
+    value: &'a mut json::Value,
+    path: &str,
+) -> Option<&'a mut json::Value> {
+    value.pointer_mut(&format!("/{}", path.replace('.', "/")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SessionDesc;
+
+    #[test]
+    fn parse_and_apply() {
+        let overrides = setting_overrides_from_args(&[
+            "--set".into(),
+            "video.codec=HEVC".into(),
+            "--set=video.encodeBitrateMbs=80".into(),
+            "--set=video.foveatedRendering=true".into(),
+            "--set=video.foveatedRendering.content.centerSizeX=0.5".into(),
+        ])
+        .unwrap();
+        assert_eq!(
+            overrides[0],
+            SettingOverride {
+                path: "video.codec.variant".into(),
+                value: json::json!("HEVC")
+            }
+        );
+        assert_eq!(overrides[2].path, "video.foveatedRendering.enabled");
+
+        let session_settings =
+            apply_setting_overrides(&SessionDesc::default().session_settings, &overrides).unwrap();
+        assert_eq!(session_settings.video.encode_bitrate_mbs, 80);
+        assert!(session_settings.video.foveated_rendering.enabled);
+
+        assert!(parse_setting_override("video.codec", "VP9").is_err());
+        assert!(parse_setting_override("video.encodeBitrateMbs", "fast").is_err());
+        assert!(parse_setting_override("video.unknownSetting", "1").is_err());
+
+        let out_of_bounds = parse_setting_override("video.encodeBitrateMbs", "900").unwrap();
+        assert!(apply_setting_overrides(
+            &SessionDesc::default().session_settings,
+            &[out_of_bounds]
+        )
+        .is_err());
+    }
+}
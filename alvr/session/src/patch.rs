@@ -0,0 +1,275 @@
+// Sharing of tuned configurations. A settings patch contains only the session settings that differ
+// from the defaults, in the session_settings JSON layout. It does not carry the trusted clients nor
+// the OpenVR config, so it can be shared between machines.
+
+use crate::{settings, SessionDesc, SessionSettings};
+use alvr_common::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_json as json;
+use settings_schema::ValidationError;
+use std::{collections::BTreeSet, fmt};
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum PatchFormat {
+    Json,
+    Toml,
+}
+
+// Values of `new` that differ from `old`. Objects are compared field by field, any other value is
+// compared as a whole. Returns None if there are no differences.
+pub(crate) fn json_patch(old: &json::Value, new: &json::Value) -> Option<json::Value> {
+    match (old, new) {
+        (json::Value::Object(old_fields), json::Value::Object(new_fields)) => {
+            let fields = new_fields
+                .iter()
+                .filter_map(|(name, new_value)| match old_fields.get(name) {
+                    Some(old_value) => json_patch(old_value, new_value)
+                        .map(|value_patch| (name.clone(), value_patch)),
+                    None => Some((name.clone(), new_value.clone())),
+                })
+                .collect::<json::Map<_, _>>();
+
+            (!fields.is_empty()).then(|| json::Value::Object(fields))
+        }
+        (old, new) => (old != new).then(|| new.clone()),
+    }
+}
+
+pub fn settings_patch(session_settings: &SessionSettings) -> json::Value {
+    let default_json = json::to_value(settings::session_settings_default()).unwrap();
+    let session_settings_json = json::to_value(session_settings).unwrap();
+
+    json_patch(&default_json, &session_settings_json).unwrap_or_else(|| json::json!({}))
+}
+
+pub fn export_settings_patch(
+    session_settings: &SessionSettings,
+    format: PatchFormat,
+) -> StrResult<String> {
+    let patch = settings_patch(session_settings);
+
+    match format {
+        PatchFormat::Json => trace_err!(json::to_string_pretty(&patch)),
+        // Converting to a TOML value first puts the plain values before the tables, as required
+        PatchFormat::Toml => trace_err!(toml::to_string_pretty(&trace_err!(
+            toml::Value::try_from(patch)
+        )?)),
+    }
+}
+
+pub fn parse_settings_patch(text: &str, format: PatchFormat) -> StrResult<json::Value> {
+    match format {
+        PatchFormat::Json => json::from_str(text).map_err(|e| format!("Invalid JSON patch: {e}")),
+        PatchFormat::Toml => {
+            let value = toml::from_str::<toml::Value>(text)
+                .map_err(|e| format!("Invalid TOML patch: {e}"))?;
+            trace_err!(json::to_value(value))
+        }
+    }
+}
+
+impl SessionDesc {
+    // The patch is applied on top of the default settings, so the result is the same as the
+    // exported settings. Nothing is changed if any value of the patch does not match the schema or
+    // the resulting settings are not valid. Paths are relative to the session settings.
+    pub fn import_settings_patch(
+        &mut self,
+        patch: &json::Value,
+    ) -> Result<(), Vec<ValidationError>> {
+        let default_json = json::to_value(settings::session_settings_default()).unwrap();
+
+        let mut invalid_fields = vec![];
+        let session_settings_json = crate::reconcile_session_settings(
+            &default_json,
+            patch,
+            &settings::settings_schema(settings::session_settings_default()),
+            "",
+            &mut invalid_fields,
+        );
+        if !invalid_fields.is_empty() {
+            return Err(invalid_fields
+                .into_iter()
+                .map(|path| ValidationError {
+                    path,
+                    message: "invalid value".into(),
+                })
+                .collect());
+        }
+
+        crate::validate_session_settings(&session_settings_json)?;
+
+        self.session_settings = json::from_value(session_settings_json).map_err(|e| {
+            vec![ValidationError {
+                path: "".into(),
+                message: e.to_string(),
+            }]
+        })?;
+
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionChange {
+    // JSON keys separated by dots
+    pub path: String,
+    // None if the value is added or removed
+    pub old_value: Option<json::Value>,
+    pub new_value: Option<json::Value>,
+}
+
+impl fmt::Display for SessionChange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (&self.old_value, &self.new_value) {
+            (Some(old_value), Some(new_value)) => {
+                write!(f, "{}: {old_value} -> {new_value}", self.path)
+            }
+            (None, Some(new_value)) => write!(f, "{}: added {new_value}", self.path),
+            (Some(old_value), None) => write!(f, "{}: removed {old_value}", self.path),
+            (None, None) => write!(f, "{}: unchanged", self.path),
+        }
+    }
+}
+
+fn diff_json(path: &str, old: &json::Value, new: &json::Value, changes: &mut Vec<SessionChange>) {
+    match (old, new) {
+        (json::Value::Object(old_fields), json::Value::Object(new_fields)) => {
+            let names = old_fields
+                .keys()
+                .chain(new_fields.keys())
+                .collect::<BTreeSet<_>>();
+
+            for name in names {
+                let child_path = crate::child_path(path, name);
+                match (old_fields.get(name), new_fields.get(name)) {
+                    (Some(old_value), Some(new_value)) => {
+                        diff_json(&child_path, old_value, new_value, changes)
+                    }
+                    (old_value, new_value) => changes.push(SessionChange {
+                        path: child_path,
+                        old_value: old_value.cloned(),
+                        new_value: new_value.cloned(),
+                    }),
+                }
+            }
+        }
+        (old, new) => {
+            if old != new {
+                changes.push(SessionChange {
+                    path: path.to_owned(),
+                    old_value: Some(old.clone()),
+                    new_value: Some(new.clone()),
+                })
+            }
+        }
+    }
+}
+
+// Changes needed to go from `old` to `new`, sorted by path
+pub fn diff_sessions(old: &SessionDesc, new: &SessionDesc) -> Vec<SessionChange> {
+    let mut changes = vec![];
+    diff_json(
+        "",
+        &json::to_value(old).unwrap(),
+        &json::to_value(new).unwrap(),
+        &mut changes,
+    );
+
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tuned_session() -> SessionDesc {
+        let mut session = SessionDesc::default();
+        session.session_settings.video.encode_bitrate_mbs = 150;
+        // Foveated rendering requires HEVC on Linux
+        session.session_settings.video.codec.variant = settings::CodecTypeDefaultVariant::HEVC;
+        session.session_settings.video.foveated_rendering.enabled = true;
+
+        session
+    }
+
+    #[test]
+    fn default_settings_patch_is_empty() {
+        assert_eq!(
+            settings_patch(&SessionDesc::default().session_settings),
+            json::json!({})
+        );
+    }
+
+    #[test]
+    fn export_import_round_trip() {
+        let session = tuned_session();
+
+        for format in [PatchFormat::Json, PatchFormat::Toml] {
+            let text = export_settings_patch(&session.session_settings, format).unwrap();
+
+            let mut imported_session = SessionDesc::default();
+            imported_session
+                .import_settings_patch(&parse_settings_patch(&text, format).unwrap())
+                .unwrap();
+
+            assert!(diff_sessions(&session, &imported_session).is_empty());
+        }
+    }
+
+    #[test]
+    fn import_invalid_patch() {
+        let mut session = SessionDesc::default();
+        let errors = |patch| {
+            session
+                .clone()
+                .import_settings_patch(&patch)
+                .unwrap_err()
+                .iter()
+                .map(|error| error.to_string())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            errors(json::json!({
+                "video": { "encodeBitrateMbs": "high", "unknownSetting": 1 }
+            })),
+            [
+                "video.encodeBitrateMbs: invalid value",
+                "video.unknownSetting: invalid value"
+            ]
+        );
+        // Values of the right type are checked against the bounds
+        assert_eq!(
+            errors(json::json!({ "video": { "encodeBitrateMbs": 900 } })),
+            ["video.encodeBitrateMbs: 900 > max 500"]
+        );
+
+        assert!(session
+            .import_settings_patch(&json::json!({ "video": { "encodeBitrateMbs": 900 } }))
+            .is_err());
+        assert!(diff_sessions(&SessionDesc::default(), &session).is_empty());
+    }
+
+    #[test]
+    fn human_readable_diff() {
+        let changes = diff_sessions(&SessionDesc::default(), &tuned_session())
+            .iter()
+            .map(|change| change.to_string())
+            .collect::<Vec<_>>();
+
+        let default_bitrate = SessionDesc::default()
+            .session_settings
+            .video
+            .encode_bitrate_mbs;
+        assert_eq!(
+            changes,
+            [
+                "sessionSettings.video.codec.variant: \"H264\" -> \"HEVC\"".into(),
+                format!("sessionSettings.video.encodeBitrateMbs: {default_bitrate} -> 150"),
+                "sessionSettings.video.foveatedRendering.enabled: false -> true".into(),
+            ]
+        );
+    }
+}
@@ -0,0 +1,293 @@
+// Session recordings are JSON lines files written by the server while streaming, to be attached to
+// bug reports. Each line is a RecordingEntry.
+
+use crate::ServerEvent;
+use serde::{Deserialize, Serialize};
+use serde_json as json;
+use std::fmt::{self, Display, Formatter};
+
+pub const RECORDING_EXTENSION: &str = "jsonl";
+
+// Serialized as { "timestampMs": ..., "type": "..." [, "data": ...] }
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct RecordingEntry {
+    // Milliseconds since the UNIX epoch
+    pub timestamp_ms: u64,
+    #[serde(flatten)]
+    pub data: RecordingData,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(tag = "type", content = "data")]
+pub enum RecordingData {
+    // The hostname of the client, recorded before ClientConnected
+    Client(Option<String>),
+    Event(ServerEvent),
+    // Client statistics sent with TimeSyncPacket. alvr_sockets depends on this crate, so the packet
+    // is recorded as it is serialized
+    TimeSync(json::Value),
+    // Target bitrate in Mbps chosen by the adaptive bitrate
+    BitrateDecision(u64),
+}
+
+// Only events useful to analyze the stream are recorded
+pub fn is_recorded_event(event: &ServerEvent) -> bool {
+    matches!(
+        event,
+        ServerEvent::ClientConnected
+            | ServerEvent::ClientDisconnected
+            | ServerEvent::Statistics(_)
+            | ServerEvent::ServerQuitting
+    )
+}
+
+#[derive(Default, Debug, PartialEq)]
+pub struct LatencyPercentiles {
+    pub p50: f32,
+    pub p90: f32,
+    pub p99: f32,
+    pub max: f32,
+}
+
+#[derive(Default, Debug, PartialEq)]
+pub struct RecordingSummary {
+    pub entries_count: usize,
+    pub invalid_lines_count: usize,
+    pub duration_s: f32,
+    pub connections_count: usize,
+    pub disconnections_count: usize,
+    // Connections after the first one
+    pub reconnects_count: usize,
+    // Total latency of the Statistics events, in ms
+    pub total_latency: Option<LatencyPercentiles>,
+    // Consecutive Statistics events reporting lost packets
+    pub loss_bursts_count: usize,
+    pub longest_loss_burst_s: usize,
+    pub packets_lost: u64,
+    pub bitrate_decisions_count: usize,
+    pub min_bitrate_mbs: Option<u64>,
+    pub max_bitrate_mbs: Option<u64>,
+}
+
+// Nearest rank percentile. `sorted` must not be empty
+fn percentile(sorted: &[f32], percent: usize) -> f32 {
+    let rank = (sorted.len() * percent + 99) / 100;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+// Invalid lines are counted and skipped, a recording can end with a truncated line
+pub fn summarize_recording(recording: &str) -> RecordingSummary {
+    let mut summary = RecordingSummary::default();
+
+    // Lines are not sorted if the system clock was adjusted during the recording
+    let mut maybe_timestamp_range_ms = None;
+    let mut latencies = vec![];
+    let mut current_burst_s = 0;
+
+    for line in recording.lines().filter(|line| !line.trim().is_empty()) {
+        let entry = match json::from_str::<RecordingEntry>(line) {
+            Ok(entry) => entry,
+            Err(_) => {
+                summary.invalid_lines_count += 1;
+                continue;
+            }
+        };
+        summary.entries_count += 1;
+        let (min, max) =
+            maybe_timestamp_range_ms.unwrap_or((entry.timestamp_ms, entry.timestamp_ms));
+        maybe_timestamp_range_ms = Some((
+            u64::min(min, entry.timestamp_ms),
+            u64::max(max, entry.timestamp_ms),
+        ));
+
+        match entry.data {
+            RecordingData::Event(ServerEvent::ClientConnected) => {
+                summary.connections_count += 1;
+                current_burst_s = 0;
+            }
+            RecordingData::Event(ServerEvent::ClientDisconnected) => {
+                summary.disconnections_count += 1;
+                current_burst_s = 0;
+            }
+            RecordingData::Event(ServerEvent::Statistics(statistics)) => {
+                latencies.push(statistics.total_latency);
+
+                // Statistics are sent once per second
+                if statistics.packets_lost_per_second > 0 {
+                    if current_burst_s == 0 {
+                        summary.loss_bursts_count += 1;
+                    }
+                    current_burst_s += 1;
+                    summary.longest_loss_burst_s =
+                        usize::max(summary.longest_loss_burst_s, current_burst_s);
+                    summary.packets_lost += statistics.packets_lost_per_second;
+                } else {
+                    current_burst_s = 0;
+                }
+            }
+            RecordingData::BitrateDecision(bitrate_mbs) => {
+                summary.bitrate_decisions_count += 1;
+                summary.min_bitrate_mbs = Some(
+                    summary
+                        .min_bitrate_mbs
+                        .map_or(bitrate_mbs, |min| u64::min(min, bitrate_mbs)),
+                );
+                summary.max_bitrate_mbs = Some(
+                    summary
+                        .max_bitrate_mbs
+                        .map_or(bitrate_mbs, |max| u64::max(max, bitrate_mbs)),
+                );
+            }
+            _ => (),
+        }
+    }
+
+    summary.duration_s = maybe_timestamp_range_ms
+        .map(|(min, max)| (max - min) as f32 / 1000.)
+        .unwrap_or_default();
+    summary.reconnects_count = summary.connections_count.saturating_sub(1);
+
+    latencies.retain(|latency| latency.is_finite());
+    if !latencies.is_empty() {
+        latencies.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+        summary.total_latency = Some(LatencyPercentiles {
+            p50: percentile(&latencies, 50),
+            p90: percentile(&latencies, 90),
+            p99: percentile(&latencies, 99),
+            max: *latencies.last().unwrap(),
+        });
+    }
+
+    summary
+}
+
+impl Display for RecordingSummary {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "Entries: {} ({} invalid lines skipped)",
+            self.entries_count, self.invalid_lines_count
+        )?;
+        writeln!(f, "Duration: {:.1}s", self.duration_s)?;
+        writeln!(
+            f,
+            "Connections: {}, disconnections: {}, reconnects: {}",
+            self.connections_count, self.disconnections_count, self.reconnects_count
+        )?;
+        match &self.total_latency {
+            Some(latency) => writeln!(
+                f,
+                "Total latency: p50 {:.1}ms, p90 {:.1}ms, p99 {:.1}ms, max {:.1}ms",
+                latency.p50, latency.p90, latency.p99, latency.max
+            )?,
+            None => writeln!(f, "Total latency: no statistics recorded")?,
+        }
+        writeln!(
+            f,
+            "Loss bursts: {}, longest {}s, {} packets lost",
+            self.loss_bursts_count, self.longest_loss_burst_s, self.packets_lost
+        )?;
+        match (self.min_bitrate_mbs, self.max_bitrate_mbs) {
+            (Some(min), Some(max)) => write!(
+                f,
+                "Bitrate decisions: {}, from {min} to {max} Mbps",
+                self.bitrate_decisions_count
+            ),
+            _ => write!(f, "Bitrate decisions: none"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Statistics;
+
+    fn statistics_line(timestamp_ms: u64, total_latency: f32, packets_lost: u64) -> String {
+        json::to_string(&RecordingEntry {
+            timestamp_ms,
+            data: RecordingData::Event(ServerEvent::Statistics(Statistics {
+                total_packets: 0,
+                packet_rate: 0,
+                packets_lost_total: 0,
+                packets_lost_per_second: packets_lost,
+                total_sent: 0,
+                sent_rate: 0.,
+                bitrate: 0,
+                ping: 0.,
+                total_latency,
+                encode_latency: 0.,
+                send_latency: 0.,
+                decode_latency: 0.,
+                fec_percentage: 0,
+                fec_failure_total: 0,
+                fec_failure_in_second: 0,
+                client_f_p_s: 0.,
+                server_f_p_s: 0.,
+                battery_h_m_d: 0,
+                battery_left: 0,
+                battery_right: 0,
+            })),
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn summary() {
+        let lines = [
+            r#"{"timestampMs":1000,"type":"Client","data":"quest"}"#.to_owned(),
+            r#"{"timestampMs":1000,"type":"Event","data":{"id":"ClientConnected"}}"#.to_owned(),
+            statistics_line(2000, 40., 0),
+            statistics_line(3000, 50., 3),
+            statistics_line(4000, 60., 2),
+            statistics_line(5000, 30., 0),
+            r#"{"timestampMs":5500,"type":"BitrateDecision","data":30}"#.to_owned(),
+            statistics_line(6000, 20., 1),
+            r#"{"timestampMs":6500,"type":"Event","data":{"id":"ClientDisconnected"}}"#.to_owned(),
+            r#"{"timestampMs":7000,"type":"Event","data":{"id":"ClientConnected"}}"#.to_owned(),
+            r#"{"timestampMs":7500,"type":"TimeSync","data":{"mode":0}}"#.to_owned(),
+            r#"{"timestampMs":8000,"type":"BitrateDecision","data":50}"#.to_owned(),
+            r#"{"timestampMs":9000,"type":"Ev"#.to_owned(),
+        ];
+
+        let summary = summarize_recording(&lines.join("\n"));
+
+        assert_eq!(
+            summary,
+            RecordingSummary {
+                entries_count: 12,
+                invalid_lines_count: 1,
+                duration_s: 7.,
+                connections_count: 2,
+                disconnections_count: 1,
+                reconnects_count: 1,
+                total_latency: Some(LatencyPercentiles {
+                    p50: 40.,
+                    p90: 60.,
+                    p99: 60.,
+                    max: 60.,
+                }),
+                loss_bursts_count: 2,
+                longest_loss_burst_s: 2,
+                packets_lost: 6,
+                bitrate_decisions_count: 2,
+                min_bitrate_mbs: Some(30),
+                max_bitrate_mbs: Some(50),
+            }
+        );
+    }
+
+    #[test]
+    fn unsorted_timestamps() {
+        let lines = [
+            r#"{"timestampMs":5000,"type":"BitrateDecision","data":30}"#,
+            r#"{"timestampMs":2000,"type":"BitrateDecision","data":30}"#,
+            r#"{"timestampMs":4000,"type":"BitrateDecision","data":30}"#,
+        ];
+        assert_eq!(summarize_recording(&lines.join("\n")).duration_s, 3.);
+
+        assert_eq!(summarize_recording("").duration_s, 0.);
+    }
+}
@@ -0,0 +1,238 @@
+// Classification of the session settings by how a change is applied to a running server. The paths
+// use the session_settings JSON layout. A setting is classified by the longest path that contains
+// it; settings not listed require a reconnection.
+
+use crate::{patch, OpenvrConfig, SessionSettings};
+use serde::{Deserialize, Serialize};
+use serde_json as json;
+
+// Ordered from the least to the most disruptive
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "camelCase")]
+pub enum ReloadRequirement {
+    // Applied to the running stream with ServerControlPacket::SettingsUpdate and the driver hooks
+    HotReload,
+    // Applied when the client connects again
+    Reconnect,
+    // Used when SteamVR initializes the driver, or by the server at startup
+    Restart,
+}
+
+use ReloadRequirement::*;
+
+pub const RELOAD_REQUIREMENTS: &[(&str, ReloadRequirement)] = &[
+    ("headset.universeId", Restart),
+    ("headset.serialNumber", Restart),
+    ("headset.trackingSystemName", Restart),
+    ("headset.modelNumber", Restart),
+    ("headset.driverVersion", Restart),
+    ("headset.manufacturerName", Restart),
+    ("headset.renderModelName", Restart),
+    ("headset.registeredDeviceType", Restart),
+    ("headset.trackingFrameOffset", Restart),
+    ("headset.positionOffset", Restart),
+    ("headset.force3dof", Restart),
+    ("headset.trackingRefOnly", Restart),
+    ("headset.enableViveTrackerProxy", Restart),
+    ("headset.controllers", Restart),
+    ("headset.controllers.content.hapticsIntensity", HotReload),
+    (
+        "headset.controllers.content.hapticsAmplitudeCurve",
+        HotReload,
+    ),
+    ("headset.controllers.content.hapticsMinDuration", HotReload),
+    (
+        "headset.controllers.content.hapticsLowDurationAmplitudeMultiplier",
+        HotReload,
+    ),
+    (
+        "headset.controllers.content.hapticsLowDurationRange",
+        HotReload,
+    ),
+    ("video.adapterIndex", Restart),
+    ("video.renderResolution", Restart),
+    ("video.recommendedTargetResolution", Restart),
+    ("video.preferredFps", Restart),
+    ("video.codec", Restart),
+    ("video.use10bitEncoder", Restart),
+    ("video.swThreadCount", Restart),
+    ("video.encodeBitrateMbs", HotReload),
+    ("video.adaptiveBitrate", Restart),
+    ("video.secondsFromVsyncToPhotons", Restart),
+    ("video.foveatedRendering.enabled", Restart),
+    ("video.foveatedRendering.content", HotReload),
+    ("video.colorCorrection.enabled", Restart),
+    ("video.colorCorrection.content", HotReload),
+    ("connection.webServerPort", Restart),
+    ("connection.webServerLanAccess", Restart),
+    ("connection.aggressiveKeyframeResend", Restart),
+    // Both scripts are read when the client connects
+    ("connection.onConnectScript", Reconnect),
+    ("connection.onDisconnectScript", Reconnect),
+    ("connection.enableFec", Restart),
+    // Dashboard preferences
+    ("extra", HotReload),
+    ("extra.clientDarkMode", Reconnect),
+    // A recording is started for each connection
+    ("extra.sessionRecording", Reconnect),
+    ("extra.patches.linuxAsyncReprojection", Restart),
+];
+
+// Only the Windows encoder is updated by SetColorCorrection() and SetFoveation(), on the other
+// platforms these are read when the encoder is created
+const WINDOWS_HOT_RELOAD_PATHS: &[&str] = &[
+    "video.foveatedRendering.content",
+    "video.colorCorrection.content",
+];
+
+fn is_under(path: &str, prefix: &str) -> bool {
+    path == prefix || (path.starts_with(prefix) && path[prefix.len()..].starts_with('.'))
+}
+
+pub fn setting_reload_requirement(path: &str) -> ReloadRequirement {
+    if !cfg!(windows)
+        && WINDOWS_HOT_RELOAD_PATHS
+            .iter()
+            .any(|prefix| is_under(path, prefix))
+    {
+        return Restart;
+    }
+
+    RELOAD_REQUIREMENTS
+        .iter()
+        .filter(|(prefix, _)| is_under(path, prefix))
+        .max_by_key(|(prefix, _)| prefix.len())
+        .map(|(_, requirement)| *requirement)
+        .unwrap_or(Reconnect)
+}
+
+fn collect_leaf_paths(value: &json::Value, path: String, paths: &mut Vec<String>) {
+    match value {
+        json::Value::Object(fields) => {
+            for (name, value) in fields {
+                let child_path = if path.is_empty() {
+                    name.clone()
+                } else {
+                    format!("{path}.{name}")
+                };
+                collect_leaf_paths(value, child_path, paths);
+            }
+        }
+        _ => paths.push(path),
+    }
+}
+
+// Paths of the settings that differ
+pub fn changed_settings(old: &SessionSettings, new: &SessionSettings) -> Vec<String> {
+    let mut paths = vec![];
+    if let Some(patch) =
+        patch::json_patch(&json::to_value(old).unwrap(), &json::to_value(new).unwrap())
+    {
+        collect_leaf_paths(&patch, String::new(), &mut paths);
+    }
+
+    paths
+}
+
+// Returns None if nothing changed
+pub fn reload_requirement(
+    old: &SessionSettings,
+    new: &SessionSettings,
+) -> Option<ReloadRequirement> {
+    changed_settings(old, new)
+        .iter()
+        .map(|path| setting_reload_requirement(path))
+        .max()
+}
+
+impl OpenvrConfig {
+    // The hot-reloadable values are applied to the driver at the start of the stream, so they do
+    // not need a restart
+    pub fn requires_restart(&self, new: &OpenvrConfig) -> bool {
+        let new = if cfg!(windows) {
+            OpenvrConfig {
+                foveation_center_size_x: self.foveation_center_size_x,
+                foveation_center_size_y: self.foveation_center_size_y,
+                foveation_center_shift_x: self.foveation_center_shift_x,
+                foveation_center_shift_y: self.foveation_center_shift_y,
+                foveation_edge_ratio_x: self.foveation_edge_ratio_x,
+                foveation_edge_ratio_y: self.foveation_edge_ratio_y,
+                brightness: self.brightness,
+                contrast: self.contrast,
+                saturation: self.saturation,
+                gamma: self.gamma,
+                sharpening: self.sharpening,
+                ..new.clone()
+            }
+        } else {
+            new.clone()
+        };
+
+        let new = OpenvrConfig {
+            encode_bitrate_mbs: self.encode_bitrate_mbs,
+            haptics_intensity: self.haptics_intensity,
+            haptics_amplitude_curve: self.haptics_amplitude_curve,
+            haptics_min_duration: self.haptics_min_duration,
+            haptics_low_duration_amplitude_multiplier: self
+                .haptics_low_duration_amplitude_multiplier,
+            haptics_low_duration_range: self.haptics_low_duration_range,
+            ..new
+        };
+
+        *self != new
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SessionDesc;
+
+    #[test]
+    fn requirement_paths_exist() {
+        let session_settings_json =
+            json::to_value(SessionDesc::default().session_settings).unwrap();
+
+        for (path, _) in RELOAD_REQUIREMENTS {
+            let value = path
+                .split('.')
+                .fold(&session_settings_json, |value, key| &value[key]);
+            assert!(!value.is_null(), "{path}");
+        }
+    }
+
+    #[test]
+    fn classification() {
+        let old = SessionDesc::default().session_settings;
+
+        let mut new = SessionDesc::default().session_settings;
+        assert_eq!(reload_requirement(&old, &new), None);
+
+        new.video.encode_bitrate_mbs += 10;
+        new.headset.controllers.content.haptics_intensity = 2.;
+        assert_eq!(reload_requirement(&old, &new), Some(HotReload));
+
+        let mut color_corrected = new.clone();
+        color_corrected.video.color_correction.content.gamma = 2.;
+        assert_eq!(
+            reload_requirement(&old, &color_corrected),
+            Some(if cfg!(windows) { HotReload } else { Restart })
+        );
+
+        new.audio.game_audio.enabled = !new.audio.game_audio.enabled;
+        assert_eq!(reload_requirement(&old, &new), Some(Reconnect));
+
+        new.video.color_correction.enabled = !new.video.color_correction.enabled;
+        assert_eq!(reload_requirement(&old, &new), Some(Restart));
+
+        assert_eq!(
+            setting_reload_requirement("headset.controllers.content.modeIdx"),
+            Restart
+        );
+        assert_eq!(setting_reload_requirement("extra.theme.variant"), HotReload);
+        assert_eq!(
+            setting_reload_requirement("extra.sessionRecording.content.maxRecordings"),
+            Reconnect
+        );
+    }
+}
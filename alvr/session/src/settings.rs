@@ -35,7 +35,9 @@ pub enum FrameSize {
     },
 }
 
-#[derive(SettingsSchema, Serialize, Deserialize, Clone)]
+// Typed value of a decoder option. Named after the MediaFormat value types.
+#[derive(SettingsSchema, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "type", content = "content")]
 pub enum MediacodecDataType {
     Float(f32),
     Int32(i32),
@@ -43,36 +45,29 @@ pub enum MediacodecDataType {
     String(String), // Note: Double, Rect and Size are for level 28 and not compatible with the Oculus Go
 }
 
-// #[derive(SettingsSchema, Serialize, Deserialize)]
-// #[serde(rename_all = "camelCase")]
-// pub struct VideoCoding {
-//     codec: CodecType,
-//     mediacodec_extra_options: Vec<(String, MediacodecDataType)>,
-// }
-
-#[derive(SettingsSchema, Serialize, Deserialize)]
+#[derive(SettingsSchema, Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct LatencyUseFrametimeDesc {
-    #[schema(advanced, min = 10000, max = 100000, step = 1000)]
+    #[schema(advanced, min = 10000, max = 100000, step = 1000, unit = "µs")]
     pub latency_target_maximum: u64,
 
-    #[schema(advanced, min = -4000, max = 8000, step = 500)]
+    #[schema(advanced, min = -4000, max = 8000, step = 500, unit = "µs")]
     pub latency_target_offset: i32,
 }
 
-#[derive(SettingsSchema, Serialize, Deserialize)]
+#[derive(SettingsSchema, Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct AdaptiveBitrateDesc {
-    #[schema(min = 10, max = 500, step = 1)]
+    #[schema(min = 10, max = 500, step = 1, unit = "Mbps")]
     pub bitrate_maximum: u64,
 
-    #[schema(advanced, min = 1000, max = 25000, step = 500)]
+    #[schema(advanced, min = 1000, max = 25000, step = 500, unit = "µs")]
     pub latency_target: u64,
 
     #[schema(advanced)]
     pub latency_use_frametime: Switch<LatencyUseFrametimeDesc>,
 
-    #[schema(advanced, min = 500, max = 5000, step = 100)]
+    #[schema(advanced, min = 500, max = 5000, step = 100, unit = "µs")]
     pub latency_threshold: u64,
 
     #[schema(advanced, min = 1, max = 10, step = 1)]
@@ -159,13 +154,18 @@ pub struct VideoDesc {
 
     #[schema(placeholder = "display_refresh_rate")]
     //
-    #[schema(advanced)]
+    #[schema(advanced, unit = "Hz")]
     pub preferred_fps: f32,
 
     pub codec: CodecType,
 
-    // #[schema(advanced)]
-    // pub video_coding: VideoCoding,
+    // Set after the default options of the client, so they can be overridden
+    #[schema(
+        advanced,
+        help = "Options of the client video decoder, like vendor low latency flags. On Android they are set on the MediaCodec format"
+    )]
+    pub codec_options: Vec<(String, MediacodecDataType)>,
+
     #[schema(advanced)]
     pub client_request_realtime_decoder: bool,
 
@@ -174,14 +174,24 @@ pub struct VideoDesc {
     #[schema(advanced)]
     pub sw_thread_count: u32,
 
-    #[schema(min = 1, max = 500)]
+    #[schema(
+        min = 1,
+        max = 500,
+        unit = "Mbps",
+        help = "Bitrate of the video stream. Overridden by the adaptive bitrate, if enabled"
+    )]
     pub encode_bitrate_mbs: u64,
 
     pub adaptive_bitrate: Switch<AdaptiveBitrateDesc>,
 
-    #[schema(advanced)]
+    #[schema(advanced, unit = "s")]
     pub seconds_from_vsync_to_photons: f32,
 
+    // The Linux encoder supports foveated rendering only with HEVC
+    #[schema(
+        requires = r#"os != "linux" || video.codec.variant == "HEVC""#,
+        help = "Reduces the resolution of the periphery of the image, to lower the bitrate"
+    )]
     pub foveated_rendering: Switch<FoveatedRenderingDesc>,
     pub color_correction: Switch<ColorCorrectionDesc>,
 }
@@ -198,10 +208,10 @@ pub enum AudioDeviceId {
 #[derive(SettingsSchema, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct AudioConfig {
-    #[schema(min = 0, max = 200)]
+    #[schema(min = 0, max = 200, unit = "ms")]
     pub average_buffering_ms: u64,
 
-    #[schema(advanced, min = 1, max = 20)]
+    #[schema(advanced, min = 1, max = 20, unit = "ms")]
     pub batch_ms: u64,
 }
 
@@ -228,11 +238,10 @@ pub struct MicrophoneDesc {
 
     #[schema(placeholder = "output_device_dropdown")]
     //
-    #[cfg(not(target_os = "linux"))]
-    #[schema(advanced)]
+    #[schema(advanced, visible_if = r#"os != "linux""#)]
     pub output_device_id: AudioDeviceId,
 
-    #[schema(advanced)]
+    #[schema(advanced, unit = "Hz")]
     pub sample_rate: u32,
 
     pub config: AudioConfig,
@@ -248,7 +257,7 @@ pub enum LinuxAudioBackend {
 #[derive(SettingsSchema, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct AudioSection {
-    #[schema(advanced)]
+    #[schema(advanced, visible_if = r#"os == "linux""#)]
     pub linux_backend: LinuxAudioBackend,
 
     pub game_audio: Switch<GameAudioDesc>,
@@ -428,6 +437,24 @@ pub enum SocketProtocol {
     },
 
     Tcp,
+
+    #[schema(advanced)]
+    Quic,
+}
+
+#[derive(SettingsSchema, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", tag = "type", content = "content")]
+pub enum BindAddress {
+    Ipv4,
+
+    // Accepts both IPv4 and IPv6 peers, and enables IPv6 discovery
+    DualStack,
+
+    // Address of the network interface to use
+    #[serde(rename_all = "camelCase")]
+    Custom {
+        address: String,
+    },
 }
 
 #[derive(SettingsSchema, Serialize, Deserialize)]
@@ -442,9 +469,28 @@ pub struct DiscoveryConfig {
 pub struct ConnectionDesc {
     pub client_discovery: Switch<DiscoveryConfig>,
 
+    #[schema(advanced)]
+    pub bind_address: BindAddress,
+
+    // Interface used for IPv6 discovery. 0 lets the OS choose
+    #[schema(advanced)]
+    pub multicast_interface_index: u32,
+
+    #[schema(advanced, min = 1024, max = 65535)]
+    pub discovery_port: u16,
+
+    #[schema(advanced, min = 1024, max = 65535)]
+    pub control_port: u16,
+
     #[schema(advanced, min = 1024, max = 65535)]
     pub web_server_port: u16,
 
+    #[schema(
+        advanced,
+        help = "Lets other devices on the network use the dashboard. They need the access token saved in web_server_token.txt in the config folder"
+    )]
+    pub web_server_lan_access: bool,
+
     pub stream_protocol: SocketProtocol,
 
     #[schema(advanced)]
@@ -488,6 +534,18 @@ pub enum LogLevel {
     Debug,
 }
 
+#[derive(SettingsSchema, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionRecordingDesc {
+    // Recorded sessions to keep. The oldest ones are deleted, with all their parts, when a new
+    // session is recorded.
+    #[schema(min = 1, max = 100, step = 1)]
+    pub max_recordings: u64,
+    // The recording of a session is split in parts of this size
+    #[schema(min = 1, max = 1000, step = 1)]
+    pub max_recording_size_mb: u64,
+}
+
 #[derive(SettingsSchema, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ExtraDesc {
@@ -499,6 +557,12 @@ pub struct ExtraDesc {
     pub update_channel: UpdateChannel,
     pub log_to_disk: bool,
 
+    #[schema(
+        advanced,
+        help = "Write statistics, bitrate decisions and connection events of each SteamVR session to a file in the recordings folder, to be attached to bug reports"
+    )]
+    pub session_recording: Switch<SessionRecordingDesc>,
+
     #[schema(advanced)]
     pub notification_level: LogLevel,
     #[schema(advanced)]
@@ -540,32 +604,17 @@ pub fn session_settings_default() -> SettingsDefault {
             codec: CodecTypeDefault {
                 variant: CodecTypeDefaultVariant::H264,
             },
-            // video_coding: VideoCodingDefault {
-            //     codec: CodecTypeDefault {
-            //         variant: CodecTypeDefaultVariant::H264,
-            //     },
-            //     mediacodec_extra_options: DictionaryDefault {
-            //         key: "".into(),
-            //         value: MediacodecDataTypeDefault {
-            //             variant: MediacodecDataTypeDefaultVariant::String,
-            //             Float: 0.0,
-            //             Int32: 0,
-            //             Int64: 0,
-            //             String: "".into(),
-            //         },
-            //         content: vec![
-            //             ("operating-rate".into(), MediacodecDataType::Int32(i32::MAX)),
-            //             ("priority".into(), MediacodecDataType::Int32(0)),
-            //             // low-latency: only applicable on API level 30. Quest 1 and 2 might not be
-            //             // cabable, since they are on level 29.
-            //             ("low-latency".into(), MediacodecDataType::Int32(1)),
-            //             (
-            //                 "vendor.qti-ext-dec-low-latency.enable".into(),
-            //                 MediacodecDataType::Int32(1),
-            //             ),
-            //         ],
-            //     },
-            // },
+            codec_options: DictionaryDefault {
+                key: "".into(),
+                value: MediacodecDataTypeDefault {
+                    variant: MediacodecDataTypeDefaultVariant::Int32,
+                    Float: 0.0,
+                    Int32: 0,
+                    Int64: 0,
+                    String: "".into(),
+                },
+                content: vec![],
+            },
             client_request_realtime_decoder: true,
             use_10bit_encoder: false,
             sw_thread_count: 0,
@@ -638,7 +687,6 @@ pub fn session_settings_default() -> SettingsDefault {
                         Name: "".into(),
                         Index: 1,
                     },
-                    #[cfg(not(target_os = "linux"))]
                     output_device_id: AudioDeviceIdDefault {
                         variant: AudioDeviceIdDefaultVariant::Default,
                         Name: "".into(),
@@ -705,7 +753,17 @@ pub fn session_settings_default() -> SettingsDefault {
                     auto_trust_clients: cfg!(debug_assertions),
                 },
             },
+            bind_address: BindAddressDefault {
+                variant: BindAddressDefaultVariant::Ipv4,
+                Custom: BindAddressCustomDefault {
+                    address: "0.0.0.0".into(),
+                },
+            },
+            multicast_interface_index: 0,
+            discovery_port: 9943,
+            control_port: 9943,
             web_server_port: 8082,
+            web_server_lan_access: false,
             stream_protocol: SocketProtocolDefault {
                 variant: if !cfg!(target_os = "linux") {
                     SocketProtocolDefaultVariant::Udp
@@ -740,6 +798,13 @@ pub fn session_settings_default() -> SettingsDefault {
                 },
             },
             log_to_disk: cfg!(debug_assertions),
+            session_recording: SwitchDefault {
+                enabled: false,
+                content: SessionRecordingDescDefault {
+                    max_recordings: 10,
+                    max_recording_size_mb: 50,
+                },
+            },
             notification_level: LogLevelDefault {
                 variant: if cfg!(debug_assertions) {
                     LogLevelDefaultVariant::Info
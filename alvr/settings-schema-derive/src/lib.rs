@@ -4,8 +4,8 @@ use proc_macro2::{Span, TokenStream as TokenStream2};
 use quote::{quote, ToTokens};
 use std::string::ToString;
 use syn::{
-    Attribute, Data, DeriveInput, Error, Fields, FieldsNamed, GenericArgument, Ident, Lit, Meta,
-    NestedMeta, PathArguments, Type,
+    Attribute, Data, DeriveInput, Error, Fields, FieldsNamed, GenericArgument, Ident, Lit, LitStr,
+    Meta, NestedMeta, PathArguments, Type,
 };
 
 fn error<T, TT: ToTokens>(message: &str, tokens: TT) -> Result<T, TokenStream> {
@@ -58,6 +58,10 @@ struct SchemaAttributes {
     max: Option<Lit>,
     step: Option<Lit>,
     gui: Option<Lit>,
+    help: Option<LitStr>,
+    unit: Option<LitStr>,
+    visible_if: Option<LitStr>,
+    requires: Option<LitStr>,
 }
 
 fn schema_attributes(attrs: Vec<Attribute>) -> Result<SchemaAttributes, TokenStream> {
@@ -68,6 +72,10 @@ fn schema_attributes(attrs: Vec<Attribute>) -> Result<SchemaAttributes, TokenStr
     let mut max = None;
     let mut step = None;
     let mut gui = None;
+    let mut help = None;
+    let mut unit = None;
+    let mut visible_if = None;
+    let mut requires = None;
     for attr in schema_attrs(attrs, "schema") {
         let parsed_attr = attr
             .parse_meta()
@@ -96,6 +104,14 @@ fn schema_attributes(attrs: Vec<Attribute>) -> Result<SchemaAttributes, TokenStr
                                     "max" => max = Some(name_value_arg.lit),
                                     "step" => step = Some(name_value_arg.lit),
                                     "gui" => gui = Some(name_value_arg.lit),
+                                    "help" => help = Some(string_literal(name_value_arg.lit)?),
+                                    "unit" => unit = Some(string_literal(name_value_arg.lit)?),
+                                    "visible_if" => {
+                                        visible_if = Some(string_literal(name_value_arg.lit)?)
+                                    }
+                                    "requires" => {
+                                        requires = Some(string_literal(name_value_arg.lit)?)
+                                    }
                                     "placeholder" => {
                                         if let Lit::Str(lit_str) = name_value_arg.lit {
                                             placeholders.push(lit_str.value());
@@ -127,9 +143,46 @@ fn schema_attributes(attrs: Vec<Attribute>) -> Result<SchemaAttributes, TokenStr
         max,
         step,
         gui,
+        help,
+        unit,
+        visible_if,
+        requires,
     })
 }
 
+fn string_literal(literal: Lit) -> Result<LitStr, TokenStream> {
+    if let Lit::Str(lit_str) = literal {
+        Ok(lit_str)
+    } else {
+        error("Expected string", literal)
+    }
+}
+
+// Fields of EntryData, except content
+fn entry_data_fields(schema_attrs: &SchemaAttributes) -> TokenStream2 {
+    let maybe_string = |maybe_lit_str: &Option<LitStr>| {
+        if let Some(lit_str) = maybe_lit_str {
+            quote!(Some(#lit_str.into()))
+        } else {
+            quote!(None)
+        }
+    };
+
+    let advanced = schema_attrs.advanced;
+    let help_ts = maybe_string(&schema_attrs.help);
+    let unit_ts = maybe_string(&schema_attrs.unit);
+    let visible_if_ts = maybe_string(&schema_attrs.visible_if);
+    let requires_ts = maybe_string(&schema_attrs.requires);
+
+    quote! {
+        advanced: #advanced,
+        help: #help_ts,
+        unit: #unit_ts,
+        visible_if: #visible_if_ts,
+        requires: #requires_ts,
+    }
+}
+
 struct TypeSchema {
     default_ty_ts: TokenStream2,
     schema_code_ts: TokenStream2,
@@ -458,7 +511,7 @@ fn schema_named_fields(
             schema_pairs_ts.push(quote!((#schema_key.into(), None)))
         }
 
-        let advanced = schema_attrs.advanced;
+        let entry_data_fields_ts = entry_data_fields(&schema_attrs);
         let TypeSchema {
             default_ty_ts,
             schema_code_ts,
@@ -471,7 +524,7 @@ fn schema_named_fields(
             (
                 #schema_key.into(),
                 Some(EntryData {
-                    advanced: #advanced,
+                    #entry_data_fields_ts
                     content: #schema_code_ts
                 })
             )
@@ -545,7 +598,7 @@ fn schema(input: DeriveInput) -> Result<TokenStream2, TokenStream> {
                 let schema_attrs = schema_attributes(variant.attrs)?;
                 let variant_ident = variant.ident;
                 let variant_string = variant_ident.to_string();
-                let advanced = schema_attrs.advanced;
+                let entry_data_fields_ts = entry_data_fields(&schema_attrs);
                 match variant.fields {
                     Fields::Named(fields_block) => {
                         let variant_fields_data =
@@ -562,7 +615,7 @@ fn schema(input: DeriveInput) -> Result<TokenStream2, TokenStream> {
                         schema_variants_ts.push(quote! {{
                             let default = default.#variant_ident;
                             Some(EntryData {
-                                advanced: #advanced,
+                                #entry_data_fields_ts
                                 content: #schema_variant_fields_code_ts
                             })
                         }});
@@ -590,7 +643,7 @@ fn schema(input: DeriveInput) -> Result<TokenStream2, TokenStream> {
                         schema_variants_ts.push(quote! {{
                             let default = default.#variant_ident;
                             Some(EntryData {
-                                advanced: #advanced,
+                                #entry_data_fields_ts
                                 content: #schema_code_ts
                             })
                         }});
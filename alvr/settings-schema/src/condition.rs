@@ -0,0 +1,109 @@
+use serde_json::Value;
+
+// Name reserved for the operating system of the server, as in std::env::consts::OS
+const OS_PATH: &str = "os";
+
+struct Comparison {
+    path: Vec<String>,
+    equal: bool,
+    value: Value,
+}
+
+/// Condition used by the attributes `#[schema(visible_if = "...")]` and
+/// `#[schema(requires = "...")]`.
+///
+/// The syntax is `<path> == <value>` or `<path> != <value>`, where the path contains the keys of the
+/// settings JSON (in the default representation) separated by dots, starting from the root, and the
+/// value is a JSON literal. The path `os` refers to the operating system of the server instead.
+/// Comparisons can be joined with `&&` and `||`, where `&&` takes precedence. Parentheses are not
+/// supported.
+///
+/// Example: `os != "linux" || video.codec.variant == "HEVC"`
+pub struct Condition {
+    // Disjunction of conjunctions
+    alternatives: Vec<Vec<Comparison>>,
+}
+
+impl Condition {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let parse_comparison = |text: &str| {
+            let (path, value, equal) = if let Some((path, value)) = text.split_once("==") {
+                (path, value, true)
+            } else if let Some((path, value)) = text.split_once("!=") {
+                (path, value, false)
+            } else {
+                return Err(format!("expected \"==\" or \"!=\" in \"{}\"", text.trim()));
+            };
+
+            let path = path
+                .trim()
+                .split('.')
+                .map(|key| {
+                    if !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+                    {
+                        Ok(key.to_owned())
+                    } else {
+                        Err(format!("invalid path \"{}\"", path.trim()))
+                    }
+                })
+                .collect::<Result<_, _>>()?;
+            let value = serde_json::from_str(value.trim())
+                .map_err(|_| format!("invalid value \"{}\"", value.trim()))?;
+
+            Ok(Comparison { path, equal, value })
+        };
+
+        Ok(Self {
+            alternatives: text
+                .split("||")
+                .map(|conjunction| conjunction.split("&&").map(parse_comparison).collect())
+                .collect::<Result<_, _>>()?,
+        })
+    }
+
+    /// `settings` is the root settings JSON in the default representation. `os` is compared with
+    /// the `os` path.
+    pub fn evaluate(&self, settings: &Value, os: &str) -> bool {
+        self.alternatives.iter().any(|conjunction| {
+            conjunction.iter().all(|comparison| {
+                let value = if comparison.path == [OS_PATH] {
+                    Value::String(os.to_owned())
+                } else {
+                    comparison
+                        .path
+                        .iter()
+                        .fold(settings, |value, key| &value[key])
+                        .clone()
+                };
+
+                (value == comparison.value) == comparison.equal
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn evaluate() {
+        let settings = json!({ "video": { "codec": { "variant": "H264" }, "bitrate": 30 } });
+        let condition =
+            Condition::parse(r#"os != "linux" || video.codec.variant == "HEVC""#).unwrap();
+
+        assert!(condition.evaluate(&settings, "windows"));
+        assert!(!condition.evaluate(&settings, "linux"));
+
+        let condition = Condition::parse("video.bitrate == 30 && video.missing == null").unwrap();
+        assert!(condition.evaluate(&settings, "linux"));
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(Condition::parse("video.codec").is_err());
+        assert!(Condition::parse("video..codec == 1").is_err());
+        assert!(Condition::parse("video.codec == HEVC").is_err());
+    }
+}
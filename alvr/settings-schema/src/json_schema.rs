@@ -0,0 +1,346 @@
+use crate::{EntryData, SchemaNode};
+use serde_json::{json, Map, Value};
+
+const DRAFT_2020_12: &str = "https://json-schema.org/draft/2020-12/schema";
+
+// The representation of the settings is the one of `<your_struct_or_enum>`, used for the content of
+// vectors and dictionaries. The default representation is the one of `<your_struct_or_enum>Default`.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Representation {
+    Default,
+    Settings,
+}
+
+// Names of the Switch states in the settings representation
+pub(crate) fn switch_states() -> (&'static str, &'static str) {
+    if cfg!(any(
+        feature = "rename_camel_case",
+        feature = "rename_snake_case"
+    )) {
+        ("enabled", "disabled")
+    } else {
+        ("Enabled", "Disabled")
+    }
+}
+
+// Help followed by the unit, if any
+pub(crate) fn entry_description(data: &EntryData) -> Option<String> {
+    match (&data.help, &data.unit) {
+        (Some(help), Some(unit)) => Some(format!("{help} ({unit})")),
+        (Some(help), None) => Some(help.clone()),
+        (None, Some(unit)) => Some(format!("Unit: {unit}")),
+        (None, None) => None,
+    }
+}
+
+// Closed object. Only the properties with a schema are required, the others are placeholders that
+// can hold any value.
+fn object(properties: Vec<(String, Option<Value>)>) -> Value {
+    let required = properties
+        .iter()
+        .filter(|(_, maybe_schema)| maybe_schema.is_some())
+        .map(|(name, _)| Value::String(name.clone()))
+        .collect::<Vec<_>>();
+    let properties = properties
+        .into_iter()
+        .map(|(name, maybe_schema)| (name, maybe_schema.unwrap_or(Value::Bool(true))))
+        .collect::<Map<_, _>>();
+
+    json!({
+        "type": "object",
+        "properties": properties,
+        "required": required,
+        "additionalProperties": false
+    })
+}
+
+// serde_json does not support i128. Values outside of the u64 and i64 ranges lose precision
+fn integer(value: i128) -> Value {
+    if let Ok(value) = u64::try_from(value) {
+        json!(value)
+    } else if let Ok(value) = i64::try_from(value) {
+        json!(value)
+    } else {
+        json!(value as f64)
+    }
+}
+
+fn entry_schema(data: &EntryData, representation: Representation) -> Value {
+    let mut schema = node_schema(&data.content, representation);
+    if let (Some(description), Value::Object(fields)) = (entry_description(data), &mut schema) {
+        fields.insert("description".into(), Value::String(description));
+    }
+
+    schema
+}
+
+fn node_schema(schema: &SchemaNode, representation: Representation) -> Value {
+    let is_default = representation == Representation::Default;
+
+    match schema {
+        SchemaNode::Section { entries } => object(
+            entries
+                .iter()
+                .map(|(name, maybe_data)| {
+                    (
+                        name.clone(),
+                        maybe_data
+                            .as_ref()
+                            .map(|data| entry_schema(data, representation)),
+                    )
+                })
+                .collect(),
+        ),
+        SchemaNode::Choice { default, variants } => {
+            let names = variants
+                .iter()
+                .map(|(name, _)| name.as_str())
+                .collect::<Vec<_>>();
+
+            if is_default {
+                let mut properties = vec![(
+                    "variant".to_owned(),
+                    Some(json!({ "enum": names, "default": default })),
+                )];
+                properties.extend(variants.iter().filter_map(|(name, maybe_data)| {
+                    let data = maybe_data.as_ref()?;
+                    Some((name.clone(), Some(entry_schema(data, representation))))
+                }));
+
+                object(properties)
+            } else if variants.iter().all(|(_, maybe_data)| maybe_data.is_none()) {
+                // Enums without data are not tagged
+                json!({ "enum": names })
+            } else {
+                let variants = variants
+                    .iter()
+                    .map(|(name, maybe_data)| {
+                        let mut properties =
+                            vec![("type".to_owned(), Some(json!({ "const": name })))];
+                        if let Some(data) = maybe_data {
+                            properties
+                                .push(("content".into(), Some(entry_schema(data, representation))));
+                        }
+
+                        object(properties)
+                    })
+                    .collect::<Vec<_>>();
+
+                json!({ "oneOf": variants })
+            }
+        }
+        SchemaNode::Optional {
+            default_set,
+            content,
+        } => {
+            if is_default {
+                object(vec![
+                    (
+                        "set".into(),
+                        Some(json!({ "type": "boolean", "default": default_set })),
+                    ),
+                    ("content".into(), Some(node_schema(content, representation))),
+                ])
+            } else {
+                json!({ "anyOf": [node_schema(content, representation), { "type": "null" }] })
+            }
+        }
+        SchemaNode::Switch {
+            default_enabled,
+            content,
+            ..
+        } => {
+            if is_default {
+                object(vec![
+                    (
+                        "enabled".into(),
+                        Some(json!({ "type": "boolean", "default": default_enabled })),
+                    ),
+                    ("content".into(), Some(node_schema(content, representation))),
+                ])
+            } else {
+                let (enabled, disabled) = switch_states();
+                json!({
+                    "oneOf": [
+                        object(vec![
+                            ("state".into(), Some(json!({ "const": enabled }))),
+                            ("content".into(), Some(node_schema(content, representation))),
+                        ]),
+                        object(vec![("state".into(), Some(json!({ "const": disabled })))]),
+                    ]
+                })
+            }
+        }
+        SchemaNode::Boolean { default } => json!({ "type": "boolean", "default": default }),
+        SchemaNode::Integer {
+            default,
+            min,
+            max,
+            step,
+            ..
+        } => {
+            let mut schema = json!({ "type": "integer", "default": integer(*default) });
+            if let Some(min) = min {
+                schema["minimum"] = integer(*min);
+            }
+            if let Some(max) = max {
+                schema["maximum"] = integer(*max);
+            }
+            // Steps count from the minimum, which JSON Schema can express only if it is a multiple
+            // of the step
+            if let Some(step) = step.filter(|step| *step > 0) {
+                if min.unwrap_or(0) % step == 0 {
+                    schema["multipleOf"] = integer(step);
+                }
+            }
+
+            schema
+        }
+        // Float steps are only a GUI hint
+        SchemaNode::Float {
+            default, min, max, ..
+        } => {
+            let mut schema = json!({ "type": "number", "default": default });
+            if let Some(min) = min {
+                schema["minimum"] = json!(min);
+            }
+            if let Some(max) = max {
+                schema["maximum"] = json!(max);
+            }
+
+            schema
+        }
+        SchemaNode::Text { default } => json!({ "type": "string", "default": default }),
+        SchemaNode::Array(elements) => json!({
+            "type": "array",
+            "prefixItems": elements
+                .iter()
+                .map(|element| node_schema(element, representation))
+                .collect::<Vec<_>>(),
+            "items": false,
+            "minItems": elements.len(),
+        }),
+        SchemaNode::Vector {
+            default_element,
+            default,
+        } => {
+            let content = json!({
+                "type": "array",
+                "items": node_schema(default_element, Representation::Settings),
+            });
+
+            if is_default {
+                let mut content = content;
+                content["default"] = default.clone();
+                object(vec![
+                    (
+                        "element".into(),
+                        Some(node_schema(default_element, representation)),
+                    ),
+                    ("content".into(), Some(content)),
+                ])
+            } else {
+                content
+            }
+        }
+        SchemaNode::Dictionary {
+            default_key,
+            default_value,
+            default,
+        } => {
+            let content = json!({
+                "type": "array",
+                "items": {
+                    "type": "array",
+                    "prefixItems": [
+                        { "type": "string" },
+                        node_schema(default_value, Representation::Settings),
+                    ],
+                    "items": false,
+                    "minItems": 2,
+                },
+            });
+
+            if is_default {
+                let mut content = content;
+                content["default"] = default.clone();
+                object(vec![
+                    (
+                        "key".into(),
+                        Some(json!({ "type": "string", "default": default_key })),
+                    ),
+                    (
+                        "value".into(),
+                        Some(node_schema(default_value, representation)),
+                    ),
+                    ("content".into(), Some(content)),
+                ])
+            } else {
+                content
+            }
+        }
+    }
+}
+
+/// JSON Schema (draft 2020-12) of the default representation described by `schema` (the one of
+/// `<your_struct_or_enum>Default`, used by session files). The content of vectors and dictionaries
+/// is described with the representation of `<your_struct_or_enum>`. The `requires` conditions are
+/// not included, they are checked by [`validate`](crate::validate).
+pub fn json_schema(schema: &SchemaNode, title: &str) -> Value {
+    let mut json_schema = node_schema(schema, Representation::Default);
+    if let Value::Object(fields) = &mut json_schema {
+        fields.insert("$schema".into(), Value::String(DRAFT_2020_12.into()));
+        fields.insert("title".into(), Value::String(title.into()));
+    }
+
+    json_schema
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn switch_and_vector() {
+        let schema = SchemaNode::Section {
+            entries: vec![(
+                "bitrate".into(),
+                Some(EntryData {
+                    advanced: false,
+                    help: Some("Video bitrate".into()),
+                    unit: Some("Mbps".into()),
+                    visible_if: None,
+                    requires: None,
+                    content: SchemaNode::Switch {
+                        default_enabled: true,
+                        content_advanced: false,
+                        content: Box::new(SchemaNode::Vector {
+                            default_element: Box::new(SchemaNode::Integer {
+                                default: 30,
+                                min: Some(10),
+                                max: None,
+                                step: Some(5),
+                                gui: None,
+                            }),
+                            default: json!([30]),
+                        }),
+                    },
+                }),
+            )],
+        };
+
+        let json_schema = json_schema(&schema, "Example");
+        assert_eq!(json_schema["$schema"], DRAFT_2020_12);
+
+        let bitrate = &json_schema["properties"]["bitrate"];
+        assert_eq!(bitrate["description"], "Video bitrate (Mbps)");
+        assert_eq!(bitrate["required"], json!(["enabled", "content"]));
+
+        let vector = &bitrate["properties"]["content"];
+        assert_eq!(
+            vector["properties"]["element"],
+            json!({ "type": "integer", "default": 30, "minimum": 10, "multipleOf": 5 })
+        );
+        assert_eq!(vector["properties"]["content"]["default"], json!([30]));
+    }
+}
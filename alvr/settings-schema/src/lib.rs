@@ -1,8 +1,17 @@
+mod condition;
+mod json_schema;
+mod typescript;
+mod validation;
+
 use serde::{Deserialize, Serialize};
 
+pub use condition::*;
+pub use json_schema::json_schema;
 pub use serde;
 pub use serde_json;
 pub use settings_schema_derive::SettingsSchema;
+pub use typescript::typescript_definition;
+pub use validation::*;
 
 /// The `Switch` is used to represent something that makes sense to specify its state only when it's enabled.
 /// This should be used differently than `Option(al)`, that represent a value that can be omitted.
@@ -68,10 +77,21 @@ pub enum NumericGuiType {
     Slider,
 }
 
-/// Data associated to a named or unnamed field. Can be set to advanced through the attribute `#[schema(advanced)]`
+/// Data associated to a named or unnamed field. Can be set to advanced through the attribute `#[schema(advanced)]`.
+/// `help` and `unit` are set through `#[schema(help = "...")]` and `#[schema(unit = "...")]`.
+/// `visible_if` and `requires` contain a [`Condition`], set through `#[schema(visible_if = "...")]` and
+/// `#[schema(requires = "...")]`. A field that is not visible should be hidden by GUIs. A field that
+/// is enabled (or set, or true, or the selected variant) while its requirement is not met is
+/// reported by [`validate`].
 #[derive(Serialize, Deserialize, Clone, Debug)]
+#[cfg_attr(feature = "rename_camel_case", serde(rename_all = "camelCase"))]
+#[cfg_attr(feature = "rename_snake_case", serde(rename_all = "snake_case"))]
 pub struct EntryData {
     pub advanced: bool,
+    pub help: Option<String>,
+    pub unit: Option<String>,
+    pub visible_if: Option<String>,
+    pub requires: Option<String>,
     pub content: SchemaNode,
 }
 
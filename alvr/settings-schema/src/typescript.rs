@@ -0,0 +1,305 @@
+use crate::{
+    json_schema::{entry_description, switch_states, Representation},
+    EntryData, SchemaNode,
+};
+
+const INDENT: &str = "    ";
+
+fn property_name(name: &str) -> String {
+    let is_identifier = name
+        .chars()
+        .next()
+        .map(|c| c.is_ascii_alphabetic() || c == '_' || c == '$')
+        .unwrap_or(false)
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$');
+
+    if is_identifier {
+        name.to_owned()
+    } else {
+        format!("{name:?}")
+    }
+}
+
+// Object type literal. Each property is (name, doc comment, type)
+fn object_type(properties: Vec<(String, Option<String>, String)>, indent: usize) -> String {
+    if properties.is_empty() {
+        return "{}".into();
+    }
+
+    let inner_indent = INDENT.repeat(indent + 1);
+    let mut text = "{\n".to_owned();
+    for (name, maybe_doc, ty) in properties {
+        if let Some(doc) = maybe_doc {
+            text += &format!("{inner_indent}/** {} */\n", doc.replace("*/", "* /"));
+        }
+        text += &format!("{inner_indent}{}: {ty};\n", property_name(&name));
+    }
+    text += &format!("{}}}", INDENT.repeat(indent));
+
+    text
+}
+
+fn entry_property(
+    name: &str,
+    data: &EntryData,
+    representation: Representation,
+    indent: usize,
+) -> (String, Option<String>, String) {
+    (
+        name.to_owned(),
+        entry_description(data),
+        node_type(&data.content, representation, indent + 1),
+    )
+}
+
+// Wraps union types used as array elements
+fn element_type(ty: String) -> String {
+    if ty.contains('|') {
+        format!("Array<{ty}>")
+    } else {
+        format!("{ty}[]")
+    }
+}
+
+fn node_type(schema: &SchemaNode, representation: Representation, indent: usize) -> String {
+    let is_default = representation == Representation::Default;
+
+    match schema {
+        SchemaNode::Section { entries } => object_type(
+            entries
+                .iter()
+                .filter_map(|(name, maybe_data)| {
+                    Some(entry_property(
+                        name,
+                        maybe_data.as_ref()?,
+                        representation,
+                        indent,
+                    ))
+                })
+                .collect(),
+            indent,
+        ),
+        SchemaNode::Choice { variants, .. } => {
+            let names = variants
+                .iter()
+                .map(|(name, _)| format!("{name:?}"))
+                .collect::<Vec<_>>()
+                .join(" | ");
+
+            if is_default {
+                let mut properties = vec![("variant".to_owned(), None, names)];
+                properties.extend(variants.iter().filter_map(|(name, maybe_data)| {
+                    Some(entry_property(
+                        name,
+                        maybe_data.as_ref()?,
+                        representation,
+                        indent,
+                    ))
+                }));
+
+                object_type(properties, indent)
+            } else if variants.iter().all(|(_, maybe_data)| maybe_data.is_none()) {
+                // Enums without data are not tagged
+                names
+            } else {
+                variants
+                    .iter()
+                    .map(|(name, maybe_data)| {
+                        let mut properties = vec![("type".to_owned(), None, format!("{name:?}"))];
+                        if let Some(data) = maybe_data {
+                            properties.push(entry_property(
+                                "content",
+                                data,
+                                representation,
+                                indent,
+                            ));
+                        }
+
+                        object_type(properties, indent)
+                    })
+                    .collect::<Vec<_>>()
+                    .join(" | ")
+            }
+        }
+        SchemaNode::Optional { content, .. } => {
+            let content_type = node_type(content, representation, indent + 1);
+            if is_default {
+                object_type(
+                    vec![
+                        ("set".into(), None, "boolean".into()),
+                        ("content".into(), None, content_type),
+                    ],
+                    indent,
+                )
+            } else {
+                format!("{content_type} | null")
+            }
+        }
+        SchemaNode::Switch { content, .. } => {
+            let content_type = node_type(content, representation, indent + 1);
+            if is_default {
+                object_type(
+                    vec![
+                        ("enabled".into(), None, "boolean".into()),
+                        ("content".into(), None, content_type),
+                    ],
+                    indent,
+                )
+            } else {
+                let (enabled, disabled) = switch_states();
+                format!(
+                    "{} | {}",
+                    object_type(
+                        vec![
+                            ("state".into(), None, format!("{enabled:?}")),
+                            ("content".into(), None, content_type),
+                        ],
+                        indent,
+                    ),
+                    object_type(
+                        vec![("state".into(), None, format!("{disabled:?}"))],
+                        indent
+                    )
+                )
+            }
+        }
+        SchemaNode::Boolean { .. } => "boolean".into(),
+        SchemaNode::Integer { .. } | SchemaNode::Float { .. } => "number".into(),
+        SchemaNode::Text { .. } => "string".into(),
+        SchemaNode::Array(elements) => format!(
+            "[{}]",
+            elements
+                .iter()
+                .map(|element| node_type(element, representation, indent))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+        SchemaNode::Vector {
+            default_element, ..
+        } => {
+            let content_type = element_type(node_type(
+                default_element,
+                Representation::Settings,
+                indent + 1,
+            ));
+            if is_default {
+                object_type(
+                    vec![
+                        (
+                            "element".into(),
+                            None,
+                            node_type(default_element, representation, indent + 1),
+                        ),
+                        ("content".into(), None, content_type),
+                    ],
+                    indent,
+                )
+            } else {
+                content_type
+            }
+        }
+        SchemaNode::Dictionary { default_value, .. } => {
+            let content_type = format!(
+                "Array<[string, {}]>",
+                node_type(default_value, Representation::Settings, indent + 1)
+            );
+            if is_default {
+                object_type(
+                    vec![
+                        ("key".into(), None, "string".into()),
+                        (
+                            "value".into(),
+                            None,
+                            node_type(default_value, representation, indent + 1),
+                        ),
+                        ("content".into(), None, content_type),
+                    ],
+                    indent,
+                )
+            } else {
+                content_type
+            }
+        }
+    }
+}
+
+/// TypeScript definition of the type `type_name`, in the default representation described by
+/// `schema` (like [`json_schema`](crate::json_schema)). Help strings and units become doc comments.
+pub fn typescript_definition(schema: &SchemaNode, type_name: &str) -> String {
+    format!(
+        "export type {type_name} = {};\n",
+        node_type(schema, Representation::Default, 0)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn choice_and_dictionary() {
+        let schema = SchemaNode::Section {
+            entries: vec![
+                (
+                    "codec".into(),
+                    Some(EntryData {
+                        advanced: false,
+                        help: None,
+                        unit: None,
+                        visible_if: None,
+                        requires: None,
+                        content: SchemaNode::Choice {
+                            default: "H264".into(),
+                            variants: vec![("H264".into(), None), ("HEVC".into(), None)],
+                        },
+                    }),
+                ),
+                (
+                    "options".into(),
+                    Some(EntryData {
+                        advanced: false,
+                        help: None,
+                        unit: Some("ms".into()),
+                        visible_if: None,
+                        requires: None,
+                        content: SchemaNode::Dictionary {
+                            default_key: "".into(),
+                            default_value: Box::new(SchemaNode::Optional {
+                                default_set: false,
+                                content: Box::new(SchemaNode::Float {
+                                    default: 0.,
+                                    min: None,
+                                    max: None,
+                                    step: None,
+                                    gui: None,
+                                }),
+                            }),
+                            default: serde_json::json!([]),
+                        },
+                    }),
+                ),
+            ],
+        };
+
+        assert_eq!(
+            typescript_definition(&schema, "Example"),
+            r#"export type Example = {
+    codec: {
+        variant: "H264" | "HEVC";
+    };
+    /** Unit: ms */
+    options: {
+        key: string;
+        value: {
+            set: boolean;
+            content: number;
+        };
+        content: Array<[string, number | null]>;
+    };
+};
+"#
+        );
+    }
+}
@@ -0,0 +1,399 @@
+use crate::{Condition, EntryData, SchemaNode};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fmt;
+
+/// Error found by [`validate`], addressed by the path of the invalid value.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ValidationError {
+    /// JSON keys (or array indices) separated by dots. Empty for the root value.
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.path.is_empty() {
+            write!(f, "{}", self.message)
+        } else {
+            write!(f, "{}: {}", self.path, self.message)
+        }
+    }
+}
+
+struct Validator<'a> {
+    root: &'a Value,
+    os: &'a str,
+    errors: Vec<ValidationError>,
+}
+
+impl Validator<'_> {
+    fn error(&mut self, path: &str, message: String) {
+        self.errors.push(ValidationError {
+            path: path.to_owned(),
+            message,
+        });
+    }
+
+    fn child_path(path: &str, name: &str) -> String {
+        if path.is_empty() {
+            name.to_owned()
+        } else {
+            format!("{path}.{name}")
+        }
+    }
+
+    // Validates the named children of an object. Children with no schema must be absent.
+    fn validate_fields<'a>(
+        &mut self,
+        path: &str,
+        value: &Value,
+        fields: impl Iterator<Item = (&'a str, Option<&'a SchemaNode>)>,
+    ) {
+        let object = if let Some(object) = value.as_object() {
+            object
+        } else {
+            self.error(path, format!("expected object, found {value}"));
+            return;
+        };
+
+        let mut known_names = vec![];
+        for (name, maybe_schema) in fields {
+            known_names.push(name);
+
+            if let Some(schema) = maybe_schema {
+                let child_path = Self::child_path(path, name);
+                match object.get(name) {
+                    Some(child) => self.validate(schema, child, &child_path),
+                    None => self.error(&child_path, "missing".into()),
+                }
+            }
+        }
+
+        for name in object.keys() {
+            if !known_names.contains(&name.as_str()) {
+                self.error(&Self::child_path(path, name), "unknown field".into());
+            }
+        }
+    }
+
+    // `active` is true if the entry is enabled, set, true or the selected variant
+    fn validate_entry(&mut self, data: &EntryData, path: &str, active: bool) {
+        if let Some(visible_if) = &data.visible_if {
+            if let Err(e) = Condition::parse(visible_if) {
+                self.error(path, format!("invalid visibility condition: {e}"));
+            }
+        }
+
+        if let Some(requires) = &data.requires {
+            match Condition::parse(requires) {
+                Ok(condition) => {
+                    if active && !condition.evaluate(self.root, self.os) {
+                        self.error(path, format!("requires {requires}"));
+                    }
+                }
+                Err(e) => self.error(path, format!("invalid requirement: {e}")),
+            }
+        }
+    }
+
+    fn validate_bool(&mut self, path: &str, value: &Value) {
+        if !value.is_boolean() {
+            self.error(path, format!("expected boolean, found {value}"));
+        }
+    }
+
+    fn validate(&mut self, schema: &SchemaNode, value: &Value, path: &str) {
+        match schema {
+            SchemaNode::Section { entries } => {
+                self.validate_fields(
+                    path,
+                    value,
+                    entries.iter().map(|(name, maybe_data)| {
+                        (name.as_str(), maybe_data.as_ref().map(|data| &data.content))
+                    }),
+                );
+
+                for (name, data) in entries
+                    .iter()
+                    .filter_map(|(name, maybe_data)| Some((name, maybe_data.as_ref()?)))
+                {
+                    let entry_value = &value[name];
+                    let active = match &data.content {
+                        SchemaNode::Optional { .. } => entry_value["set"] == true,
+                        SchemaNode::Switch { .. } => entry_value["enabled"] == true,
+                        SchemaNode::Boolean { .. } => *entry_value == true,
+                        _ => true,
+                    };
+                    self.validate_entry(data, &Self::child_path(path, name), active);
+                }
+            }
+            SchemaNode::Choice { variants, .. } => {
+                self.validate_fields(
+                    path,
+                    value,
+                    variants
+                        .iter()
+                        .map(|(name, maybe_data)| {
+                            (name.as_str(), maybe_data.as_ref().map(|data| &data.content))
+                        })
+                        .chain([("variant", None)]),
+                );
+
+                match value.get("variant") {
+                    Some(Value::String(variant)) => {
+                        if !variants.iter().any(|(name, _)| name == variant) {
+                            self.error(
+                                &Self::child_path(path, "variant"),
+                                format!("unknown variant \"{variant}\""),
+                            );
+                        }
+                    }
+                    Some(variant) => self.error(
+                        &Self::child_path(path, "variant"),
+                        format!("expected string, found {variant}"),
+                    ),
+                    None => self.error(&Self::child_path(path, "variant"), "missing".into()),
+                }
+
+                for (name, data) in variants
+                    .iter()
+                    .filter_map(|(name, maybe_data)| Some((name, maybe_data.as_ref()?)))
+                {
+                    let active = value["variant"] == name.as_str();
+                    self.validate_entry(data, &Self::child_path(path, name), active);
+                }
+            }
+            SchemaNode::Optional { content, .. } => {
+                self.validate_fields(
+                    path,
+                    value,
+                    [("set", None), ("content", Some(content.as_ref()))].into_iter(),
+                );
+                if let Some(set) = value.get("set") {
+                    self.validate_bool(&Self::child_path(path, "set"), set);
+                }
+            }
+            SchemaNode::Switch { content, .. } => {
+                self.validate_fields(
+                    path,
+                    value,
+                    [("enabled", None), ("content", Some(content.as_ref()))].into_iter(),
+                );
+                if let Some(enabled) = value.get("enabled") {
+                    self.validate_bool(&Self::child_path(path, "enabled"), enabled);
+                }
+            }
+            SchemaNode::Boolean { .. } => self.validate_bool(path, value),
+            SchemaNode::Integer { min, max, step, .. } => {
+                let integer = match (value.as_i64(), value.as_u64()) {
+                    (Some(integer), _) => integer as i128,
+                    (None, Some(integer)) => integer as i128,
+                    _ => {
+                        self.error(path, format!("expected integer, found {value}"));
+                        return;
+                    }
+                };
+
+                if let Some(min) = *min {
+                    if integer < min {
+                        self.error(path, format!("{integer} < min {min}"));
+                    }
+                }
+                if let Some(max) = *max {
+                    if integer > max {
+                        self.error(path, format!("{integer} > max {max}"));
+                    }
+                }
+                if let Some(step) = step.filter(|step| *step > 0) {
+                    // Steps count from the minimum, if any
+                    if (integer - min.unwrap_or(0)) % step != 0 {
+                        self.error(path, format!("{integer} is not a multiple of step {step}"));
+                    }
+                }
+            }
+            // Float steps are only a GUI hint, values do not need to be multiples
+            SchemaNode::Float { min, max, .. } => {
+                let float = if let Some(float) = value.as_f64() {
+                    float
+                } else {
+                    self.error(path, format!("expected number, found {value}"));
+                    return;
+                };
+
+                if let Some(min) = *min {
+                    if float < min {
+                        self.error(path, format!("{float} < min {min}"));
+                    }
+                }
+                if let Some(max) = *max {
+                    if float > max {
+                        self.error(path, format!("{float} > max {max}"));
+                    }
+                }
+            }
+            SchemaNode::Text { .. } => {
+                if !value.is_string() {
+                    self.error(path, format!("expected string, found {value}"));
+                }
+            }
+            SchemaNode::Array(elements) => match value.as_array() {
+                Some(array) if array.len() == elements.len() => {
+                    for (idx, (schema, element)) in elements.iter().zip(array).enumerate() {
+                        self.validate(schema, element, &Self::child_path(path, &idx.to_string()));
+                    }
+                }
+                Some(array) => self.error(
+                    path,
+                    format!(
+                        "expected {} elements, found {}",
+                        elements.len(),
+                        array.len()
+                    ),
+                ),
+                None => self.error(path, format!("expected array, found {value}")),
+            },
+            // The content contains settings values, which the schema does not describe. Only the
+            // default element is validated.
+            SchemaNode::Vector {
+                default_element, ..
+            } => {
+                self.validate_fields(
+                    path,
+                    value,
+                    [
+                        ("element", Some(default_element.as_ref())),
+                        ("content", None),
+                    ]
+                    .into_iter(),
+                );
+                if let Some(content) = value.get("content").filter(|content| !content.is_array()) {
+                    self.error(
+                        &Self::child_path(path, "content"),
+                        format!("expected array, found {content}"),
+                    );
+                }
+            }
+            SchemaNode::Dictionary { default_value, .. } => {
+                self.validate_fields(
+                    path,
+                    value,
+                    [
+                        ("key", None),
+                        ("value", Some(default_value.as_ref())),
+                        ("content", None),
+                    ]
+                    .into_iter(),
+                );
+                if let Some(key) = value.get("key").filter(|key| !key.is_string()) {
+                    self.error(
+                        &Self::child_path(path, "key"),
+                        format!("expected string, found {key}"),
+                    );
+                }
+                if let Some(content) = value.get("content").filter(|content| !content.is_array()) {
+                    self.error(
+                        &Self::child_path(path, "content"),
+                        format!("expected array, found {content}"),
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// Checks that `value` has the layout of the default representation described by `schema` (the
+/// one of `<your_struct_or_enum>Default`), that numbers respect their bounds and integer steps and
+/// that the requirements of active entries are met. `value` is the root for the condition paths,
+/// and the `os` path is compared with the current operating system.
+/// All the errors are returned, not only the first one.
+pub fn validate(schema: &SchemaNode, value: &Value) -> Result<(), Vec<ValidationError>> {
+    let mut validator = Validator {
+        root: value,
+        os: std::env::consts::OS,
+        errors: vec![],
+    };
+    validator.validate(schema, value, "");
+
+    if validator.errors.is_empty() {
+        Ok(())
+    } else {
+        Err(validator.errors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn entry(content: SchemaNode, requires: Option<&str>) -> Option<EntryData> {
+        Some(EntryData {
+            advanced: false,
+            help: None,
+            unit: None,
+            visible_if: None,
+            requires: requires.map(|requires| requires.into()),
+            content,
+        })
+    }
+
+    #[test]
+    fn requirements() {
+        let schema = SchemaNode::Section {
+            entries: vec![
+                (
+                    "codec".into(),
+                    entry(
+                        SchemaNode::Choice {
+                            default: "H264".into(),
+                            variants: vec![("H264".into(), None), ("HEVC".into(), None)],
+                        },
+                        None,
+                    ),
+                ),
+                (
+                    "foveation".into(),
+                    entry(
+                        SchemaNode::Switch {
+                            default_enabled: false,
+                            content_advanced: false,
+                            content: Box::new(SchemaNode::Boolean { default: false }),
+                        },
+                        Some(r#"os != "linux" || codec.variant == "HEVC""#),
+                    ),
+                ),
+            ],
+        };
+
+        let validate_on_linux = |value: &Value| {
+            let mut validator = Validator {
+                root: value,
+                os: "linux",
+                errors: vec![],
+            };
+            validator.validate(&schema, value, "");
+
+            validator
+                .errors
+                .iter()
+                .map(|error| error.to_string())
+                .collect::<Vec<_>>()
+        };
+
+        let mut value = json!({
+            "codec": { "variant": "H264" },
+            "foveation": { "enabled": false, "content": true }
+        });
+        assert!(validate_on_linux(&value).is_empty());
+
+        value["foveation"]["enabled"] = json!(true);
+        assert_eq!(
+            validate_on_linux(&value),
+            [r#"foveation: requires os != "linux" || codec.variant == "HEVC""#]
+        );
+
+        value["codec"]["variant"] = json!("HEVC");
+        assert!(validate_on_linux(&value).is_empty());
+    }
+}
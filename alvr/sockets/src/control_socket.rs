@@ -1,4 +1,7 @@
-use super::{Ldc, CONTROL_PORT, LOCAL_IP};
+use super::{
+    network, tls, Ldc, LinkConditions, LinkState, NetworkConfig, PeerIp, PrivateIdentity,
+    PublicIdentity,
+};
 use alvr_common::prelude::*;
 use bytes::Bytes;
 use futures::{
@@ -6,12 +9,31 @@ use futures::{
     SinkExt, StreamExt,
 };
 use serde::{de::DeserializeOwned, Serialize};
-use std::{marker::PhantomData, net::IpAddr};
-use tokio::net::{TcpListener, TcpStream};
+use std::{marker::PhantomData, time::Duration};
+use tokio::{
+    io::{self, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    sync::mpsc,
+    time::{self, Instant},
+};
+use tokio_rustls::{rustls::ServerName, TlsAcceptor, TlsConnector, TlsStream};
 use tokio_util::codec::Framed;
 
+// Size of the in-memory pipe used by loopback sockets
+const LOOPBACK_BUFFER_SIZE: usize = 64 * 1024;
+
+// Lost chunks of a loopback link are retransmitted like TCP segments. The link breaks when a chunk
+// cannot be delivered, so a link outage is detected by the peers.
+const LOOPBACK_RETRANSMISSION_TIMEOUT: Duration = Duration::from_millis(200);
+const LOOPBACK_MAX_RETRANSMISSIONS: usize = 10;
+
+// Transport under TLS: a TCP stream, or an in-memory pipe for loopback sockets
+trait ControlIo: AsyncRead + AsyncWrite + Send + Unpin {}
+impl<T: AsyncRead + AsyncWrite + Send + Unpin> ControlIo for T {}
+
+type TlsFramed = Framed<TlsStream<Box<dyn ControlIo>>, Ldc>;
+
 pub struct ControlSocketSender<T> {
-    inner: SplitSink<Framed<TcpStream, Ldc>, Bytes>,
+    inner: SplitSink<TlsFramed, Bytes>,
     _phantom: PhantomData<T>,
 }
 
@@ -23,7 +45,7 @@ impl<S: Serialize> ControlSocketSender<S> {
 }
 
 pub struct ControlSocketReceiver<T> {
-    inner: SplitStream<Framed<TcpStream, Ldc>>,
+    inner: SplitStream<TlsFramed>,
     _phantom: PhantomData<T>,
 }
 
@@ -36,37 +58,211 @@ impl<R: DeserializeOwned> ControlSocketReceiver<R> {
 
 // Proto-control-socket that can send and receive any packet. After the split, only the packets of
 // the specified types can be exchanged
+// The socket is encrypted with TLS. The server authenticates using its own identity and checks the
+// certificate of the client against the one pinned in its public identity, if any.
 pub struct ProtoControlSocket {
-    inner: Framed<TcpStream, Ldc>,
+    inner: TlsFramed,
 }
 
 pub enum PeerType {
-    AnyClient(Vec<IpAddr>),
+    AnyClient(Vec<(PeerIp, PublicIdentity)>),
     Server,
 }
 
+// The server is the TLS client
+async fn connect_tls(
+    socket: Box<dyn ControlIo>,
+    client_identity: &PublicIdentity,
+    identity: &PrivateIdentity,
+) -> StrResult<io::Result<TlsStream<Box<dyn ControlIo>>>> {
+    let pinned_certificate = if let Some(certificate_pem) = &client_identity.certificate_pem {
+        Some(tls::certificate_from_pem(certificate_pem)?)
+    } else {
+        None
+    };
+    let connector = TlsConnector::from(tls::client_config(identity, pinned_certificate)?);
+    let server_name = trace_err!(ServerName::try_from(tls::SERVER_NAME))?;
+
+    Ok(connector
+        .connect(server_name, socket)
+        .await
+        .map(TlsStream::from))
+}
+
+async fn accept_tls(
+    socket: Box<dyn ControlIo>,
+    identity: &PrivateIdentity,
+) -> StrResult<TlsStream<Box<dyn ControlIo>>> {
+    let acceptor = TlsAcceptor::from(tls::server_config(identity)?);
+
+    Ok(trace_err!(acceptor.accept(socket).await)?.into())
+}
+
+async fn connect_to_client(
+    client_ip: PeerIp,
+    client_identity: &PublicIdentity,
+    identity: &PrivateIdentity,
+    config: &NetworkConfig,
+) -> StrResult<TlsStream<Box<dyn ControlIo>>> {
+    let socket = network::connect_tcp(config.bind_ip, client_ip, config.control_port).await?;
+    trace_err!(socket.set_nodelay(true))?;
+
+    connect_tls(Box::new(socket), client_identity, identity)
+        .await?
+        .map_err(|e| {
+            format!(
+                "TLS handshake with {} ({client_ip}) failed: {e}",
+                client_identity.hostname
+            )
+        })
+}
+
+// Forwards the bytes written to one end of a loopback link to the other end. Chunks are delivered
+// in order, a lost chunk also delays the ones that follow.
+async fn relay_loopback(
+    mut source: impl AsyncRead + Unpin,
+    mut sink: impl AsyncWrite + Unpin,
+    conditions: LinkConditions,
+) {
+    let (sender, mut receiver) = mpsc::unbounded_channel::<(Instant, Vec<u8>)>();
+
+    let read_loop = async move {
+        let mut link = LinkState::new(conditions);
+        let mut last_arrival_time = Instant::now();
+        let mut buffer = vec![0; LOOPBACK_BUFFER_SIZE];
+
+        while let Ok(len @ 1..) = source.read(&mut buffer).await {
+            let mut send_time = Instant::now();
+            let mut arrival_time = None;
+            for _ in 0..=LOOPBACK_MAX_RETRANSMISSIONS {
+                arrival_time = link.transmit(len, send_time);
+                if arrival_time.is_some() {
+                    break;
+                }
+                send_time += LOOPBACK_RETRANSMISSION_TIMEOUT;
+            }
+
+            let arrival_time = match arrival_time {
+                Some(time) => time.max(last_arrival_time),
+                None => {
+                    time::sleep_until(send_time).await;
+                    break;
+                }
+            };
+            last_arrival_time = arrival_time;
+
+            if sender.send((arrival_time, buffer[..len].to_vec())).is_err() {
+                break;
+            }
+        }
+    };
+
+    let write_loop = async move {
+        while let Some((arrival_time, chunk)) = receiver.recv().await {
+            time::sleep_until(arrival_time).await;
+            if sink.write_all(&chunk).await.is_err() {
+                break;
+            }
+        }
+    };
+
+    tokio::join!(read_loop, write_loop);
+}
+
 impl ProtoControlSocket {
-    pub async fn connect_to(peer: PeerType) -> StrResult<(Self, IpAddr)> {
-        let socket = match peer {
-            PeerType::AnyClient(ips) => {
-                let client_addresses = ips
-                    .iter()
-                    .map(|&ip| (ip, CONTROL_PORT).into())
-                    .collect::<Vec<_>>();
-                trace_err!(TcpStream::connect(client_addresses.as_slice()).await)?
+    pub async fn connect_to(
+        peer: PeerType,
+        identity: &PrivateIdentity,
+        config: &NetworkConfig,
+    ) -> StrResult<(Self, PeerIp)> {
+        let (socket, peer_ip) = match peer {
+            PeerType::AnyClient(clients) => {
+                let mut res = fmt_e!("No client to connect to");
+                for (ip, client_identity) in clients {
+                    res = connect_to_client(ip, &client_identity, identity, config)
+                        .await
+                        .map(|socket| (socket, ip));
+                    if res.is_ok() {
+                        break;
+                    }
+                }
+
+                res?
             }
             PeerType::Server => {
-                let listener = trace_err!(TcpListener::bind((LOCAL_IP, CONTROL_PORT)).await)?;
-                let (socket, _) = trace_err!(listener.accept().await)?;
-                socket
+                let listener = network::bind_tcp(config.bind_ip, config.control_port)?;
+                let (socket, server_address) = trace_err!(listener.accept().await)?;
+                trace_err!(socket.set_nodelay(true))?;
+
+                (
+                    accept_tls(Box::new(socket), identity).await?,
+                    PeerIp::from_address(server_address),
+                )
             }
         };
 
-        trace_err!(socket.set_nodelay(true))?;
-        let peer_ip = trace_err!(socket.peer_addr())?.ip();
-        let socket = Framed::new(socket, Ldc::new());
+        Ok((
+            Self {
+                inner: Framed::new(socket, Ldc::new()),
+            },
+            peer_ip,
+        ))
+    }
+
+    // In-process connection, used by tests. The TLS handshake is the same as over TCP, with the
+    // client certificate already pinned by the server. Returns the server and the client sockets.
+    pub async fn loopback_pair(
+        server_identity: &PrivateIdentity,
+        client_identity: &PrivateIdentity,
+        server_to_client: LinkConditions,
+        client_to_server: LinkConditions,
+    ) -> StrResult<(Self, Self)> {
+        let (server_pipe, server_link_end) = io::duplex(LOOPBACK_BUFFER_SIZE);
+        let (client_pipe, client_link_end) = io::duplex(LOOPBACK_BUFFER_SIZE);
 
-        Ok((Self { inner: socket }, peer_ip))
+        // When any direction ends, both link ends are dropped and the sockets are disconnected
+        tokio::spawn(async move {
+            let (server_source, server_sink) = io::split(server_link_end);
+            let (client_source, client_sink) = io::split(client_link_end);
+
+            tokio::select! {
+                _ = relay_loopback(server_source, client_sink, server_to_client) => (),
+                _ = relay_loopback(client_source, server_sink, client_to_server) => (),
+            }
+        });
+
+        let pinned_identity = PublicIdentity {
+            hostname: client_identity.hostname.clone(),
+            certificate_pem: Some(client_identity.certificate_pem.clone()),
+        };
+
+        let (server_socket, client_socket) = tokio::try_join!(
+            async {
+                trace_err!(
+                    connect_tls(Box::new(server_pipe), &pinned_identity, server_identity).await?
+                )
+            },
+            accept_tls(Box::new(client_pipe), client_identity),
+        )?;
+
+        Ok((
+            Self {
+                inner: Framed::new(server_socket, Ldc::new()),
+            },
+            Self {
+                inner: Framed::new(client_socket, Ldc::new()),
+            },
+        ))
+    }
+
+    // Certificate presented by the peer during the TLS handshake
+    pub fn peer_certificate_pem(&self) -> Option<String> {
+        let (_, connection) = self.inner.get_ref().get_ref();
+
+        connection
+            .peer_certificates()
+            .and_then(|certificates| certificates.first())
+            .map(tls::certificate_to_pem)
     }
 
     pub async fn send<S: Serialize>(&mut self, packet: &S) -> StrResult {
@@ -96,3 +292,111 @@ impl ProtoControlSocket {
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{ClientControlPacket, ServerControlPacket};
+
+    #[tokio::test]
+    async fn loopback_handshake() {
+        let server_identity = crate::create_identity(Some("server".into())).unwrap();
+        let client_identity = crate::create_identity(None).unwrap();
+
+        let (server_socket, mut client_socket) = ProtoControlSocket::loopback_pair(
+            &server_identity,
+            &client_identity,
+            LinkConditions::default(),
+            LinkConditions::default(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            server_socket.peer_certificate_pem(),
+            Some(client_identity.certificate_pem)
+        );
+
+        let (mut sender, _) = server_socket.split::<String, ()>();
+        sender.send(&"hello".into()).await.unwrap();
+        assert_eq!(client_socket.recv::<String>().await.unwrap(), "hello");
+    }
+
+    #[tokio::test]
+    async fn handshake_over_lossy_link() {
+        let server_identity = crate::create_identity(Some("server".into())).unwrap();
+        let client_identity = crate::create_identity(None).unwrap();
+        let conditions = LinkConditions {
+            loss_rate: 0.3,
+            delay: Duration::from_millis(10),
+            jitter: Duration::from_millis(10),
+            seed: 42,
+            ..Default::default()
+        };
+
+        let start = Instant::now();
+        let (mut server_socket, mut client_socket) = ProtoControlSocket::loopback_pair(
+            &server_identity,
+            &client_identity,
+            conditions,
+            conditions,
+        )
+        .await
+        .unwrap();
+        // At least one round trip
+        assert!(Instant::now() - start >= conditions.delay * 2);
+
+        client_socket
+            .send(&ClientControlPacket::RequestIdr)
+            .await
+            .unwrap();
+        assert!(matches!(
+            server_socket.recv().await.unwrap(),
+            ClientControlPacket::RequestIdr
+        ));
+    }
+
+    // The server detects the disconnection when it fails to send a keepalive
+    #[tokio::test]
+    async fn keepalive_timeout() {
+        let server_identity = crate::create_identity(Some("server".into())).unwrap();
+        let client_identity = crate::create_identity(None).unwrap();
+        let conditions = LinkConditions {
+            outage_after: Some(Duration::from_millis(500)),
+            ..Default::default()
+        };
+
+        let outage_start = Instant::now() + Duration::from_millis(500);
+        let (server_socket, client_socket) = ProtoControlSocket::loopback_pair(
+            &server_identity,
+            &client_identity,
+            conditions,
+            conditions,
+        )
+        .await
+        .unwrap();
+        let (mut sender, _) = server_socket.split::<ServerControlPacket, ClientControlPacket>();
+        let (_, mut receiver) = client_socket.split::<(), ServerControlPacket>();
+
+        let mut received_count = 0;
+        let res = time::timeout(Duration::from_secs(10), async {
+            loop {
+                if let Err(e) = sender.send(&ServerControlPacket::KeepAlive).await {
+                    break e;
+                }
+                if let Ok(Ok(ServerControlPacket::KeepAlive)) =
+                    time::timeout(Duration::from_millis(100), receiver.recv()).await
+                {
+                    received_count += 1;
+                }
+            }
+        })
+        .await;
+
+        assert!(res.is_ok());
+        assert!(received_count > 0);
+        let retransmissions_duration =
+            LOOPBACK_RETRANSMISSION_TIMEOUT * LOOPBACK_MAX_RETRANSMISSIONS as u32;
+        assert!(Instant::now() >= outage_start + retransmissions_duration);
+    }
+}
@@ -1,6 +1,9 @@
 mod control_socket;
+mod mdns;
+mod network;
 mod packets;
 mod stream_socket;
+mod tls;
 
 use alvr_common::prelude::*;
 use rand::Rng;
@@ -8,11 +11,14 @@ use serde::{Deserialize, Serialize};
 use std::net::{IpAddr, Ipv4Addr};
 
 pub use control_socket::*;
+pub use mdns::*;
+pub use network::*;
 pub use packets::*;
 pub use stream_socket::*;
 
 pub const LOCAL_IP: IpAddr = IpAddr::V4(Ipv4Addr::UNSPECIFIED);
 pub const CONTROL_PORT: u16 = 9943;
+pub const DISCOVERY_PORT: u16 = 9943;
 pub const MAX_HANDSHAKE_PACKET_SIZE_BYTES: usize = 4_000;
 
 type Ldc = tokio_util::codec::LengthDelimitedCodec;
@@ -23,6 +29,7 @@ pub struct PublicIdentity {
     pub certificate_pem: Option<String>,
 }
 
+#[derive(Serialize, Deserialize, Clone)]
 pub struct PrivateIdentity {
     pub hostname: String,
     pub certificate_pem: String,
@@ -38,22 +45,13 @@ pub fn create_identity(hostname: Option<String>) -> StrResult<PrivateIdentity> {
         rand::thread_rng().gen_range(0..10),
     ));
 
-    #[cfg(target_os = "android")]
     let certificate = trace_err!(rcgen::generate_simple_self_signed([hostname.clone()]))?;
 
-    #[cfg(not(target_os = "android"))]
-    return Ok(PrivateIdentity {
-        hostname,
-        certificate_pem: String::new(),
-        key_pem: String::new(),
-    });
-
-    #[cfg(target_os = "android")]
-    return Ok(PrivateIdentity {
+    Ok(PrivateIdentity {
         hostname,
         certificate_pem: trace_err!(certificate.serialize_pem())?,
         key_pem: certificate.serialize_private_key_pem(),
-    });
+    })
 }
 
 mod util {
@@ -0,0 +1,208 @@
+// mDNS/DNS-SD discovery, alternative to the broadcast discovery for networks that drop broadcast
+// packets. The server advertises its discovery socket as an _alvr._udp service. The client resolves
+// it and sends the same handshake packet directly to the resolved addresses, so the server handles
+// both paths with the same trust flow.
+//
+// The mDNS responder and resolver run on a separate thread, owned by the ServiceDaemon.
+
+use crate::NetworkConfig;
+use alvr_common::{prelude::*, ALVR_VERSION};
+use mdns_sd::{IfKind, Receiver, ServiceDaemon, ServiceEvent, ServiceInfo};
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+};
+
+pub const MDNS_SERVICE_TYPE: &str = "_alvr._udp.local.";
+
+// TXT record keys
+pub const MDNS_VERSION_KEY: &str = "version";
+pub const MDNS_HOSTNAME_KEY: &str = "hostname";
+
+// Keep only the characters allowed in a DNS label
+fn sanitize_hostname(hostname: &str) -> String {
+    let label = hostname
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect::<String>();
+    let label = label.trim_matches('-');
+
+    if label.is_empty() {
+        "alvr-server".into()
+    } else {
+        label.into()
+    }
+}
+
+// Link-local IPv6 addresses are unusable without the interface index, which mDNS does not provide
+fn is_reachable(bind_ip: IpAddr, ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(_) => bind_ip.is_ipv4() || bind_ip.is_unspecified(),
+        IpAddr::V6(ipv6) => bind_ip.is_ipv6() && ipv6.segments()[0] & 0xffc0 != 0xfe80,
+    }
+}
+
+fn new_daemon(config: &NetworkConfig) -> StrResult<ServiceDaemon> {
+    let daemon = trace_err!(ServiceDaemon::new())?;
+    if config.bind_ip.is_ipv4() {
+        trace_err!(daemon.disable_interface(IfKind::IPv6))?;
+    }
+
+    Ok(daemon)
+}
+
+// Advertises the server discovery socket until dropped
+pub struct MdnsAdvertiser {
+    daemon: ServiceDaemon,
+    fullname: String,
+}
+
+impl MdnsAdvertiser {
+    pub fn new(config: &NetworkConfig) -> StrResult<Self> {
+        let hostname = gethostname::gethostname().to_string_lossy().into_owned();
+        let label = sanitize_hostname(&hostname);
+
+        let properties = HashMap::from([
+            (MDNS_VERSION_KEY.to_owned(), ALVR_VERSION.to_string()),
+            (MDNS_HOSTNAME_KEY.to_owned(), hostname),
+        ]);
+
+        // With no address, the addresses of all the interfaces are advertised
+        let addresses = if config.bind_ip.is_unspecified() {
+            vec![]
+        } else {
+            vec![config.bind_ip]
+        };
+
+        let service = trace_err!(ServiceInfo::new(
+            MDNS_SERVICE_TYPE,
+            &label,
+            &format!("{label}.local."),
+            &addresses[..],
+            config.discovery_port,
+            properties,
+        ))?;
+        let service = if addresses.is_empty() {
+            service.enable_addr_auto()
+        } else {
+            service
+        };
+
+        let daemon = new_daemon(config)?;
+        let fullname = service.get_fullname().to_owned();
+        trace_err!(daemon.register(service))?;
+
+        Ok(Self { daemon, fullname })
+    }
+}
+
+impl Drop for MdnsAdvertiser {
+    fn drop(&mut self) {
+        // Unregistering sends a goodbye packet, so clients forget the server immediately
+        self.daemon.unregister(&self.fullname).ok();
+        self.daemon.shutdown().ok();
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct MdnsServer {
+    pub hostname: String,
+    pub version: String,
+    pub addresses: Vec<SocketAddr>,
+}
+
+// Resolves the servers advertised on the local network
+pub struct MdnsBrowser {
+    daemon: ServiceDaemon,
+    events: Receiver<ServiceEvent>,
+    bind_ip: IpAddr,
+    // Indexed by service instance name
+    servers: HashMap<String, MdnsServer>,
+}
+
+impl MdnsBrowser {
+    pub fn new(config: &NetworkConfig) -> StrResult<Self> {
+        let daemon = new_daemon(config)?;
+        let events = trace_err!(daemon.browse(MDNS_SERVICE_TYPE))?;
+
+        Ok(Self {
+            daemon,
+            events,
+            bind_ip: config.bind_ip,
+            servers: HashMap::new(),
+        })
+    }
+
+    // Does not block. Returns all the servers resolved so far that are still advertised.
+    pub fn servers(&mut self) -> Vec<MdnsServer> {
+        while let Ok(event) = self.events.try_recv() {
+            match event {
+                ServiceEvent::ServiceResolved(service) => {
+                    let server = MdnsServer {
+                        hostname: service
+                            .get_property_val_str(MDNS_HOSTNAME_KEY)
+                            .unwrap_or_else(|| service.get_hostname())
+                            .to_owned(),
+                        version: service
+                            .get_property_val_str(MDNS_VERSION_KEY)
+                            .unwrap_or_default()
+                            .to_owned(),
+                        addresses: service
+                            .get_addresses()
+                            .iter()
+                            .filter(|ip| is_reachable(self.bind_ip, **ip))
+                            .map(|ip| {
+                                crate::peer_address(self.bind_ip, (*ip).into(), service.get_port())
+                            })
+                            .collect(),
+                    };
+                    debug!(
+                        "mDNS resolved server {} (v{}) at {:?}",
+                        server.hostname, server.version, server.addresses
+                    );
+
+                    self.servers
+                        .insert(service.get_fullname().to_owned(), server);
+                }
+                ServiceEvent::ServiceRemoved(_, fullname) => {
+                    self.servers.remove(&fullname);
+                }
+                _ => (),
+            }
+        }
+
+        self.servers.values().cloned().collect()
+    }
+}
+
+impl Drop for MdnsBrowser {
+    fn drop(&mut self) {
+        self.daemon.shutdown().ok();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    #[test]
+    fn hostname_label() {
+        assert_eq!(sanitize_hostname("My PC.lan"), "My-PC-lan");
+        assert_eq!(sanitize_hostname("..."), "alvr-server");
+    }
+
+    #[test]
+    fn reachable_addresses() {
+        let ipv4 = IpAddr::V4(Ipv4Addr::new(192, 168, 1, 2));
+        let ipv6 = IpAddr::V6(Ipv6Addr::new(0xfd00, 0, 0, 0, 0, 0, 0, 1));
+        let link_local = IpAddr::V6(Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 1));
+        let dual_stack = IpAddr::V6(Ipv6Addr::UNSPECIFIED);
+
+        assert!(is_reachable(crate::LOCAL_IP, ipv4));
+        assert!(!is_reachable(crate::LOCAL_IP, ipv6));
+        assert!(is_reachable(dual_stack, ipv4));
+        assert!(is_reachable(dual_stack, ipv6));
+        assert!(!is_reachable(dual_stack, link_local));
+    }
+}
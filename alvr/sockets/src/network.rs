@@ -0,0 +1,276 @@
+// Bind address and ports used by the sockets, and the destinations of the discovery packets.
+//
+// Binding the unspecified IPv6 address creates dual-stack sockets, which accept both IPv4 and IPv6
+// peers. IPv4 peers are then seen with IPv4-mapped IPv6 addresses, so peer addresses must go
+// through canonical_ip() before being compared or stored.
+// IPv6 has no broadcast, so discovery uses a link-local multicast group instead.
+// IPv6 link-local addresses are ambiguous without the interface they were reached through, so peers
+// are stored as PeerIp, which keeps the scope id of the address they were seen with.
+
+use crate::{CONTROL_PORT, DISCOVERY_PORT, LOCAL_IP};
+use alvr_common::prelude::*;
+use alvr_session::{BindAddress, ConnectionDesc};
+use socket2::{Domain, Protocol, Socket, Type};
+use std::{
+    fmt::{self, Display},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV6},
+};
+use tokio::net::{TcpListener, TcpSocket, TcpStream, UdpSocket};
+
+// ff02::414c:5652, the last two groups spell "ALVR"
+pub const DISCOVERY_MULTICAST_GROUP: Ipv6Addr =
+    Ipv6Addr::new(0xff02, 0, 0, 0, 0, 0, 0x414c, 0x5652);
+
+const LISTEN_BACKLOG: i32 = 128;
+
+#[derive(Clone, Copy, Debug)]
+pub struct NetworkConfig {
+    pub bind_ip: IpAddr,
+    // Interface used to send and receive the IPv6 discovery packets. 0 lets the OS choose.
+    pub multicast_interface: u32,
+    pub discovery_port: u16,
+    pub control_port: u16,
+}
+
+impl Default for NetworkConfig {
+    fn default() -> Self {
+        Self {
+            bind_ip: LOCAL_IP,
+            multicast_interface: 0,
+            discovery_port: DISCOVERY_PORT,
+            control_port: CONTROL_PORT,
+        }
+    }
+}
+
+impl NetworkConfig {
+    pub fn from_settings(connection: &ConnectionDesc) -> StrResult<Self> {
+        let bind_ip = match &connection.bind_address {
+            BindAddress::Ipv4 => LOCAL_IP,
+            BindAddress::DualStack => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+            BindAddress::Custom { address } => address
+                .parse()
+                .map_err(|e| format!("Invalid bind address \"{address}\": {e}"))?,
+        };
+
+        Ok(Self {
+            bind_ip,
+            multicast_interface: connection.multicast_interface_index,
+            discovery_port: connection.discovery_port,
+            control_port: connection.control_port,
+        })
+    }
+
+    fn is_dual_stack(&self) -> bool {
+        self.bind_ip == IpAddr::V6(Ipv6Addr::UNSPECIFIED)
+    }
+
+    // Where the client sends its discovery packets
+    pub fn discovery_addresses(&self) -> Vec<SocketAddr> {
+        let multicast_address = SocketAddr::V6(SocketAddrV6::new(
+            DISCOVERY_MULTICAST_GROUP,
+            self.discovery_port,
+            0,
+            self.multicast_interface,
+        ));
+
+        match self.bind_ip {
+            IpAddr::V4(_) => vec![(Ipv4Addr::BROADCAST, self.discovery_port).into()],
+            IpAddr::V6(_) if self.is_dual_stack() => vec![
+                (Ipv4Addr::BROADCAST.to_ipv6_mapped(), self.discovery_port).into(),
+                multicast_address,
+            ],
+            IpAddr::V6(_) => vec![multicast_address],
+        }
+    }
+}
+
+pub fn canonical_ip(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V6(ipv6) => ipv6.to_ipv4_mapped().map(IpAddr::V4).unwrap_or(ip),
+        ip => ip,
+    }
+}
+
+// IP of a peer. The scope id is 0 for anything but IPv6 link-local addresses.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct PeerIp {
+    pub ip: IpAddr,
+    pub scope_id: u32,
+}
+
+impl PeerIp {
+    // Peer seen as the source of a packet or a connection
+    pub fn from_address(address: SocketAddr) -> Self {
+        let ip = canonical_ip(address.ip());
+        let scope_id = match address {
+            SocketAddr::V6(address) if ip.is_ipv6() => address.scope_id(),
+            _ => 0,
+        };
+
+        Self { ip, scope_id }
+    }
+
+    pub fn socket_address(self, port: u16) -> SocketAddr {
+        match canonical_ip(self.ip) {
+            IpAddr::V6(ipv6) => SocketAddrV6::new(ipv6, port, 0, self.scope_id).into(),
+            ip => (ip, port).into(),
+        }
+    }
+}
+
+impl From<IpAddr> for PeerIp {
+    fn from(ip: IpAddr) -> Self {
+        Self {
+            ip: canonical_ip(ip),
+            scope_id: 0,
+        }
+    }
+}
+
+impl Display for PeerIp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.scope_id != 0 {
+            write!(f, "{}%{}", self.ip, self.scope_id)
+        } else {
+            write!(f, "{}", self.ip)
+        }
+    }
+}
+
+// Address of a peer as seen by a socket bound to `bind_ip`
+pub fn peer_address(bind_ip: IpAddr, peer_ip: PeerIp, port: u16) -> SocketAddr {
+    match (bind_ip, canonical_ip(peer_ip.ip)) {
+        (IpAddr::V6(_), IpAddr::V4(ipv4)) => (ipv4.to_ipv6_mapped(), port).into(),
+        _ => peer_ip.socket_address(port),
+    }
+}
+
+fn bind_socket(ip: IpAddr, port: u16, socket_type: Type, protocol: Protocol) -> StrResult<Socket> {
+    let address = SocketAddr::new(ip, port);
+    let socket = trace_err!(Socket::new(
+        Domain::for_address(address),
+        socket_type,
+        Some(protocol)
+    ))?;
+
+    // The default differs between OSes. Only sockets bound to all the interfaces are dual-stack.
+    if ip.is_ipv6() {
+        trace_err!(socket.set_only_v6(!ip.is_unspecified()))?;
+    }
+
+    // Same as the standard library
+    #[cfg(not(windows))]
+    if socket_type == Type::STREAM {
+        trace_err!(socket.set_reuse_address(true))?;
+    }
+
+    trace_err!(socket.set_nonblocking(true))?;
+    socket
+        .bind(&address.into())
+        .map_err(|e| format!("Cannot bind {address}: {e}"))?;
+
+    Ok(socket)
+}
+
+pub fn bind_std_udp(ip: IpAddr, port: u16) -> StrResult<std::net::UdpSocket> {
+    Ok(bind_socket(ip, port, Type::DGRAM, Protocol::UDP)?.into())
+}
+
+pub fn bind_udp(ip: IpAddr, port: u16) -> StrResult<UdpSocket> {
+    trace_err!(UdpSocket::from_std(bind_std_udp(ip, port)?))
+}
+
+pub fn bind_tcp(ip: IpAddr, port: u16) -> StrResult<TcpListener> {
+    let socket = bind_socket(ip, port, Type::STREAM, Protocol::TCP)?;
+    trace_err!(socket.listen(LISTEN_BACKLOG))?;
+
+    trace_err!(TcpListener::from_std(socket.into()))
+}
+
+// The connection leaves from the bind address, if it is not unspecified
+pub async fn connect_tcp(bind_ip: IpAddr, peer_ip: PeerIp, port: u16) -> StrResult<TcpStream> {
+    if bind_ip.is_unspecified() {
+        return trace_err!(TcpStream::connect(peer_ip.socket_address(port)).await);
+    }
+
+    let socket = if bind_ip.is_ipv4() {
+        trace_err!(TcpSocket::new_v4())?
+    } else {
+        trace_err!(TcpSocket::new_v6())?
+    };
+    trace_err!(socket.bind((bind_ip, 0).into()))?;
+
+    trace_err!(socket.connect(peer_address(bind_ip, peer_ip, port)).await)
+}
+
+// Socket used by the server to receive the discovery packets
+pub fn bind_discovery_listener(config: &NetworkConfig) -> StrResult<UdpSocket> {
+    let socket = bind_udp(config.bind_ip, config.discovery_port)?;
+
+    if config.bind_ip.is_ipv6() {
+        // Without IPv6 on the interface, IPv4 discovery can still work
+        if let Err(e) =
+            socket.join_multicast_v6(&DISCOVERY_MULTICAST_GROUP, config.multicast_interface)
+        {
+            warn!("Cannot join the IPv6 discovery group: {e}");
+        }
+    }
+
+    Ok(socket)
+}
+
+// Socket used by the client to send the discovery packets and receive the server response. It uses
+// an ephemeral port, so the server can run on the same host.
+pub fn bind_discovery_sender(config: &NetworkConfig) -> StrResult<UdpSocket> {
+    let socket = bind_socket(config.bind_ip, 0, Type::DGRAM, Protocol::UDP)?;
+
+    if config.bind_ip.is_ipv4() || config.is_dual_stack() {
+        trace_err!(socket.set_broadcast(true))?;
+    }
+    if config.bind_ip.is_ipv6() {
+        trace_err!(socket.set_multicast_if_v6(config.multicast_interface))?;
+    }
+
+    trace_err!(UdpSocket::from_std(socket.into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mapped_addresses() {
+        let ipv4 = IpAddr::V4(Ipv4Addr::new(192, 168, 1, 2));
+        let mapped = IpAddr::V6(Ipv4Addr::new(192, 168, 1, 2).to_ipv6_mapped());
+
+        assert_eq!(canonical_ip(mapped), ipv4);
+        assert_eq!(
+            peer_address(IpAddr::V6(Ipv6Addr::UNSPECIFIED), ipv4.into(), 9944),
+            SocketAddr::new(mapped, 9944)
+        );
+        assert_eq!(
+            peer_address(LOCAL_IP, mapped.into(), 9944),
+            SocketAddr::new(ipv4, 9944)
+        );
+    }
+
+    #[test]
+    fn link_local_scope() {
+        let link_local = Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 1);
+        let source = SocketAddr::V6(SocketAddrV6::new(link_local, 50000, 0, 3));
+
+        let peer_ip = PeerIp::from_address(source);
+        assert_eq!(peer_ip.scope_id, 3);
+        assert_eq!(
+            peer_address(IpAddr::V6(Ipv6Addr::UNSPECIFIED), peer_ip, 9944),
+            SocketAddr::V6(SocketAddrV6::new(link_local, 9944, 0, 3))
+        );
+
+        let mapped = SocketAddr::new(Ipv4Addr::new(192, 168, 1, 2).to_ipv6_mapped().into(), 5000);
+        assert_eq!(
+            PeerIp::from_address(mapped),
+            IpAddr::V4(Ipv4Addr::new(192, 168, 1, 2)).into()
+        );
+    }
+}
@@ -1,6 +1,6 @@
 use std::{collections::HashMap, time::Duration};
 
-use crate::StreamId;
+use crate::{DropPolicy, QueueBound, StreamId, StreamOptions, StreamPriority};
 use alvr_common::{
     glam::{Quat, Vec2, Vec3},
     semver::Version,
@@ -13,6 +13,27 @@ pub const HAPTICS: StreamId = 1;
 pub const AUDIO: StreamId = 2;
 pub const VIDEO: StreamId = 3;
 
+// Tracking and haptics are sent ahead of anything else. Only the most recent packets are kept when
+// the consumer cannot keep up: input packets contain the full state, and a video packet loss
+// triggers an IDR request.
+pub fn default_stream_options(stream_id: StreamId) -> StreamOptions {
+    let (priority, capacity) = match stream_id {
+        INPUT | HAPTICS => (StreamPriority::High, 64),
+        AUDIO => (StreamPriority::Normal, 64),
+        VIDEO => (StreamPriority::Low, 1024),
+        _ => return StreamOptions::default(),
+    };
+
+    StreamOptions {
+        priority,
+        queue_bound: Some(QueueBound {
+            capacity,
+            drop_policy: DropPolicy::DropOldest,
+        }),
+        fec: None,
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone)]
 pub struct ClientHandshakePacket {
     pub alvr_name: String,
@@ -71,6 +92,8 @@ pub enum ServerControlPacket {
     TimeSync(TimeSyncPacket), // legacy
     Reserved(String),
     ReservedBuffer(Vec<u8>),
+    // Session (JSON) with hot-reloaded settings, applied to the running stream
+    SettingsUpdate(String),
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -131,6 +154,9 @@ pub struct TimeSyncPacket {
     pub fps: f32,
     pub server_total_latency: u32,
     pub tracking_recv_frame_index: u64,
+    // Buffer of the game audio played by the client, since the client started
+    pub audio_buffer_overflows: u64,
+    pub audio_buffer_underflows: u64,
 }
 
 #[derive(Serialize, Deserialize)]
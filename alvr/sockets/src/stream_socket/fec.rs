@@ -0,0 +1,541 @@
+// Forward error correction for stream packets. Each packet is split into data shards of at most
+// `shard_size` bytes and Reed-Solomon parity shards are added. The receiver can reconstruct the
+// packet from any set of shards as long as they are at least as many as the data shards.
+// Optionally, the receiver requests the missing shards of a packet (NACK) until a deadline expires.
+// The newest packet is requested after half the deadline, as no following shards reveal its loss.
+//
+// Shard packet layout: stream ID (u16), group index (u32), shard index (u16), data shard count
+// (u16), parity shard count (u16), packet size (u32), shard bytes.
+// NACK packet layout: NACK stream ID (u16), group index (u32), shard indices (u16 each).
+
+use super::StreamId;
+use alvr_common::prelude::*;
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use reed_solomon_erasure::galois_8::ReedSolomon;
+use std::{
+    collections::{BTreeMap, VecDeque},
+    time::{Duration, Instant},
+};
+
+const SHARD_HEADER_SIZE: usize = 4 + 2 + 2 + 2 + 4;
+
+// Limit of the GF(2^8) Reed-Solomon implementation
+const MAX_TOTAL_SHARDS: usize = 256;
+
+// Number of recently sent packets that can be resent after a NACK
+const RESEND_HISTORY_SIZE: usize = 128;
+
+// NACKs for a stream are sent on a separate stream ID, reserved by setting the highest bit
+pub fn nack_stream_id(stream_id: StreamId) -> StreamId {
+    stream_id | 0x8000
+}
+
+#[derive(Clone, Copy)]
+pub struct FecConfig {
+    // Maximum size of a shard payload. It should fit a single datagram.
+    pub shard_size: usize,
+    // Number of parity shards relative to the data shards, rounded up
+    pub parity_percentage: u16,
+    // If set, missing shards are requested again. Packets are delivered in order, so a packet that
+    // cannot be reconstructed holds back the following ones until this deadline.
+    pub nack_deadline: Option<Duration>,
+}
+
+struct ShardHeader {
+    group_index: u32,
+    shard_index: u16,
+    data_shard_count: u16,
+    parity_shard_count: u16,
+    packet_size: u32,
+}
+
+impl ShardHeader {
+    fn write(&self, buffer: &mut BytesMut) {
+        buffer.put_u32(self.group_index);
+        buffer.put_u16(self.shard_index);
+        buffer.put_u16(self.data_shard_count);
+        buffer.put_u16(self.parity_shard_count);
+        buffer.put_u32(self.packet_size);
+    }
+
+    fn read(buffer: &mut BytesMut) -> StrResult<Self> {
+        if buffer.len() < SHARD_HEADER_SIZE {
+            return fmt_e!("Shard too small");
+        }
+
+        Ok(Self {
+            group_index: buffer.get_u32(),
+            shard_index: buffer.get_u16(),
+            data_shard_count: buffer.get_u16(),
+            parity_shard_count: buffer.get_u16(),
+            packet_size: buffer.get_u32(),
+        })
+    }
+}
+
+pub struct FecEncoder {
+    config: FecConfig,
+    next_group_index: u32,
+    sent_groups: VecDeque<(u32, Vec<Bytes>)>,
+}
+
+impl FecEncoder {
+    pub fn new(config: FecConfig) -> Self {
+        Self {
+            config,
+            next_group_index: 0,
+            sent_groups: VecDeque::new(),
+        }
+    }
+
+    // Returns the shard packets ready to be sent
+    pub fn encode(&mut self, stream_id: StreamId, packet: &[u8]) -> StrResult<Vec<Bytes>> {
+        let shard_size = self.config.shard_size.max(1);
+        let mut data_shard_count = ((packet.len() + shard_size - 1) / shard_size).max(1);
+        let mut parity_shard_count =
+            (data_shard_count * self.config.parity_percentage as usize + 99) / 100;
+
+        // Keep the same proportion of parity with bigger shards. They will need IP fragmentation.
+        // With a very high percentage the proportion cannot be kept, at least one data shard is
+        // needed.
+        if data_shard_count + parity_shard_count > MAX_TOTAL_SHARDS {
+            data_shard_count =
+                (MAX_TOTAL_SHARDS * 100 / (100 + self.config.parity_percentage as usize)).max(1);
+            parity_shard_count = MAX_TOTAL_SHARDS - data_shard_count;
+        }
+        let shard_size = (packet.len() + data_shard_count - 1) / data_shard_count;
+
+        let mut shards = packet
+            .chunks(shard_size.max(1))
+            .map(|chunk| {
+                let mut shard = chunk.to_vec();
+                shard.resize(shard_size, 0);
+                shard
+            })
+            .collect::<Vec<_>>();
+        shards.resize(data_shard_count + parity_shard_count, vec![0; shard_size]);
+
+        if parity_shard_count > 0 {
+            let encoder = trace_err!(ReedSolomon::new(data_shard_count, parity_shard_count))?;
+            trace_err!(encoder.encode(&mut shards))?;
+        }
+
+        let group_index = self.next_group_index;
+        self.next_group_index = self.next_group_index.wrapping_add(1);
+
+        let shard_packets = shards
+            .into_iter()
+            .enumerate()
+            .map(|(shard_index, shard)| {
+                let mut buffer = BytesMut::with_capacity(2 + SHARD_HEADER_SIZE + shard.len());
+                buffer.put_u16(stream_id);
+                ShardHeader {
+                    group_index,
+                    shard_index: shard_index as _,
+                    data_shard_count: data_shard_count as _,
+                    parity_shard_count: parity_shard_count as _,
+                    packet_size: packet.len() as _,
+                }
+                .write(&mut buffer);
+                buffer.put_slice(&shard);
+
+                buffer.freeze()
+            })
+            .collect::<Vec<_>>();
+
+        if self.config.nack_deadline.is_some() {
+            self.sent_groups
+                .push_back((group_index, shard_packets.clone()));
+            if self.sent_groups.len() > RESEND_HISTORY_SIZE {
+                self.sent_groups.pop_front();
+            }
+        }
+
+        Ok(shard_packets)
+    }
+
+    // Returns the shard packets requested by a NACK. Shards that are too old are ignored.
+    pub fn shards_to_resend(&self, mut nack: BytesMut) -> StrResult<Vec<Bytes>> {
+        if nack.len() < 4 {
+            return fmt_e!("NACK too small");
+        }
+        let group_index = nack.get_u32();
+
+        let shard_packets =
+            if let Some((_, shards)) = self.sent_groups.iter().find(|(i, _)| *i == group_index) {
+                shards
+            } else {
+                return Ok(vec![]);
+            };
+
+        let mut requested_shards = vec![];
+        while nack.len() >= 2 {
+            if let Some(shard) = shard_packets.get(nack.get_u16() as usize) {
+                requested_shards.push(shard.clone());
+            }
+        }
+
+        Ok(requested_shards)
+    }
+}
+
+struct ShardGroup {
+    data_shard_count: usize,
+    parity_shard_count: usize,
+    shard_size: usize,
+    packet_size: usize,
+    shards: Vec<Option<Vec<u8>>>,
+    received_count: usize,
+    deadline: Instant,
+    // When the group is NACKed if it is still the newest one
+    nack_time: Instant,
+    nack_sent: bool,
+}
+
+impl ShardGroup {
+    fn is_complete(&self) -> bool {
+        self.received_count >= self.data_shard_count
+    }
+
+    fn reconstruct(mut self) -> StrResult<BytesMut> {
+        if self.parity_shard_count > 0 {
+            let decoder = trace_err!(ReedSolomon::new(
+                self.data_shard_count,
+                self.parity_shard_count
+            ))?;
+            trace_err!(decoder.reconstruct_data(&mut self.shards))?;
+        }
+
+        // The packet size comes from the peer
+        let mut packet = BytesMut::with_capacity(usize::min(
+            self.packet_size,
+            self.data_shard_count * self.shard_size,
+        ));
+        for shard in self.shards.into_iter().take(self.data_shard_count) {
+            packet.put_slice(&trace_none!(shard)?);
+        }
+        packet.truncate(self.packet_size);
+
+        Ok(packet)
+    }
+}
+
+pub struct FecDecoder {
+    config: FecConfig,
+    // Groups before this one have been delivered or discarded
+    next_group_index: u32,
+    groups: BTreeMap<u32, ShardGroup>,
+}
+
+impl FecDecoder {
+    pub fn new(config: FecConfig) -> Self {
+        Self {
+            config,
+            next_group_index: 0,
+            groups: BTreeMap::new(),
+        }
+    }
+
+    // `shard_packet` must not contain the stream ID
+    pub fn push_shard(&mut self, mut shard_packet: BytesMut, now: Instant) -> StrResult {
+        let header = ShardHeader::read(&mut shard_packet)?;
+
+        if header.group_index < self.next_group_index {
+            return Ok(());
+        }
+
+        let data_shard_count = header.data_shard_count as usize;
+        let total_shard_count = data_shard_count + header.parity_shard_count as usize;
+        let shard_index = header.shard_index as usize;
+        let shard_size = shard_packet.len();
+        if data_shard_count == 0 || total_shard_count > MAX_TOTAL_SHARDS {
+            return fmt_e!("Invalid shard counts");
+        }
+        if shard_index >= total_shard_count {
+            return fmt_e!("Invalid shard index");
+        }
+        if header.packet_size as usize > data_shard_count * shard_size {
+            return fmt_e!("Invalid packet size");
+        }
+
+        let nack_deadline = self.config.nack_deadline.unwrap_or_default();
+        let group = self
+            .groups
+            .entry(header.group_index)
+            .or_insert_with(|| ShardGroup {
+                data_shard_count,
+                parity_shard_count: header.parity_shard_count as _,
+                shard_size,
+                packet_size: header.packet_size as _,
+                shards: vec![None; total_shard_count],
+                received_count: 0,
+                deadline: now + nack_deadline,
+                nack_time: now + nack_deadline / 2,
+                nack_sent: false,
+            });
+
+        if group.shards.len() != total_shard_count
+            || group.data_shard_count != data_shard_count
+            || group.shard_size != shard_size
+        {
+            return fmt_e!("Inconsistent shard group");
+        }
+
+        if group.shards[shard_index].is_none() {
+            group.shards[shard_index] = Some(shard_packet.to_vec());
+            group.received_count += 1;
+        }
+
+        Ok(())
+    }
+
+    // Returns the next packet in order, if it can be reconstructed. A packet is discarded if it is
+    // incomplete after its deadline and shards of following packets have been received, or if its
+    // shards are corrupted.
+    pub fn pop_packet(&mut self, now: Instant) -> StrResult<Option<BytesMut>> {
+        while let Some((&group_index, group)) = self.groups.iter().next() {
+            if group.is_complete() || (self.groups.len() > 1 && now >= group.deadline) {
+                let group = trace_none!(self.groups.remove(&group_index))?;
+                self.next_group_index = group_index.wrapping_add(1);
+
+                if group.is_complete() {
+                    match group.reconstruct() {
+                        Ok(packet) => return Ok(Some(packet)),
+                        Err(e) => debug!("Discarded packet: {e}"),
+                    }
+                }
+            } else {
+                break;
+            }
+        }
+
+        Ok(None)
+    }
+
+    // Time when pop_packet() or take_nacks() should be called again even if no shard is received
+    pub fn next_deadline(&self) -> Option<Instant> {
+        let maybe_discard_time = if self.groups.len() > 1 {
+            self.groups.values().next().map(|group| group.deadline)
+        } else {
+            None
+        };
+        let maybe_nack_time = if self.config.nack_deadline.is_some() {
+            self.groups
+                .values()
+                .filter(|group| !group.is_complete() && !group.nack_sent)
+                .map(|group| group.nack_time)
+                .min()
+        } else {
+            None
+        };
+
+        maybe_discard_time.into_iter().chain(maybe_nack_time).min()
+    }
+
+    // Returns the NACK packets for incomplete groups followed by newer ones, and for the newest
+    // group after its NACK time. Each group is NACKed only once.
+    pub fn take_nacks(&mut self, nack_stream_id: StreamId, now: Instant) -> Vec<Bytes> {
+        if self.config.nack_deadline.is_none() {
+            return vec![];
+        }
+
+        let newest_group_index = match self.groups.keys().next_back() {
+            Some(index) => *index,
+            None => return vec![],
+        };
+
+        let mut nacks = vec![];
+        for (&group_index, group) in &mut self.groups {
+            if (group_index == newest_group_index && now < group.nack_time)
+                || group.is_complete()
+                || group.nack_sent
+            {
+                continue;
+            }
+
+            let mut nack = BytesMut::new();
+            nack.put_u16(nack_stream_id);
+            nack.put_u32(group_index);
+            for (shard_index, shard) in group.shards.iter().enumerate() {
+                if shard.is_none() {
+                    nack.put_u16(shard_index as _);
+                }
+            }
+            group.nack_sent = true;
+
+            nacks.push(nack.freeze());
+        }
+
+        nacks
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: FecConfig = FecConfig {
+        shard_size: 100,
+        parity_percentage: 50,
+        nack_deadline: Some(Duration::from_millis(10)),
+    };
+
+    fn strip_stream_id(mut shard: Bytes) -> BytesMut {
+        shard.advance(2);
+        BytesMut::from(&shard[..])
+    }
+
+    #[test]
+    fn reconstruct_with_lost_shards() {
+        let packet = (0..950).map(|i| i as u8).collect::<Vec<_>>();
+
+        let mut encoder = FecEncoder::new(CONFIG);
+        let shards = encoder.encode(0, &packet).unwrap();
+        assert_eq!(shards.len(), 15);
+
+        let mut decoder = FecDecoder::new(CONFIG);
+        let now = Instant::now();
+        // Lose as many shards as there are parity shards
+        for shard in shards.into_iter().skip(5) {
+            decoder.push_shard(strip_stream_id(shard), now).unwrap();
+        }
+
+        assert_eq!(&decoder.pop_packet(now).unwrap().unwrap()[..], &packet[..]);
+    }
+
+    #[test]
+    fn max_parity_percentage() {
+        let config = FecConfig {
+            parity_percentage: u16::MAX,
+            ..CONFIG
+        };
+        let packet = (0..950).map(|i| i as u8).collect::<Vec<_>>();
+
+        let mut encoder = FecEncoder::new(config);
+        let shards = encoder.encode(0, &packet).unwrap();
+        assert_eq!(shards.len(), MAX_TOTAL_SHARDS);
+
+        let mut decoder = FecDecoder::new(config);
+        let now = Instant::now();
+        decoder
+            .push_shard(strip_stream_id(shards[MAX_TOTAL_SHARDS - 1].clone()), now)
+            .unwrap();
+
+        assert_eq!(&decoder.pop_packet(now).unwrap().unwrap()[..], &packet[..]);
+    }
+
+    #[test]
+    fn resend_after_nack() {
+        let mut encoder = FecEncoder::new(CONFIG);
+        let first_shards = encoder.encode(0, &[1; 300]).unwrap();
+        let second_shards = encoder.encode(0, &[2; 300]).unwrap();
+
+        let mut decoder = FecDecoder::new(CONFIG);
+        let now = Instant::now();
+        decoder
+            .push_shard(strip_stream_id(first_shards[0].clone()), now)
+            .unwrap();
+        for shard in second_shards {
+            decoder.push_shard(strip_stream_id(shard), now).unwrap();
+        }
+
+        // The second packet is held back by the first one
+        assert!(decoder.pop_packet(now).unwrap().is_none());
+
+        let nacks = decoder.take_nacks(nack_stream_id(0), now);
+        assert_eq!(nacks.len(), 1);
+        assert!(decoder.take_nacks(nack_stream_id(0), now).is_empty());
+
+        for shard in encoder
+            .shards_to_resend(strip_stream_id(nacks[0].clone()))
+            .unwrap()
+        {
+            decoder.push_shard(strip_stream_id(shard), now).unwrap();
+        }
+
+        assert_eq!(&decoder.pop_packet(now).unwrap().unwrap()[..], &[1; 300]);
+        assert_eq!(&decoder.pop_packet(now).unwrap().unwrap()[..], &[2; 300]);
+    }
+
+    #[test]
+    fn discard_after_deadline() {
+        let mut encoder = FecEncoder::new(CONFIG);
+        let first_shards = encoder.encode(0, &[1; 300]).unwrap();
+        let second_shards = encoder.encode(0, &[2; 300]).unwrap();
+
+        let mut decoder = FecDecoder::new(CONFIG);
+        let now = Instant::now();
+        decoder
+            .push_shard(strip_stream_id(first_shards[0].clone()), now)
+            .unwrap();
+        for shard in second_shards {
+            decoder.push_shard(strip_stream_id(shard), now).unwrap();
+        }
+
+        // The first packet is NACKed before being discarded
+        assert!(decoder.next_deadline().unwrap() < now + CONFIG.nack_deadline.unwrap());
+
+        let deadline = now + CONFIG.nack_deadline.unwrap();
+        assert_eq!(
+            &decoder.pop_packet(deadline).unwrap().unwrap()[..],
+            &[2; 300]
+        );
+    }
+
+    #[test]
+    fn nack_newest_packet() {
+        let mut encoder = FecEncoder::new(CONFIG);
+        let shards = encoder.encode(0, &[1; 300]).unwrap();
+
+        let mut decoder = FecDecoder::new(CONFIG);
+        let now = Instant::now();
+        decoder
+            .push_shard(strip_stream_id(shards[0].clone()), now)
+            .unwrap();
+
+        // No following packet reveals the loss, so the NACK is sent after a delay
+        assert!(decoder.take_nacks(nack_stream_id(0), now).is_empty());
+        let nack_time = decoder.next_deadline().unwrap();
+        assert!(nack_time > now);
+        let nacks = decoder.take_nacks(nack_stream_id(0), nack_time);
+        assert_eq!(nacks.len(), 1);
+        assert!(decoder.next_deadline().is_none());
+
+        for shard in encoder
+            .shards_to_resend(strip_stream_id(nacks[0].clone()))
+            .unwrap()
+        {
+            decoder.push_shard(strip_stream_id(shard), now).unwrap();
+        }
+        assert_eq!(&decoder.pop_packet(now).unwrap().unwrap()[..], &[1; 300]);
+    }
+
+    #[test]
+    fn reject_invalid_shards() {
+        let mut decoder = FecDecoder::new(CONFIG);
+        let now = Instant::now();
+
+        let shard = |data_shard_count, packet_size| {
+            let mut buffer = BytesMut::new();
+            ShardHeader {
+                group_index: 0,
+                shard_index: 0,
+                data_shard_count,
+                parity_shard_count: 0,
+                packet_size,
+            }
+            .write(&mut buffer);
+            buffer.put_slice(&[0; 10]);
+
+            buffer
+        };
+
+        assert!(decoder
+            .push_shard(BytesMut::from(&[0; 4][..]), now)
+            .is_err());
+        assert!(decoder.push_shard(shard(0, 0), now).is_err());
+        assert!(decoder.push_shard(shard(1, u32::MAX), now).is_err());
+        assert!(decoder.push_shard(shard(1, 10), now).is_ok());
+        assert!(decoder.push_shard(shard(2, 10), now).is_err());
+    }
+}
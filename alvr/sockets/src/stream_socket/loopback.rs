@@ -0,0 +1,350 @@
+// In-process transport, used to run a server and a client pipeline in the same test process. The
+// two StreamSockets exchange packets through memory, so no port is bound and any number of links
+// can coexist.
+//
+// Each direction of the link can emulate a real network: packets can be lost, delayed, reordered
+// (by the jitter) and throttled, and the link can go down. The random decisions come from a seeded
+// generator, so the same sequence of packets always produces the same losses and delays. The same
+// conditions are used by ProtoControlSocket::loopback_pair().
+
+use super::PacketQueues;
+use alvr_common::prelude::*;
+use bytes::{Buf, Bytes, BytesMut};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::{
+    cmp::Reverse,
+    collections::BinaryHeap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::{
+    sync::mpsc,
+    time::{self, Instant},
+};
+
+#[derive(Clone, Copy, Debug)]
+pub struct LinkConditions {
+    // Probability for each packet to be lost, from 0 to 1
+    pub loss_rate: f32,
+    pub delay: Duration,
+    // Each packet is delayed by an additional random duration up to this value. Packets that are
+    // sent less than `jitter` apart can arrive out of order.
+    pub jitter: Duration,
+    // None means unlimited. Packets wait for the link to be free, so a burst increases the delay.
+    pub bandwidth_bps: Option<u64>,
+    // All the packets sent after this duration from the link creation are lost
+    pub outage_after: Option<Duration>,
+    pub seed: u64,
+}
+
+impl Default for LinkConditions {
+    fn default() -> Self {
+        Self {
+            loss_rate: 0.,
+            delay: Duration::ZERO,
+            jitter: Duration::ZERO,
+            bandwidth_bps: None,
+            outage_after: None,
+            seed: 0,
+        }
+    }
+}
+
+// Arrival time, send order (to keep packets with the same arrival time in order), packet
+type InFlightPacket = (Instant, u64, Bytes);
+
+pub(crate) struct LinkState {
+    conditions: LinkConditions,
+    rng: StdRng,
+    creation_time: Instant,
+    sent_count: u64,
+    // When the last packet finishes being transmitted, for the bandwidth limit
+    busy_until: Instant,
+}
+
+impl LinkState {
+    pub(crate) fn new(conditions: LinkConditions) -> Self {
+        let now = Instant::now();
+        Self {
+            conditions,
+            rng: StdRng::seed_from_u64(conditions.seed),
+            creation_time: now,
+            sent_count: 0,
+            busy_until: now,
+        }
+    }
+
+    // Returns the arrival time of a packet sent at `send_time`, or None if it is lost
+    pub(crate) fn transmit(&mut self, size: usize, send_time: Instant) -> Option<Instant> {
+        // A lost packet still occupies the link
+        let transmitted_time = if let Some(bandwidth_bps) = self.conditions.bandwidth_bps {
+            let transmission_duration =
+                Duration::from_secs_f64(size as f64 * 8. / bandwidth_bps as f64);
+            self.busy_until = self.busy_until.max(send_time) + transmission_duration;
+
+            self.busy_until
+        } else {
+            send_time
+        };
+
+        // Always draw both values, so the decisions for a packet do not depend on the previous ones
+        let lost = self.rng.gen::<f32>() < self.conditions.loss_rate;
+        let jitter = self.conditions.jitter.mul_f32(self.rng.gen());
+
+        let link_down = self
+            .conditions
+            .outage_after
+            .map(|outage_after| send_time >= self.creation_time + outage_after)
+            .unwrap_or(false);
+
+        (!lost && !link_down).then(|| transmitted_time + self.conditions.delay + jitter)
+    }
+}
+
+#[derive(Clone)]
+pub struct LoopbackStreamSendSocket {
+    state: Arc<Mutex<LinkState>>,
+    sender: mpsc::UnboundedSender<InFlightPacket>,
+}
+
+impl LoopbackStreamSendSocket {
+    // `packet` must start with the stream ID
+    pub fn send(&self, packet: Bytes) -> StrResult {
+        let mut state = self.state.lock().unwrap();
+
+        if let Some(arrival_time) = state.transmit(packet.len(), Instant::now()) {
+            let send_index = state.sent_count;
+            state.sent_count += 1;
+
+            trace_err!(self.sender.send((arrival_time, send_index, packet)))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+pub type LoopbackStreamReceiveSocket = mpsc::UnboundedReceiver<InFlightPacket>;
+
+// One end of an in-process link
+pub struct LoopbackEndpoint {
+    send_socket: LoopbackStreamSendSocket,
+    receive_socket: LoopbackStreamReceiveSocket,
+}
+
+impl LoopbackEndpoint {
+    pub(super) fn split(self) -> (LoopbackStreamSendSocket, LoopbackStreamReceiveSocket) {
+        (self.send_socket, self.receive_socket)
+    }
+}
+
+fn half_link(
+    conditions: LinkConditions,
+) -> (LoopbackStreamSendSocket, LoopbackStreamReceiveSocket) {
+    let (sender, receiver) = mpsc::unbounded_channel();
+
+    let send_socket = LoopbackStreamSendSocket {
+        state: Arc::new(Mutex::new(LinkState::new(conditions))),
+        sender,
+    };
+
+    (send_socket, receiver)
+}
+
+pub fn endpoint_pair(
+    first_to_second: LinkConditions,
+    second_to_first: LinkConditions,
+) -> (LoopbackEndpoint, LoopbackEndpoint) {
+    let (first_send_socket, second_receive_socket) = half_link(first_to_second);
+    let (second_send_socket, first_receive_socket) = half_link(second_to_first);
+
+    (
+        LoopbackEndpoint {
+            send_socket: first_send_socket,
+            receive_socket: first_receive_socket,
+        },
+        LoopbackEndpoint {
+            send_socket: second_send_socket,
+            receive_socket: second_receive_socket,
+        },
+    )
+}
+
+// Ends when the peer StreamSocket is dropped and all the packets in flight are delivered
+pub async fn receive_loop(
+    mut socket: LoopbackStreamReceiveSocket,
+    packet_queues: PacketQueues,
+) -> StrResult {
+    let mut in_flight = BinaryHeap::new();
+    let mut disconnected = false;
+
+    loop {
+        let next_arrival_time = in_flight
+            .peek()
+            .map(|Reverse((arrival_time, ..)): &Reverse<InFlightPacket>| *arrival_time);
+
+        if disconnected && next_arrival_time.is_none() {
+            break Ok(());
+        }
+
+        tokio::select! {
+            maybe_packet = socket.recv(), if !disconnected => match maybe_packet {
+                Some(packet) => in_flight.push(Reverse(packet)),
+                None => disconnected = true,
+            },
+            _ = time::sleep_until(next_arrival_time.unwrap_or_else(Instant::now)),
+                if next_arrival_time.is_some() =>
+            {
+                let now = Instant::now();
+                while in_flight
+                    .peek()
+                    .map(|Reverse((arrival_time, ..))| *arrival_time <= now)
+                    .unwrap_or(false)
+                {
+                    let Reverse((_, _, packet)) = trace_none!(in_flight.pop())?;
+                    let mut packet = BytesMut::from(&packet[..]);
+
+                    let stream_id = packet.get_u16();
+                    let queue = packet_queues.lock().await.get(&stream_id).cloned();
+                    if let Some(queue) = queue {
+                        queue.push(packet).await;
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{StreamSocketBuilder, INPUT};
+    use bytes::BufMut;
+    use std::net::{IpAddr, Ipv4Addr};
+
+    fn packet(index: u16) -> Bytes {
+        let mut packet = BytesMut::new();
+        packet.put_u16(index);
+
+        packet.freeze()
+    }
+
+    fn received_indices(conditions: LinkConditions, count: u16) -> Vec<u16> {
+        let (send_socket, mut receive_socket) = half_link(conditions);
+        for index in 0..count {
+            send_socket.send(packet(index)).unwrap();
+        }
+
+        let mut arrivals = vec![];
+        while let Ok((arrival_time, _, mut packet)) = receive_socket.try_recv() {
+            arrivals.push((arrival_time, packet.get_u16()));
+        }
+        arrivals.sort();
+
+        arrivals.into_iter().map(|(_, index)| index).collect()
+    }
+
+    #[test]
+    fn deterministic_loss() {
+        let conditions = LinkConditions {
+            loss_rate: 0.5,
+            seed: 42,
+            ..Default::default()
+        };
+
+        let received = received_indices(conditions, 100);
+        assert!(!received.is_empty() && received.len() < 100);
+        assert_eq!(received, received_indices(conditions, 100));
+    }
+
+    #[test]
+    fn jitter_reorders() {
+        let conditions = LinkConditions {
+            jitter: Duration::from_millis(100),
+            ..Default::default()
+        };
+
+        let received = received_indices(conditions, 100);
+        assert_eq!(received.len(), 100);
+        assert!(received.windows(2).any(|pair| pair[0] > pair[1]));
+    }
+
+    #[tokio::test]
+    async fn outage() {
+        let conditions = LinkConditions {
+            outage_after: Some(Duration::from_millis(50)),
+            ..Default::default()
+        };
+        let (send_socket, mut receive_socket) = half_link(conditions);
+
+        send_socket.send(packet(0)).unwrap();
+        time::sleep(Duration::from_millis(50)).await;
+        send_socket.send(packet(1)).unwrap();
+
+        assert!(receive_socket.try_recv().is_ok());
+        assert!(receive_socket.try_recv().is_err());
+    }
+
+    #[test]
+    fn bandwidth_limit() {
+        // One bit per millisecond
+        let conditions = LinkConditions {
+            bandwidth_bps: Some(1000),
+            ..Default::default()
+        };
+        let (send_socket, mut receive_socket) = half_link(conditions);
+
+        let start = Instant::now();
+        for index in 0..10 {
+            send_socket.send(packet(index)).unwrap();
+        }
+
+        // Each packet takes 16ms to be transmitted
+        let mut last_arrival_time = start;
+        while let Ok((arrival_time, ..)) = receive_socket.try_recv() {
+            last_arrival_time = arrival_time;
+        }
+        assert!(last_arrival_time - start >= Duration::from_millis(160));
+    }
+
+    #[tokio::test]
+    async fn stream_socket_round_trip() {
+        let conditions = LinkConditions {
+            delay: Duration::from_millis(20),
+            ..Default::default()
+        };
+        let (server_builder, client_builder) =
+            StreamSocketBuilder::loopback_pair(conditions, conditions);
+
+        let server_socket = Arc::new(server_builder.connect_to_loopback().unwrap());
+        let client_socket = Arc::new(
+            client_builder
+                .accept_from_server(IpAddr::V4(Ipv4Addr::LOCALHOST).into(), 0)
+                .await
+                .unwrap(),
+        );
+
+        let mut sender = server_socket.request_stream::<u32>(INPUT).await.unwrap();
+        let mut receiver = client_socket
+            .subscribe_to_stream::<u32>(INPUT)
+            .await
+            .unwrap();
+
+        for socket in [&server_socket, &client_socket] {
+            let socket = Arc::clone(socket);
+            tokio::spawn(async move {
+                tokio::select! {
+                    _ = socket.send_loop() => (),
+                    _ = socket.receive_loop() => (),
+                }
+            });
+        }
+
+        let start = Instant::now();
+        sender.send(&42).await.unwrap();
+        let packet = receiver.recv().await.unwrap();
+
+        assert_eq!(packet.header, 42);
+        assert!(!packet.had_packet_loss);
+        assert!(Instant::now() - start >= conditions.delay);
+    }
+}
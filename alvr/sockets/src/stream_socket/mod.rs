@@ -4,42 +4,85 @@
 // StreamSender and StreamReceiver endpoints allow for convenient conversion of the header to/from
 // bytes while still handling the additional byte buffer with zero copies and extra allocations.
 
+mod fec;
+mod loopback;
+mod mtu;
+mod queue;
+mod quic;
+mod statistics;
 mod tcp;
 mod throttled_udp;
 mod udp;
 
+use crate::{PeerIp, PrivateIdentity};
 use alvr_common::prelude::*;
 use alvr_session::SocketProtocol;
-use bytes::{Buf, BufMut, BytesMut};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use fec::{FecDecoder, FecEncoder};
 use futures::SinkExt;
+use loopback::{LoopbackStreamReceiveSocket, LoopbackStreamSendSocket};
+use queue::{PacketQueue, SendScheduler};
+use quic::{QuicStreamReceiveSocket, QuicStreamSendSocket};
 use serde::{de::DeserializeOwned, Serialize};
+use statistics::StatisticsCollector;
 use std::{
     collections::HashMap,
     marker::PhantomData,
     net::IpAddr,
     ops::{Deref, DerefMut},
     sync::Arc,
+    time::Instant,
 };
 use tcp::{TcpStreamReceiveSocket, TcpStreamSendSocket};
 use throttled_udp::{ThrottledUdpStreamReceiveSocket, ThrottledUdpStreamSendSocket};
 use tokio::net;
-use tokio::sync::{mpsc, Mutex};
+use tokio::{
+    sync::{Mutex, Notify},
+    task::JoinHandle,
+};
 use udp::{UdpStreamReceiveSocket, UdpStreamSendSocket};
 
+pub use fec::FecConfig;
+pub(crate) use loopback::LinkState;
+pub use loopback::{LinkConditions, LoopbackEndpoint};
+pub use queue::{DropPolicy, QueueBound, StreamOptions, StreamPriority};
+pub use statistics::{NetworkStatistics, StreamStatistics};
+
 // todo: when const_generics reaches stable, convert this to an enum
 pub type StreamId = u16;
 
+type PacketQueues = Arc<Mutex<HashMap<StreamId, Arc<PacketQueue<BytesMut>>>>>;
+
 #[derive(Clone)]
 enum StreamSendSocket {
     Udp(UdpStreamSendSocket),
     ThrottledUdp(ThrottledUdpStreamSendSocket),
     Tcp(TcpStreamSendSocket),
+    Quic(QuicStreamSendSocket),
+    Loopback(LoopbackStreamSendSocket),
+}
+
+impl StreamSendSocket {
+    // `packet` must start with the stream ID
+    async fn send(&self, stream_id: StreamId, packet: Bytes) -> StrResult {
+        match self {
+            StreamSendSocket::Udp(socket) => socket.send(packet).await,
+            StreamSendSocket::Tcp(socket) => {
+                trace_err!(socket.lock().await.send(packet).await)
+            }
+            StreamSendSocket::ThrottledUdp(socket) => trace_err!(socket.send(packet).await),
+            StreamSendSocket::Quic(socket) => socket.send(stream_id, packet).await,
+            StreamSendSocket::Loopback(socket) => socket.send(packet),
+        }
+    }
 }
 
 enum StreamReceiveSocket {
     Udp(UdpStreamReceiveSocket),
     ThrottledUdp(ThrottledUdpStreamReceiveSocket),
     Tcp(TcpStreamReceiveSocket),
+    Quic(QuicStreamReceiveSocket),
+    Loopback(LoopbackStreamReceiveSocket),
 }
 
 pub struct SendBufferLock<'a> {
@@ -88,34 +131,46 @@ impl<T> SenderBuffer<T> {
 
 pub struct StreamSender<T> {
     stream_id: StreamId,
-    socket: StreamSendSocket,
+    // consumed by StreamSocket::send_loop()
+    queue: Arc<PacketQueue<Vec<Bytes>>>,
     // if the packet index overflows the worst that happens is a false positive packet loss
     next_packet_index: u32,
+    // Shared with the task that resends the shards requested by the peer
+    fec: Option<(Arc<Mutex<FecEncoder>>, JoinHandle<()>)>,
+    statistics: Arc<StatisticsCollector>,
     _phantom: PhantomData<T>,
 }
 
 impl<T> StreamSender<T> {
     // The buffer is moved into the method. There is no way of reusing the same buffer twice without
-    // extra copies/allocations
+    // extra copies/allocations. If the send queue is full, the packet could be dropped depending on
+    // the stream drop policy.
     pub async fn send_buffer(&mut self, mut buffer: SenderBuffer<T>) -> StrResult {
         buffer.inner[2..6].copy_from_slice(&self.next_packet_index.to_be_bytes());
         self.next_packet_index += 1;
 
-        match &self.socket {
-            StreamSendSocket::Udp(socket) => trace_err!(
-                socket
-                    .inner
-                    .lock()
-                    .await
-                    .send((buffer.inner.freeze(), socket.peer_addr))
-                    .await
-            ),
-            StreamSendSocket::Tcp(socket) => {
-                trace_err!(socket.lock().await.send(buffer.inner.freeze()).await)
-            }
-            StreamSendSocket::ThrottledUdp(socket) => {
-                trace_err!(socket.send(buffer.inner.freeze()).await)
-            }
+        let packets = if let Some((encoder, _)) = &self.fec {
+            // The stream ID is already part of each shard
+            encoder
+                .lock()
+                .await
+                .encode(self.stream_id, &buffer.inner[2..])?
+        } else {
+            vec![buffer.inner.freeze()]
+        };
+
+        if !self.queue.push(packets).await {
+            self.statistics.report_dropped(self.stream_id);
+        }
+
+        Ok(())
+    }
+}
+
+impl<T> Drop for StreamSender<T> {
+    fn drop(&mut self) {
+        if let Some((_, nack_task)) = &self.fec {
+            nack_task.abort();
         }
     }
 }
@@ -154,8 +209,7 @@ impl<T: Serialize> StreamSender<T> {
 }
 
 enum StreamReceiverType {
-    Queue(mpsc::UnboundedReceiver<BytesMut>),
-    // QuicReliable(...)
+    Queue(Arc<PacketQueue<BytesMut>>),
 }
 
 pub struct ReceivedPacket<T> {
@@ -168,18 +222,59 @@ pub struct StreamReceiver<T> {
     stream_id: StreamId,
     receiver: StreamReceiverType,
     next_packet_index: u32,
+    // FEC decoder and socket used to send NACKs
+    fec: Option<(FecDecoder, StreamSendSocket)>,
+    statistics: Arc<StatisticsCollector>,
     _phantom: PhantomData<T>,
 }
 
 impl<T: DeserializeOwned> StreamReceiver<T> {
     pub async fn recv(&mut self) -> StrResult<ReceivedPacket<T>> {
-        let mut bytes = match &mut self.receiver {
-            StreamReceiverType::Queue(receiver) => trace_none!(receiver.recv().await)?,
+        let StreamReceiverType::Queue(queue) = &self.receiver;
+
+        let mut bytes = if let Some((decoder, nack_socket)) = &mut self.fec {
+            loop {
+                if let Some(packet) = decoder.pop_packet(Instant::now())? {
+                    break packet;
+                }
+
+                let nack_stream_id = fec::nack_stream_id(self.stream_id);
+                for nack in decoder.take_nacks(nack_stream_id, Instant::now()) {
+                    nack_socket.send(nack_stream_id, nack).await?;
+                }
+
+                let shard = if let Some(deadline) = decoder.next_deadline() {
+                    let deadline = tokio::time::Instant::from_std(deadline);
+                    match tokio::time::timeout_at(deadline, queue.pop()).await {
+                        Ok(shard) => trace_none!(shard)?,
+                        Err(_) => continue,
+                    }
+                } else {
+                    trace_none!(queue.pop().await)?
+                };
+
+                // A corrupted shard must not close the stream
+                if let Err(e) = decoder.push_shard(shard, Instant::now()) {
+                    debug!("Dropped shard: {e}");
+                }
+            }
+        } else {
+            trace_none!(queue.pop().await)?
         };
 
+        let packet_size = bytes.len();
         let packet_index = bytes.get_u32();
         let had_packet_loss = packet_index != self.next_packet_index;
-        self.next_packet_index = packet_index + 1;
+        self.statistics.report_received(
+            self.stream_id,
+            packet_size,
+            packet_index,
+            self.next_packet_index,
+        );
+        // Reordered packets must not move the expected index backwards
+        if packet_index.wrapping_sub(self.next_packet_index) as i32 >= 0 {
+            self.next_packet_index = packet_index.wrapping_add(1);
+        }
 
         let mut bytes_reader = bytes.reader();
         let header = trace_err!(bincode::deserialize_from(&mut bytes_reader))?;
@@ -198,23 +293,61 @@ pub enum StreamSocketBuilder {
     Tcp(net::TcpListener),
     Udp(net::UdpSocket),
     ThrottledUdp(net::UdpSocket),
+    Quic(quinn::Endpoint),
+    Loopback(LoopbackEndpoint),
 }
 
 impl StreamSocketBuilder {
+    // In-process link, used by tests. Returns the server and the client builders. The client
+    // builder is used with accept_from_server(), the server builder with connect_to_loopback().
+    pub fn loopback_pair(
+        server_to_client: LinkConditions,
+        client_to_server: LinkConditions,
+    ) -> (Self, Self) {
+        let (server_endpoint, client_endpoint) =
+            loopback::endpoint_pair(server_to_client, client_to_server);
+
+        (
+            StreamSocketBuilder::Loopback(server_endpoint),
+            StreamSocketBuilder::Loopback(client_endpoint),
+        )
+    }
+
+    pub fn connect_to_loopback(self) -> StrResult<StreamSocket> {
+        if let StreamSocketBuilder::Loopback(endpoint) = self {
+            let (send_socket, receive_socket) = endpoint.split();
+
+            Ok(StreamSocket::new(
+                StreamSendSocket::Loopback(send_socket),
+                StreamReceiveSocket::Loopback(receive_socket),
+            ))
+        } else {
+            fmt_e!("Not a loopback stream socket")
+        }
+    }
+
+    // `identity` authenticates the client to the server when using QUIC
     pub async fn listen_for_server(
+        bind_ip: IpAddr,
         port: u16,
         stream_socket_config: SocketProtocol,
+        identity: &PrivateIdentity,
     ) -> StrResult<Self> {
         Ok(match stream_socket_config {
-            SocketProtocol::Udp => StreamSocketBuilder::Udp(udp::bind(port).await?),
-            SocketProtocol::Tcp => StreamSocketBuilder::Tcp(tcp::listen_for_server(port).await?),
-            SocketProtocol::ThrottledUdp { .. } => {
-                StreamSocketBuilder::ThrottledUdp(throttled_udp::listen_for_server(port).await?)
+            SocketProtocol::Udp => StreamSocketBuilder::Udp(udp::bind(bind_ip, port).await?),
+            SocketProtocol::Tcp => {
+                StreamSocketBuilder::Tcp(tcp::listen_for_server(bind_ip, port).await?)
+            }
+            SocketProtocol::ThrottledUdp { .. } => StreamSocketBuilder::ThrottledUdp(
+                throttled_udp::listen_for_server(bind_ip, port).await?,
+            ),
+            SocketProtocol::Quic => {
+                StreamSocketBuilder::Quic(quic::listen_for_server(bind_ip, port, identity).await?)
             }
         })
     }
 
-    pub async fn accept_from_server(self, server_ip: IpAddr, port: u16) -> StrResult<StreamSocket> {
+    pub async fn accept_from_server(self, server_ip: PeerIp, port: u16) -> StrResult<StreamSocket> {
         let (send_socket, receive_socket) = match self {
             StreamSocketBuilder::Udp(socket) => {
                 let (send_socket, receive_socket) = udp::connect(socket, server_ip, port).await?;
@@ -239,24 +372,31 @@ impl StreamSocketBuilder {
                     StreamReceiveSocket::ThrottledUdp(receive_socket),
                 )
             }
+            StreamSocketBuilder::Quic(endpoint) => {
+                let (send_socket, receive_socket) =
+                    quic::accept_from_server(endpoint, server_ip).await?;
+                (
+                    StreamSendSocket::Quic(send_socket),
+                    StreamReceiveSocket::Quic(receive_socket),
+                )
+            }
+            StreamSocketBuilder::Loopback(_) => return self.connect_to_loopback(),
         };
 
-        Ok(StreamSocket {
-            send_socket,
-            receive_socket: Arc::new(Mutex::new(Some(receive_socket))),
-            packet_queues: Arc::new(Mutex::new(HashMap::new())),
-        })
+        Ok(StreamSocket::new(send_socket, receive_socket))
     }
 
     pub async fn connect_to_client(
-        client_ip: IpAddr,
+        bind_ip: IpAddr,
+        client_ip: PeerIp,
         port: u16,
         protocol: SocketProtocol,
         video_byterate: u32,
+        client_certificate_pem: Option<&str>,
     ) -> StrResult<StreamSocket> {
         let (send_socket, receive_socket) = match protocol {
             SocketProtocol::Udp => {
-                let sock = udp::bind(port).await?;
+                let sock = udp::bind(bind_ip, port).await?;
                 let (send_socket, receive_socket) = udp::connect(sock, client_ip, port).await?;
                 (
                     StreamSendSocket::Udp(send_socket),
@@ -264,7 +404,8 @@ impl StreamSocketBuilder {
                 )
             }
             SocketProtocol::Tcp => {
-                let (send_socket, receive_socket) = tcp::connect_to_client(client_ip, port).await?;
+                let (send_socket, receive_socket) =
+                    tcp::connect_to_client(bind_ip, client_ip, port).await?;
                 (
                     StreamSendSocket::Tcp(send_socket),
                     StreamReceiveSocket::Tcp(receive_socket),
@@ -272,6 +413,7 @@ impl StreamSocketBuilder {
             }
             SocketProtocol::ThrottledUdp { bitrate_multiplier } => {
                 let (send_socket, receive_socket) = throttled_udp::connect_to_client(
+                    bind_ip,
                     client_ip,
                     port,
                     video_byterate,
@@ -283,28 +425,112 @@ impl StreamSocketBuilder {
                     StreamReceiveSocket::ThrottledUdp(receive_socket),
                 )
             }
+            SocketProtocol::Quic => {
+                let client_certificate_pem = match client_certificate_pem {
+                    Some(certificate_pem) => certificate_pem,
+                    None => return fmt_e!("QUIC requires the certificate of the client"),
+                };
+                let (send_socket, receive_socket) =
+                    quic::connect_to_client(bind_ip, client_ip, port, client_certificate_pem)
+                        .await?;
+                (
+                    StreamSendSocket::Quic(send_socket),
+                    StreamReceiveSocket::Quic(receive_socket),
+                )
+            }
         };
 
-        Ok(StreamSocket {
-            send_socket,
-            receive_socket: Arc::new(Mutex::new(Some(receive_socket))),
-            packet_queues: Arc::new(Mutex::new(HashMap::new())),
-        })
+        Ok(StreamSocket::new(send_socket, receive_socket))
     }
 }
 
 pub struct StreamSocket {
     send_socket: StreamSendSocket,
     receive_socket: Arc<Mutex<Option<StreamReceiveSocket>>>,
-    packet_queues: Arc<Mutex<HashMap<StreamId, mpsc::UnboundedSender<BytesMut>>>>,
+    packet_queues: PacketQueues,
+    scheduler: SendScheduler,
+    statistics: Arc<StatisticsCollector>,
+    ping_queue: Arc<PacketQueue<BytesMut>>,
+    pong_queue: Arc<PacketQueue<BytesMut>>,
 }
 
 impl StreamSocket {
+    fn new(send_socket: StreamSendSocket, receive_socket: StreamReceiveSocket) -> Self {
+        let ping_queue = Arc::new(PacketQueue::new(None, Arc::new(Notify::new())));
+        let pong_queue = Arc::new(PacketQueue::new(None, Arc::new(Notify::new())));
+
+        let packet_queues = [
+            (statistics::PING, Arc::clone(&ping_queue)),
+            (statistics::PONG, Arc::clone(&pong_queue)),
+        ]
+        .into_iter()
+        .collect();
+
+        Self {
+            send_socket,
+            receive_socket: Arc::new(Mutex::new(Some(receive_socket))),
+            packet_queues: Arc::new(Mutex::new(packet_queues)),
+            scheduler: SendScheduler::new(),
+            statistics: Arc::new(StatisticsCollector::new()),
+            ping_queue,
+            pong_queue,
+        }
+    }
+
     pub async fn request_stream<T>(&self, stream_id: StreamId) -> StrResult<StreamSender<T>> {
+        self.request_stream_with_options(stream_id, crate::default_stream_options(stream_id))
+            .await
+    }
+
+    // The peer should subscribe to the stream with the same options
+    pub async fn request_stream_with_options<T>(
+        &self,
+        stream_id: StreamId,
+        options: StreamOptions,
+    ) -> StrResult<StreamSender<T>> {
+        let queue = self.scheduler.register(stream_id, options);
+
+        // NACKs are served as they arrive, even if the stream has no new packets to send. The task
+        // ends when receive_loop() closes the queues or the sender is dropped.
+        let fec = if let Some(config) = options.fec {
+            let nack_queue = Arc::new(PacketQueue::new(None, Arc::new(Notify::new())));
+            self.packet_queues
+                .lock()
+                .await
+                .insert(fec::nack_stream_id(stream_id), Arc::clone(&nack_queue));
+
+            let encoder = Arc::new(Mutex::new(FecEncoder::new(config)));
+            let nack_task = tokio::spawn({
+                let encoder = Arc::clone(&encoder);
+                let queue = Arc::clone(&queue);
+                let statistics = Arc::clone(&self.statistics);
+                async move {
+                    while let Some(nack) = nack_queue.pop().await {
+                        let shards = match encoder.lock().await.shards_to_resend(nack) {
+                            Ok(shards) => shards,
+                            Err(e) => {
+                                debug!("Dropped NACK: {e}");
+                                continue;
+                            }
+                        };
+                        if !shards.is_empty() && !queue.push(shards).await {
+                            statistics.report_dropped(stream_id);
+                        }
+                    }
+                }
+            });
+
+            Some((encoder, nack_task))
+        } else {
+            None
+        };
+
         Ok(StreamSender {
             stream_id,
-            socket: self.send_socket.clone(),
+            queue,
             next_packet_index: 0,
+            fec,
+            statistics: Arc::clone(&self.statistics),
             _phantom: PhantomData,
         })
     }
@@ -313,19 +539,74 @@ impl StreamSocket {
         &self,
         stream_id: StreamId,
     ) -> StrResult<StreamReceiver<T>> {
-        let (enqueuer, dequeuer) = mpsc::unbounded_channel();
-        self.packet_queues.lock().await.insert(stream_id, enqueuer);
+        self.subscribe_to_stream_with_options(stream_id, crate::default_stream_options(stream_id))
+            .await
+    }
+
+    pub async fn subscribe_to_stream_with_options<T>(
+        &self,
+        stream_id: StreamId,
+        options: StreamOptions,
+    ) -> StrResult<StreamReceiver<T>> {
+        let queue = Arc::new(PacketQueue::new(
+            options.queue_bound,
+            Arc::new(Notify::new()),
+        ));
+        self.packet_queues
+            .lock()
+            .await
+            .insert(stream_id, Arc::clone(&queue));
 
         Ok(StreamReceiver {
             stream_id,
-            receiver: StreamReceiverType::Queue(dequeuer),
+            receiver: StreamReceiverType::Queue(queue),
             next_packet_index: 0,
+            fec: options
+                .fec
+                .map(|config| (FecDecoder::new(config), self.send_socket.clone())),
+            statistics: Arc::clone(&self.statistics),
             _phantom: PhantomData,
         })
     }
 
+    // Sends the packets of all the StreamSenders in priority order, and the pings used to measure
+    // the RTT. It must run for as long as the senders are in use.
+    pub async fn send_loop(&self) -> StrResult {
+        let mut ping_interval = tokio::time::interval(statistics::PING_INTERVAL);
+
+        loop {
+            tokio::select! {
+                (stream_id, packets) = self.scheduler.pop() => {
+                    let size = packets.iter().map(|packet| packet.len()).sum();
+                    for packet in packets {
+                        self.send_socket.send(stream_id, packet).await?;
+                    }
+                    self.statistics.report_sent(stream_id, size);
+                }
+                _ = ping_interval.tick() => {
+                    let ping = self.statistics.ping_packet();
+                    self.send_socket.send(statistics::PING, ping).await?;
+                }
+                Some(ping) = self.ping_queue.pop() => {
+                    let pong = StatisticsCollector::pong_packet(ping);
+                    self.send_socket.send(statistics::PONG, pong).await?;
+                }
+                Some(pong) = self.pong_queue.pop() => {
+                    // A malformed pong from the peer must not close the stream
+                    if let Err(e) = self.statistics.report_pong(pong) {
+                        debug!("Dropped pong: {e}");
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn statistics(&self) -> NetworkStatistics {
+        self.statistics.snapshot()
+    }
+
     pub async fn receive_loop(&self) -> StrResult {
-        match self.receive_socket.lock().await.take().unwrap() {
+        let res = match self.receive_socket.lock().await.take().unwrap() {
             StreamReceiveSocket::Udp(socket) => {
                 udp::receive_loop(socket, Arc::clone(&self.packet_queues)).await
             }
@@ -335,6 +616,19 @@ impl StreamSocket {
             StreamReceiveSocket::ThrottledUdp(socket) => {
                 throttled_udp::receive_loop(socket, Arc::clone(&self.packet_queues)).await
             }
+            StreamReceiveSocket::Quic(socket) => {
+                quic::receive_loop(socket, Arc::clone(&self.packet_queues)).await
+            }
+            StreamReceiveSocket::Loopback(socket) => {
+                loopback::receive_loop(socket, Arc::clone(&self.packet_queues)).await
+            }
+        };
+
+        // Wake up the receivers, which would otherwise wait forever
+        for queue in self.packet_queues.lock().await.values() {
+            queue.close();
         }
+
+        res
     }
 }
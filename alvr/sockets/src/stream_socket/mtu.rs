@@ -0,0 +1,515 @@
+// Path MTU discovery and fragmentation for the UDP based stream sockets.
+//
+// During connection setup both peers send probes of decreasing size to each other and acknowledge
+// the probes they receive. The largest acknowledged size becomes the maximum datagram payload.
+// Probing needs the don't fragment flag, which is set on Linux, Android and Windows. Elsewhere (like
+// macOS) the probes of the peer are still acknowledged but the Ethernet size is used. Packets of the peer that arrive while probing are
+// handed to the receive loop.
+// Bigger packets are split into fragments, which are reassembled by the receive loop. If a packet
+// cannot be reassembled in time it is discarded, and its missing packet index makes the
+// StreamReceiver report a packet loss.
+//
+// Probe layout: probe stream ID (u16), probe size (u16), padding.
+// Fragment layout: fragment stream ID (u16), packet index (u32), fragment index (u16), fragment
+// count (u16), chunk of the original packet (which starts with its own stream ID).
+
+use super::{PacketQueues, StreamId};
+use alvr_common::prelude::*;
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    os::raw::c_int,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+use tokio::{net::UdpSocket, time};
+
+// Reserved stream IDs
+const MTU_PROBE: StreamId = 0x7FFD;
+const MTU_PROBE_ACK: StreamId = 0x7FFE;
+const FRAGMENT: StreamId = 0x7FFF;
+
+// UDP payload sizes for Ethernet, PPPoE, common VPN tunnels and the IPv6 minimum MTU
+const MTU_CANDIDATES: [usize; 4] = [1472, 1452, 1392, 1232];
+
+const PROBE_DURATION: Duration = Duration::from_millis(300);
+const PROBE_INTERVAL: Duration = Duration::from_millis(20);
+
+const FRAGMENT_HEADER_SIZE: usize = 2 + 4 + 2 + 2;
+const REASSEMBLY_TIMEOUT: Duration = Duration::from_millis(100);
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn replace_pmtu_discovery_mode(socket: &UdpSocket, mode: c_int) -> StrResult<c_int> {
+    use std::{io, mem, os::unix::io::AsRawFd};
+
+    let (level, name) = if trace_err!(socket.local_addr())?.is_ipv4() {
+        (libc::IPPROTO_IP, libc::IP_MTU_DISCOVER)
+    } else {
+        (libc::IPPROTO_IPV6, libc::IPV6_MTU_DISCOVER)
+    };
+
+    let mut previous_mode: c_int = 0;
+    let mut len = mem::size_of::<c_int>() as libc::socklen_t;
+    unsafe {
+        if libc::getsockopt(
+            socket.as_raw_fd(),
+            level,
+            name,
+            &mut previous_mode as *mut _ as *mut libc::c_void,
+            &mut len,
+        ) != 0
+            || libc::setsockopt(
+                socket.as_raw_fd(),
+                level,
+                name,
+                &mode as *const _ as *const libc::c_void,
+                len,
+            ) != 0
+        {
+            return fmt_e!("{}", io::Error::last_os_error());
+        }
+    }
+
+    Ok(previous_mode)
+}
+
+// Oversized probes must be dropped instead of being fragmented by the OS. Returns None where the
+// flag cannot be set: without it every probe would be delivered.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn set_dont_fragment(socket: &UdpSocket) -> StrResult<Option<c_int>> {
+    replace_pmtu_discovery_mode(socket, libc::IP_PMTUDISC_PROBE).map(Some)
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn restore_fragmentation(socket: &UdpSocket, previous_mode: c_int) -> StrResult {
+    replace_pmtu_discovery_mode(socket, previous_mode).map(|_| ())
+}
+
+#[cfg(windows)]
+fn replace_dont_fragment(socket: &UdpSocket, value: c_int) -> StrResult<c_int> {
+    use std::{io, mem, os::windows::io::AsRawSocket};
+    use winapi::{
+        shared::{
+            ws2def::{IPPROTO_IP, IPPROTO_IPV6},
+            ws2ipdef::{IPV6_DONTFRAG, IP_DONTFRAGMENT},
+        },
+        um::winsock2,
+    };
+
+    let (level, name) = if trace_err!(socket.local_addr())?.is_ipv4() {
+        (IPPROTO_IP, IP_DONTFRAGMENT)
+    } else {
+        (IPPROTO_IPV6 as c_int, IPV6_DONTFRAG)
+    };
+
+    let mut previous_value: c_int = 0;
+    let mut len = mem::size_of::<c_int>() as c_int;
+    unsafe {
+        if winsock2::getsockopt(
+            socket.as_raw_socket() as _,
+            level,
+            name,
+            &mut previous_value as *mut _ as *mut _,
+            &mut len,
+        ) != 0
+            || winsock2::setsockopt(
+                socket.as_raw_socket() as _,
+                level,
+                name,
+                &value as *const _ as *const _,
+                len,
+            ) != 0
+        {
+            return fmt_e!("{}", io::Error::last_os_error());
+        }
+    }
+
+    Ok(previous_value)
+}
+
+#[cfg(windows)]
+fn set_dont_fragment(socket: &UdpSocket) -> StrResult<Option<c_int>> {
+    replace_dont_fragment(socket, 1).map(Some)
+}
+
+#[cfg(windows)]
+fn restore_fragmentation(socket: &UdpSocket, previous_value: c_int) -> StrResult {
+    replace_dont_fragment(socket, previous_value).map(|_| ())
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android", windows)))]
+fn set_dont_fragment(_: &UdpSocket) -> StrResult<Option<c_int>> {
+    Ok(None)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android", windows)))]
+fn restore_fragmentation(_: &UdpSocket, _: c_int) -> StrResult {
+    Ok(())
+}
+
+// `prefix_size` is the size of the length prefix used by framed sockets. Returns the maximum
+// datagram payload that reaches the peer and the packets of the peer received in the meantime,
+// starting with their stream ID.
+pub async fn probe(
+    socket: &UdpSocket,
+    peer_addr: SocketAddr,
+    prefix_size: usize,
+) -> StrResult<(usize, Vec<BytesMut>)> {
+    let previous_mode = set_dont_fragment(socket)?;
+    let res = exchange_probes(socket, peer_addr, prefix_size, previous_mode.is_some()).await;
+    if let Some(mode) = previous_mode {
+        restore_fragmentation(socket, mode)?;
+    }
+
+    let (mtu, early_packets) = res?;
+    if previous_mode.is_some() {
+        debug!("Stream MTU to {peer_addr}: {mtu}");
+    } else {
+        debug!("Path MTU discovery not supported, stream MTU to {peer_addr}: {mtu}");
+    }
+
+    Ok((mtu, early_packets))
+}
+
+// Stray probes are received as regular packets by the receive loop, so they must be well formed
+fn probe_packet(stream_id: StreamId, size: usize, len: usize, prefix_size: usize) -> Vec<u8> {
+    let mut packet = Vec::with_capacity(len);
+    if prefix_size > 0 {
+        // Length prefix as written by LengthDelimitedCodec
+        packet.put_uint((len - prefix_size) as u64, prefix_size);
+    }
+    packet.put_u16(stream_id);
+    packet.put_u16(size as u16);
+    packet.resize(len, 0);
+
+    packet
+}
+
+async fn exchange_probes(
+    socket: &UdpSocket,
+    peer_addr: SocketAddr,
+    prefix_size: usize,
+    send_probes: bool,
+) -> StrResult<(usize, Vec<BytesMut>)> {
+    let deadline = time::Instant::now() + PROBE_DURATION;
+    let mut interval = time::interval(PROBE_INTERVAL);
+    let mut buffer = vec![0; u16::MAX as usize];
+    let mut max_acked_size = None;
+    let mut early_packets = vec![];
+
+    loop {
+        tokio::select! {
+            _ = time::sleep_until(deadline) => break,
+            _ = interval.tick(), if send_probes => {
+                for &size in &MTU_CANDIDATES {
+                    if max_acked_size.map(|acked| size <= acked).unwrap_or(false) {
+                        break;
+                    }
+
+                    let probe = probe_packet(MTU_PROBE, size, size, prefix_size);
+
+                    // Sizes above the local interface MTU fail immediately
+                    socket.send_to(&probe, peer_addr).await.ok();
+                }
+            }
+            res = socket.recv_from(&mut buffer) => {
+                // Errors like ICMP port unreachable are expected until the peer binds its socket
+                let (len, address) = match res {
+                    Ok(res) => res,
+                    Err(_) => continue,
+                };
+                if address != peer_addr || len < prefix_size + 2 {
+                    continue;
+                }
+
+                let mut packet = &buffer[prefix_size..len];
+                let stream_id = packet.get_u16();
+
+                if stream_id != MTU_PROBE && stream_id != MTU_PROBE_ACK {
+                    // The peer already finished probing
+                    early_packets.push(BytesMut::from(&buffer[prefix_size..len]));
+                } else if packet.len() >= 2 {
+                    let size = packet.get_u16() as usize;
+
+                    if stream_id == MTU_PROBE && size == len {
+                        let ack = probe_packet(MTU_PROBE_ACK, size, prefix_size + 4, prefix_size);
+                        socket.send_to(&ack, peer_addr).await.ok();
+                    } else if stream_id == MTU_PROBE_ACK
+                        && send_probes
+                        && MTU_CANDIDATES.contains(&size)
+                    {
+                        // The size is written by the peer, only the sizes of the sent probes are
+                        // valid
+                        max_acked_size = Some(max_acked_size.unwrap_or(0).max(size));
+                    }
+                }
+            }
+        }
+    }
+
+    let mtu = if send_probes {
+        max_acked_size.unwrap_or(MTU_CANDIDATES[MTU_CANDIDATES.len() - 1])
+    } else {
+        // Bigger packets are fragmented by the OS
+        MTU_CANDIDATES[0]
+    };
+
+    Ok((mtu, early_packets))
+}
+
+#[derive(Clone)]
+pub struct Fragmenter {
+    max_chunk_size: usize,
+    next_packet_index: Arc<AtomicU32>,
+}
+
+impl Fragmenter {
+    // `max_packet_size` excludes any length prefix
+    pub fn new(max_packet_size: usize) -> Self {
+        Self {
+            max_chunk_size: max_packet_size - FRAGMENT_HEADER_SIZE,
+            next_packet_index: Arc::new(AtomicU32::new(0)),
+        }
+    }
+
+    // `packet` must start with the stream ID. Packets that fit are returned unchanged.
+    pub fn split(&self, packet: Bytes) -> Vec<Bytes> {
+        if packet.len() <= self.max_chunk_size + FRAGMENT_HEADER_SIZE {
+            return vec![packet];
+        }
+
+        let packet_index = self.next_packet_index.fetch_add(1, Ordering::Relaxed);
+        let fragment_count = (packet.len() + self.max_chunk_size - 1) / self.max_chunk_size;
+
+        packet
+            .chunks(self.max_chunk_size)
+            .enumerate()
+            .map(|(fragment_index, chunk)| {
+                let mut fragment = BytesMut::with_capacity(FRAGMENT_HEADER_SIZE + chunk.len());
+                fragment.put_u16(FRAGMENT);
+                fragment.put_u32(packet_index);
+                fragment.put_u16(fragment_index as _);
+                fragment.put_u16(fragment_count as _);
+                fragment.put_slice(chunk);
+
+                fragment.freeze()
+            })
+            .collect()
+    }
+}
+
+struct PartialPacket {
+    fragments: Vec<Option<BytesMut>>,
+    received_count: usize,
+    first_arrival: Instant,
+}
+
+#[derive(Default)]
+pub struct Reassembler {
+    partial_packets: HashMap<u32, PartialPacket>,
+}
+
+impl Reassembler {
+    // `packet` is a datagram payload, starting with the stream ID. Returns the stream ID and the
+    // content of complete packets. Malformed packets are dropped, they must not close the stream.
+    pub fn push(&mut self, mut packet: BytesMut, now: Instant) -> Option<(StreamId, BytesMut)> {
+        if packet.len() < 2 {
+            debug!("Dropped packet: too small");
+            return None;
+        }
+        let stream_id = packet.get_u16();
+        if stream_id != FRAGMENT {
+            return Some((stream_id, packet));
+        }
+
+        match self.push_fragment(packet, now) {
+            Ok(Some(mut packet)) if packet.len() >= 2 => Some((packet.get_u16(), packet)),
+            Ok(Some(_)) => {
+                debug!("Dropped packet: too small");
+                None
+            }
+            Ok(None) => None,
+            Err(e) => {
+                debug!("Dropped fragment: {e}");
+                None
+            }
+        }
+    }
+
+    // `fragment` must not contain the stream ID. Returns the reassembled packet, starting with its
+    // stream ID, once all fragments are received.
+    fn push_fragment(
+        &mut self,
+        mut fragment: BytesMut,
+        now: Instant,
+    ) -> StrResult<Option<BytesMut>> {
+        if fragment.len() < FRAGMENT_HEADER_SIZE - 2 {
+            return fmt_e!("Fragment too small");
+        }
+        let packet_index = fragment.get_u32();
+        let fragment_index = fragment.get_u16() as usize;
+        let fragment_count = fragment.get_u16() as usize;
+
+        self.partial_packets
+            .retain(|_, packet| now - packet.first_arrival < REASSEMBLY_TIMEOUT);
+
+        let packet = self
+            .partial_packets
+            .entry(packet_index)
+            .or_insert_with(|| PartialPacket {
+                fragments: vec![None; fragment_count],
+                received_count: 0,
+                first_arrival: now,
+            });
+
+        if fragment_index >= packet.fragments.len() || packet.fragments.len() != fragment_count {
+            return fmt_e!("Invalid fragment index");
+        }
+
+        if packet.fragments[fragment_index].is_none() {
+            packet.fragments[fragment_index] = Some(fragment);
+            packet.received_count += 1;
+        }
+
+        if packet.received_count < fragment_count {
+            return Ok(None);
+        }
+
+        let packet = trace_none!(self.partial_packets.remove(&packet_index))?;
+        let mut buffer = BytesMut::new();
+        for fragment in packet.fragments {
+            buffer.unsplit(trace_none!(fragment)?);
+        }
+
+        Ok(Some(buffer))
+    }
+}
+
+// Used by the receive loops, `packet` starts with the stream ID
+pub async fn dispatch_packet(
+    reassembler: &mut Reassembler,
+    packet_queues: &PacketQueues,
+    packet: BytesMut,
+) {
+    if let Some((stream_id, packet)) = reassembler.push(packet, Instant::now()) {
+        let queue = packet_queues.lock().await.get(&stream_id).cloned();
+        if let Some(queue) = queue {
+            queue.push(packet).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fragment_and_reassemble() {
+        let packet = (0..5000).map(|i| i as u8).collect::<Bytes>();
+
+        let fragments = Fragmenter::new(1400).split(packet.clone());
+        assert_eq!(fragments.len(), 4);
+        assert!(fragments.iter().all(|fragment| fragment.len() <= 1400));
+
+        let mut reassembler = Reassembler::default();
+        let now = Instant::now();
+        let mut reassembled = None;
+        // Fragments can arrive out of order
+        for fragment in fragments.into_iter().rev() {
+            assert!(reassembled.is_none());
+            reassembled = reassembler.push(BytesMut::from(&fragment[..]), now);
+        }
+
+        let (stream_id, reassembled) = reassembled.unwrap();
+        assert_eq!(stream_id, u16::from_be_bytes([packet[0], packet[1]]));
+        assert_eq!(&reassembled[..], &packet[2..]);
+    }
+
+    #[test]
+    fn drop_malformed_packets() {
+        let mut reassembler = Reassembler::default();
+        let now = Instant::now();
+
+        assert!(reassembler.push(BytesMut::from(&[0][..]), now).is_none());
+
+        let mut fragment = BytesMut::new();
+        fragment.put_u16(FRAGMENT);
+        fragment.put_u32(0);
+        assert!(reassembler.push(fragment, now).is_none());
+
+        let mut fragment = BytesMut::new();
+        fragment.put_u16(FRAGMENT);
+        fragment.put_u32(0);
+        fragment.put_u16(2);
+        fragment.put_u16(2);
+        assert!(reassembler.push(fragment, now).is_none());
+
+        // The reassembler is still usable
+        let packet = BytesMut::from(&[0, 3, 42][..]);
+        assert_eq!(
+            reassembler.push(packet, now),
+            Some((3, BytesMut::from(&[42][..])))
+        );
+    }
+
+    #[tokio::test]
+    async fn keep_packets_received_while_probing() {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let peer_socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let peer_addr = peer_socket.local_addr().unwrap();
+
+        peer_socket
+            .send_to(&[0, 3, 42], socket.local_addr().unwrap())
+            .await
+            .unwrap();
+
+        let (_, early_packets) = probe(&socket, peer_addr, 0).await.unwrap();
+        assert_eq!(early_packets, vec![BytesMut::from(&[0, 3, 42][..])]);
+    }
+
+    #[tokio::test]
+    async fn ignore_invalid_acks() {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let peer_socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let peer_addr = peer_socket.local_addr().unwrap();
+
+        for size in [0, 1000, 60000, MTU_CANDIDATES[2]] {
+            peer_socket
+                .send_to(
+                    &probe_packet(MTU_PROBE_ACK, size, 4, 0),
+                    socket.local_addr().unwrap(),
+                )
+                .await
+                .unwrap();
+        }
+
+        let (mtu, _) = exchange_probes(&socket, peer_addr, 0, true).await.unwrap();
+        assert_eq!(mtu, MTU_CANDIDATES[2]);
+    }
+
+    #[test]
+    fn discard_after_timeout() {
+        let fragmenter = Fragmenter::new(1400);
+        let first_fragments = fragmenter.split(vec![1; 2000].into());
+        let second_fragments = fragmenter.split(vec![2; 2000].into());
+
+        let mut reassembler = Reassembler::default();
+        let now = Instant::now();
+        reassembler
+            .push_fragment(BytesMut::from(&first_fragments[0][2..]), now)
+            .unwrap();
+
+        let later = now + REASSEMBLY_TIMEOUT;
+        reassembler
+            .push_fragment(BytesMut::from(&second_fragments[0][2..]), later)
+            .unwrap();
+        assert!(reassembler
+            .push_fragment(BytesMut::from(&first_fragments[1][2..]), later)
+            .unwrap()
+            .is_none());
+    }
+}
@@ -0,0 +1,270 @@
+// Bounded packet queues and the priority scheduler used on the send side.
+//
+// A StreamSender does not write to the socket directly. Its packets are pushed to a queue owned
+// by the scheduler, and StreamSocket::send_loop() sends them in priority order. This way tracking
+// and haptics are not delayed by a burst of video waiting for the socket (or the rate limiter).
+
+use super::{fec::FecConfig, StreamId};
+use bytes::Bytes;
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+use tokio::sync::Notify;
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum StreamPriority {
+    Low,
+    Normal,
+    High,
+}
+
+// What to do when a packet is pushed to a full queue
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DropPolicy {
+    DropOldest,
+    DropNewest,
+    // Wait for the consumer. On the receive side this blocks the receive loop for every stream.
+    Block,
+}
+
+#[derive(Clone, Copy)]
+pub struct QueueBound {
+    pub capacity: usize,
+    pub drop_policy: DropPolicy,
+}
+
+#[derive(Clone, Copy)]
+pub struct StreamOptions {
+    pub priority: StreamPriority,
+    // Applies to both the send queue and the receive queue. None means unbounded.
+    pub queue_bound: Option<QueueBound>,
+    // The peer must use the same FEC configuration
+    pub fec: Option<FecConfig>,
+}
+
+impl Default for StreamOptions {
+    fn default() -> Self {
+        Self {
+            priority: StreamPriority::Normal,
+            queue_bound: None,
+            fec: None,
+        }
+    }
+}
+
+struct QueueState<T> {
+    packets: VecDeque<T>,
+    closed: bool,
+}
+
+pub struct PacketQueue<T> {
+    state: Mutex<QueueState<T>>,
+    bound: Option<QueueBound>,
+    // Can be shared between queues, so a single consumer can wait on all of them
+    pushed: Arc<Notify>,
+    popped: Notify,
+}
+
+impl<T> PacketQueue<T> {
+    pub fn new(bound: Option<QueueBound>, pushed: Arc<Notify>) -> Self {
+        Self {
+            state: Mutex::new(QueueState {
+                packets: VecDeque::new(),
+                closed: false,
+            }),
+            bound,
+            pushed,
+            popped: Notify::new(),
+        }
+    }
+
+    // Returns false if a packet was dropped
+    pub async fn push(&self, packet: T) -> bool {
+        loop {
+            {
+                let mut state = self.state.lock().unwrap();
+
+                let drop_policy = self
+                    .bound
+                    .filter(|bound| state.packets.len() >= bound.capacity)
+                    .map(|bound| bound.drop_policy);
+
+                // A closed queue is not consumed anymore, there is no point in waiting
+                if drop_policy != Some(DropPolicy::Block) || state.closed {
+                    match drop_policy {
+                        Some(DropPolicy::DropNewest) => return false,
+                        Some(DropPolicy::DropOldest) => {
+                            state.packets.pop_front();
+                        }
+                        _ => (),
+                    }
+                    state.packets.push_back(packet);
+                    self.pushed.notify_one();
+
+                    return drop_policy != Some(DropPolicy::DropOldest);
+                }
+            }
+
+            self.popped.notified().await;
+        }
+    }
+
+    pub fn try_pop(&self) -> Option<T> {
+        let packet = self.state.lock().unwrap().packets.pop_front();
+        if packet.is_some() {
+            self.popped.notify_one();
+        }
+
+        packet
+    }
+
+    // Returns None once the queue is closed and empty
+    pub async fn pop(&self) -> Option<T> {
+        loop {
+            if let Some(packet) = self.try_pop() {
+                return Some(packet);
+            }
+            if self.state.lock().unwrap().closed {
+                return None;
+            }
+
+            self.pushed.notified().await;
+        }
+    }
+
+    pub fn close(&self) {
+        self.state.lock().unwrap().closed = true;
+        self.pushed.notify_one();
+        self.popped.notify_one();
+    }
+}
+
+struct ScheduledQueue {
+    priority: StreamPriority,
+    stream_id: StreamId,
+    // Each element contains all the datagrams of one packet
+    queue: Arc<PacketQueue<Vec<Bytes>>>,
+}
+
+pub struct SendScheduler {
+    // Sorted by descending priority
+    queues: Mutex<Vec<ScheduledQueue>>,
+    packet_pushed: Arc<Notify>,
+}
+
+impl SendScheduler {
+    pub fn new() -> Self {
+        Self {
+            queues: Mutex::new(vec![]),
+            packet_pushed: Arc::new(Notify::new()),
+        }
+    }
+
+    pub fn register(
+        &self,
+        stream_id: StreamId,
+        options: StreamOptions,
+    ) -> Arc<PacketQueue<Vec<Bytes>>> {
+        let queue = Arc::new(PacketQueue::new(
+            options.queue_bound,
+            Arc::clone(&self.packet_pushed),
+        ));
+
+        let mut queues = self.queues.lock().unwrap();
+        // Insert after the queues with the same priority, so they keep the registration order
+        let index = queues
+            .iter()
+            .position(|entry| entry.priority < options.priority)
+            .unwrap_or(queues.len());
+        queues.insert(
+            index,
+            ScheduledQueue {
+                priority: options.priority,
+                stream_id,
+                queue: Arc::clone(&queue),
+            },
+        );
+
+        queue
+    }
+
+    fn try_pop(&self) -> Option<(StreamId, Vec<Bytes>)> {
+        let mut queues = self.queues.lock().unwrap();
+
+        // Forget the queues of dropped senders once they are empty
+        queues.retain(|entry| {
+            Arc::strong_count(&entry.queue) > 1
+                || !entry.queue.state.lock().unwrap().packets.is_empty()
+        });
+
+        queues.iter().find_map(|entry| {
+            entry
+                .queue
+                .try_pop()
+                .map(|packet| (entry.stream_id, packet))
+        })
+    }
+
+    pub async fn pop(&self) -> (StreamId, Vec<Bytes>) {
+        loop {
+            if let Some(packet) = self.try_pop() {
+                return packet;
+            }
+
+            self.packet_pushed.notified().await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(priority: StreamPriority, drop_policy: DropPolicy) -> StreamOptions {
+        StreamOptions {
+            priority,
+            queue_bound: Some(QueueBound {
+                capacity: 2,
+                drop_policy,
+            }),
+            fec: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn drop_policies() {
+        let queue = PacketQueue::new(
+            options(StreamPriority::Normal, DropPolicy::DropOldest).queue_bound,
+            Arc::new(Notify::new()),
+        );
+        assert!(queue.push(1).await);
+        assert!(queue.push(2).await);
+        assert!(!queue.push(3).await);
+        assert_eq!(queue.try_pop(), Some(2));
+
+        let queue = PacketQueue::new(
+            options(StreamPriority::Normal, DropPolicy::DropNewest).queue_bound,
+            Arc::new(Notify::new()),
+        );
+        assert!(queue.push(1).await);
+        assert!(queue.push(2).await);
+        assert!(!queue.push(3).await);
+        assert_eq!(queue.try_pop(), Some(1));
+        assert_eq!(queue.try_pop(), Some(2));
+        assert_eq!(queue.try_pop(), None);
+    }
+
+    #[tokio::test]
+    async fn high_priority_first() {
+        let scheduler = SendScheduler::new();
+        let video = scheduler.register(3, options(StreamPriority::Low, DropPolicy::Block));
+        let input = scheduler.register(0, options(StreamPriority::High, DropPolicy::Block));
+
+        video.push(vec![Bytes::from_static(b"video")]).await;
+        input.push(vec![Bytes::from_static(b"input")]).await;
+
+        assert_eq!(scheduler.pop().await.0, 0);
+        assert_eq!(scheduler.pop().await.0, 3);
+    }
+}
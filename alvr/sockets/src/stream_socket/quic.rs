@@ -0,0 +1,318 @@
+use super::{PacketQueues, StreamId};
+use crate::{
+    network,
+    tls::{self, PinnedServerCertificate, SERVER_NAME},
+    Ldc, PeerIp, PrivateIdentity, HAPTICS, INPUT,
+};
+use alvr_common::prelude::*;
+use bytes::{Buf, Bytes, BytesMut};
+use futures::{stream::SelectAll, SinkExt, StreamExt};
+use quinn::{
+    ClientConfig, Connection, Endpoint, EndpointConfig, RecvStream, SendStream, ServerConfig,
+    TokioRuntime, TransportConfig,
+};
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    net::IpAddr,
+    sync::Arc,
+};
+use tokio::sync::Mutex;
+use tokio_util::codec::{FramedRead, FramedWrite};
+
+// Ethernet MTU minus IPv4 and UDP headers. Legacy video packets are sized for plain UDP, so with
+// the QUIC overhead the largest ones still fall back to a reliable stream.
+const MAX_UDP_PAYLOAD_SIZE: u16 = 1472;
+const ALPN_PROTOCOL: &[u8] = b"alvr";
+
+// Streams that cannot tolerate loss are sent over QUIC streams, the others as datagrams
+fn is_reliable(stream_id: StreamId) -> bool {
+    matches!(stream_id, INPUT | HAPTICS)
+}
+
+#[derive(Clone)]
+pub struct QuicStreamSendSocket {
+    connection: Connection,
+    reliable_streams: Arc<Mutex<HashMap<StreamId, FramedWrite<SendStream, Ldc>>>>,
+}
+
+impl QuicStreamSendSocket {
+    pub async fn send(&self, stream_id: StreamId, data: Bytes) -> StrResult {
+        let fits_datagram = self
+            .connection
+            .max_datagram_size()
+            .map(|max_size| data.len() <= max_size)
+            .unwrap_or(false);
+
+        if !is_reliable(stream_id) && fits_datagram {
+            return trace_err!(self.connection.send_datagram(data));
+        }
+
+        let mut reliable_streams = self.reliable_streams.lock().await;
+        let stream = match reliable_streams.entry(stream_id) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let stream = trace_err!(self.connection.open_uni().await)?;
+                entry.insert(FramedWrite::new(stream, Ldc::new()))
+            }
+        };
+
+        trace_err!(stream.send(data).await)
+    }
+}
+
+pub struct QuicStreamReceiveSocket {
+    connection: Connection,
+}
+
+fn transport_config() -> Arc<TransportConfig> {
+    let mut config = TransportConfig::default();
+    config.initial_max_udp_payload_size(MAX_UDP_PAYLOAD_SIZE);
+
+    Arc::new(config)
+}
+
+fn socket(connection: Connection) -> (QuicStreamSendSocket, QuicStreamReceiveSocket) {
+    (
+        QuicStreamSendSocket {
+            connection: connection.clone(),
+            reliable_streams: Arc::new(Mutex::new(HashMap::new())),
+        },
+        QuicStreamReceiveSocket { connection },
+    )
+}
+
+// The client authenticates with the same identity used for the control socket
+pub async fn listen_for_server(
+    ip: IpAddr,
+    port: u16,
+    identity: &PrivateIdentity,
+) -> StrResult<Endpoint> {
+    let mut crypto = trace_err!(rustls::ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_single_cert(
+            vec![tls::certificate_from_pem(&identity.certificate_pem)?],
+            tls::private_key_from_pem(&identity.key_pem)?,
+        ))?;
+    crypto.alpn_protocols = vec![ALPN_PROTOCOL.to_vec()];
+
+    let mut config = ServerConfig::with_crypto(Arc::new(crypto));
+    config.transport_config(transport_config());
+
+    trace_err!(Endpoint::new(
+        EndpointConfig::default(),
+        Some(config),
+        network::bind_std_udp(ip, port)?,
+        TokioRuntime,
+    ))
+}
+
+pub async fn accept_from_server(
+    endpoint: Endpoint,
+    server_ip: PeerIp,
+) -> StrResult<(QuicStreamSendSocket, QuicStreamReceiveSocket)> {
+    let connecting = trace_none!(endpoint.accept().await)?;
+
+    let server_address = connecting.remote_address();
+    if PeerIp::from_address(server_address) != server_ip {
+        return fmt_e!("Connected to wrong server: {server_address} != {server_ip}");
+    }
+
+    Ok(socket(trace_err!(connecting.await)?))
+}
+
+pub async fn connect_to_client(
+    bind_ip: IpAddr,
+    client_ip: PeerIp,
+    port: u16,
+    client_certificate_pem: &str,
+) -> StrResult<(QuicStreamSendSocket, QuicStreamReceiveSocket)> {
+    // Only the certificate the client presented on the control socket is accepted
+    let client_certificate = tls::certificate_from_pem(client_certificate_pem)?;
+    let mut crypto = rustls::ClientConfig::builder()
+        .with_safe_defaults()
+        .with_custom_certificate_verifier(Arc::new(PinnedServerCertificate(Some(
+            client_certificate,
+        ))))
+        .with_no_client_auth();
+    crypto.alpn_protocols = vec![ALPN_PROTOCOL.to_vec()];
+
+    let mut config = ClientConfig::new(Arc::new(crypto));
+    config.transport_config(transport_config());
+
+    // Use an ephemeral port, like TCP does, so the server can run on the same host as the client
+    let endpoint = trace_err!(Endpoint::new(
+        EndpointConfig::default(),
+        None,
+        network::bind_std_udp(bind_ip, 0)?,
+        TokioRuntime,
+    ))?;
+    let client_addr = network::peer_address(bind_ip, client_ip, port);
+    let connecting = trace_err!(endpoint.connect_with(config, client_addr, SERVER_NAME))?;
+
+    Ok(socket(trace_err!(connecting.await)?))
+}
+
+pub async fn receive_loop(
+    socket: QuicStreamReceiveSocket,
+    packet_queues: PacketQueues,
+) -> StrResult {
+    let mut reliable_streams = SelectAll::<FramedRead<RecvStream, Ldc>>::new();
+
+    loop {
+        let mut packet = tokio::select! {
+            res = socket.connection.read_datagram() => BytesMut::from(&trace_err!(res)?[..]),
+            res = socket.connection.accept_uni() => {
+                reliable_streams.push(FramedRead::new(trace_err!(res)?, Ldc::new()));
+                continue;
+            }
+            Some(res) = reliable_streams.next() => trace_err!(res)?,
+        };
+
+        // A malformed packet from the peer must not close the stream
+        if packet.len() < 2 {
+            debug!("Dropped packet: too small");
+            continue;
+        }
+        let stream_id = packet.get_u16();
+        let queue = packet_queues.lock().await.get(&stream_id).cloned();
+        if let Some(queue) = queue {
+            queue.push(packet).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        stream_socket::{StreamReceiveSocket, StreamSendSocket, StreamSocket},
+        VIDEO,
+    };
+    use std::{net::Ipv4Addr, time::Duration};
+
+    const LOCALHOST: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+    async fn connect(
+        client_identity: &PrivateIdentity,
+        pinned_certificate_pem: &str,
+    ) -> StrResult<(StreamSocket, StreamSocket)> {
+        let endpoint = listen_for_server(LOCALHOST, 0, client_identity).await?;
+        let port = trace_err!(endpoint.local_addr())?.port();
+
+        let accept = tokio::spawn(accept_from_server(endpoint, LOCALHOST.into()));
+        let (server_send, server_receive) =
+            connect_to_client(LOCALHOST, LOCALHOST.into(), port, pinned_certificate_pem).await?;
+        let (client_send, client_receive) = trace_err!(accept.await)??;
+
+        Ok((
+            StreamSocket::new(
+                StreamSendSocket::Quic(server_send),
+                StreamReceiveSocket::Quic(server_receive),
+            ),
+            StreamSocket::new(
+                StreamSendSocket::Quic(client_send),
+                StreamReceiveSocket::Quic(client_receive),
+            ),
+        ))
+    }
+
+    #[tokio::test]
+    async fn round_trip() {
+        let client_identity = crate::create_identity(None).unwrap();
+        let (server_socket, client_socket) =
+            connect(&client_identity, &client_identity.certificate_pem)
+                .await
+                .unwrap();
+        let server_socket = Arc::new(server_socket);
+        let client_socket = Arc::new(client_socket);
+
+        // Datagram and reliable streams
+        let mut video_sender = server_socket.request_stream::<u32>(VIDEO).await.unwrap();
+        let mut input_sender = client_socket.request_stream::<u32>(INPUT).await.unwrap();
+        let mut video_receiver = client_socket
+            .subscribe_to_stream::<u32>(VIDEO)
+            .await
+            .unwrap();
+        let mut input_receiver = server_socket
+            .subscribe_to_stream::<u32>(INPUT)
+            .await
+            .unwrap();
+
+        for socket in [&server_socket, &client_socket] {
+            let socket = Arc::clone(socket);
+            tokio::spawn(async move {
+                tokio::select! {
+                    _ = socket.send_loop() => (),
+                    _ = socket.receive_loop() => (),
+                }
+            });
+        }
+
+        video_sender.send(&1).await.unwrap();
+        input_sender.send(&2).await.unwrap();
+
+        assert_eq!(video_receiver.recv().await.unwrap().header, 1);
+        assert_eq!(input_receiver.recv().await.unwrap().header, 2);
+    }
+
+    #[tokio::test]
+    async fn drop_short_datagram() {
+        let client_identity = crate::create_identity(None).unwrap();
+        let endpoint = listen_for_server(LOCALHOST, 0, &client_identity)
+            .await
+            .unwrap();
+        let port = endpoint.local_addr().unwrap().port();
+
+        let accept = tokio::spawn(accept_from_server(endpoint, LOCALHOST.into()));
+        let (server_send, server_receive) = connect_to_client(
+            LOCALHOST,
+            LOCALHOST.into(),
+            port,
+            &client_identity.certificate_pem,
+        )
+        .await
+        .unwrap();
+        let (client_send, client_receive) = accept.await.unwrap().unwrap();
+
+        server_send
+            .connection
+            .send_datagram(Bytes::from_static(&[0]))
+            .unwrap();
+
+        let server_socket = Arc::new(StreamSocket::new(
+            StreamSendSocket::Quic(server_send),
+            StreamReceiveSocket::Quic(server_receive),
+        ));
+        let client_socket = Arc::new(StreamSocket::new(
+            StreamSendSocket::Quic(client_send),
+            StreamReceiveSocket::Quic(client_receive),
+        ));
+        let mut video_sender = server_socket.request_stream::<u32>(VIDEO).await.unwrap();
+        let mut video_receiver = client_socket
+            .subscribe_to_stream::<u32>(VIDEO)
+            .await
+            .unwrap();
+
+        tokio::spawn(async move { server_socket.send_loop().await });
+        tokio::spawn(async move { client_socket.receive_loop().await });
+
+        video_sender.send(&1).await.unwrap();
+
+        let packet = tokio::time::timeout(Duration::from_secs(5), video_receiver.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(packet.header, 1);
+    }
+
+    #[tokio::test]
+    async fn wrong_client_certificate() {
+        let client_identity = crate::create_identity(None).unwrap();
+        let other_identity = crate::create_identity(None).unwrap();
+
+        assert!(connect(&client_identity, &other_identity.certificate_pem)
+            .await
+            .is_err());
+    }
+}
@@ -0,0 +1,302 @@
+// Per-stream counters and round trip time estimation.
+//
+// The RTT is measured with ping packets sent by StreamSocket::send_loop(). The peer echoes them
+// back unchanged (apart from the stream ID), so only the local clock is involved.
+//
+// Ping layout: ping or pong stream ID (u16), send time in microseconds since the socket creation
+// (u64).
+
+use super::StreamId;
+use alvr_common::prelude::*;
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use serde::Serialize;
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+// Reserved stream IDs
+pub const PING: StreamId = 0x7FFB;
+pub const PONG: StreamId = 0x7FFC;
+
+pub const PING_INTERVAL: Duration = Duration::from_secs(1);
+
+const THROUGHPUT_WINDOW: Duration = Duration::from_secs(1);
+
+// Skipped packet indices remembered to recognize reordered packets
+const MAX_MISSING_INDICES: usize = 1024;
+
+#[derive(Serialize, Clone, Default, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct StreamStatistics {
+    pub packets_sent: u64,
+    // Includes the stream framing and FEC redundancy
+    pub bytes_sent: u64,
+    // Packets discarded by the send queue before reaching the socket
+    pub packets_dropped: u64,
+    pub packets_received: u64,
+    // Packets after FEC decoding and reassembly, without the stream ID
+    pub bytes_received: u64,
+    // Packets that never arrived or arrived too late, detected from the packet indices. Reordered
+    // packets are not counted.
+    pub packets_lost: u64,
+    pub packets_reordered: u64,
+    pub send_throughput_bps: f64,
+    pub receive_throughput_bps: f64,
+}
+
+#[derive(Serialize, Clone, Default, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct NetworkStatistics {
+    pub streams: HashMap<StreamId, StreamStatistics>,
+    // None until the first pong is received
+    pub rtt: Option<Duration>,
+    pub smoothed_rtt: Option<Duration>,
+    pub rtt_jitter: Duration,
+}
+
+#[derive(Default)]
+struct ThroughputWindow {
+    samples: VecDeque<(Instant, usize)>,
+    total_bytes: usize,
+}
+
+impl ThroughputWindow {
+    fn push(&mut self, bytes: usize, now: Instant) {
+        self.samples.push_back((now, bytes));
+        self.total_bytes += bytes;
+        self.prune(now);
+    }
+
+    fn prune(&mut self, now: Instant) {
+        while let Some(&(time, bytes)) = self.samples.front() {
+            if now - time <= THROUGHPUT_WINDOW {
+                break;
+            }
+            self.samples.pop_front();
+            self.total_bytes -= bytes;
+        }
+    }
+
+    fn bits_per_second(&mut self, now: Instant) -> f64 {
+        self.prune(now);
+        self.total_bytes as f64 * 8. / THROUGHPUT_WINDOW.as_secs_f64()
+    }
+}
+
+#[derive(Default)]
+struct StreamCounters {
+    statistics: StreamStatistics,
+    send_window: ThroughputWindow,
+    receive_window: ThroughputWindow,
+    missing_indices: VecDeque<u32>,
+}
+
+#[derive(Default)]
+struct StatisticsState {
+    streams: HashMap<StreamId, StreamCounters>,
+    rtt: Option<Duration>,
+    smoothed_rtt: Option<Duration>,
+    rtt_jitter: Duration,
+}
+
+pub struct StatisticsCollector {
+    start_instant: Instant,
+    state: Mutex<StatisticsState>,
+}
+
+impl StatisticsCollector {
+    pub fn new() -> Self {
+        Self {
+            start_instant: Instant::now(),
+            state: Mutex::new(StatisticsState::default()),
+        }
+    }
+
+    pub fn report_sent(&self, stream_id: StreamId, bytes: usize) {
+        let mut state = self.state.lock().unwrap();
+        let counters = state.streams.entry(stream_id).or_default();
+        counters.statistics.packets_sent += 1;
+        counters.statistics.bytes_sent += bytes as u64;
+        counters.send_window.push(bytes, Instant::now());
+    }
+
+    pub fn report_dropped(&self, stream_id: StreamId) {
+        let mut state = self.state.lock().unwrap();
+        state
+            .streams
+            .entry(stream_id)
+            .or_default()
+            .statistics
+            .packets_dropped += 1;
+    }
+
+    // `expected_index` is the index that follows the last received packet
+    pub fn report_received(
+        &self,
+        stream_id: StreamId,
+        bytes: usize,
+        packet_index: u32,
+        expected_index: u32,
+    ) {
+        let mut state = self.state.lock().unwrap();
+        let counters = state.streams.entry(stream_id).or_default();
+        counters.statistics.packets_received += 1;
+        counters.statistics.bytes_received += bytes as u64;
+        counters.receive_window.push(bytes, Instant::now());
+
+        // The wrapping difference distinguishes packets that skipped ahead from late ones
+        let distance = packet_index.wrapping_sub(expected_index) as i32;
+        if distance > 0 {
+            counters.statistics.packets_lost += distance as u64;
+
+            let missing_count = usize::min(distance as usize, MAX_MISSING_INDICES);
+            counters.missing_indices.extend(
+                (0..missing_count as u32).map(|offset| packet_index.wrapping_sub(offset + 1)),
+            );
+            let excess = counters
+                .missing_indices
+                .len()
+                .saturating_sub(MAX_MISSING_INDICES);
+            counters.missing_indices.drain(..excess);
+        } else if distance < 0 {
+            // Duplicates and packets older than the remembered ones stay counted as lost
+            if let Some(position) = counters
+                .missing_indices
+                .iter()
+                .position(|&index| index == packet_index)
+            {
+                counters.missing_indices.remove(position);
+                counters.statistics.packets_lost -= 1;
+                counters.statistics.packets_reordered += 1;
+            }
+        }
+    }
+
+    pub fn ping_packet(&self) -> Bytes {
+        let mut packet = BytesMut::with_capacity(2 + 8);
+        packet.put_u16(PING);
+        packet.put_u64(self.start_instant.elapsed().as_micros() as u64);
+
+        packet.freeze()
+    }
+
+    // `ping` must not contain the stream ID
+    pub fn pong_packet(mut ping: BytesMut) -> Bytes {
+        let mut packet = BytesMut::with_capacity(2 + ping.len());
+        packet.put_u16(PONG);
+        packet.put(&mut ping);
+
+        packet.freeze()
+    }
+
+    // `pong` must not contain the stream ID
+    pub fn report_pong(&self, mut pong: BytesMut) -> StrResult {
+        if pong.len() < 8 {
+            return fmt_e!("Pong too small");
+        }
+        let send_time = Duration::from_micros(pong.get_u64());
+        let rtt = self.start_instant.elapsed().saturating_sub(send_time);
+
+        let mut state = self.state.lock().unwrap();
+
+        // Same estimators as RFC 6298 (smoothed RTT) and RFC 3550 (jitter)
+        if let Some(previous_rtt) = state.rtt {
+            let difference = if rtt > previous_rtt {
+                rtt - previous_rtt
+            } else {
+                previous_rtt - rtt
+            };
+            state.rtt_jitter = if difference > state.rtt_jitter {
+                state.rtt_jitter + (difference - state.rtt_jitter) / 16
+            } else {
+                state.rtt_jitter - (state.rtt_jitter - difference) / 16
+            };
+        }
+        state.smoothed_rtt = Some(
+            state
+                .smoothed_rtt
+                .map(|smoothed_rtt| smoothed_rtt * 7 / 8 + rtt / 8)
+                .unwrap_or(rtt),
+        );
+        state.rtt = Some(rtt);
+
+        Ok(())
+    }
+
+    pub fn snapshot(&self) -> NetworkStatistics {
+        let now = Instant::now();
+        let mut state = self.state.lock().unwrap();
+
+        NetworkStatistics {
+            streams: state
+                .streams
+                .iter_mut()
+                .map(|(stream_id, counters)| {
+                    let mut statistics = counters.statistics.clone();
+                    statistics.send_throughput_bps = counters.send_window.bits_per_second(now);
+                    statistics.receive_throughput_bps =
+                        counters.receive_window.bits_per_second(now);
+
+                    (*stream_id, statistics)
+                })
+                .collect(),
+            rtt: state.rtt,
+            smoothed_rtt: state.smoothed_rtt,
+            rtt_jitter: state.rtt_jitter,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn loss_and_reorder() {
+        let collector = StatisticsCollector::new();
+        collector.report_received(0, 100, 0, 0);
+        collector.report_received(0, 100, 3, 1);
+        collector.report_received(0, 100, 2, 4);
+        // Duplicate
+        collector.report_received(0, 100, 2, 4);
+
+        let statistics = collector.snapshot().streams[&0].clone();
+        assert_eq!(statistics.packets_received, 4);
+        assert_eq!(statistics.bytes_received, 400);
+        assert_eq!(statistics.packets_lost, 1);
+        assert_eq!(statistics.packets_reordered, 1);
+        assert_eq!(statistics.receive_throughput_bps, 3200.);
+    }
+
+    #[test]
+    fn reorder_is_not_loss() {
+        let collector = StatisticsCollector::new();
+        let mut expected_index = 1;
+        for packet_index in [1, 3, 2, 4] {
+            collector.report_received(0, 100, packet_index, expected_index);
+            // Same as StreamReceiver::recv()
+            if packet_index.wrapping_sub(expected_index) as i32 >= 0 {
+                expected_index = packet_index + 1;
+            }
+        }
+
+        let statistics = collector.snapshot().streams[&0].clone();
+        assert_eq!(statistics.packets_lost, 0);
+        assert_eq!(statistics.packets_reordered, 1);
+    }
+
+    #[test]
+    fn ping_round_trip() {
+        let collector = StatisticsCollector::new();
+
+        let mut ping = BytesMut::from(&collector.ping_packet()[..]);
+        assert_eq!(ping.get_u16(), PING);
+        let mut pong = BytesMut::from(&StatisticsCollector::pong_packet(ping)[..]);
+        assert_eq!(pong.get_u16(), PONG);
+        collector.report_pong(pong).unwrap();
+
+        assert!(collector.snapshot().rtt.is_some());
+    }
+}
@@ -1,32 +1,32 @@
-use super::StreamId;
-use crate::{Ldc, LOCAL_IP};
+use super::PacketQueues;
+use crate::{network, Ldc, PeerIp};
 use alvr_common::prelude::*;
-use bytes::{Buf, Bytes, BytesMut};
+use bytes::{Buf, Bytes};
 use futures::{
     stream::{SplitSink, SplitStream},
     StreamExt,
 };
-use std::{collections::HashMap, net::IpAddr, sync::Arc};
+use std::{net::IpAddr, sync::Arc};
 use tokio::{
     net::{TcpListener, TcpStream},
-    sync::{mpsc, Mutex},
+    sync::Mutex,
 };
 use tokio_util::codec::Framed;
 
 pub type TcpStreamSendSocket = Arc<Mutex<SplitSink<Framed<TcpStream, Ldc>, Bytes>>>;
 pub type TcpStreamReceiveSocket = SplitStream<Framed<TcpStream, Ldc>>;
 
-pub async fn listen_for_server(port: u16) -> StrResult<TcpListener> {
-    trace_err!(TcpListener::bind((LOCAL_IP, port)).await)
+pub async fn listen_for_server(ip: IpAddr, port: u16) -> StrResult<TcpListener> {
+    network::bind_tcp(ip, port)
 }
 
 pub async fn accept_from_server(
     listener: TcpListener,
-    server_ip: IpAddr,
+    server_ip: PeerIp,
 ) -> StrResult<(TcpStreamSendSocket, TcpStreamReceiveSocket)> {
     let (socket, server_address) = trace_err!(listener.accept().await)?;
 
-    if server_address.ip() != server_ip {
+    if PeerIp::from_address(server_address) != server_ip {
         return fmt_e!("Connected to wrong client: {server_address} != {server_ip}");
     }
 
@@ -38,10 +38,11 @@ pub async fn accept_from_server(
 }
 
 pub async fn connect_to_client(
-    client_ip: IpAddr,
+    bind_ip: IpAddr,
+    client_ip: PeerIp,
     port: u16,
 ) -> StrResult<(TcpStreamSendSocket, TcpStreamReceiveSocket)> {
-    let socket = trace_err!(TcpStream::connect((client_ip, port)).await)?;
+    let socket = network::connect_tcp(bind_ip, client_ip, port).await?;
     trace_err!(socket.set_nodelay(true))?;
     let socket = Framed::new(socket, Ldc::new());
     let (send_socket, receive_socket) = socket.split();
@@ -51,14 +52,15 @@ pub async fn connect_to_client(
 
 pub async fn receive_loop(
     mut socket: TcpStreamReceiveSocket,
-    packet_enqueuers: Arc<Mutex<HashMap<StreamId, mpsc::UnboundedSender<BytesMut>>>>,
+    packet_queues: PacketQueues,
 ) -> StrResult {
     while let Some(maybe_packet) = socket.next().await {
         let mut packet = trace_err!(maybe_packet)?;
 
         let stream_id = packet.get_u16();
-        if let Some(enqueuer) = packet_enqueuers.lock().await.get_mut(&stream_id) {
-            trace_err!(enqueuer.send(packet))?;
+        let queue = packet_queues.lock().await.get(&stream_id).cloned();
+        if let Some(queue) = queue {
+            queue.push(packet).await;
         }
     }
 
@@ -1,7 +1,10 @@
-use super::StreamId;
-use crate::LOCAL_IP;
+use super::{
+    mtu::{self, Fragmenter, Reassembler},
+    PacketQueues,
+};
+use crate::{network, PeerIp};
 use alvr_common::prelude::*;
-use bytes::{Buf, BufMut, Bytes, BytesMut};
+use bytes::{BufMut, Bytes, BytesMut};
 use futures::{Stream, StreamExt};
 use governor::{
     clock,
@@ -10,7 +13,6 @@ use governor::{
 };
 use nonzero_ext::NonZero;
 use std::{
-    collections::HashMap,
     io,
     mem::MaybeUninit,
     net::{IpAddr, SocketAddr},
@@ -18,11 +20,7 @@ use std::{
     sync::Arc,
     task::{Context, Poll},
 };
-use tokio::{
-    io::ReadBuf,
-    net::UdpSocket,
-    sync::{mpsc, Mutex},
-};
+use tokio::{io::ReadBuf, net::UdpSocket};
 
 const INITIAL_RD_CAPACITY: usize = 64 * 1024;
 
@@ -36,25 +34,28 @@ const RESERVE_BYTERATE: u32 = 5_000_000 / 8;
 pub struct ThrottledUdpStreamSendSocket {
     inner: Arc<UdpSocket>,
     limiter: Arc<Option<RateLimiter<NotKeyed, InMemoryState, clock::DefaultClock>>>,
+    fragmenter: Fragmenter,
 }
 
 impl ThrottledUdpStreamSendSocket {
     pub async fn send(&self, data: Bytes) -> io::Result<()> {
-        if let Some(ref limiter) = *self.limiter {
-            if let Some(len) = NonZero::new(data.len() as u32) {
-                limiter.until_n_ready(len).await.ok();
+        for fragment in self.fragmenter.split(data) {
+            if let Some(ref limiter) = *self.limiter {
+                if let Some(len) = NonZero::new(fragment.len() as u32) {
+                    limiter.until_n_ready(len).await.ok();
+                }
             }
+            self.inner.send(&fragment).await?;
         }
-        match self.inner.send(&data).await {
-            Ok(_) => Ok(()),
-            Err(e) => Err(e),
-        }
+
+        Ok(())
     }
 }
 
 pub struct ThrottledUdpStreamReceiveSocket {
     pub inner: Arc<UdpSocket>,
     buffer: BytesMut,
+    early_packets: Vec<BytesMut>,
 }
 
 // Code taken from https://github.com/tokio-rs/tokio/blob/master/tokio-util/src/udp/frame.rs
@@ -85,7 +86,8 @@ impl Stream for ThrottledUdpStreamReceiveSocket {
 }
 
 pub async fn connect_to_client(
-    client_ip: IpAddr,
+    bind_ip: IpAddr,
+    client_ip: PeerIp,
     port: u16,
     video_byterate: u32,
     bitrate_multiplier: f32,
@@ -93,8 +95,9 @@ pub async fn connect_to_client(
     ThrottledUdpStreamSendSocket,
     ThrottledUdpStreamReceiveSocket,
 )> {
-    let client_addr: SocketAddr = (client_ip, port).into();
-    let socket = trace_err!(UdpSocket::bind((LOCAL_IP, port)).await)?;
+    let client_addr = network::peer_address(bind_ip, client_ip, port);
+    let socket = network::bind_udp(bind_ip, port)?;
+    let (mtu, early_packets) = mtu::probe(&socket, client_addr, 0).await?;
     trace_err!(socket.connect(client_addr).await)?;
 
     let rx = Arc::new(socket);
@@ -115,27 +118,30 @@ pub async fn connect_to_client(
         ThrottledUdpStreamSendSocket {
             inner: tx,
             limiter: Arc::new(limiter),
+            fragmenter: Fragmenter::new(mtu),
         },
         ThrottledUdpStreamReceiveSocket {
             inner: rx,
             buffer: BytesMut::new(),
+            early_packets,
         },
     ))
 }
 
-pub async fn listen_for_server(port: u16) -> StrResult<UdpSocket> {
-    trace_err!(UdpSocket::bind((LOCAL_IP, port)).await)
+pub async fn listen_for_server(ip: IpAddr, port: u16) -> StrResult<UdpSocket> {
+    network::bind_udp(ip, port)
 }
 
 pub async fn accept_from_server(
     socket: UdpSocket,
-    server_ip: IpAddr,
+    server_ip: PeerIp,
     port: u16,
 ) -> StrResult<(
     ThrottledUdpStreamSendSocket,
     ThrottledUdpStreamReceiveSocket,
 )> {
-    let server_addr: SocketAddr = (server_ip, port).into();
+    let server_addr = network::peer_address(trace_err!(socket.local_addr())?.ip(), server_ip, port);
+    let (mtu, early_packets) = mtu::probe(&socket, server_addr, 0).await?;
     trace_err!(socket.connect(server_addr).await)?;
 
     let rx = Arc::new(socket);
@@ -145,25 +151,30 @@ pub async fn accept_from_server(
         ThrottledUdpStreamSendSocket {
             inner: tx,
             limiter: Arc::new(None),
+            fragmenter: Fragmenter::new(mtu),
         },
         ThrottledUdpStreamReceiveSocket {
             inner: rx,
             buffer: BytesMut::new(),
+            early_packets,
         },
     ))
 }
 
 pub async fn receive_loop(
     mut socket: ThrottledUdpStreamReceiveSocket,
-    packet_enqueuers: Arc<Mutex<HashMap<StreamId, mpsc::UnboundedSender<BytesMut>>>>,
+    packet_queues: PacketQueues,
 ) -> StrResult {
+    let mut reassembler = Reassembler::default();
+
+    for packet in std::mem::take(&mut socket.early_packets) {
+        mtu::dispatch_packet(&mut reassembler, &packet_queues, packet).await;
+    }
+
     while let Some(maybe_packet) = socket.next().await {
-        let (mut packet_bytes, _) = trace_err!(maybe_packet)?;
+        let (packet, _) = trace_err!(maybe_packet)?;
 
-        let stream_id = packet_bytes.get_u16();
-        if let Some(enqueuer) = packet_enqueuers.lock().await.get_mut(&stream_id) {
-            trace_err!(enqueuer.send(packet_bytes))?;
-        }
+        mtu::dispatch_packet(&mut reassembler, &packet_queues, packet).await;
     }
 
     Ok(())
@@ -1,77 +1,106 @@
-use super::StreamId;
-use crate::{Ldc, LOCAL_IP};
+use super::{
+    mtu::{self, Fragmenter, Reassembler},
+    PacketQueues,
+};
+use crate::{network, Ldc, PeerIp};
 use alvr_common::prelude::*;
 use bytes::{Buf, Bytes, BytesMut};
-use futures::{
-    stream::{SplitSink, SplitStream},
-    StreamExt,
-};
+use futures::SinkExt;
 use std::{
-    collections::HashMap,
     net::{IpAddr, SocketAddr},
     sync::Arc,
 };
-use tokio::{
-    net::UdpSocket,
-    sync::{mpsc, Mutex},
-};
+use tokio::{net::UdpSocket, sync::Mutex};
 use tokio_util::udp::UdpFramed;
 
+// Size of the length prefix added by LengthDelimitedCodec
+const LENGTH_PREFIX_SIZE: usize = 4;
+
 #[allow(clippy::type_complexity)]
 #[derive(Clone)]
 pub struct UdpStreamSendSocket {
     pub peer_addr: SocketAddr,
-    pub inner: Arc<Mutex<SplitSink<UdpFramed<Ldc>, (Bytes, SocketAddr)>>>,
+    pub inner: Arc<Mutex<UdpFramed<Ldc, Arc<UdpSocket>>>>,
+    fragmenter: Fragmenter,
+}
+
+impl UdpStreamSendSocket {
+    pub async fn send(&self, packet: Bytes) -> StrResult {
+        // Keep the lock so that fragments are not interleaved with other packets
+        let mut inner = self.inner.lock().await;
+        for fragment in self.fragmenter.split(packet) {
+            trace_err!(inner.send((fragment, self.peer_addr)).await)?;
+        }
+
+        Ok(())
+    }
 }
 
 // peer_addr is needed to check that the packet comes from the desired device. Connecting directly
-// to the peer is not supported by UdpFramed.
+// to the peer is not supported by UdpFramed. Packets are not received with UdpFramed, which would
+// keep failing after a malformed length prefix.
 pub struct UdpStreamReceiveSocket {
     pub peer_addr: SocketAddr,
-    pub inner: SplitStream<UdpFramed<Ldc>>,
+    pub inner: Arc<UdpSocket>,
+    early_packets: Vec<BytesMut>,
 }
 
-pub async fn bind(port: u16) -> StrResult<UdpSocket> {
-    trace_err!(UdpSocket::bind((LOCAL_IP, port)).await)
+pub async fn bind(ip: IpAddr, port: u16) -> StrResult<UdpSocket> {
+    network::bind_udp(ip, port)
 }
 
 pub async fn connect(
     socket: UdpSocket,
-    peer_ip: IpAddr,
+    peer_ip: PeerIp,
     port: u16,
 ) -> StrResult<(UdpStreamSendSocket, UdpStreamReceiveSocket)> {
-    let peer_addr = (peer_ip, port).into();
-    let socket = UdpFramed::new(socket, Ldc::new());
-    let (send_socket, receive_socket) = socket.split();
+    let peer_addr = network::peer_address(trace_err!(socket.local_addr())?.ip(), peer_ip, port);
+    let (mtu, early_packets) = mtu::probe(&socket, peer_addr, LENGTH_PREFIX_SIZE).await?;
+
+    let socket = Arc::new(socket);
+    let send_socket = UdpFramed::new(Arc::clone(&socket), Ldc::new());
 
     Ok((
         UdpStreamSendSocket {
             peer_addr,
             inner: Arc::new(Mutex::new(send_socket)),
+            fragmenter: Fragmenter::new(mtu - LENGTH_PREFIX_SIZE),
         },
         UdpStreamReceiveSocket {
             peer_addr,
-            inner: receive_socket,
+            inner: socket,
+            early_packets,
         },
     ))
 }
 
 pub async fn receive_loop(
-    mut socket: UdpStreamReceiveSocket,
-    packet_enqueuers: Arc<Mutex<HashMap<StreamId, mpsc::UnboundedSender<BytesMut>>>>,
+    socket: UdpStreamReceiveSocket,
+    packet_queues: PacketQueues,
 ) -> StrResult {
-    while let Some(maybe_packet) = socket.inner.next().await {
-        let (mut packet_bytes, address) = trace_err!(maybe_packet)?;
+    let mut reassembler = Reassembler::default();
+
+    for packet in socket.early_packets {
+        mtu::dispatch_packet(&mut reassembler, &packet_queues, packet).await;
+    }
+
+    let mut buffer = vec![0; u16::MAX as usize];
+    loop {
+        let (len, address) = trace_err!(socket.inner.recv_from(&mut buffer).await)?;
 
         if address != socket.peer_addr {
             continue;
         }
 
-        let stream_id = packet_bytes.get_u16();
-        if let Some(enqueuer) = packet_enqueuers.lock().await.get_mut(&stream_id) {
-            trace_err!(enqueuer.send(packet_bytes))?;
+        // Length prefix as written by LengthDelimitedCodec
+        if len < LENGTH_PREFIX_SIZE
+            || (&buffer[..LENGTH_PREFIX_SIZE]).get_u32() as usize != len - LENGTH_PREFIX_SIZE
+        {
+            debug!("Dropped packet: invalid length prefix");
+            continue;
         }
-    }
 
-    Ok(())
+        let packet = BytesMut::from(&buffer[LENGTH_PREFIX_SIZE..len]);
+        mtu::dispatch_packet(&mut reassembler, &packet_queues, packet).await;
+    }
 }
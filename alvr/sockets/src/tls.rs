@@ -0,0 +1,103 @@
+// Peers use self-signed certificates, so there is no certificate authority to check against.
+// Instead, the server pins the certificate of each trusted client the first time it connects to it.
+
+use crate::PrivateIdentity;
+use alvr_common::prelude::*;
+use rustls::{
+    client::{ServerCertVerified, ServerCertVerifier},
+    server::{ClientCertVerified, ClientCertVerifier},
+    Certificate, DistinguishedNames, PrivateKey, ServerName,
+};
+use std::{sync::Arc, time::SystemTime};
+
+// Peers are identified by their certificate, not by this name
+pub const SERVER_NAME: &str = "alvr.client";
+
+const CERTIFICATE_TAG: &str = "CERTIFICATE";
+
+pub fn certificate_from_pem(certificate_pem: &str) -> StrResult<Certificate> {
+    Ok(Certificate(
+        trace_err!(pem::parse(certificate_pem))?.contents,
+    ))
+}
+
+pub fn certificate_to_pem(certificate: &Certificate) -> String {
+    pem::encode(&pem::Pem {
+        tag: CERTIFICATE_TAG.into(),
+        contents: certificate.0.clone(),
+    })
+}
+
+pub fn private_key_from_pem(key_pem: &str) -> StrResult<PrivateKey> {
+    Ok(PrivateKey(trace_err!(pem::parse(key_pem))?.contents))
+}
+
+// Accepts only the pinned certificate if there is one, otherwise any certificate
+pub struct PinnedServerCertificate(pub Option<Certificate>);
+
+impl ServerCertVerifier for PinnedServerCertificate {
+    fn verify_server_cert(
+        &self,
+        end_entity: &Certificate,
+        _: &[Certificate],
+        _: &ServerName,
+        _: &mut dyn Iterator<Item = &[u8]>,
+        _: &[u8],
+        _: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        match &self.0 {
+            Some(pinned_certificate) if pinned_certificate != end_entity => Err(
+                rustls::Error::InvalidCertificateData("Certificate does not match".into()),
+            ),
+            _ => Ok(ServerCertVerified::assertion()),
+        }
+    }
+}
+
+// The client has no way of knowing the server certificate in advance. A certificate is still
+// required so that the connection is mutually authenticated.
+struct AnyClientCertificate;
+
+impl ClientCertVerifier for AnyClientCertificate {
+    fn client_auth_root_subjects(&self) -> Option<DistinguishedNames> {
+        Some(vec![])
+    }
+
+    fn verify_client_cert(
+        &self,
+        _: &Certificate,
+        _: &[Certificate],
+        _: SystemTime,
+    ) -> Result<ClientCertVerified, rustls::Error> {
+        Ok(ClientCertVerified::assertion())
+    }
+}
+
+// Used by the ALVR client, which listens for the server
+pub fn server_config(identity: &PrivateIdentity) -> StrResult<Arc<rustls::ServerConfig>> {
+    let config = trace_err!(rustls::ServerConfig::builder()
+        .with_safe_defaults()
+        .with_client_cert_verifier(Arc::new(AnyClientCertificate))
+        .with_single_cert(
+            vec![certificate_from_pem(&identity.certificate_pem)?],
+            private_key_from_pem(&identity.key_pem)?,
+        ))?;
+
+    Ok(Arc::new(config))
+}
+
+// Used by the ALVR server, which connects to the client
+pub fn client_config(
+    identity: &PrivateIdentity,
+    pinned_certificate: Option<Certificate>,
+) -> StrResult<Arc<rustls::ClientConfig>> {
+    let config = trace_err!(rustls::ClientConfig::builder()
+        .with_safe_defaults()
+        .with_custom_certificate_verifier(Arc::new(PinnedServerCertificate(pinned_certificate)))
+        .with_single_cert(
+            vec![certificate_from_pem(&identity.certificate_pem)?],
+            private_key_from_pem(&identity.key_pem)?,
+        ))?;
+
+    Ok(Arc::new(config))
+}
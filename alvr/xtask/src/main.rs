@@ -44,6 +44,9 @@ SUBCOMMANDS:
     bump-alxr-versions  Bump alxr-client package versions
     clippy              Show warnings for selected clippy lints
     prettier            Format JS and CSS files with prettier; Requires Node.js and NPM.
+    generate-settings-schema Write the JSON Schema and TypeScript definitions of the session settings to build folder
+    generate-openapi    Write the OpenAPI description of the versioned web API to build folder
+    summarize-recording Print total latency percentiles, packet loss bursts and reconnects of a session recording
 
 FLAGS:
     --reproducible      Force cargo to build reproducibly. Used only for build subcommands
@@ -66,6 +69,7 @@ ARGS:
     --version <VERSION> Specify version to set with the bump-(alxr-)versions subcommand
     --root <PATH>       Installation root. By default no root is set and paths are calculated using
                         relative paths, which requires conforming to FHS on Linux.
+    --recording <PATH>  Session recording (.jsonl) to read with the summarize-recording subcommand
 "#;
 
 pub fn remove_build_dir() {
@@ -993,6 +997,46 @@ fn clippy() {
     .unwrap();
 }
 
+// Both describe the session_settings of session.json
+fn generate_settings_schema() {
+    let schema = alvr_session::settings_schema(alvr_session::session_settings_default());
+
+    let build_dir = afs::build_dir();
+    fs::create_dir_all(&build_dir).unwrap();
+
+    let json_schema = settings_schema::json_schema(&schema, "SessionSettings");
+    fs::write(
+        build_dir.join("settings.schema.json"),
+        serde_json::to_string_pretty(&json_schema).unwrap(),
+    )
+    .unwrap();
+
+    fs::write(
+        build_dir.join("settings.d.ts"),
+        settings_schema::typescript_definition(&schema, "SessionSettings"),
+    )
+    .unwrap();
+}
+
+fn generate_openapi() {
+    let build_dir = afs::build_dir();
+    fs::create_dir_all(&build_dir).unwrap();
+
+    fs::write(
+        build_dir.join("openapi.json"),
+        serde_json::to_string_pretty(&alvr_session::openapi_v1()).unwrap(),
+    )
+    .unwrap();
+}
+
+fn summarize_recording(maybe_path: Option<String>) {
+    let path = maybe_path.expect("Missing --recording <PATH>");
+    let recording = fs::read_to_string(&path).unwrap();
+
+    println!("{path}");
+    println!("{}", alvr_session::summarize_recording(&recording));
+}
+
 fn prettier() {
     command::run("npx -p prettier@2.2.1 prettier --config alvr/xtask/.prettierrc --write '**/*[!.min].{css,js}'").unwrap();
 }
@@ -1024,6 +1068,7 @@ fn main() {
         let gpl = args.contains("--gpl");
         let reproducible = args.contains("--reproducible");
         let root: Option<String> = args.opt_value_from_str("--root").unwrap();
+        let recording: Option<String> = args.opt_value_from_str("--recording").unwrap();
 
         let default_var = String::from("n5.0");
         let mut ffmpeg_version: String =
@@ -1181,6 +1226,9 @@ fn main() {
                 "bump-alxr-versions" => version::bump_alxr_version(version, is_nightly),
                 "clippy" => clippy(),
                 "prettier" => prettier(),
+                "generate-settings-schema" => generate_settings_schema(),
+                "generate-openapi" => generate_openapi(),
+                "summarize-recording" => summarize_recording(recording),
                 _ => {
                     println!("\nUnrecognized subcommand.");
                     println!("{HELP_STR}");